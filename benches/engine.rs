@@ -0,0 +1,101 @@
+//! Benchmarks for the parts of the engine that bots and netplay lean on.
+//! Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use oorandom::Rand32;
+use tetris::{
+    config::Handling,
+    grid::{Cell, Grid, Pos, GAME_GRID_HEIGHT, GAME_GRID_WIDTH},
+    input::Action,
+    mode::Mode,
+    piece::{Piece, Tetromino},
+    rules::Game,
+    ruleset::{Memoryless, Randomizer},
+};
+
+/// Eight rows of garbage at the bottom, each with a gap in a different column.
+fn stack() -> Grid {
+    let mut grid = Grid::new();
+    for y in GAME_GRID_HEIGHT - 8..GAME_GRID_HEIGHT {
+        for x in 0..GAME_GRID_WIDTH {
+            if x != (y * 7) % GAME_GRID_WIDTH {
+                grid.set(Pos::new(x as i8, y as i8), Cell::Garbage);
+            }
+        }
+    }
+    grid
+}
+
+fn collision(c: &mut Criterion) {
+    let grid = stack();
+    let pieces = Tetromino::ALL.map(Piece::new);
+    c.bench_function("collision: every piece at every position", |b| {
+        b.iter(|| {
+            let mut fits = 0;
+            for piece in &pieces {
+                for y in -2..GAME_GRID_HEIGHT as i8 {
+                    for x in -2..GAME_GRID_WIDTH as i8 + 2 {
+                        fits += grid.bits().fits(piece, Pos::new(x, y)) as u32;
+                    }
+                }
+            }
+            black_box(fits)
+        })
+    });
+}
+
+fn line_clear(c: &mut Criterion) {
+    let mut grid = stack();
+    for y in GAME_GRID_HEIGHT - 4..GAME_GRID_HEIGHT {
+        for x in 0..GAME_GRID_WIDTH {
+            grid.set(Pos::new(x as i8, y as i8), Cell::Garbage);
+        }
+    }
+    c.bench_function("line clear: four rows", |b| {
+        b.iter_batched_ref(|| grid.clone(), |grid| black_box(grid.clear_full_rows()), BatchSize::SmallInput)
+    });
+}
+
+fn piece_generation(c: &mut Criterion) {
+    c.bench_function("piece generation: 1000 pieces", |b| {
+        b.iter(|| {
+            let mut rng = Rand32::new(black_box(7));
+            for _ in 0..1000 {
+                black_box(Memoryless.next(&mut rng));
+            }
+        })
+    });
+}
+
+/// Presses and releases `action` over two ticks.
+fn tap(game: &mut Game, action: Action) {
+    game.tick(&[(action, true)]);
+    game.tick(&[(action, false)]);
+}
+
+/// Plays a game to the end, dropping each piece into the next of a spread of columns.
+fn play(seed: u64) -> Game {
+    let mut game = Game::new(Mode::Marathon, seed, Handling::default());
+    for n in 0i32.. {
+        if game.gameover {
+            break;
+        }
+        let shift = (n * 7) % 10 - 5;
+        let action = if shift < 0 { Action::Left } else { Action::Right };
+        for _ in 0..shift.abs() {
+            tap(&mut game, action);
+        }
+        if n % 2 == 0 {
+            tap(&mut game, Action::RotRight);
+        }
+        tap(&mut game, Action::HardDrop);
+    }
+    game
+}
+
+fn full_game(c: &mut Criterion) {
+    c.bench_function("full game: spreading pieces until topping out", |b| b.iter(|| black_box(play(black_box(42)).tick)));
+}
+
+criterion_group!(benches, collision, line_clear, piece_generation, full_game);
+criterion_main!(benches);
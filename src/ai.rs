@@ -0,0 +1,502 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use oorandom::Rand32;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::random_seed,
+    bitgrid::BitGrid,
+    bot::{Bot, FromBot, REPLY_TICKS},
+    grid::{Grid, Pos},
+    input::Action,
+    piece::{MovingPiece, Piece, Tetromino},
+    rotation::Rotation,
+    rules::{Game, TICKS_PER_SECOND},
+    worker::{Stop, Worker},
+};
+
+/// How many inputs the AI makes for one piece before it drops it wherever it is, in case it can't get where it wanted.
+const MAX_INPUTS_PER_PIECE: u32 = 16;
+
+/// How well the built-in AI plays, picked before a game against the computer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    /// Slow, and drops a lot of pieces in the wrong place, leaving holes.
+    Beginner,
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+    /// As fast as the inputs go, looking a piece ahead, and never misdropping.
+    Insane,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 5] = [Difficulty::Beginner, Difficulty::Easy, Difficulty::Medium, Difficulty::Hard, Difficulty::Insane];
+
+    pub fn cycle(self, forward: bool) -> Self {
+        let i = Self::ALL.iter().position(|&d| d == self).unwrap_or(0);
+        let n = Self::ALL.len();
+        Self::ALL[if forward { (i + 1) % n } else { (i + n - 1) % n }]
+    }
+    /// How the AI plays at this difficulty.
+    pub fn skill(self) -> Skill {
+        let (reaction_ticks, ticks_per_input, max_pps, misdrop_percent, depth) = match self {
+            Difficulty::Beginner => (16, 4, 0.6, 25, 1),
+            Difficulty::Easy => (10, 3, 1., 10, 1),
+            Difficulty::Medium => (6, 2, 1.5, 4, 1),
+            Difficulty::Hard => (3, 2, 2.5, 1, 2),
+            Difficulty::Insane => (0, 1, 8., 0, 2),
+        };
+        Skill { reaction_ticks, ticks_per_input, max_pps, misdrop_percent, depth }
+    }
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Difficulty::Beginner => "Beginner",
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+            Difficulty::Insane => "Insane",
+        })
+    }
+}
+
+/// What holds the AI back at a difficulty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Skill {
+    /// How many ticks the AI waits after a piece appears before its first input.
+    pub reaction_ticks: u32,
+    /// How many ticks apart its inputs are.
+    pub ticks_per_input: u32,
+    /// The most pieces it places a second.
+    pub max_pps: f32,
+    /// How likely it is to drop a piece a column away from where it meant to, in percent.
+    pub misdrop_percent: u32,
+    /// How many pieces it looks at: 1 for the current one, 2 for the next one as well.
+    pub depth: u32,
+}
+
+impl Skill {
+    /// The fewest ticks a piece is in play for before it is dropped.
+    fn piece_ticks(&self) -> u32 {
+        (TICKS_PER_SECOND as f32 / self.max_pps).ceil() as u32
+    }
+}
+
+/// How much each feature of a board counts towards the AI thinking it is a good one.
+/// The more a feature is worth avoiding, the more negative its weight.
+/// They can be set in the config to change how the computer plays, e.g. a very negative `holes` for one that digs
+/// and keeps its board clean, or a high `lines` for one that goes for clears to send garbage.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    /// Per block of the columns' heights added up.
+    pub height: f32,
+    /// Per line the placement clears, which is how keen it is to attack.
+    pub lines: f32,
+    /// Per empty cell with a block above it.
+    pub holes: f32,
+    /// Per block of difference in height between neighbouring columns.
+    pub bumpiness: f32,
+    /// Per block of depth of the columns lower than those either side of them.
+    pub wells: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights { height: -0.51, lines: 0.76, holes: -0.36, bumpiness: -0.18, wells: 0. }
+    }
+}
+
+impl Weights {
+    /// How good `grid` is to have after clearing `lines` lines.
+    pub fn evaluate(&self, grid: &BitGrid, lines: usize) -> f32 {
+        let heights = grid.column_heights();
+        let height: usize = heights.iter().sum();
+        let bumpiness: usize = heights.windows(2).map(|pair| pair[0].abs_diff(pair[1])).sum();
+        // The walls are as high as the board
+        let side = |x: Option<usize>| x.and_then(|x| heights.get(x)).map_or(grid.size().1 as usize, |&h| h);
+        let wells: usize = heights.iter().enumerate().map(|(x, &h)| side(x.checked_sub(1)).min(side(Some(x + 1))).saturating_sub(h)).sum();
+        self.height * height as f32
+            + self.lines * lines as f32
+            + self.holes * grid.count_holes() as f32
+            + self.bumpiness * bumpiness as f32
+            + self.wells * wells as f32
+    }
+}
+
+/// Where the AI wants a piece to go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    /// The piece to place, which is the held or next one if it has to be held first.
+    pub kind: Tetromino,
+    pub hold: bool,
+    /// How many quarter turns right of its spawn orientation the piece goes in.
+    pub rotation: u8,
+    /// The column the piece is dropped in, as the `x` of its position.
+    pub x: i8,
+}
+
+/// A piece the AI could place, with each of its orientations as `(quarter turns right, piece)`.
+#[derive(Debug, Clone)]
+struct Candidate {
+    kind: Tetromino,
+    hold: bool,
+    orientations: Vec<(u8, Piece)>,
+    /// The orientations of the piece that comes after it, if it is known.
+    next: Option<Vec<(u8, Piece)>>,
+}
+
+/// The board as the AI is to search it, sent off to the search thread.
+#[derive(Debug, Clone)]
+struct Search {
+    /// Which search this is, to tell whether its result is still wanted when it comes back.
+    id: u32,
+    grid: BitGrid,
+    /// Where the piece starts from, which it is turned at and shifted sideways from before dropping.
+    start: Pos,
+    candidates: Vec<Candidate>,
+    weights: Weights,
+    depth: u32,
+}
+
+/// Calls `f` with each way a piece with `orientations` can be dropped on `grid` from `start`, as
+/// `(rotation, x, grid after, lines cleared)`: every orientation in every column it can be shifted to
+/// from the start without hitting anything.
+fn drops(grid: &BitGrid, start: Pos, orientations: &[(u8, Piece)], mut f: impl FnMut(u8, i8, BitGrid, usize)) {
+    for &(rotation, piece) in orientations {
+        if !grid.fits(&piece, start) {
+            continue;
+        }
+        for step in [-1, 1] {
+            let mut x = if step < 0 { start.x } else { start.x + 1 };
+            while grid.fits(&piece, Pos::new(x, start.y)) {
+                let mut y = start.y;
+                while grid.fits(&piece, Pos::new(x, y + 1)) {
+                    y += 1;
+                }
+                let mut after = *grid;
+                // Locking a piece out above the top is topping out
+                if after.place(&piece, Pos::new(x, y)) {
+                    let lines = after.clear_full_rows();
+                    f(rotation, x, after, lines);
+                }
+                x += step;
+            }
+        }
+    }
+}
+
+/// Finds the best placement of any of the candidates by how the board looks after it,
+/// or after the best placement of the piece after it too when searching two deep, with how good that board is.
+fn search(search: &Search, stop: &Stop) -> Option<(f32, Placement)> {
+    let mut best: Option<(f32, Placement)> = None;
+    for candidate in &search.candidates {
+        if stop.requested() {
+            return None;
+        }
+        drops(&search.grid, search.start, &candidate.orientations, |rotation, x, grid, lines| {
+            let next = candidate.next.as_ref().filter(|_| search.depth > 1);
+            let score = match next {
+                Some(next) => {
+                    let mut best_next = None;
+                    drops(&grid, search.start, next, |_, _, grid, more| {
+                        let score = search.weights.evaluate(&grid, lines + more);
+                        best_next = Some(best_next.map_or(score, |best: f32| best.max(score)));
+                    });
+                    // Nowhere for the next piece to go is as bad as it gets
+                    best_next.unwrap_or(f32::MIN)
+                }
+                None => search.weights.evaluate(&grid, lines),
+            };
+            if best.is_none_or(|(best, _)| score > best) {
+                best = Some((score, Placement { kind: candidate.kind, hold: candidate.hold, rotation, x }));
+            }
+        });
+    }
+    best
+}
+
+/// The best placement of the current piece of `game` at `start`, holding it or not, by how good the board is
+/// right after it, with how good that is. This is what a placement is judged against when a game is reviewed.
+pub(crate) fn best_placement(game: &Game, start: Pos, weights: Weights) -> Option<(f32, Placement)> {
+    search(&Search::new(game, 0, start, true, weights, 1), &Stop::default())
+}
+
+/// Where a piece put as `placement` lands on `grid`, turned and shifted from `start` and dropped straight down.
+pub(crate) fn landing(game: &Game, grid: &BitGrid, start: Pos, placement: Placement) -> Option<MovingPiece> {
+    let (_, turned) = orientations(game, placement.kind, start.x).into_iter().find(|&(rotation, _)| rotation == placement.rotation)?;
+    let mut landing = MovingPiece::new(turned, Pos::new(placement.x, start.y));
+    while grid.fits(&landing.piece, Pos::new(landing.pos.x, landing.pos.y + 1)) {
+        landing.pos.y += 1;
+    }
+    Some(landing)
+}
+
+/// Each orientation `kind` can be turned to in `game`, turning it right from its spawn orientation in column `x`.
+pub(crate) fn orientations(game: &Game, kind: Tetromino, x: i8) -> Vec<(u8, Piece)> {
+    // Turned in the open so the rotation system turns it in place
+    let open = Grid::with_size(game.grid.size());
+    let mut piece = MovingPiece::new(Piece::new(kind), Pos::new(x, 0));
+    let mut orientations = vec![(0, piece.piece)];
+    for rotation in 1..4 {
+        let Some(turned) = game.rotation.rotate(&piece, Rotation::Right, &open) else {
+            break;
+        };
+        piece = turned;
+        orientations.push((rotation, piece.piece));
+    }
+    orientations
+}
+
+/// Where an `Ai` gets its placements from.
+enum Brain {
+    /// The built-in search, with the weights it scores boards by.
+    Search(Worker<Search, (u32, Option<Placement>)>, Weights),
+    /// An external program, which may also make the inputs itself.
+    Bot(Bot),
+}
+
+/// A computer player, which picks where each piece goes on a thread of its own, or has a bot pick,
+/// and then plays the inputs to get it there, one every few ticks.
+pub struct Ai {
+    brain: Brain,
+    skill: Skill,
+    /// Decides the misdrops.
+    rng: Rand32,
+    /// How many pieces had been placed when the AI last asked for a placement, so it asks once a piece.
+    asked: Option<u32>,
+    /// How many ticks ago the AI asked.
+    waited: u32,
+    plan: Option<Placement>,
+    /// Inputs a bot asked for that are still to be made, before anything else.
+    queued: VecDeque<Action>,
+    /// How many inputs the AI has made for the current piece.
+    inputs: u32,
+    wait: u32,
+}
+
+impl Ai {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Ai::with_weights(difficulty, Weights::default())
+    }
+    /// An AI that judges boards by `weights` instead of the usual ones.
+    pub fn with_weights(difficulty: Difficulty, weights: Weights) -> Self {
+        let worker = Worker::spawn("ai", |job: Search, stop| (job.id, search(&job, stop).map(|(_, placement)| placement)));
+        Ai::with_brain(Brain::Search(worker, weights), difficulty.skill())
+    }
+    /// An AI played by the bot `command` starts, or the built-in one if it can't be started.
+    pub fn bot(command: &str, game: &Game) -> Self {
+        match Bot::spawn(command, game) {
+            // How well a bot plays is up to the bot, it only has to keep to the pace of the inputs
+            Ok(bot) => Ai::with_brain(Brain::Bot(bot), Difficulty::Insane.skill()),
+            Err(e) => {
+                warn!("Could not start the bot {command}, playing the built-in AI instead: {e}");
+                Ai::new(Difficulty::default())
+            }
+        }
+    }
+    fn with_brain(brain: Brain, skill: Skill) -> Self {
+        Ai { brain, skill, rng: Rand32::new(random_seed()), asked: None, waited: 0, plan: None, queued: VecDeque::new(), inputs: 0, wait: 0 }
+    }
+    /// Takes in the placements and inputs that have come back for the piece `game` is on.
+    fn receive(&mut self, game: &Game) {
+        match &self.brain {
+            Brain::Search(worker, _) => {
+                for (pieces, plan) in worker.results() {
+                    if pieces == game.pieces {
+                        self.plan = plan.map(|mut plan| {
+                            if self.rng.rand_range(0..100) < self.skill.misdrop_percent {
+                                plan.x += if self.rng.rand_range(0..2) == 0 { -1 } else { 1 };
+                            }
+                            plan
+                        });
+                    }
+                }
+            }
+            Brain::Bot(bot) => {
+                // A bot that answers too late, or about another piece, is ignored
+                for reply in bot.replies().filter(|reply| reply.piece() == game.pieces && self.waited <= REPLY_TICKS) {
+                    match reply {
+                        FromBot::Place { hold, rotation, x, .. } => {
+                            let kind = match hold {
+                                true => game.hold_piece.unwrap_or(game.next_piece).kind,
+                                false => game.cur_piece.as_ref().map_or(game.next_piece.kind, |piece| piece.piece.kind),
+                            };
+                            self.plan = Some(Placement { kind, hold, rotation: rotation % 4, x });
+                        }
+                        FromBot::Inputs { inputs, .. } => {
+                            let playable = |action: &Action| matches!(action, Action::Left | Action::Right | Action::RotLeft | Action::RotRight | Action::SoftDrop | Action::HardDrop | Action::Hold);
+                            self.queued.extend(inputs.into_iter().filter(playable));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// The inputs the AI makes on the next tick of `game`.
+    pub fn inputs(&mut self, game: &Game) -> Vec<(Action, bool)> {
+        self.receive(game);
+        let Some(piece) = &game.cur_piece else {
+            return Vec::new();
+        };
+        if self.asked != Some(game.pieces) {
+            self.ask(game, piece.pos);
+        }
+        self.waited += 1;
+        if self.wait > 0 || self.waited <= self.skill.reaction_ticks {
+            self.wait = self.wait.saturating_sub(1);
+            return Vec::new();
+        }
+        let action = if let Some(action) = self.queued.pop_front() {
+            action
+        } else if let Some(plan) = self.plan {
+            self.follow(game, plan)
+        } else if self.waited > REPLY_TICKS {
+            // Out of time to decide
+            Action::HardDrop
+        } else {
+            return Vec::new();
+        };
+        if action == Action::HardDrop && self.waited < self.skill.piece_ticks() {
+            return Vec::new();
+        }
+        self.inputs += 1;
+        self.wait = self.skill.ticks_per_input - 1;
+        vec![(action, true), (action, false)]
+    }
+    /// Waits up to `timeout` for where the current piece of `game` is to go, once it has been asked about,
+    /// so that the AI keeps up with a game played faster than real time, e.g. without a window.
+    pub fn wait_for_plan(&mut self, game: &Game, timeout: Duration) {
+        let started = Instant::now();
+        while self.asked == Some(game.pieces) && self.plan.is_none() && self.queued.is_empty() && started.elapsed() < timeout {
+            thread::sleep(Duration::from_millis(1));
+            self.receive(game);
+        }
+    }
+    /// The next input towards putting the current piece of `game` where `plan` says.
+    fn follow(&self, game: &Game, plan: Placement) -> Action {
+        let Some(piece) = &game.cur_piece else {
+            return Action::HardDrop;
+        };
+        if self.inputs >= MAX_INPUTS_PER_PIECE {
+            Action::HardDrop
+        } else if piece.piece.kind != plan.kind {
+            // The piece the plan was for was held, or the one it was to be swapped for
+            if plan.hold && game.can_hold() {
+                Action::Hold
+            } else {
+                Action::HardDrop
+            }
+        } else if piece.piece.rotation != plan.rotation {
+            if (plan.rotation + 4 - piece.piece.rotation) % 4 == 3 {
+                Action::RotLeft
+            } else {
+                Action::RotRight
+            }
+        } else if piece.pos.x < plan.x {
+            Action::Right
+        } else if piece.pos.x > plan.x {
+            Action::Left
+        } else {
+            Action::HardDrop
+        }
+    }
+    /// Sends the search for where the current piece, at `start`, should go.
+    fn ask(&mut self, game: &Game, start: Pos) {
+        self.asked = Some(game.pieces);
+        self.waited = 0;
+        self.plan = None;
+        self.queued.clear();
+        self.inputs = 0;
+        match &mut self.brain {
+            Brain::Search(worker, weights) => {
+                worker.send(Search::new(game, game.pieces, start, true, *weights, self.skill.depth));
+            }
+            Brain::Bot(bot) => bot.ask(game),
+        }
+    }
+}
+
+impl Search {
+    /// The search for where the current piece of `game`, at `start`, should go, considering holding it if `hold`.
+    fn new(game: &Game, id: u32, start: Pos, hold: bool, weights: Weights, depth: u32) -> Self {
+        let turns = |kind| orientations(game, kind, start.x);
+        let current = game.cur_piece.as_ref().map_or(game.next_piece.kind, |piece| piece.piece.kind);
+        let next = game.next_piece.kind;
+        let mut candidates = vec![Candidate { kind: current, hold: false, orientations: turns(current), next: Some(turns(next)) }];
+        if hold && game.can_hold() {
+            // Holding with nothing held uses up the next piece, and what comes after it isn't known yet
+            let (other, next) = match game.hold_piece {
+                Some(held) => (held.kind, Some(turns(next))),
+                None => (next, None),
+            };
+            candidates.push(Candidate { kind: other, hold: true, orientations: turns(other), next });
+        }
+        Search { id, grid: *game.grid.bits(), start, candidates, weights, depth }
+    }
+}
+
+/// Suggests where the current piece of a game should go, by the same search the AI plays with,
+/// looking a piece ahead. Searching again whenever the board or the pieces change keeps it up to date
+/// through undos as well as placements.
+pub struct Hinter {
+    worker: Worker<Search, (u32, Option<Placement>)>,
+    /// The board and the current, next and held pieces when last searched, where the current one was, and that search's id.
+    asked: Option<(BitGrid, [Option<Tetromino>; 3], Pos, u32)>,
+    /// Where the current piece would land in the suggested placement.
+    hint: Option<MovingPiece>,
+}
+
+impl Default for Hinter {
+    fn default() -> Self {
+        Hinter {
+            worker: Worker::spawn("hints", |job: Search, stop| (job.id, search(&job, stop).map(|(_, placement)| placement))),
+            asked: None,
+            hint: None,
+        }
+    }
+}
+
+impl Hinter {
+    /// Searches again if `game` has changed since the last search, and takes in what the last search came to.
+    pub fn update(&mut self, game: &Game) {
+        let Some(piece) = &game.cur_piece else {
+            return;
+        };
+        let pieces = [Some(piece.piece.kind), Some(game.next_piece.kind), game.hold_piece.map(|piece| piece.kind)];
+        let grid = *game.grid.bits();
+        let changed = self.asked.is_none_or(|(asked, asked_pieces, ..)| asked != grid || asked_pieces != pieces);
+        if changed {
+            let id = self.asked.map_or(0, |(.., id)| id.wrapping_add(1));
+            // A new piece is searched for as soon as it appears, from the spawn
+            let start = piece.pos;
+            self.asked = Some((grid, pieces, start, id));
+            self.hint = None;
+            self.worker.send(Search::new(game, id, start, false, Weights::default(), 2));
+        }
+        let Some((_, _, start, id)) = self.asked else {
+            return;
+        };
+        for (searched, placement) in self.worker.results() {
+            if searched != id {
+                continue;
+            }
+            self.hint = placement.and_then(|placement| landing(game, game.grid.bits(), start, placement));
+        }
+    }
+    /// Where the current piece would land in the suggested placement, once there is one.
+    pub fn hint(&self) -> Option<&MovingPiece> {
+        self.hint.as_ref()
+    }
+}
@@ -0,0 +1,114 @@
+use ggez::graphics::{Color, Rect};
+
+use crate::{
+    ai::{best_placement, landing, Weights},
+    config::GameConfig,
+    grid::Grid,
+    piece::MovingPiece,
+    render::{self, Renderer, Viewport, GRID_CELL_SIZE},
+    replay::{Playback, Replay},
+    rules::{Game, TICKS_PER_SECOND},
+    theme::Theme,
+    worker::{Stop, Worker},
+};
+
+/// How many of the worst placements a review points out.
+const MISTAKES_SHOWN: usize = 3;
+/// How much worse than the best placement the board has to be left for a placement to count as a mistake,
+/// going by `Weights::evaluate`. About a hole and a bit of unevenness.
+const MIN_LOSS: f32 = 1.;
+/// How long a game is played on for after its last input before the review gives up on it, like `Replay::verify`.
+const REVIEW_AFTER_INPUTS: u32 = 60 * TICKS_PER_SECOND;
+
+/// A placement that left the board a lot worse than it could have been.
+#[derive(Debug, Clone)]
+pub struct Mistake {
+    /// How far into the game the piece was placed.
+    pub ms: u32,
+    /// The board before the piece was placed.
+    pub board: Grid,
+    /// Where the piece went.
+    pub played: MovingPiece,
+    /// Where the AI would have put it instead, which may be the held piece.
+    pub suggested: MovingPiece,
+    /// How much better the board would have been, going by `Weights::evaluate`.
+    pub loss: f32,
+}
+
+/// Plays `replay` out, judging every placement against the best the AI can find for that piece,
+/// and returns the worst ones, worst first. It stops early if asked to.
+pub fn review(replay: &Replay, config: &GameConfig, stop: &Stop) -> Vec<Mistake> {
+    let mut game = Game::with_config(replay.mode, replay.mode.rules(), replay.seed, replay.handling, config);
+    game.start_level = replay.level;
+    let mut playback = Playback::new(replay);
+    let last_input = replay.events.last().map_or(0, |&(tick, ..)| tick);
+    let weights = Weights::default();
+    // The board and the best placement when the current piece appeared, and which piece that was
+    let mut appeared: Option<(u32, Grid, f32, Option<MovingPiece>)> = None;
+    let mut mistakes = Vec::new();
+    while !game.gameover && game.tick <= last_input.saturating_add(REVIEW_AFTER_INPUTS) && !stop.requested() {
+        if let Some(piece) = game.cur_piece.as_ref().filter(|_| appeared.as_ref().is_none_or(|&(pieces, ..)| pieces != game.pieces)) {
+            let start = piece.pos;
+            let best = best_placement(&game, start, weights);
+            let suggested = best.and_then(|(_, placement)| landing(&game, game.grid.bits(), start, placement));
+            appeared = Some((game.pieces, game.grid.clone(), best.map_or(f32::MIN, |(score, _)| score), suggested));
+        }
+        let inputs: Vec<_> = playback.inputs(game.tick.wrapping_add(1)).collect();
+        let result = game.tick(&inputs);
+        let (Some(played), Some((_, board, best, Some(suggested)))) = (result.locked_piece, &appeared) else {
+            continue;
+        };
+        let mut after = *board.bits();
+        // Locking out above the top is as bad as a placement gets, but the game is over then anyway
+        if !after.place(&played.piece, played.pos) {
+            continue;
+        }
+        let lines = after.clear_full_rows();
+        let loss = best - weights.evaluate(&after, lines);
+        if loss >= MIN_LOSS {
+            mistakes.push(Mistake { ms: game.ms(), board: board.clone(), played, suggested: suggested.clone(), loss });
+        }
+    }
+    mistakes.sort_by(|a, b| b.loss.total_cmp(&a.loss));
+    mistakes.truncate(MISTAKES_SHOWN);
+    mistakes
+}
+
+/// Reviews a game on a thread of its own once it is over, so the results can be shown straight away
+/// and the mistakes added when they are found.
+pub struct Review {
+    worker: Worker<Replay, Vec<Mistake>>,
+    /// The mistakes found, once the review is done.
+    mistakes: Option<Vec<Mistake>>,
+}
+
+impl Review {
+    pub fn start(replay: Replay, config: &GameConfig) -> Self {
+        let config = config.clone();
+        let worker = Worker::spawn("review", move |replay: Replay, stop| review(&replay, &config, stop));
+        worker.send(replay);
+        Review { worker, mistakes: None }
+    }
+    /// Takes in the mistakes if the review has just been done.
+    pub fn update(&mut self) {
+        if self.mistakes.is_none() {
+            self.mistakes = self.worker.results().next();
+        }
+    }
+    /// The mistakes found, once the review is done.
+    pub fn found(&self) -> Option<&[Mistake]> {
+        self.mistakes.as_deref()
+    }
+}
+
+/// Draws `mistake` inside `rect`: the board as it was with the piece where it went, and the suggestion outlined.
+pub fn draw_mistake(r: &mut dyn Renderer, mistake: &Mistake, rect: Rect, theme: &Theme) {
+    let (width, height) = mistake.board.size();
+    let from = render::cell_rect(0., 0.);
+    let from = Rect::new(from.x, from.y, width as f32 * GRID_CELL_SIZE.0 as f32, height as f32 * GRID_CELL_SIZE.1 as f32);
+    r.draw_panel(rect, Color::BLACK);
+    let r = &mut Viewport::new(r, from, rect);
+    render::draw_grid(r, &mistake.board, theme);
+    render::draw_moving_piece(r, &mistake.played, theme);
+    render::draw_piece_outline(r, &mistake.suggested, theme);
+}
@@ -0,0 +1,721 @@
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ggez::{
+    event::{self, winit_event::TouchPhase, Axis, Button, GamepadId, MouseButton},
+    graphics::{self, Rect},
+    input::keyboard::{KeyCode, KeyInput, KeyMods},
+    Context, GameResult,
+};
+use log::{error, info, warn};
+
+use crate::{
+    ai::Hinter,
+    clip::ClipRecorder,
+    clipboard,
+    config::{Config, GameConfig},
+    discord::{Activity, Presence},
+    effects::{Effects, FloatingText, RowFlash, Shake, Toast},
+    fumen::{Placements, Setup, VIEWER},
+    gamepad::Stick,
+    leaderboard::Leaderboard,
+    input::{Action, Keybindings},
+    mode::Mode,
+    profile::ProfileData,
+    reload::Reloader,
+    render::{self, QuadBatch, Viewport, PORTRAIT_VIEW, SCREEN_SIZE},
+    replay::{Playback, Replay, ReplayRecorder},
+    rules::Game,
+    save::{SavedGame, Slot},
+    script::Script,
+    scene::{Scene, Transition},
+    scores::{Board, ScoreEntry},
+    screenshot,
+    stats_window::{Snapshot, StatsWindow},
+    storage::Storage,
+    theme::Theme,
+    twitch::TwitchVotes,
+    touch::TouchControls,
+};
+
+/// The most time that is caught up on in ticks between two frames.
+const MAX_CATCH_UP: Duration = Duration::from_millis(250);
+
+/// The game being played along with everything around it: settings, profiles and so on.
+/// Every scene gets at it.
+pub struct GameState {
+    pub game: Game,
+    pub config: Config,
+    /// The board size, speed and so on every game is set up with.
+    pub game_config: GameConfig,
+    /// Where the config, scores, replays and so on are kept.
+    pub storage: Rc<dyn Storage>,
+    pub theme: Theme,
+    /// The high scores and statistics of the profile being played, and where they are kept.
+    pub data: ProfileData,
+    /// Where this game ended up on each of the mode's leaderboards (`Board::of`), once it is over.
+    pub high_score_ranks: Vec<Option<usize>>,
+    /// Suggests placements in Practice when hints are on, started the first time they are.
+    pub hinter: Option<Hinter>,
+    /// The keybindings for the current mode.
+    pub bindings: Keybindings,
+    /// A saved game found when the profile was loaded, which the player is asked whether to continue.
+    pub resume: Option<SavedGame>,
+    /// Whether to save a screenshot once the current frame has been drawn.
+    screenshot: bool,
+    /// Whether to save a card with the results of the game once the current frame has been drawn.
+    pub results_card: bool,
+    /// The last stretch of frames, when the clip recorder is on.
+    clip: ClipRecorder,
+    reloader: Reloader,
+    /// Messages, floating texts and so on shown over the scenes for a little while.
+    pub effects: Effects,
+    /// Sends games to the global leaderboard and fetches it, if there is one in the config.
+    pub leaderboard: Leaderboard,
+    /// Shows what is being played on Discord, if there is a Discord application in the config.
+    pub presence: Presence,
+    /// The votes of the Twitch chat in the config, if there is one.
+    pub twitch: Option<TwitchVotes>,
+    /// The stats window for streaming, while it is on in the config.
+    pub stats_window: Option<StatsWindow>,
+    /// Whether the Twitch chat has changed this game, which its replay can't play back,
+    /// so it is kept out of the records.
+    pub chat_changed_game: bool,
+    /// The pieces placed this game, to share the board as a fumen with F8.
+    pub placements: Placements,
+    /// The keys held down and the action they were pressed as.
+    pub held_keys: BTreeMap<KeyCode, Action>,
+    /// How long the restart key has been held for.
+    pub restart_held_ms: Option<u32>,
+    pub touch: TouchControls,
+    pub stick: Stick,
+    /// Actions pressed (`true`) or released since the last tick, passed to the game on the next one.
+    pub input_queue: Vec<(Action, bool)>,
+    /// The replay being watched, whose inputs are played instead of the player's.
+    pub playback: Option<Playback>,
+    /// The seed new games are started with, from `--seed` or the settings, or `None` for a random one each time.
+    pub seed: Option<u64>,
+    /// The level new games start at.
+    pub level: u32,
+    /// The custom mode every game is played as, from `--script`, until a mode is picked from the menu.
+    pub script: Option<&'static Script>,
+    /// The board and pieces every Practice game starts from, from a fumen, until a mode is picked from the menu.
+    pub setup: Option<Setup>,
+    /// How far into the next tick the frame being drawn is, from 0 to 1.
+    pub tick_progress: f32,
+}
+
+/// A seed for a new game, different every time.
+pub fn random_seed() -> u64 {
+    let mut seed: [u8; 8] = [0; 8];
+    match getrandom::getrandom(&mut seed[..]) {
+        Ok(()) => u64::from_ne_bytes(seed),
+        Err(e) => {
+            warn!("Could not create RNG seed, using the time instead: {e}");
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+        }
+    }
+}
+
+impl GameState {
+    /// Our new function will set up the initial state of our game.
+    /// Games are started with `seed` if there is one, and a random seed otherwise.
+    pub fn new(config: Config, game_config: GameConfig, data: ProfileData, storage: Rc<dyn Storage>, seed: Option<u64>) -> Self {
+        let mode = config.mode;
+        let game = Game::with_config(mode, mode.rules(), seed.unwrap_or_else(random_seed), config.profile().handling, &game_config);
+        GameState {
+            placements: Placements::new(&game),
+            game,
+            bindings: config.profile().bindings(mode),
+            config,
+            storage,
+            theme: Theme::with_palette(&game_config.palette),
+            game_config,
+            data,
+            high_score_ranks: Vec::new(),
+            hinter: None,
+            resume: None,
+            screenshot: false,
+            results_card: false,
+            clip: ClipRecorder::default(),
+            reloader: Reloader::default(),
+            effects: Effects::default(),
+            leaderboard: Leaderboard::default(),
+            presence: Presence::default(),
+            twitch: None,
+            stats_window: None,
+            chat_changed_game: false,
+            held_keys: BTreeMap::new(),
+            restart_held_ms: None,
+            touch: TouchControls::default(),
+            stick: Stick::default(),
+            input_queue: Vec::new(),
+            playback: None,
+            seed,
+            level: 0,
+            script: None,
+            setup: None,
+            tick_progress: 0.,
+        }
+    }
+    /// Switches to playing `game`, letting go of everything held in the last one.
+    fn start(&mut self, game: Game) {
+        self.bindings = self.config.profile().bindings(game.mode);
+        self.game = game;
+        self.high_score_ranks.clear();
+        self.held_keys.clear();
+        self.restart_held_ms = None;
+        self.touch = TouchControls::default();
+        self.stick = Stick::default();
+        self.input_queue.clear();
+        self.playback = None;
+        self.chat_changed_game = false;
+        self.placements = Placements::new(&self.game);
+        // Messages stay up, they are usually about why the game changed
+        self.effects.remove::<FloatingText>();
+        self.effects.remove::<Shake>();
+        self.effects.remove::<RowFlash>();
+    }
+    /// Starts a new game of `mode`, keeping the settings.
+    pub fn reset(&mut self, mode: Mode) {
+        self.config.mode = mode;
+        if mode != Mode::Practice {
+            self.setup = None;
+        }
+        let seed = self.seed.unwrap_or_else(random_seed);
+        let handling = self.config.profile().handling;
+        let mut game = match self.script {
+            Some(script) => {
+                info!("Starting {} at level {} with seed {seed}", script.name(), self.level);
+                Game::with_config(script.base(), script, seed, handling, &self.game_config)
+            }
+            None => match &self.setup {
+                Some(setup) => {
+                    info!("Starting {mode} from a fumen with seed {seed}");
+                    setup.game(seed, handling, &self.game_config)
+                }
+                None => {
+                    info!("Starting {mode} at level {} with seed {seed}", self.level);
+                    Game::with_config(mode, mode.rules(), seed, handling, &self.game_config)
+                }
+            },
+        };
+        game.start_level = self.level;
+        self.start(game);
+        // Replays can't be played back without the script or the fumen
+        if self.script.is_none() && self.setup.is_none() {
+            self.start_recording();
+        }
+    }
+    /// Starts Practice from the board and pieces of the fumen in `text`, which every restart goes back to.
+    pub fn practise(&mut self, text: &str) -> Result<(), String> {
+        let setup = Setup::from_fumen(text, self.game_config.grid_size)?;
+        self.setup = Some(setup);
+        self.script = None;
+        self.reset(Mode::Practice);
+        Ok(())
+    }
+    fn start_recording(&mut self) {
+        let game = &self.game;
+        let recorder = ReplayRecorder::new(self.storage.clone(), &self.data.dir, game.mode, game.seed, game.start_level, game.handling);
+        self.game.input_listener = Some(Box::new(recorder));
+    }
+    /// Plays a replay from the start.
+    pub fn watch(&mut self, replay: Replay) {
+        info!("Watching a replay of {} with seed {}", replay.mode, replay.seed);
+        let mut game = Game::with_config(replay.mode, replay.mode.rules(), replay.seed, replay.handling, &self.game_config);
+        game.start_level = replay.level;
+        self.start(game);
+        self.playback = Some(Playback::new(&replay));
+    }
+    /// Whether the game counts towards the statistics and high scores and gets saved,
+    /// which replays and scripted games don't.
+    pub fn keeps_records(&self) -> bool {
+        self.playback.is_none() && self.script.is_none() && self.setup.is_none() && !self.chat_changed_game
+    }
+    /// Whether the falling piece has a placement suggested for it: in Practice, with hints on.
+    pub fn shows_hints(&self) -> bool {
+        self.config.hints && self.game.mode == Mode::Practice && !self.game.gameover
+    }
+    /// Whether the player's own inputs go into the game.
+    pub fn accepts_input(&self) -> bool {
+        !self.game.gameover && self.playback.is_none()
+    }
+    /// Continues a saved game.
+    pub fn restore(&mut self, save: SavedGame) {
+        self.reset(save.mode);
+        // A replay has to start from the beginning of the game
+        self.game.input_listener = None;
+        info!("Continuing a saved game from {} ms in", save.tick * self.game.ms_per_tick());
+        self.game.restore(save);
+    }
+    /// Continues the saved game found when the profile was loaded, if `resume`, and forgets about it either way.
+    pub fn answer_resume(&mut self, resume: bool) {
+        if let Some(save) = self.resume.take() {
+            if resume {
+                self.restore(save);
+            }
+        }
+        for slot in [Slot::Quit, Slot::Autosave] {
+            if let Err(e) = SavedGame::delete(&*self.storage, &self.data.dir, slot) {
+                warn!("Could not delete saved game: {e}");
+            }
+        }
+    }
+    /// Adds the game that just ended to the statistics and leaderboards.
+    pub fn record_score(&mut self) {
+        if !self.keeps_records() {
+            return;
+        }
+        if let Err(e) = self.data.stats.save(&*self.storage, &self.data.dir) {
+            warn!("Could not save statistics: {e}");
+        }
+        let game = &self.game;
+        let entry = ScoreEntry::new(self.config.profile.clone(), game.score, game.lines, game.ms());
+        self.high_score_ranks = Board::of(game.mode)
+            .iter()
+            .map(|&board| self.data.high_scores.add(board, entry.clone()))
+            .collect();
+        let personal_best = self.high_score_ranks.first() == Some(&Some(0));
+        let (score, lines, checksum) = (game.score, game.lines, game.checksum());
+        if let Some(listener) = &mut self.game.input_listener {
+            listener.game_over(score, lines, checksum, personal_best);
+        }
+        let replay = self.game.input_listener.as_ref().and_then(|listener| listener.replay());
+        if let (Some(address), Some(replay)) = (&self.config.leaderboard, replay) {
+            for &board in Board::of(self.game.mode) {
+                self.leaderboard.submit(address, board, entry.clone(), replay.clone());
+            }
+        }
+        if self.high_score_ranks.iter().any(Option::is_some) {
+            if let Err(e) = self.data.high_scores.save(&*self.storage, &self.data.dir) {
+                warn!("Could not save high scores: {e}");
+            }
+        }
+    }
+    /// Saves the changed settings and puts them into effect, starting over if they call for a new game.
+    pub fn apply_settings(&mut self, ctx: &Context, seed: Option<u64>) {
+        self.seed = seed;
+        if let Err(e) = self.config.save(&*self.storage) {
+            warn!("Could not save config: {e}");
+        }
+        self.apply_config(ctx);
+    }
+    /// Loads the config and theme again if they have been edited outside the game, and puts them into effect.
+    pub fn reload_edited(&mut self, ctx: &Context) {
+        let changed = self.reloader.poll(ctx, &*self.storage, self.config.theme.as_deref());
+        // The settings menu saving the config changes it too
+        if changed.config && Config::load(&*self.storage).ok().as_ref() != Some(&self.config) {
+            self.reload_config(ctx);
+        } else if changed.theme {
+            self.load_theme(ctx);
+            self.show_toast("Reloaded the theme".to_owned());
+        }
+    }
+    /// Loads the config again, along with the theme it names, and puts it into effect.
+    pub fn reload_config(&mut self, ctx: &Context) {
+        match Config::load(&*self.storage) {
+            Ok(config) => {
+                info!("Reloading the config");
+                self.config = config;
+                self.apply_config(ctx);
+                self.show_toast("Reloaded the config and theme".to_owned());
+            }
+            Err(e) => {
+                warn!("Could not reload config: {e}");
+                self.show_toast(format!("Could not reload config: {e}"));
+            }
+        }
+    }
+    /// Puts the config into effect, starting over if it calls for a new game.
+    fn apply_config(&mut self, ctx: &Context) {
+        self.load_theme(ctx);
+        if self.config.profile_dir() != self.data.dir {
+            self.save_on_exit();
+            self.load_profile();
+        } else if self.config.mode != self.game.mode || self.seed.is_some_and(|seed| seed != self.game.seed) {
+            self.reset(self.config.mode);
+        } else {
+            self.bindings = self.config.profile().bindings(self.game.mode);
+            let handling = self.config.profile().handling;
+            if self.playback.is_none() && handling != self.game.handling {
+                // The replay only knows the handling the game started with
+                self.game.input_listener = None;
+                self.game.handling = handling;
+            }
+        }
+    }
+    pub fn load_theme(&mut self, ctx: &Context) {
+        let theme = match self.config.theme.as_deref() {
+            Some(name) => Theme::load(ctx, Some(name)),
+            None => Ok(Theme::with_palette(&self.game_config.palette)),
+        };
+        self.theme = theme.unwrap_or_else(|e| {
+            warn!("Could not load theme, using the default: {e}");
+            Theme::with_palette(&self.game_config.palette)
+        });
+        info!("Using theme {}", self.config.theme.as_deref().unwrap_or("classic"));
+    }
+    /// Switches to the profile selected in the config, starting a new game.
+    pub fn load_profile(&mut self) {
+        info!("Loading profile {}", self.config.profile);
+        self.data = ProfileData::load(&*self.storage, self.config.profile_dir());
+        self.reset(self.config.mode);
+        self.resume = self.data.saved_game(&*self.storage);
+    }
+    /// Leaves the profile cleanly, saving the game in progress to be continued next time.
+    pub fn save_on_exit(&self) {
+        if self.accepts_input() && self.keeps_records() {
+            if let Err(e) = self.game.to_save().save(&*self.storage, &self.data.dir, Slot::Quit) {
+                warn!("Could not save game: {e}");
+            }
+        }
+        // Leaving properly, so there is nothing to recover
+        if let Err(e) = SavedGame::delete(&*self.storage, &self.data.dir, Slot::Autosave) {
+            warn!("Could not delete autosave: {e}");
+        }
+    }
+    pub fn release_all(&mut self) {
+        for action in std::mem::take(&mut self.held_keys).into_values() {
+            self.input_queue.push((action, false));
+        }
+    }
+    pub fn touch_actions(&mut self, actions: Vec<(Action, bool)>) {
+        for (action, pressed) in actions {
+            if self.playback.is_none() && (!pressed || !self.game.gameover) {
+                self.input_queue.push((action, pressed));
+            }
+        }
+    }
+    /// Reports a bug that panicked while running the game, and ends the game instead of the whole program.
+    /// The last autosave is offered to be resumed.
+    pub fn report_bug(&mut self, panic: Box<dyn Any + Send>) {
+        let message = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown error");
+        error!("The game was stopped by a bug: {message}");
+        self.show_toast(format!("The game was stopped by a bug: {message}"));
+        self.game.gameover = true;
+        if self.playback.is_none() {
+            self.resume = self.data.saved_game(&*self.storage);
+        }
+    }
+    pub fn show_toast(&mut self, message: String) {
+        self.effects.remove::<Toast>();
+        self.effects.spawn(Toast::new(message));
+    }
+}
+
+/// Runs the scenes, passing ggez's events to the one on top.
+pub struct App {
+    pub state: GameState,
+    /// The scene on top gets the input, the ones below are only drawn if it is shown over them.
+    scenes: Vec<Box<dyn Scene>>,
+    /// Time that has passed but not been run as ticks yet.
+    unticked: Duration,
+    batch: QuadBatch,
+}
+
+impl App {
+    pub fn new(state: GameState, scenes: Vec<Box<dyn Scene>>) -> Self {
+        App {
+            state,
+            scenes,
+            unticked: Duration::ZERO,
+            batch: QuadBatch::default(),
+        }
+    }
+    fn transition(&mut self, ctx: &mut Context, transition: Transition) {
+        match transition {
+            Transition::None => (),
+            Transition::Push(scene) => self.scenes.push(scene),
+            Transition::Pop(n) => {
+                let n = n.min(self.scenes.len() - 1);
+                self.scenes.truncate(self.scenes.len() - n);
+            }
+            Transition::Replace(scene) => {
+                self.scenes.pop();
+                self.scenes.push(scene);
+            }
+            Transition::Quit => ctx.request_quit(),
+        }
+    }
+    /// Whether a game is being played, under whatever is on top of it.
+    fn in_game(&self) -> bool {
+        self.scenes.iter().any(|scene| scene.plays_game())
+    }
+    /// The part of the layout the scenes are drawn in that is shown, and where in the window it goes.
+    fn view(&self, ctx: &Context) -> (Rect, Rect) {
+        let from = if self.state.config.portrait && self.in_game() {
+            PORTRAIT_VIEW
+        } else {
+            Rect::new(0., 0., SCREEN_SIZE.0, SCREEN_SIZE.1)
+        };
+        (from, render::fit(from, ctx.gfx.drawable_size()))
+    }
+    /// Where a point on the window is in the layout the scenes are drawn in.
+    fn to_layout(&self, ctx: &Context, x: f32, y: f32) -> (f32, f32) {
+        let (from, to) = self.view(ctx);
+        (from.x + (x - to.x) * from.w / to.w, from.y + (y - to.y) * from.h / to.h)
+    }
+}
+
+impl event::EventHandler<ggez::GameError> for App {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        let tick = Duration::from_secs(1) / self.state.game_config.tick_rate();
+        // After a long stall, e.g. the window being dragged, skip ahead rather than playing it all at once
+        self.unticked = (self.unticked + ctx.time.delta()).min(MAX_CATCH_UP);
+        while self.unticked >= tick {
+            self.unticked -= tick;
+            let transition = self.scenes.last_mut().expect("There is always a scene").update(&mut self.state, ctx);
+            self.transition(ctx, transition);
+        }
+        self.state.effects.update(ctx.time.delta());
+        self.state.reload_edited(ctx);
+        for message in self.state.leaderboard.update() {
+            self.state.show_toast(message);
+        }
+        let activity = if self.in_game() {
+            Activity::of(&self.state.game, self.state.playback.is_some())
+        } else {
+            Activity::menus()
+        };
+        self.state.presence.update(self.state.config.discord.as_deref(), activity);
+        let state = &mut self.state;
+        match (&mut state.twitch, &state.config.twitch) {
+            (Some(votes), Some(channel)) if votes.channel() == channel => {
+                for message in votes.update() {
+                    state.show_toast(message);
+                }
+            }
+            (_, channel) => state.twitch = channel.as_deref().map(TwitchVotes::join),
+        }
+        match (&mut state.stats_window, state.config.stats_window) {
+            (Some(window), true) => window.update(Snapshot::of(&state.game)),
+            (None, true) => state.stats_window = Some(StatsWindow::open()),
+            (_, false) => state.stats_window = None,
+        }
+        for saved in self.state.clip.saved() {
+            match saved {
+                Ok(path) => self.state.show_toast(format!("Saved clip to {}", path.display())),
+                Err(e) => {
+                    warn!("Could not save clip: {e}");
+                    self.state.show_toast(format!("Could not save clip: {e}"));
+                }
+            }
+        }
+        // The game only moves on while it's the top scene, so a paused piece stays still
+        let top = self.scenes.last().expect("There is always a scene");
+        self.state.tick_progress = if top.plays_game() { self.unticked.as_secs_f32() / tick.as_secs_f32() } else { 0. };
+
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        if self.in_game() {
+            let state = &self.state;
+            let replay = if state.playback.is_some() { " (replay)" } else { "" };
+            let game = &state.game;
+            ctx.gfx.set_window_title(&format!("Tetris - {}{replay} - Score: {} - Lines: {}", game.mode, game.score, game.lines));
+        } else {
+            ctx.gfx.set_window_title("Tetris");
+        }
+
+        let (view_from, view_to) = self.view(ctx);
+        let state = &mut self.state;
+        let mut canvas =
+            graphics::Canvas::from_frame(ctx, graphics::Color::BLACK);
+
+        {
+            let batched = &mut self.batch.frame(ctx, &mut canvas);
+            // The scenes are laid out for a screen `SCREEN_SIZE` big, and scaled to fit the window
+            let r = &mut Viewport::new(batched, view_from, view_to);
+            let bottom = self.scenes.iter().rposition(|scene| !scene.is_overlay()).unwrap_or(0);
+            for scene in &self.scenes[bottom..] {
+                scene.draw(state, r);
+            }
+
+            state.effects.draw(r);
+        }
+
+        canvas.finish(ctx)?;
+
+        if std::mem::take(&mut state.screenshot) {
+            let message = match screenshot::save(ctx) {
+                Ok(path) => format!("Saved screenshot to {path}"),
+                Err(e) => format!("Could not save screenshot: {e}"),
+            };
+            state.show_toast(message);
+        }
+        if std::mem::take(&mut state.results_card) {
+            let message = match screenshot::save_card(ctx, &state.game, &state.theme) {
+                Ok(path) => format!("Saved the results to {path}"),
+                Err(e) => format!("Could not save the results: {e}"),
+            };
+            state.show_toast(message);
+        }
+        if state.config.clip_recorder {
+            if let Err(e) = state.clip.capture(ctx) {
+                warn!("Could not record frame: {e}");
+            }
+        }
+
+        ggez::timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, repeated: bool) -> Result<(), ggez::GameError> {
+        let Some(keycode) = input.keycode else {
+            return Ok(());
+        };
+        if input.mods.contains(KeyMods::SHIFT) && keycode == KeyCode::Escape {
+            ctx.request_quit();
+            return Ok(());
+        }
+        if keycode == KeyCode::F12 {
+            self.state.screenshot = true;
+            return Ok(());
+        }
+        if keycode == KeyCode::F5 {
+            self.state.reload_config(ctx);
+            return Ok(());
+        }
+        if keycode == KeyCode::F8 {
+            let message = match self.state.placements.encode(&self.state.game) {
+                Ok(fumen) => {
+                    let url = format!("{VIEWER}{fumen}");
+                    info!("Exported the board as {url}");
+                    match clipboard::copy(&url) {
+                        Ok(()) => "Copied the board to the clipboard as a fumen".to_owned(),
+                        Err(e) => format!("Could not copy the fumen, it is in the log: {e}"),
+                    }
+                }
+                Err(e) => format!("Could not export the board: {e}"),
+            };
+            self.state.show_toast(message);
+            return Ok(());
+        }
+        if keycode == KeyCode::F9 {
+            let message = if !self.state.config.clip_recorder {
+                "Turn on the clip recorder in the settings first".to_owned()
+            } else {
+                match self.state.clip.save(ctx) {
+                    Ok(path) => format!("Saving clip to {}", path.display()),
+                    Err(e) => format!("Could not save clip: {e}"),
+                }
+            };
+            self.state.show_toast(message);
+            return Ok(());
+        }
+        let transition = self.scenes.last_mut().expect("There is always a scene").key_down(&mut self.state, ctx, keycode, input.mods, repeated);
+        self.transition(ctx, transition);
+        Ok(())
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, input: KeyInput) -> Result<(), ggez::GameError> {
+        let Some(keycode) = input.keycode else {
+            return Ok(());
+        };
+        if let Some(action) = self.state.held_keys.remove(&keycode) {
+            self.state.input_queue.push((action, false));
+        }
+        if self.state.bindings.action(keycode) == Some(Action::Restart) {
+            self.state.restart_held_ms = None;
+        }
+        self.scenes.last_mut().expect("There is always a scene").key_up(&mut self.state, keycode);
+
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> Result<(), ggez::GameError> {
+        self.scenes.last_mut().expect("There is always a scene").text_input(&mut self.state, character);
+        Ok(())
+    }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> Result<bool, ggez::GameError> {
+        if self.in_game() {
+            self.state.save_on_exit();
+        }
+        // A clip cut off halfway isn't worth anything
+        self.state.clip.finish();
+        Ok(false)
+    }
+
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) -> Result<(), ggez::GameError> {
+        // Key releases don't reach us while unfocused
+        if !gained {
+            self.state.release_all();
+        }
+        Ok(())
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) -> Result<(), ggez::GameError> {
+        let transition = self.scenes.last_mut().expect("There is always a scene").gamepad_button_down(&mut self.state, ctx, btn, id);
+        self.transition(ctx, transition);
+        Ok(())
+    }
+
+    fn gamepad_button_up_event(&mut self, _ctx: &mut Context, btn: Button, id: GamepadId) -> Result<(), ggez::GameError> {
+        match self.state.config.profile().buttons.action(btn) {
+            Some(Action::Pause) | None => (),
+            Some(_) if self.state.playback.is_some() => (),
+            Some(action) => self.state.input_queue.push((action, false)),
+        }
+        self.scenes.last_mut().expect("There is always a scene").gamepad_button_up(&mut self.state, btn, id);
+        Ok(())
+    }
+
+    fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) -> Result<(), ggez::GameError> {
+        if axis == Axis::LeftStickX {
+            self.state.stick.set_x(value);
+        }
+        Ok(())
+    }
+
+    fn touch_event(&mut self, ctx: &mut Context, phase: TouchPhase, x: f64, y: f64) -> Result<(), ggez::GameError> {
+        let (x, y) = self.to_layout(ctx, x as f32, y as f32);
+        let state = &mut self.state;
+        let actions = match phase {
+            TouchPhase::Started if self.scenes.last().is_some_and(|scene| scene.plays_game()) => {
+                state.touch.start(x, y, state.config.touch_buttons, state.config.portrait)
+            }
+            TouchPhase::Started => Vec::new(),
+            TouchPhase::Moved => state.touch.moved(x, y),
+            TouchPhase::Ended | TouchPhase::Cancelled => state.touch.end(x, y),
+        };
+        state.touch_actions(actions);
+        Ok(())
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> Result<(), ggez::GameError> {
+        let (x, y) = self.to_layout(ctx, x, y);
+        if button == MouseButton::Left && self.scenes.last().is_some_and(|scene| scene.plays_game()) {
+            let config = &self.state.config;
+            let actions = self.state.touch.start(x, y, config.touch_buttons, config.portrait);
+            self.state.touch_actions(actions);
+        }
+        Ok(())
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) -> Result<(), ggez::GameError> {
+        let (x, y) = self.to_layout(ctx, x, y);
+        let actions = self.state.touch.moved(x, y);
+        self.state.touch_actions(actions);
+        Ok(())
+    }
+
+    fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> Result<(), ggez::GameError> {
+        let (x, y) = self.to_layout(ctx, x, y);
+        if button == MouseButton::Left {
+            let actions = self.state.touch.end(x, y);
+            self.state.touch_actions(actions);
+        }
+        Ok(())
+    }
+}
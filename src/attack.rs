@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+
+/// The most rows any one entry of a table may send, so no table can end a game with a single clear.
+pub const MAX_ROWS: u32 = 20;
+/// The most entries a combo table may have.
+const MAX_COMBO_ENTRIES: usize = 32;
+
+/// How many rows of garbage clears send to the opponent in versus games.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttackTable {
+    /// Rows sent for clearing 0 to 4 lines at once.
+    pub lines: [u32; 5],
+    /// Rows sent for T-spins clearing 0 to 3 lines.
+    pub t_spin: [u32; 4],
+    /// Rows added for each clear in a row after the first, the last one counting for any longer combo.
+    pub combo: Vec<u32>,
+    /// Rows added for a tetris or T-spin clear straight after another one.
+    pub back_to_back: u32,
+    /// Rows added for a clear that leaves the board empty.
+    pub perfect_clear: u32,
+}
+
+impl Default for AttackTable {
+    fn default() -> Self {
+        AttackTable {
+            lines: [0, 0, 1, 2, 4],
+            t_spin: [0, 2, 4, 6],
+            combo: vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+            back_to_back: 1,
+            perfect_clear: 10,
+        }
+    }
+}
+
+impl AttackTable {
+    /// The tables a host can pick from without writing their own, by name, the default first.
+    pub fn presets() -> [(&'static str, AttackTable); 3] {
+        [
+            ("Guideline", AttackTable::default()),
+            // T-spins send no more than the same clear without one, and nothing adds to a clear
+            (
+                "Classic",
+                AttackTable {
+                    lines: [0, 0, 1, 2, 4],
+                    t_spin: [0, 0, 1, 2],
+                    combo: vec![0],
+                    back_to_back: 0,
+                    perfect_clear: 0,
+                },
+            ),
+            (
+                "Combo",
+                AttackTable {
+                    lines: [0, 0, 1, 2, 4],
+                    t_spin: [0, 2, 4, 6],
+                    combo: vec![0, 1, 2, 3, 4, 5, 6, 7, 8],
+                    back_to_back: 1,
+                    perfect_clear: 10,
+                },
+            ),
+        ]
+    }
+    /// The name of the preset this table is, if it is one.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        AttackTable::presets().into_iter().find(|(_, table)| table == self).map(|(name, _)| name)
+    }
+    /// Checks that the table can be played by, e.g. before playing by one from a config file or from another player.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.combo.len() > MAX_COMBO_ENTRIES {
+            return Err(format!("the combo table has {} entries, more than {MAX_COMBO_ENTRIES}", self.combo.len()));
+        }
+        let entries = self.lines.iter().chain(&self.t_spin).chain(&self.combo).chain([&self.back_to_back, &self.perfect_clear]);
+        match entries.max() {
+            Some(&rows) if rows > MAX_ROWS => Err(format!("it sends {rows} rows at once, more than {MAX_ROWS}")),
+            _ => Ok(()),
+        }
+    }
+    fn base(&self, lines: u32, t_spin: bool) -> u32 {
+        let table: &[u32] = if t_spin { &self.t_spin } else { &self.lines };
+        table.get(lines as usize).or(table.last()).copied().unwrap_or(0)
+    }
+}
+
+/// Keeps track of a player's combo and back-to-back, to work out how much their clears send.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attacker {
+    /// How many pieces in a row have cleared lines, less one, or `None` if the last one didn't.
+    combo: Option<u32>,
+    /// Whether the last clear was a tetris or a T-spin.
+    back_to_back: bool,
+}
+
+impl Attacker {
+    /// How many rows of garbage a piece locking and clearing `lines` sends, by `table`,
+    /// with `perfect` if the clear left the board empty.
+    pub fn piece_locked(&mut self, table: &AttackTable, lines: u32, t_spin: bool, perfect: bool) -> u32 {
+        if lines == 0 {
+            self.combo = None;
+            return table.base(0, t_spin);
+        }
+        let combo = self.combo.map_or(0, |c| c + 1);
+        self.combo = Some(combo);
+        let difficult = lines >= 4 || t_spin;
+        let back_to_back = difficult && self.back_to_back;
+        self.back_to_back = difficult;
+
+        let combo_bonus = table.combo.get(combo as usize).or(table.combo.last()).copied().unwrap_or(0);
+        let bonus = if back_to_back { table.back_to_back } else { 0 } + if perfect { table.perfect_clear } else { 0 };
+        table.base(lines, t_spin) + combo_bonus + bonus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What each of `clears` sends in turn, as `(lines, t_spin)`.
+    fn sent(table: &AttackTable, clears: &[(u32, bool)]) -> Vec<u32> {
+        let mut attacker = Attacker::default();
+        clears.iter().map(|&(lines, t_spin)| attacker.piece_locked(table, lines, t_spin, false)).collect()
+    }
+
+    #[test]
+    fn clears_send_by_the_table() {
+        let table = AttackTable::default();
+        for lines in 0..=4 {
+            assert_eq!(sent(&table, &[(lines, false)]), [table.lines[lines as usize]]);
+        }
+        for lines in 0..=3 {
+            assert_eq!(sent(&table, &[(lines, true)]), [table.t_spin[lines as usize]]);
+        }
+    }
+
+    #[test]
+    fn combos_add_up_until_a_piece_clears_nothing() {
+        let table = AttackTable::default();
+        // Singles send nothing by themselves, so all they send is the combo
+        assert_eq!(sent(&table, &[(1, false); 6]), [0, 1, 1, 2, 2, 3]);
+        assert_eq!(sent(&table, &[(1, false), (1, false), (0, false), (1, false), (1, false)]), [0, 1, 0, 0, 1]);
+        // The last entry counts for any longer combo
+        assert_eq!(*sent(&table, &[(1, false); 20]).last().unwrap(), 5);
+    }
+
+    #[test]
+    fn back_to_back_needs_nothing_easier_in_between() {
+        let table = AttackTable { combo: vec![0], ..AttackTable::default() };
+        assert_eq!(sent(&table, &[(4, false), (0, false), (4, false)]), [4, 0, 5]);
+        assert_eq!(sent(&table, &[(4, false), (0, false), (2, true), (0, false), (4, false)]), [4, 0, 5, 0, 5]);
+        assert_eq!(sent(&table, &[(4, false), (2, false), (4, false)]), [4, 1, 4]);
+    }
+
+    #[test]
+    fn perfect_clears_send_extra() {
+        let table = AttackTable::default();
+        assert_eq!(Attacker::default().piece_locked(&table, 4, false, true), 4 + table.perfect_clear);
+        assert_eq!(Attacker::default().piece_locked(&table, 1, false, true), table.perfect_clear);
+    }
+
+    #[test]
+    fn presets_are_valid_and_known_by_name() {
+        for (name, table) in AttackTable::presets() {
+            assert_eq!(table.validate(), Ok(()), "{name}");
+            assert_eq!(table.preset_name(), Some(name));
+        }
+        assert_eq!(AttackTable::default().preset_name(), Some("Guideline"));
+        assert_eq!(AttackTable { back_to_back: 2, ..AttackTable::default() }.preset_name(), None);
+    }
+
+    #[test]
+    fn tables_that_send_too_much_are_refused() {
+        assert!(AttackTable { perfect_clear: MAX_ROWS, ..AttackTable::default() }.validate().is_ok());
+        assert!(AttackTable { perfect_clear: MAX_ROWS + 1, ..AttackTable::default() }.validate().is_err());
+        assert!(AttackTable { lines: [0, 0, 1, 2, 100], ..AttackTable::default() }.validate().is_err());
+        assert!(AttackTable { combo: vec![1; MAX_COMBO_ENTRIES + 1], ..AttackTable::default() }.validate().is_err());
+    }
+}
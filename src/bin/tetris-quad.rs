@@ -0,0 +1,76 @@
+use std::{path::Path, rc::Rc};
+
+use log::{warn, LevelFilter};
+use macroquad::{window::Conf, Window};
+
+use tetris::{
+    app::GameState,
+    config::{Config, GameConfig},
+    logging::{self, LOG_FILE},
+    mode::Mode,
+    profile::ProfileData,
+    quad,
+    render::SCREEN_SIZE,
+    storage::{FileStorage, Storage},
+};
+
+const USAGE: &str = "\
+Usage: tetris-quad [options]
+
+Plays in a window drawn with macroquad rather than ggez, as whoever played last in the ggez window,
+with their keys, scores and statistics. A game left in either is carried on with in the other.
+
+Options:
+    --mode <mode>          Start a new game of this mode instead
+    --seed <number>        Start every game with this seed
+    --log-level <level>    How much to log: off, error, warn, info, debug or trace
+    --help                 Show this";
+
+fn main() {
+    let mut mode = None;
+    let mut seed = None;
+    let mut log_level = LevelFilter::Info;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--mode" => args.next().and_then(|key| Mode::ALL.into_iter().find(|m| m.key() == key.to_lowercase())).map(|m| mode = Some(m)),
+            "--seed" => args.next().and_then(|s| s.parse().ok()).map(|s| seed = Some(s)),
+            "--log-level" => args.next().and_then(|l| l.parse().ok()).map(|l| log_level = l),
+            "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ => None,
+        };
+        if parsed.is_none() {
+            eprintln!("Could not understand {arg}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    }
+    let storage = match FileStorage::without_window() {
+        Ok(storage) => storage,
+        Err(e) => {
+            eprintln!("Could not find where the game keeps its files: {e}");
+            std::process::exit(1);
+        }
+    };
+    logging::init(Path::new(&storage.location(LOG_FILE)), log_level);
+    let config = Config::load(&storage).unwrap_or_else(|e| {
+        warn!("Could not load config, using defaults: {e}");
+        Config::default()
+    });
+    let mut state = GameState::new(config, GameConfig::default(), ProfileData::default(), Rc::new(storage), seed);
+    state.load_profile();
+    state.answer_resume(mode.is_none());
+    if let Some(mode) = mode {
+        state.reset(mode);
+    }
+    let conf = Conf {
+        window_title: "Tetris".to_owned(),
+        window_width: (SCREEN_SIZE.0 / 2.) as i32,
+        window_height: (SCREEN_SIZE.1 / 2.) as i32,
+        window_resizable: true,
+        ..Conf::default()
+    };
+    Window::from_config(conf, quad::run(state));
+}
@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use log::{error, LevelFilter};
+
+use tetris::{config::GameConfig, headless, lobby::RoomRules, logging, net::DEFAULT_PORT, server};
+
+const USAGE: &str = "\
+Usage: tetris-server [options]
+
+Plays online versus matches between the players who join it, two at a time.
+
+Options:
+    --port <number>          Listen on this port instead of 7777
+    --rules <file or text>   Play every match by these room rules, written as JSON, e.g.
+                             {\"best_of\": 3, \"race\": true}, with the usual ones for any left out
+    --log-level <level>      How much to log: off, error, warn, info, debug or trace
+    --verify <file or text>  Play a replay out and check it ends the way it says, then quit,
+                             failing if it doesn't, e.g. before a leaderboard takes it
+    --help                   Show this";
+
+fn main() {
+    let mut port = DEFAULT_PORT;
+    let mut rules = RoomRules::default();
+    let mut log_level = LevelFilter::Info;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--port" => args.next().and_then(|p| p.parse().ok()).map(|p| port = p),
+            "--rules" => args.next().map(|arg| rules = room_rules(&arg)),
+            "--log-level" => args.next().and_then(|l| l.parse().ok()).map(|l| log_level = l),
+            "--verify" => args.next().map(|arg| verify(&arg)),
+            "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ => None,
+        };
+        if parsed.is_none() {
+            eprintln!("Could not understand {arg}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    }
+    logging::init(Path::new("tetris-server.log"), log_level);
+    if let Err(e) = server::run(port, rules) {
+        error!("Could not listen on port {port}: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// The room rules in the file `arg`, or `arg` itself, quitting if they can't be read or played by.
+fn room_rules(arg: &str) -> RoomRules {
+    let text = std::fs::read_to_string(arg).unwrap_or_else(|_| arg.to_owned());
+    let rules = serde_json::from_str::<RoomRules>(&text).map_err(|e| format!("The rules could not be read: {e}")).and_then(|rules| rules.validate().map(|()| rules));
+    rules.unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    })
+}
+
+/// Verifies the replay in the file `arg`, or `arg` itself, and quits with whether it holds up.
+fn verify(arg: &str) -> ! {
+    std::process::exit(if headless::verify(arg, &GameConfig::default()) { 0 } else { 1 });
+}
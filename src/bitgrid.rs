@@ -0,0 +1,268 @@
+use crate::{
+    grid::{Pos, GAME_GRID_SIZE, MAX_GRID_HEIGHT, MAX_GRID_WIDTH},
+    piece::Piece,
+};
+
+/// Which cells of the playing field are filled, as one bit per cell, row 0 at the top.
+/// It is small and cheap to copy, for checking placements many times over, like a bot does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BitGrid {
+    /// Bit `x` of row `y` is set if the cell at `(x, y)` is filled. Only the first `height` rows are used.
+    rows: [u16; MAX_GRID_HEIGHT],
+    width: i8,
+    height: i8,
+}
+
+/// Keeps a side of a grid between 1 and `max` cells.
+const fn clamp_side(n: i8, max: usize) -> i8 {
+    if n < 1 {
+        1
+    } else if n as usize > max {
+        max as i8
+    } else {
+        n
+    }
+}
+
+impl Default for BitGrid {
+    fn default() -> Self {
+        BitGrid::new()
+    }
+}
+
+impl BitGrid {
+    pub const fn new() -> Self {
+        BitGrid::with_size(GAME_GRID_SIZE)
+    }
+    /// An empty grid `width` by `height` cells, which are kept within 1 and `MAX_GRID_WIDTH` and `MAX_GRID_HEIGHT`.
+    pub const fn with_size((width, height): (i8, i8)) -> Self {
+        BitGrid {
+            rows: [0; MAX_GRID_HEIGHT],
+            width: clamp_side(width, MAX_GRID_WIDTH),
+            height: clamp_side(height, MAX_GRID_HEIGHT),
+        }
+    }
+
+    /// How many cells across and down the grid is.
+    pub fn size(&self) -> (i8, i8) {
+        (self.width, self.height)
+    }
+    /// A row with every cell filled.
+    fn full_row(&self) -> u16 {
+        ((1u32 << self.width) - 1) as u16
+    }
+    /// The rows from top to bottom, as masks with bit `x` set for each filled cell.
+    pub fn rows(&self) -> &[u16] {
+        &self.rows[..self.height as usize]
+    }
+    pub fn is_filled(&self, pos: Pos) -> bool {
+        self.rows()
+            .get(pos.y as usize)
+            .is_some_and(|row| 0 <= pos.x && pos.x < self.width && row & 1 << pos.x != 0)
+    }
+    /// Whether `pos` is an empty cell or above the top of the grid, but not beside or below it.
+    pub fn is_free_or_above(&self, pos: Pos) -> bool {
+        0 <= pos.x && pos.x < self.width && pos.y < self.height && !self.is_filled(pos)
+    }
+    /// Whether `piece` placed at `at` is clear of the blocks and walls.
+    pub fn fits(&self, piece: &Piece, at: Pos) -> bool {
+        piece.points(at).all(|pos| self.is_free_or_above(pos))
+    }
+    /// Fills or empties the cell at `pos`, returning `false` if `pos` is outside the grid.
+    pub fn set(&mut self, pos: Pos, filled: bool) -> bool {
+        if pos.x < 0 || pos.x >= self.width || pos.y >= self.height {
+            return false;
+        }
+        match self.rows.get_mut(pos.y as usize) {
+            Some(row) if filled => *row |= 1 << pos.x,
+            Some(row) => *row &= !(1 << pos.x),
+            None => return false,
+        }
+        true
+    }
+    /// Fills the cells of `piece` placed at `at`, returning `false` if any of them are outside the grid.
+    pub fn place(&mut self, piece: &Piece, at: Pos) -> bool {
+        let mut inside = true;
+        for pos in piece.points(at) {
+            inside &= self.set(pos, true);
+        }
+        inside
+    }
+    /// The rows that are full, from the top.
+    pub fn full_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        let full = self.full_row();
+        (0..self.height as usize).filter(move |&y| self.rows[y] == full)
+    }
+    /// Removes every full row, moving the rows above down to fill the gaps, and returns how many there were.
+    pub fn clear_full_rows(&mut self) -> usize {
+        let full = self.full_row();
+        let mut to = self.height as usize;
+        for from in (0..self.height as usize).rev() {
+            if self.rows[from] != full {
+                to -= 1;
+                self.rows[to] = self.rows[from];
+            }
+        }
+        self.rows[..to].fill(0);
+        to
+    }
+    /// How high each column is stacked, counting from the bottom up to its highest block.
+    pub fn column_heights(&self) -> Vec<usize> {
+        let mut heights = vec![0; self.width as usize];
+        let mut seen = 0;
+        for (y, &row) in self.rows().iter().enumerate() {
+            let new = row & !seen;
+            for (x, height) in heights.iter_mut().enumerate() {
+                if new & 1 << x != 0 {
+                    *height = self.height as usize - y;
+                }
+            }
+            seen |= row;
+        }
+        heights
+    }
+    /// How many empty cells have a block somewhere above them.
+    pub fn count_holes(&self) -> usize {
+        let mut covered = 0;
+        let mut holes = 0;
+        for &row in self.rows() {
+            holes += (covered & !row).count_ones() as usize;
+            covered |= row;
+        }
+        holes
+    }
+    /// Pushes everything up to make room for `rows` at the bottom.
+    /// Returns `false` if blocks were pushed off the top.
+    pub fn insert_rows_at_bottom(&mut self, rows: &[u16]) -> bool {
+        let height = self.height as usize;
+        let n = rows.len().min(height);
+        let fits = self.rows[..n].iter().all(|&row| row == 0);
+        self.rows[..height].rotate_left(n);
+        self.rows[height - n..height].copy_from_slice(&rows[rows.len() - n..]);
+        fits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        grid::{Cell, Grid},
+        piece::Tetromino,
+    };
+
+    /// A grid as wide as the rows and as high as there are of them, with `#` filled, from the top.
+    fn bits(rows: &[&str]) -> BitGrid {
+        let mut grid = BitGrid::with_size((rows[0].len() as i8, rows.len() as i8));
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                grid.set(Pos::new(x as i8, y as i8), c == '#');
+            }
+        }
+        grid
+    }
+
+    fn drawn(grid: &BitGrid) -> Vec<String> {
+        let (width, height) = grid.size();
+        (0..height).map(|y| (0..width).map(|x| if grid.is_filled(Pos::new(x, y)) { '#' } else { '.' }).collect()).collect()
+    }
+
+    #[test]
+    fn sizes_are_kept_within_the_largest_grid() {
+        assert_eq!(BitGrid::new().size(), GAME_GRID_SIZE);
+        assert_eq!(BitGrid::with_size((0, -3)).size(), (1, 1));
+        assert_eq!(BitGrid::with_size((100, 100)).size(), (MAX_GRID_WIDTH as i8, MAX_GRID_HEIGHT as i8));
+        assert_eq!(BitGrid::with_size((MAX_GRID_WIDTH as i8, 4)).full_rows().count(), 0);
+    }
+
+    #[test]
+    fn setting_cells_inside_and_outside() {
+        let mut grid = BitGrid::with_size((4, 4));
+        assert!(grid.set(Pos::new(3, 3), true));
+        assert!(grid.is_filled(Pos::new(3, 3)));
+        assert!(grid.set(Pos::new(3, 3), false));
+        assert!(!grid.is_filled(Pos::new(3, 3)));
+        for outside in [Pos::new(-1, 0), Pos::new(4, 0), Pos::new(0, 4), Pos::new(0, -1)] {
+            assert!(!grid.set(outside, true));
+            assert!(!grid.is_filled(outside));
+        }
+        assert_eq!(grid, BitGrid::with_size((4, 4)));
+    }
+
+    #[test]
+    fn free_cells_include_those_above_but_not_beside_or_below() {
+        let grid = bits(&["....", ".#..", "...."]);
+        assert!(grid.is_free_or_above(Pos::new(0, 0)));
+        assert!(grid.is_free_or_above(Pos::new(1, -5)));
+        assert!(!grid.is_free_or_above(Pos::new(1, 1)));
+        assert!(!grid.is_free_or_above(Pos::new(-1, 0)));
+        assert!(!grid.is_free_or_above(Pos::new(4, 0)));
+        assert!(!grid.is_free_or_above(Pos::new(0, 3)));
+    }
+
+    #[test]
+    fn placing_pieces() {
+        let mut grid = BitGrid::with_size((4, 4));
+        let o = Piece::new(Tetromino::O);
+        assert!(grid.fits(&o, Pos::new(1, 3)));
+        assert!(grid.place(&o, Pos::new(1, 3)));
+        assert_eq!(drawn(&grid), ["....", "....", "##..", "##.."]);
+        assert!(!grid.fits(&o, Pos::new(2, 3)));
+        assert!(grid.fits(&o, Pos::new(3, 3)));
+        assert!(!grid.fits(&o, Pos::new(4, 3)));
+        // Cells above the top are still placed, but the piece doesn't all fit
+        assert!(!grid.place(&o, Pos::new(3, 0)));
+        assert_eq!(drawn(&grid), ["..##", "....", "##..", "##.."]);
+    }
+
+    #[test]
+    fn clearing_full_rows() {
+        let mut grid = bits(&["#...", "####", ".#..", "####"]);
+        assert_eq!(grid.full_rows().collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(grid.clear_full_rows(), 2);
+        assert_eq!(drawn(&grid), ["....", "....", "#...", ".#.."]);
+        assert_eq!(grid.clear_full_rows(), 0);
+    }
+
+    #[test]
+    fn heights_and_holes() {
+        let grid = bits(&["....", ".#..", "###.", "#..#"]);
+        assert_eq!(grid.column_heights(), [2, 3, 2, 1]);
+        assert_eq!(grid.count_holes(), 2);
+        assert_eq!(BitGrid::with_size((4, 4)).column_heights(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn inserting_rows_at_the_bottom() {
+        let mut grid = bits(&["....", "....", ".#..", "...."]);
+        assert!(grid.insert_rows_at_bottom(&[0b0111, 0b1110]));
+        assert_eq!(drawn(&grid), [".#..", "....", "###.", ".###"]);
+        assert!(!grid.insert_rows_at_bottom(&[0b0001]));
+        assert_eq!(drawn(&grid), ["....", "###.", ".###", "#..."]);
+    }
+
+    #[test]
+    fn agrees_with_the_grid_it_is_kept_beside() {
+        let mut grid = Grid::with_size((10, 20));
+        let mut rng = oorandom::Rand32::new(3);
+        for _ in 0..80 {
+            let pos = Pos::new(rng.rand_range(0..10) as i8, rng.rand_range(8..20) as i8);
+            grid.set(pos, Cell::Garbage);
+        }
+        for x in 0..10 {
+            grid.set(Pos::new(x, 17), Cell::Garbage);
+        }
+        let from_cells = |grid: &Grid| {
+            let mut bits = BitGrid::with_size(grid.size());
+            for (y, row) in grid.rows().enumerate() {
+                for (x, cell) in row.iter().enumerate() {
+                    bits.set(Pos::new(x as i8, y as i8), !cell.is_empty());
+                }
+            }
+            bits
+        };
+        assert_eq!(grid.bits(), &from_cells(&grid));
+        assert!(!grid.clear_full_rows().is_empty());
+        assert_eq!(grid.bits(), &from_cells(&grid));
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::orientations,
+    grid::Cell,
+    input::Action,
+    piece::Tetromino,
+    rules::{Game, TICKS_PER_SECOND},
+};
+
+/// How long a bot has to answer before its piece is dropped where it is, in ticks.
+pub const REPLY_TICKS: u32 = TICKS_PER_SECOND;
+
+/// A piece's orientations by quarter turns right of spawn, each as the cells it covers around the piece's position.
+type Orientations = Vec<[(i8, i8); 4]>;
+
+/// What the game sends a bot, as a line of JSON each on its standard input.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToBot {
+    /// Sent first: the size of the board, how long the bot has to answer each piece, and the orientations of each piece.
+    Start { width: i8, height: i8, reply_ms: u32, pieces: Vec<(String, Orientations)> },
+    /// Sent when a piece appears, asking where it should go: the board from the top down with `.` for empty cells,
+    /// `G` for garbage and the piece's letter for the rest, and everything the bot needs to decide.
+    /// Only a reply with the same `piece` counts.
+    Piece {
+        piece: u32,
+        board: Vec<String>,
+        current: String,
+        x: i8,
+        y: i8,
+        queue: Vec<String>,
+        hold: Option<String>,
+        can_hold: bool,
+        incoming: u32,
+    },
+}
+
+/// What a bot sends back, as a line of JSON each on its standard output.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FromBot {
+    /// Put the piece in orientation `rotation` with its position at column `x`, holding it first if `hold`,
+    /// which the game plays out with the same inputs a player would make.
+    Place { piece: u32, hold: bool, rotation: u8, x: i8 },
+    /// Make these inputs, one after another.
+    Inputs { piece: u32, inputs: Vec<Action> },
+}
+
+impl FromBot {
+    pub fn piece(&self) -> u32 {
+        match *self {
+            FromBot::Place { piece, .. } | FromBot::Inputs { piece, .. } => piece,
+        }
+    }
+}
+
+fn letter(kind: Tetromino) -> String {
+    format!("{kind:?}")
+}
+
+/// An external program playing a board through the bot protocol: JSON lines over its standard input and output.
+/// Its replies are read on a thread of their own and picked up with `replies`, so a slow bot never holds up a tick.
+#[derive(Debug)]
+pub struct Bot {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    replies: Receiver<FromBot>,
+}
+
+impl Bot {
+    /// Starts the program in `command`, split at spaces into the program and its arguments, to play `game`.
+    pub fn spawn(command: &str, game: &Game) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "there is no bot command"))?;
+        let mut child = Command::new(program).args(words).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().expect("The bot's output is piped");
+        let (sender, replies) = mpsc::channel();
+        thread::Builder::new().name("bot".to_owned()).spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        debug!("Bot output closed: {e}");
+                        break;
+                    }
+                };
+                match serde_json::from_str(&line) {
+                    Ok(reply) => {
+                        if sender.send(reply).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("Ignoring bot reply that could not be read ({e}): {line}"),
+                }
+            }
+        })?;
+        info!("Started bot {command}");
+        let mut bot = Bot { stdin: child.stdin.take(), child, replies };
+        let (width, height) = game.grid.size();
+        let pieces = Tetromino::ALL
+            .into_iter()
+            .map(|kind| {
+                let turns = orientations(game, kind, game.grid.size().0 / 2);
+                (letter(kind), turns.iter().map(|(_, piece)| piece.offsets.map(|p| (p.x, p.y))).collect())
+            })
+            .collect();
+        bot.send(&ToBot::Start { width, height, reply_ms: REPLY_TICKS * game.ms_per_tick(), pieces });
+        Ok(bot)
+    }
+    fn send(&mut self, message: &ToBot) {
+        let Some(stdin) = &mut self.stdin else {
+            return;
+        };
+        let mut line = serde_json::to_string(message).expect("Messages can always be written");
+        line.push('\n');
+        if let Err(e) = stdin.write_all(line.as_bytes()).and_then(|()| stdin.flush()) {
+            warn!("Could not send to the bot: {e}");
+            self.stdin = None;
+        }
+    }
+    /// Asks where the current piece of `game` should go.
+    pub fn ask(&mut self, game: &Game) {
+        let Some(current) = &game.cur_piece else {
+            return;
+        };
+        let cell = |cell: Cell| match cell {
+            Cell::Empty => '.',
+            Cell::Garbage => 'G',
+            Cell::Filled(kind) => letter(kind).chars().next().unwrap_or('#'),
+        };
+        self.send(&ToBot::Piece {
+            piece: game.pieces,
+            board: game.grid.rows().map(|row| row.iter().map(|&c| cell(c)).collect()).collect(),
+            current: letter(current.piece.kind),
+            x: current.pos.x,
+            y: current.pos.y,
+            queue: vec![letter(game.next_piece.kind)],
+            hold: game.hold_piece.map(|piece| letter(piece.kind)),
+            can_hold: game.can_hold(),
+            incoming: game.pending_garbage(),
+        });
+    }
+    /// The replies that have come in since last time.
+    pub fn replies(&self) -> impl Iterator<Item = FromBot> + '_ {
+        self.replies.try_iter()
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        // Closing its input is the bot's cue to quit, but one that doesn't shouldn't be left running
+        self.stdin = None;
+        if let Err(e) = self.child.kill() {
+            debug!("Could not stop the bot: {e}");
+        }
+        let _ = self.child.wait();
+    }
+}
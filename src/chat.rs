@@ -0,0 +1,95 @@
+use ggez::{graphics::Color, input::keyboard::KeyCode};
+
+use crate::render::Renderer;
+
+/// The messages sent with F1 to F4, for when there is no time to type.
+pub const QUICK_MESSAGES: [&str; 4] = ["Good luck!", "Good game!", "Well played!", "Rematch?"];
+const QUICK_KEYS: [KeyCode; 4] = [KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4];
+/// How many of the latest messages are kept and shown.
+const MAX_LINES: usize = 8;
+/// The longest message that can be sent, in characters.
+pub const MAX_LENGTH: usize = 100;
+
+/// What a key pressed did to the chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatKey {
+    /// The key is not for the chat.
+    Ignored,
+    Handled,
+    /// A message to send the other player.
+    Send(String),
+}
+
+/// The messages sent back and forth between the players of an online match, and the one being typed.
+/// Tab starts typing a message, Return sends it and Escape gives up on it.
+#[derive(Debug, Clone, Default)]
+pub struct Chat {
+    /// Who sent each of the latest messages, and what it said.
+    lines: Vec<(String, String)>,
+    typing: Option<String>,
+}
+
+impl Chat {
+    /// Adds a message from `from` to those shown.
+    pub fn add(&mut self, from: &str, text: &str) {
+        let text: String = text.chars().filter(|c| !c.is_control()).take(MAX_LENGTH).collect();
+        self.lines.push((from.to_owned(), text));
+        if self.lines.len() > MAX_LINES {
+            self.lines.remove(0);
+        }
+    }
+    pub fn is_typing(&self) -> bool {
+        self.typing.is_some()
+    }
+    pub fn key_down(&mut self, keycode: KeyCode) -> ChatKey {
+        let Some(typed) = &mut self.typing else {
+            if keycode == KeyCode::Tab {
+                self.typing = Some(String::new());
+                return ChatKey::Handled;
+            }
+            return match QUICK_KEYS.iter().position(|&key| key == keycode) {
+                Some(i) => ChatKey::Send(QUICK_MESSAGES[i].to_owned()),
+                None => ChatKey::Ignored,
+            };
+        };
+        match keycode {
+            KeyCode::Return => match self.typing.take().filter(|typed| !typed.trim().is_empty()) {
+                Some(typed) => ChatKey::Send(typed),
+                None => ChatKey::Handled,
+            },
+            KeyCode::Escape => {
+                self.typing = None;
+                ChatKey::Handled
+            }
+            KeyCode::Back => {
+                typed.pop();
+                ChatKey::Handled
+            }
+            // Everything else is typed, and comes in as text
+            _ => ChatKey::Handled,
+        }
+    }
+    pub fn text_input(&mut self, character: char) {
+        if let Some(typed) = &mut self.typing {
+            if !character.is_control() && typed.chars().count() < MAX_LENGTH {
+                typed.push(character);
+            }
+        }
+    }
+    /// Draws the latest messages with their top left corner at `(x, y)`, and under them the one being typed.
+    pub fn draw(&self, r: &mut dyn Renderer, (x, y): (f32, f32)) {
+        for (i, (from, text)) in self.lines.iter().enumerate() {
+            r.draw_text(&format!("{from}: {text}"), 18., [x, y + 24. * i as f32], Color::WHITE);
+        }
+        let prompt = match &self.typing {
+            Some(typed) => format!("Say: {typed}_"),
+            None => "Tab: chat  F1-F4: quick chat".to_owned(),
+        };
+        let colour = if self.typing.is_some() { Color::YELLOW } else { Color::new(0.7, 0.7, 0.7, 1.) };
+        r.draw_text(&prompt, 18., [x, y + 24. * MAX_LINES as f32 + 8.], colour);
+    }
+    /// How tall `draw` draws the chat.
+    pub fn height() -> f32 {
+        24. * (MAX_LINES + 1) as f32 + 8.
+    }
+}
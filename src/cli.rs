@@ -0,0 +1,91 @@
+use log::LevelFilter;
+
+use crate::mode::Mode;
+
+pub const USAGE: &str = "\
+Usage: tetris [options] [replay]
+
+Options:
+    --mode <marathon|sprint|ultra>  Start straight into a game of this mode
+    --seed <number>                 Start every game with this seed
+    --level <number>                Start every game at this level
+    --replay <file or text>         Watch a replay, also given without --replay
+    --script <file>                 Play the custom mode written in this script
+    --practice <file or text>       Practise the board and pieces of a fumen
+    --fullscreen                    Start in fullscreen
+    --config <file>                 Use this config file instead of the usual one
+    --log-level <level>             How much to log: off, error, warn, info, debug or trace
+    --export-stats <file>           Export the statistics of whoever played last and quit
+    --fumen <file or text>          Print the pieces placed in a replay as a fumen link and quit
+    --headless                      Don't open a window, print what happens instead, and quit:
+                                    verify the replay if one is given, play the AI against itself
+                                    with --versus, or have the AI play the --mode otherwise
+    --versus                        With --headless, play versus games of the AI against itself
+    --games <number>                With --headless, how many games the AI plays, 1 unless given
+    --stats-window                  Open just the stats window, showing the game sent on standard input,
+                                    as the game does itself when the stats window is on in the config
+    --help                          Show this";
+
+/// What the game was started with on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    pub mode: Option<Mode>,
+    pub seed: Option<u64>,
+    pub level: Option<u32>,
+    /// A replay to watch, as a file or as the shared text itself.
+    pub replay: Option<String>,
+    pub script: Option<String>,
+    /// A fumen to practise, as a file or as the fumen itself.
+    pub practice: Option<String>,
+    pub fullscreen: bool,
+    pub config: Option<String>,
+    pub log_level: Option<LevelFilter>,
+    pub export_stats: Option<String>,
+    /// A replay to print as a fumen, as a file or as the shared text itself.
+    pub fumen: Option<String>,
+    pub headless: bool,
+    pub stats_window: bool,
+    pub versus: bool,
+    pub games: Option<u32>,
+    pub help: bool,
+}
+
+impl Args {
+    /// Parses the arguments after the program name, returning a message to show if they don't make sense.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |what: &str| args.next().ok_or_else(|| format!("{arg} needs {what}"));
+            match arg.as_str() {
+                "--mode" => {
+                    let key = value("a mode")?;
+                    let mode = Mode::ALL.into_iter().find(|m| m.key() == key.to_lowercase());
+                    parsed.mode = Some(mode.ok_or_else(|| format!("There is no mode {key}"))?);
+                }
+                "--seed" => parsed.seed = Some(value("a number")?.parse().map_err(|_| "--seed needs a number")?),
+                "--level" => parsed.level = Some(value("a number")?.parse().map_err(|_| "--level needs a number")?),
+                "--replay" => parsed.replay = Some(value("a replay")?),
+                "--script" => parsed.script = Some(value("a file")?),
+                "--practice" => parsed.practice = Some(value("a fumen")?),
+                "--fullscreen" => parsed.fullscreen = true,
+                "--config" => parsed.config = Some(value("a file")?),
+                "--log-level" => {
+                    let level = value("a level")?;
+                    parsed.log_level = Some(level.parse().map_err(|_| format!("There is no log level {level}"))?);
+                }
+                "--export-stats" => parsed.export_stats = Some(value("a file to write to")?),
+                "--fumen" => parsed.fumen = Some(value("a replay")?),
+                "--headless" => parsed.headless = true,
+                "--stats-window" => parsed.stats_window = true,
+                "--versus" => parsed.versus = true,
+                "--games" => parsed.games = Some(value("a number")?.parse().map_err(|_| "--games needs a number")?),
+                "--help" | "-h" => parsed.help = true,
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+                _ if parsed.replay.is_none() => parsed.replay = Some(arg),
+                _ => return Err(format!("Unexpected argument {arg}")),
+            }
+        }
+        Ok(parsed)
+    }
+}
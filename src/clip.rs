@@ -0,0 +1,124 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use ggez::{
+    graphics::{Canvas, Color, DrawParam, Image, ImageFormat, Sampler},
+    Context, GameError, GameResult,
+};
+
+use crate::worker::Worker;
+
+const CLIP_DIR: &str = "clips";
+/// How far back a clip goes.
+const CLIP_SECONDS: u32 = 30;
+const CLIP_FPS: u32 = 10;
+/// How many times smaller than the window the clip is, on a monitor that isn't scaled.
+const CLIP_SCALE: f32 = 4.;
+
+/// Keeps the last half minute or so of frames, shrunk down, so they can be saved as a GIF
+/// after something worth sharing happens.
+#[derive(Debug, Default)]
+pub struct ClipRecorder {
+    /// The captured frames as RGBA pixels, oldest first.
+    frames: VecDeque<Vec<u8>>,
+    /// What the frames are shrunk into.
+    image: Option<Image>,
+    last_capture: Option<Instant>,
+    /// Encodes the clips being saved, one after the other, once the first one is.
+    encoder: Option<Worker<Clip, Result<PathBuf, String>>>,
+}
+
+/// A clip waiting to be encoded.
+struct Clip {
+    path: PathBuf,
+    width: u16,
+    height: u16,
+    frames: VecDeque<Vec<u8>>,
+}
+
+impl ClipRecorder {
+    /// Captures the frame just drawn if it is time for the next one.
+    pub fn capture(&mut self, ctx: &mut Context) -> GameResult {
+        let interval = Duration::from_secs(1) / CLIP_FPS;
+        if self.last_capture.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last_capture = Some(Instant::now());
+
+        // Shrunk more on a scaled up monitor, so clips come out the same size on any of them
+        let shrink = CLIP_SCALE * ctx.gfx.window().scale_factor() as f32;
+        let (width, height) = ((ctx.gfx.frame().width() as f32 / shrink) as u32, (ctx.gfx.frame().height() as f32 / shrink) as u32);
+        let image = match &self.image {
+            Some(image) if image.width() == width && image.height() == height => image.clone(),
+            _ => {
+                // The window changed size, so the old frames don't fit any more
+                self.frames.clear();
+                let image = Image::new_canvas_image(ctx, ImageFormat::Rgba8UnormSrgb, width, height, 1);
+                self.image = Some(image.clone());
+                image
+            }
+        };
+        let mut canvas = Canvas::from_image(ctx, image.clone(), Color::BLACK);
+        canvas.set_sampler(Sampler::linear_clamp());
+        let scale = 1. / shrink;
+        canvas.draw(ctx.gfx.frame(), DrawParam::new().scale([scale, scale]));
+        canvas.finish(ctx)?;
+
+        if self.frames.len() >= (CLIP_SECONDS * CLIP_FPS) as usize {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(image.to_pixels(ctx)?);
+        Ok(())
+    }
+    /// Starts writing the frames kept so far to a GIF in the background, returning where it will be.
+    pub fn save(&mut self, ctx: &Context) -> GameResult<PathBuf> {
+        let Some(image) = &self.image else {
+            return Err(GameError::CustomError("Nothing has been recorded yet".to_owned()));
+        };
+        let (width, height) = (image.width() as u16, image.height() as u16);
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+        let path = ctx.fs.user_data_dir().join(CLIP_DIR).join(format!("{millis}.gif"));
+
+        // Encoding takes a good while, so it's kept off the game's thread
+        let encoder = self.encoder.get_or_insert_with(|| Worker::spawn("clip encoder", |clip: Clip, _| {
+            let Clip { path, width, height, frames } = clip;
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| File::create(&path))
+                .map_err(|e| e.to_string())
+                .and_then(|file| encode_gif(file, width, height, frames).map_err(|e| e.to_string()))
+                .map(|()| path)
+        }));
+        let clip = Clip { path: path.clone(), width, height, frames: self.frames.clone() };
+        if !encoder.send(clip) {
+            self.encoder = None;
+            return Err(GameError::CustomError("The clip encoder has stopped".to_owned()));
+        }
+        Ok(path)
+    }
+    /// Where the clips finished since last time were saved, or why they couldn't be.
+    pub fn saved(&self) -> Vec<Result<PathBuf, String>> {
+        self.encoder.iter().flat_map(Worker::results).collect()
+    }
+    /// Waits for the clips being saved to be finished, before quitting.
+    pub fn finish(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.finish();
+        }
+    }
+}
+
+fn encode_gif(file: File, width: u16, height: u16, frames: VecDeque<Vec<u8>>) -> Result<(), gif::EncodingError> {
+    let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for mut pixels in frames {
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+        frame.delay = (100 / CLIP_FPS) as u16;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
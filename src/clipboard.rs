@@ -0,0 +1,44 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// The programs that put what they are given on the clipboard, tried in order until one works:
+/// Wayland's, X's two, macOS's and Windows'.
+const COPIERS: [&[&str]; 5] = [&["wl-copy"], &["xclip", "-selection", "clipboard"], &["xsel", "--clipboard", "--input"], &["pbcopy"], &["clip"]];
+
+/// Puts `text` on the clipboard, with whichever of the usual programs for it is there, as ggez can't.
+pub fn copy(text: &str) -> Result<(), String> {
+    for copier in COPIERS {
+        let Ok(mut child) = Command::new(copier[0]).args(&copier[1..]).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() else {
+            continue;
+        };
+        let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        if child.wait().is_ok_and(|status| status.success()) && written {
+            return Ok(());
+        }
+    }
+    Err("There is nothing to copy to the clipboard with".to_owned())
+}
+
+/// The programs that print what is on the clipboard, in the same order.
+const PASTERS: [&[&str]; 5] = [
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["xsel", "--clipboard", "--output"],
+    &["pbpaste"],
+    &["powershell", "-NoProfile", "-Command", "Get-Clipboard"],
+];
+
+/// The text on the clipboard, with whichever of the usual programs for it is there.
+pub fn paste() -> Result<String, String> {
+    for paster in PASTERS {
+        let Ok(output) = Command::new(paster[0]).args(&paster[1..]).stdin(Stdio::null()).stderr(Stdio::null()).output() else {
+            continue;
+        };
+        if output.status.success() {
+            return String::from_utf8(output.stdout).map_err(|_| "The clipboard doesn't hold text".to_owned());
+        }
+    }
+    Err("There is nothing to paste from the clipboard with".to_owned())
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use ggez::{graphics::Color, GameResult};
+use log::{warn, LevelFilter};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::{Difficulty, Weights},
+    attack::AttackTable,
+    handicap::Handicap,
+    gamepad::{ButtonBindings, StickSettings},
+    grid::{Pos, GAME_GRID_SIZE},
+    input::{BindingOverrides, Keybindings},
+    mode::Mode,
+    piece::NUM_COLOURS,
+    render::{COLOURS, GRID_CELL_SIZE, SCREEN_SIZE},
+    rules::{fnv1a, TICKS_PER_SECOND},
+    storage::Storage,
+};
+
+pub const CONFIG_FILE: &str = "config.toml";
+/// The most times a second the game logic can run, so a tick is never shorter than a millisecond.
+pub const MAX_TICKS_PER_SECOND: u32 = 1000;
+const DEFAULT_PROFILE: &str = "default";
+const PROFILES_DIR: &str = "profiles";
+
+/// How the pieces respond to held keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handling {
+    /// Delayed auto shift: how long a direction has to be held before it starts repeating (ms).
+    pub das: u32,
+    /// Auto repeat rate: time between each shift once auto shift has kicked in (ms).
+    /// With 0 the piece goes straight to the wall.
+    pub arr: u32,
+    /// Soft drop factor: how many times faster than gravity a soft dropped piece falls.
+    pub sdf: u8,
+    /// Hard drops are ignored for this long after a piece spawns or shifts sideways (ms),
+    /// guarding against misdrops. 0 turns it off.
+    pub misdrop_guard: u32,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: 167,
+            arr: 33,
+            sdf: 6,
+            misdrop_guard: 0,
+        }
+    }
+}
+
+/// The numbers the game is built around. They are the usual constants unless a mode, a theme or a test
+/// wants them otherwise, and the game is set up from them when it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameConfig {
+    /// How many cells across and down the board is, at most `MAX_GRID_WIDTH` by `MAX_GRID_HEIGHT`.
+    pub grid_size: (i8, i8),
+    /// How big a cell is drawn, in pixels. Everything else on the screen is scaled along with it.
+    pub cell_size: f32,
+    /// How many times a second the game logic runs. Replays and saves count time in ticks,
+    /// so they only play back the same at the rate they were made at. Kept between 1 and `MAX_TICKS_PER_SECOND`.
+    pub ticks_per_second: u32,
+    /// How many frames a piece waits before falling a row, instead of what the mode's rules say for the level.
+    /// At least 1.
+    pub frames_per_row: Option<u8>,
+    /// The colours of the pieces when no theme is chosen.
+    pub palette: [Color; NUM_COLOURS],
+    /// Where new pieces appear, from the top left of the board.
+    pub spawn: Pos,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            grid_size: GAME_GRID_SIZE,
+            cell_size: GRID_CELL_SIZE.0 as f32,
+            ticks_per_second: TICKS_PER_SECOND,
+            frames_per_row: None,
+            palette: COLOURS,
+            spawn: Pos::new(GAME_GRID_SIZE.0 / 2, -2),
+        }
+    }
+}
+
+impl GameConfig {
+    /// `ticks_per_second`, kept between 1 and `MAX_TICKS_PER_SECOND`.
+    pub fn tick_rate(&self) -> u32 {
+        self.ticks_per_second.clamp(1, MAX_TICKS_PER_SECOND)
+    }
+    /// How long a tick is, at least a millisecond.
+    pub fn ms_per_tick(&self) -> u32 {
+        1000 / self.tick_rate()
+    }
+    /// `frames_per_row`, at least 1.
+    pub fn frames_per_row(&self) -> Option<u8> {
+        self.frames_per_row.map(|frames| frames.max(1))
+    }
+    /// How much bigger than the usual layout everything is drawn.
+    pub fn scale(&self) -> f32 {
+        self.cell_size / GRID_CELL_SIZE.0 as f32
+    }
+    /// How big the window is.
+    pub fn screen_size(&self) -> (f32, f32) {
+        (SCREEN_SIZE.0 * self.scale(), SCREEN_SIZE.1 * self.scale())
+    }
+    /// A hash of the numbers that change how a game plays out, leaving out how it looks,
+    /// for telling whether another player's boards will play out the same as the copies of them here.
+    pub fn rules_hash(&self) -> u64 {
+        let bytes = [self.grid_size.0 as u8, self.grid_size.1 as u8, self.frames_per_row().unwrap_or(0), self.spawn.x as u8, self.spawn.y as u8]
+            .into_iter()
+            .chain(self.tick_rate().to_le_bytes());
+        fnv1a(bytes)
+    }
+}
+
+/// A named set of keybindings and handling settings, so several people can share one machine.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub handling: Handling,
+    pub stick: StickSettings,
+    pub bindings: Keybindings,
+    pub buttons: ButtonBindings,
+    /// Keybinding overrides for specific modes, by `Mode::key`.
+    pub modes: BTreeMap<String, BindingOverrides>,
+}
+
+impl Profile {
+    /// The keybindings to use in the given mode.
+    pub fn bindings(&self, mode: Mode) -> Keybindings {
+        match self.modes.get(mode.key()) {
+            Some(overrides) => self.bindings.with_overrides(overrides),
+            None => self.bindings.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The name of the profile in use.
+    pub profile: String,
+    /// The mode to play.
+    pub mode: Mode,
+    /// Whether to show on-screen buttons for touchscreens.
+    pub touch_buttons: bool,
+    /// Whether to play in a tall window, with the next and held pieces above the board and the on-screen buttons below it,
+    /// as on a phone held upright.
+    pub portrait: bool,
+    /// Whether to show which keys are held, for streaming and tutorials.
+    pub key_overlay: bool,
+    /// Whether to open a second window with the stats, the next and held pieces and the keys held,
+    /// on a green background to key out, to capture apart from the game when streaming.
+    pub stats_window: bool,
+    /// Whether Practice shows where the AI would put the current piece.
+    pub hints: bool,
+    /// Whether to draw the falling piece sliding between rows instead of jumping a row at a time.
+    pub smooth_fall: bool,
+    /// Whether to keep the last half minute of play around to save as a GIF with F9.
+    pub clip_recorder: bool,
+    /// The installed theme to use, or the built-in one if none.
+    pub theme: Option<String>,
+    /// How much goes into the log file: off, error, warn, info, debug or trace.
+    pub log_level: LevelFilter,
+    /// How much garbage clears send in versus games, and in online games hosted here unless a preset is picked.
+    pub attack: AttackTable,
+    /// The address of the global leaderboard server Sprint times and Ultra scores are sent to, e.g. `http://example.com/tetris`.
+    /// Nothing is sent anywhere unless one is set.
+    pub leaderboard: Option<String>,
+    /// The command that starts a bot to play versus games against the computer instead of the built-in AI, e.g. `./my-bot --fast`.
+    /// It is talked to over its standard input and output, see `bot::ToBot` and `bot::FromBot`.
+    pub bot: Option<String>,
+    /// The application ID of a Discord application to show what is being played on the player's Discord profile as,
+    /// from the Discord developer portal. Nothing is shown unless one is set.
+    pub discord: Option<String>,
+    /// The Twitch channel whose viewers vote in its chat on what happens to the game, for streaming.
+    /// Nothing is connected to unless one is set.
+    pub twitch: Option<String>,
+    /// How well the built-in AI plays versus games against the computer.
+    pub difficulty: Difficulty,
+    /// How the built-in AI judges boards in versus games against the computer, to tune the way it plays.
+    pub ai_weights: Weights,
+    /// The handicaps of the left and right players of local versus games.
+    pub handicaps: [Handicap; 2],
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            profile: DEFAULT_PROFILE.to_owned(),
+            mode: Mode::default(),
+            touch_buttons: false,
+            portrait: false,
+            key_overlay: false,
+            stats_window: false,
+            hints: false,
+            smooth_fall: false,
+            clip_recorder: false,
+            theme: None,
+            log_level: LevelFilter::Info,
+            attack: AttackTable::default(),
+            leaderboard: None,
+            bot: None,
+            discord: None,
+            twitch: None,
+            difficulty: Difficulty::default(),
+            ai_weights: Weights::default(),
+            handicaps: [Handicap::default(); 2],
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_owned(), Profile::default())]),
+        }
+    }
+}
+
+impl Config {
+    pub fn profile(&self) -> &Profile {
+        &self.profiles[&self.profile]
+    }
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        self.profiles.get_mut(&self.profile).unwrap()
+    }
+    /// Switches to the next (or previous when `forward` is false) profile in alphabetical order.
+    pub fn cycle_profile(&mut self, forward: bool) {
+        let names: Vec<_> = self.profiles.keys().collect();
+        let i = names.iter().position(|&name| *name == self.profile).unwrap_or(0);
+        let i = if forward { (i + 1) % names.len() } else { (i + names.len() - 1) % names.len() };
+        self.profile = names[i].clone();
+    }
+    /// Adds a copy of the current profile under a new name and switches to it.
+    pub fn new_profile(&mut self) {
+        let name = (2..)
+            .map(|n| format!("profile {n}"))
+            .find(|name| !self.profiles.contains_key(name))
+            .unwrap();
+        let profile = self.profile().clone();
+        self.profiles.insert(name.clone(), profile);
+        self.profile = name;
+    }
+    /// Switches to the next (or previous) of `themes`, with the built-in theme before them all.
+    pub fn cycle_theme(&mut self, themes: &[String], forward: bool) {
+        let n = themes.len() + 1;
+        let i = self.theme.as_ref().and_then(|name| themes.iter().position(|t| t == name)).map_or(0, |i| i + 1);
+        let i = if forward { (i + 1) % n } else { (i + n - 1) % n };
+        self.theme = i.checked_sub(1).map(|i| themes[i].clone());
+    }
+    /// Makes sure the selected profile exists.
+    fn normalise(mut self) -> Self {
+        self.profiles.entry(self.profile.clone()).or_default();
+        if let Err(e) = self.attack.validate() {
+            warn!("Playing by the default attack table, as the one in the config can't be played by: {e}");
+            self.attack = AttackTable::default();
+        }
+        if let Some(e) = self.handicaps.iter().find_map(|handicap| handicap.validate().err()) {
+            warn!("Playing versus games without handicaps, as the config has {e}");
+            self.handicaps = [Handicap::default(); 2];
+        }
+        self
+    }
+    /// The storage directory of the selected profile's scores, statistics, saved games and replays.
+    pub fn profile_dir(&self) -> String {
+        let name: String = self
+            .profile
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        format!("{PROFILES_DIR}/{name}")
+    }
+    /// Loads the config, falling back to the defaults if there isn't one.
+    pub fn load(storage: &dyn Storage) -> GameResult<Self> {
+        match storage.read(CONFIG_FILE)? {
+            Some(data) => Ok(toml::from_slice::<Self>(&data)?.normalise()),
+            None => Ok(Config::default()),
+        }
+    }
+    pub fn save(&self, storage: &dyn Storage) -> GameResult {
+        // Going through a `Value` puts the tables after the plain values, which TOML needs whatever order the fields are in
+        let config = toml::Value::try_from(self)?;
+        storage.write(CONFIG_FILE, toml::to_string_pretty(&config)?.as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use ggez::{
+    event::{Button, GamepadId},
+    graphics::{Color, Rect},
+    input::keyboard::{KeyCode, KeyMods},
+    Context,
+};
+use log::info;
+
+use crate::{
+    ai::{Ai, Difficulty},
+    app::{random_seed, GameState},
+    config::Handling,
+    mode::Mode,
+    render::{Renderer, SCREEN_SIZE},
+    scene::{Scene, Transition},
+    settings::MenuInput,
+    widget::TetrisWidget,
+};
+
+/// How long the title screen waits for input before a demo starts (ms).
+pub const IDLE_MS: u32 = 30_000;
+/// How long a demo goes on for before going back to the title screen, if the AI hasn't topped out by then (ms).
+const DEMO_MS: u32 = 60_000;
+
+/// A game of Marathon played by the AI, shown on the title screen after it has been left alone for a while,
+/// like the arcade machines did. Any key or button goes back to the title screen.
+pub struct DemoScene {
+    board: TetrisWidget,
+    ai: Ai,
+}
+
+impl DemoScene {
+    pub fn new(state: &GameState) -> Self {
+        let seed = random_seed();
+        info!("Starting a demo with seed {seed}");
+        let mode = Mode::Marathon;
+        DemoScene {
+            board: TetrisWidget::new(mode, mode.rules(), seed, Handling::default(), &state.game_config),
+            ai: Ai::new(Difficulty::Hard),
+        }
+    }
+}
+
+impl Scene for DemoScene {
+    fn update(&mut self, _state: &mut GameState, _ctx: &mut Context) -> Transition {
+        let game = &self.board.game;
+        if game.gameover || game.ms() >= DEMO_MS {
+            return Transition::Pop(1);
+        }
+        let inputs = self.ai.inputs(game);
+        self.board.tick(&inputs);
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        self.board.draw(r, self.board.layout(), 0., &state.theme);
+        r.draw_panel(Rect::new(0., 0., SCREEN_SIZE.0, 72.), Color::new(0., 0., 0., 0.8));
+        r.draw_text("DEMO", 48., [16., 12.], Color::YELLOW);
+        r.draw_text("Press any key", 20., [SCREEN_SIZE.0 - 160., 28.], Color::WHITE);
+    }
+    fn menu_input(&mut self, _state: &mut GameState, _ctx: &mut Context, _input: MenuInput) -> Transition {
+        Transition::Pop(1)
+    }
+    fn key_down(&mut self, _state: &mut GameState, _ctx: &mut Context, _keycode: KeyCode, _mods: KeyMods, _repeated: bool) -> Transition {
+        Transition::Pop(1)
+    }
+    fn gamepad_button_down(&mut self, _state: &mut GameState, _ctx: &mut Context, _btn: Button, _id: GamepadId) -> Transition {
+        Transition::Pop(1)
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::info;
+use serde_json::{json, Value};
+
+use crate::{rules::Game, worker::Worker};
+
+/// Discord only shows so many changes of an activity, so the score and lines are sent this often at most.
+const MIN_INTERVAL: Duration = Duration::from_secs(15);
+/// How long Discord is given to answer before the connection is given up on.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the player is doing, as shown on their Discord profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    /// The first line, e.g. "Playing Sprint".
+    pub details: String,
+    /// The second line, e.g. "27 lines, level 3, 1200 points".
+    pub state: Option<String>,
+    /// When the game started, in seconds since the Unix epoch, for Discord to count the elapsed time up from.
+    pub start: Option<u64>,
+}
+
+impl Activity {
+    /// The game being played, or watched as a replay.
+    pub fn of(game: &Game, replay: bool) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let verb = if replay { "Watching a replay of" } else { "Playing" };
+        Activity {
+            details: format!("{verb} {}", game.mode),
+            state: Some(format!("{} lines, level {}, {} points", game.lines, game.level(), game.score)),
+            start: Some(now.saturating_sub(game.ms() as u64 / 1000)),
+        }
+    }
+    /// Anywhere but in a game.
+    pub fn menus() -> Self {
+        Activity { details: "In the menus".to_owned(), state: None, start: None }
+    }
+    fn to_json(&self) -> Value {
+        let mut activity = json!({ "details": self.details });
+        if let Some(state) = &self.state {
+            activity["state"] = json!(state);
+        }
+        if let Some(start) = self.start {
+            activity["timestamps"] = json!({ "start": start });
+        }
+        activity
+    }
+}
+
+trait Pipe: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Pipe for T {}
+
+/// The local connection to the Discord client, which it listens for on a socket (a named pipe on Windows)
+/// called `discord-ipc-0` to `discord-ipc-9`. Messages both ways are an opcode and a length, as little-endian
+/// 32-bit numbers, and that many bytes of JSON.
+struct Connection {
+    pipe: Box<dyn Pipe>,
+    nonce: u64,
+}
+
+impl Connection {
+    #[cfg(unix)]
+    fn open(i: u32) -> io::Result<Box<dyn Pipe>> {
+        let dir = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+            .into_iter()
+            .find_map(|var| std::env::var(var).ok())
+            .unwrap_or_else(|| "/tmp".to_owned());
+        let stream = std::os::unix::net::UnixStream::connect(format!("{dir}/discord-ipc-{i}"))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Box::new(stream))
+    }
+    #[cfg(windows)]
+    fn open(i: u32) -> io::Result<Box<dyn Pipe>> {
+        let pipe = std::fs::OpenOptions::new().read(true).write(true).open(format!(r"\\.\pipe\discord-ipc-{i}"))?;
+        Ok(Box::new(pipe))
+    }
+    #[cfg(not(any(unix, windows)))]
+    fn open(_i: u32) -> io::Result<Box<dyn Pipe>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Discord can't be talked to here"))
+    }
+    /// Connects to the Discord client running on this machine as the Discord application `app_id`.
+    fn connect(app_id: &str) -> Result<Self, String> {
+        let pipe = (0..10).find_map(|i| Self::open(i).ok()).ok_or("Discord isn't running")?;
+        let mut connection = Connection { pipe, nonce: 0 };
+        connection.send(0, &json!({ "v": 1, "client_id": app_id }))?;
+        let ready = connection.receive()?;
+        if ready["evt"] != "READY" {
+            return Err(format!("Discord would not connect: {}", ready["data"]["message"]));
+        }
+        Ok(connection)
+    }
+    fn send(&mut self, opcode: u32, message: &Value) -> Result<(), String> {
+        let body = message.to_string();
+        let mut frame = Vec::with_capacity(8 + body.len());
+        frame.extend(opcode.to_le_bytes());
+        frame.extend((body.len() as u32).to_le_bytes());
+        frame.extend(body.as_bytes());
+        self.pipe.write_all(&frame).map_err(|e| e.to_string())
+    }
+    fn receive(&mut self) -> Result<Value, String> {
+        let mut header = [0; 8];
+        self.pipe.read_exact(&mut header).map_err(|e| e.to_string())?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let mut body = vec![0; len as usize];
+        self.pipe.read_exact(&mut body).map_err(|e| e.to_string())?;
+        serde_json::from_slice(&body).map_err(|e| e.to_string())
+    }
+    fn set_activity(&mut self, activity: &Activity) -> Result<(), String> {
+        self.nonce += 1;
+        let command = json!({
+            "cmd": "SET_ACTIVITY",
+            "args": { "pid": std::process::id(), "activity": activity.to_json() },
+            "nonce": self.nonce.to_string(),
+        });
+        self.send(1, &command)?;
+        let reply = self.receive()?;
+        if reply["evt"] == "ERROR" {
+            return Err(format!("Discord would not show the activity: {}", reply["data"]["message"]));
+        }
+        Ok(())
+    }
+}
+
+/// Shows what is being played on the player's Discord profile, when a Discord application is set in the config.
+/// Discord is talked to on a worker so it never holds up the game, and connected to again with the next change
+/// if it isn't running or goes away, which is only logged.
+#[derive(Default)]
+pub struct Presence {
+    worker: Option<Worker<Activity, Result<(), String>>>,
+    /// The application the worker connects as.
+    app_id: String,
+    sent: Option<(Activity, Instant)>,
+    /// Whether the last activity got through, so a Discord that isn't running is only logged once.
+    shown: bool,
+}
+
+impl Presence {
+    /// Shows `activity` as the Discord application `app_id`, or stops showing anything if there is none.
+    pub fn update(&mut self, app_id: Option<&str>, activity: Activity) {
+        for result in self.worker.iter().flat_map(Worker::results) {
+            match &result {
+                Ok(()) if !self.shown => info!("Showing the game on Discord"),
+                Err(e) if self.shown => info!("Not showing the game on Discord: {e}"),
+                _ => (),
+            }
+            self.shown = result.is_ok();
+        }
+        let Some(app_id) = app_id else {
+            // Closing the connection takes the activity off the profile
+            self.worker = None;
+            self.sent = None;
+            return;
+        };
+        if self.worker.is_none() || self.app_id != app_id {
+            let id = app_id.to_owned();
+            let mut connection = None;
+            let worker = Worker::spawn("discord", move |activity: Activity, _| {
+                if connection.is_none() {
+                    connection = Some(Connection::connect(&id)?);
+                }
+                let result = connection.as_mut().expect("Connected just now").set_activity(&activity);
+                if result.is_err() {
+                    connection = None;
+                }
+                result
+            });
+            self.worker = Some(worker);
+            self.app_id = app_id.to_owned();
+            self.sent = None;
+            self.shown = true;
+        }
+        // A new game or scene is shown straight away, and the same one's progress now and then
+        let due = match &self.sent {
+            None => true,
+            Some((sent, at)) => sent.details != activity.details || (*sent != activity && at.elapsed() >= MIN_INTERVAL),
+        };
+        if due {
+            if let Some(worker) = &self.worker {
+                worker.send(activity.clone());
+            }
+            self.sent = Some((activity, Instant::now()));
+        }
+    }
+}
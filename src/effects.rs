@@ -0,0 +1,203 @@
+use std::{any::Any, time::Duration};
+
+use ggez::graphics::{Color, Rect};
+
+use crate::{
+    render::{board_offset, cell_rect, Renderer, SCREEN_SIZE},
+};
+
+/// Something shown for a little while, like a message, a floating score or the screen shaking.
+/// They all live in `Effects` and are updated and drawn together, rather than each having its own timer.
+pub trait Effect: Any {
+    /// Moves the effect on by `dt` of real time, returning whether it should be kept.
+    fn update(&mut self, dt: Duration) -> bool;
+    fn draw(&self, _r: &mut dyn Renderer) {}
+    /// How far the board is pushed aside by the effect, in pixels.
+    fn shake(&self) -> [f32; 2] {
+        [0., 0.]
+    }
+}
+
+/// The effects going on, on top of the scenes.
+#[derive(Default)]
+pub struct Effects {
+    entities: Vec<Box<dyn Effect>>,
+}
+
+impl Effects {
+    pub fn spawn(&mut self, effect: impl Effect) {
+        self.entities.push(Box::new(effect));
+    }
+    /// Stops every effect of type `T`, e.g. an old message before a new one is shown.
+    pub fn remove<T: Effect>(&mut self) {
+        self.entities.retain(|e| !(&**e as &dyn Any).is::<T>());
+    }
+    pub fn update(&mut self, dt: Duration) {
+        self.entities.retain_mut(|e| e.update(dt));
+    }
+    pub fn draw(&self, r: &mut dyn Renderer) {
+        for e in &self.entities {
+            e.draw(r);
+        }
+    }
+    /// Names a clear beside the board, shaking it for a tetris.
+    pub fn spawn_clear(&mut self, lines: u32, t_spin: bool) {
+        let name = match lines {
+            0 if t_spin => "T-spin",
+            0 => return,
+            1 => "Single",
+            2 => "Double",
+            3 => "Triple",
+            _ => "Tetris",
+        };
+        let (text, colour) = match t_spin {
+            true if lines > 0 => (format!("T-spin {name}"), Color::MAGENTA),
+            true => (name.to_owned(), Color::MAGENTA),
+            _ if lines >= 4 => (name.to_owned(), Color::YELLOW),
+            _ => (name.to_owned(), Color::WHITE),
+        };
+        self.spawn(FloatingText::at_cell(text, (-3.5, 10.), colour));
+        if lines >= 4 {
+            self.spawn(Shake::new(8., Duration::from_millis(300)));
+        }
+    }
+    /// How far the board is pushed aside by all the effects together.
+    pub fn shake(&self) -> [f32; 2] {
+        self.entities.iter().map(|e| e.shake()).fold([0., 0.], |[x, y], [dx, dy]| [x + dx, y + dy])
+    }
+}
+
+/// How far into its life an effect of `lifetime` that is `age` old is, from 0 to 1.
+fn progress(age: Duration, lifetime: Duration) -> f32 {
+    (age.as_secs_f32() / lifetime.as_secs_f32()).min(1.)
+}
+
+/// A short message at the bottom of the screen.
+pub struct Toast {
+    message: String,
+    left: Duration,
+}
+
+impl Toast {
+    const DURATION: Duration = Duration::from_secs(2);
+
+    pub fn new(message: String) -> Self {
+        Toast {
+            message,
+            left: Self::DURATION,
+        }
+    }
+}
+
+impl Effect for Toast {
+    fn update(&mut self, dt: Duration) -> bool {
+        self.left = self.left.saturating_sub(dt);
+        !self.left.is_zero()
+    }
+    fn draw(&self, r: &mut dyn Renderer) {
+        r.draw_text(&self.message, 16., [16., SCREEN_SIZE.1 - 32.], Color::WHITE);
+    }
+}
+
+/// Text that rises from a point and fades away, like the name of a clear beside the board.
+pub struct FloatingText {
+    text: String,
+    at: [f32; 2],
+    colour: Color,
+    age: Duration,
+}
+
+impl FloatingText {
+    const LIFETIME: Duration = Duration::from_millis(1200);
+    /// How far the text rises over its life, in pixels.
+    const RISE: f32 = 48.;
+
+    /// Text centred on the cell `(x, y)` of the board.
+    pub fn at_cell(text: String, (x, y): (f32, f32), colour: Color) -> Self {
+        let Rect { x, y, w, h } = cell_rect(x, y);
+        FloatingText {
+            text,
+            at: [x + w / 2., y + h / 2.],
+            colour,
+            age: Duration::ZERO,
+        }
+    }
+}
+
+impl Effect for FloatingText {
+    fn update(&mut self, dt: Duration) -> bool {
+        self.age += dt;
+        self.age < Self::LIFETIME
+    }
+    fn draw(&self, r: &mut dyn Renderer) {
+        let t = progress(self.age, Self::LIFETIME);
+        let colour = Color { a: self.colour.a * (1. - t), ..self.colour };
+        r.draw_text_centred(&self.text, 32., [self.at[0], self.at[1] - Self::RISE * t], colour);
+    }
+}
+
+/// The board shaking from side to side, dying down over `duration`.
+pub struct Shake {
+    strength: f32,
+    duration: Duration,
+    age: Duration,
+}
+
+impl Shake {
+    /// Shakes by up to `strength` pixels.
+    pub fn new(strength: f32, duration: Duration) -> Self {
+        Shake {
+            strength,
+            duration,
+            age: Duration::ZERO,
+        }
+    }
+}
+
+impl Effect for Shake {
+    fn update(&mut self, dt: Duration) -> bool {
+        self.age += dt;
+        self.age < self.duration
+    }
+    fn shake(&self) -> [f32; 2] {
+        let t = progress(self.age, self.duration);
+        let swing = (self.age.as_secs_f32() * 60.).sin();
+        [self.strength * (1. - t) * swing, 0.]
+    }
+}
+
+/// The rows that were just cleared lighting up where they were.
+pub struct RowFlash {
+    rows: Vec<usize>,
+    grid_size: (i8, i8),
+    age: Duration,
+}
+
+impl RowFlash {
+    const LIFETIME: Duration = Duration::from_millis(250);
+
+    /// Flashes `rows` of a board of `grid_size`, counting from the top.
+    pub fn new(rows: Vec<usize>, grid_size: (i8, i8)) -> Self {
+        RowFlash {
+            rows,
+            grid_size,
+            age: Duration::ZERO,
+        }
+    }
+}
+
+impl Effect for RowFlash {
+    fn update(&mut self, dt: Duration) -> bool {
+        self.age += dt;
+        self.age < Self::LIFETIME
+    }
+    fn draw(&self, r: &mut dyn Renderer) {
+        let alpha = 0.8 * (1. - progress(self.age, Self::LIFETIME));
+        let [dx, dy] = board_offset(self.grid_size);
+        for &row in &self.rows {
+            let rect = cell_rect(0., row as f32);
+            let rect = Rect::new(rect.x + dx, rect.y + dy, rect.w * self.grid_size.0 as f32, rect.h);
+            r.draw_panel(rect, Color::new(1., 1., 1., alpha));
+        }
+    }
+}
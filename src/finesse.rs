@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use ggez::{
+    event::{Button, GamepadId},
+    graphics::Color,
+    input::keyboard::{KeyCode, KeyMods},
+    Context, GameResult,
+};
+use log::{info, warn};
+use oorandom::Rand32;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::orientations,
+    app::{random_seed, GameState},
+    bitgrid::BitGrid,
+    grid::{Grid, Pos},
+    input::Action,
+    mode::Mode,
+    piece::{MovingPiece, Piece, Tetromino},
+    render::{self, Renderer, SCREEN_SIZE},
+    rules::Game,
+    scene::{Scene, Transition},
+    settings::MenuInput,
+    storage::Storage,
+    versioned::{self, Versioned},
+    versus::Controls,
+};
+
+const FINESSE_FILE: &str = "finesse.json";
+
+/// The cells a piece covers, sorted so that placements covering the same cells compare equal
+/// however the piece was turned to get there.
+type Cells = [(i8, i8); 4];
+
+fn cells(piece: &Piece, at: Pos) -> Cells {
+    let mut cells = [(0, 0); 4];
+    for (cell, pos) in cells.iter_mut().zip(piece.points(at)) {
+        *cell = (pos.x, pos.y);
+    }
+    cells.sort_unstable();
+    cells
+}
+
+/// Where `piece` comes to rest dropped straight down in column `x` of `grid` from the top.
+fn landing(grid: &BitGrid, piece: &Piece, x: i8) -> Option<Pos> {
+    let mut at = Pos::new(x, 0);
+    if !grid.fits(piece, at) {
+        return None;
+    }
+    while grid.fits(piece, Pos::new(x, at.y + 1)) {
+        at.y += 1;
+    }
+    Some(at)
+}
+
+/// Every different place a `kind` can be dropped on the empty board of `game`, coming in at column `spawn_x`,
+/// as the orientation it is dropped in and where it lands.
+pub fn placements(game: &Game, kind: Tetromino, spawn_x: i8) -> Vec<MovingPiece> {
+    let empty = BitGrid::with_size(game.grid.size());
+    let mut placements: Vec<MovingPiece> = Vec::new();
+    for (_, piece) in orientations(game, kind, spawn_x) {
+        for x in -2..game.grid.size().0 + 2 {
+            let Some(at) = landing(&empty, &piece, x) else {
+                continue;
+            };
+            if !placements.iter().any(|p| cells(&p.piece, p.pos) == cells(&piece, at)) {
+                placements.push(MovingPiece::new(piece, at));
+            }
+        }
+    }
+    placements
+}
+
+/// The fewest inputs that put a `kind` coming in at column `spawn_x` where `target` is on the empty board of `game`:
+/// taps sideways, holding sideways until the wall, and turns, each counting as one. This is what finesse is measured against.
+pub fn optimal_inputs(game: &Game, kind: Tetromino, spawn_x: i8, target: &MovingPiece) -> Option<u32> {
+    let empty = BitGrid::with_size(game.grid.size());
+    let turns = orientations(game, kind, spawn_x);
+    let goal = cells(&target.piece, target.pos);
+    // Kept a couple of rows down so that turning near the top doesn't matter
+    let fits = |turn: usize, x: i8| empty.fits(&turns[turn].1, Pos::new(x, 2));
+    let mut seen = vec![(0, spawn_x)];
+    let mut queue = VecDeque::from([(0, spawn_x, 0)]);
+    while let Some((turn, x, inputs)) = queue.pop_front() {
+        if landing(&empty, &turns[turn].1, x).is_some_and(|at| cells(&turns[turn].1, at) == goal) {
+            return Some(inputs);
+        }
+        let wall = |step: i8| {
+            let mut x = x;
+            while fits(turn, x + step) {
+                x += step;
+            }
+            x
+        };
+        let n = turns.len();
+        let next = [(turn, x - 1), (turn, x + 1), (turn, wall(-1)), (turn, wall(1)), ((turn + 1) % n, x), ((turn + n - 1) % n, x)];
+        for (turn, x) in next {
+            if fits(turn, x) && !seen.contains(&(turn, x)) {
+                seen.push((turn, x));
+                queue.push_back((turn, x, inputs + 1));
+            }
+        }
+    }
+    None
+}
+
+/// How one piece has done in the finesse trainer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PieceFinesse {
+    pub placed: u32,
+    /// How many were put where they were asked to go.
+    pub correct: u32,
+    /// How many were put there with the fewest inputs.
+    pub clean: u32,
+}
+
+impl PieceFinesse {
+    fn record(&mut self, correct: bool, clean: bool) {
+        self.placed += 1;
+        self.correct += correct as u32;
+        self.clean += clean as u32;
+    }
+    /// The share of placements that were right with the fewest inputs, in percent.
+    pub fn accuracy(&self) -> u32 {
+        (self.clean * 100).checked_div(self.placed).unwrap_or(0)
+    }
+}
+
+/// The finesse of each piece over every session in the trainer, by the piece's letter.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FinesseStats {
+    pub pieces: BTreeMap<String, PieceFinesse>,
+}
+
+impl Versioned for FinesseStats {
+    const VERSION: u32 = 1;
+}
+
+impl FinesseStats {
+    pub fn load(storage: &dyn Storage, dir: &str) -> GameResult<Self> {
+        Ok(versioned::load(storage, &format!("{dir}/{FINESSE_FILE}"))?.unwrap_or_default())
+    }
+    pub fn save(&self, storage: &dyn Storage, dir: &str) -> GameResult {
+        versioned::save(storage, &format!("{dir}/{FINESSE_FILE}"), self, true)
+    }
+}
+
+/// Drills finesse: each piece comes with a place to put it, outlined on an empty board, and after it is dropped
+/// the trainer says whether it went there with the fewest inputs. Pieces don't fall, and can't be held.
+pub struct FinesseScene {
+    board: Game,
+    controls: Controls,
+    rng: Rand32,
+    /// Where the current piece is to go, with the fewest inputs that get it there.
+    target: Option<(MovingPiece, u32)>,
+    /// The sideways moves and turns pressed since the last piece was dropped.
+    inputs: u32,
+    /// What was said about the last piece.
+    verdict: Option<(String, Color)>,
+    session: PieceFinesse,
+    stats: FinesseStats,
+}
+
+impl FinesseScene {
+    pub fn new(state: &GameState) -> Self {
+        let seed = random_seed();
+        info!("Starting the finesse trainer with seed {seed}");
+        let mut config = state.game_config.clone();
+        config.frames_per_row = Some(u8::MAX);
+        let mode = Mode::Practice;
+        let board = Game::with_config(mode, mode.rules(), seed, state.config.profile().handling, &config);
+        let stats = FinesseStats::load(&*state.storage, &state.data.dir).unwrap_or_else(|e| {
+            warn!("Could not load finesse statistics: {e}");
+            FinesseStats::default()
+        });
+        FinesseScene {
+            board,
+            controls: Controls::new(state.config.profile().bindings(mode)),
+            rng: Rand32::new(seed),
+            target: None,
+            inputs: 0,
+            verdict: None,
+            session: PieceFinesse::default(),
+            stats,
+        }
+    }
+    /// Says how `locked` went against the target, and keeps it in the statistics.
+    fn judge(&mut self, state: &GameState, locked: &MovingPiece) {
+        let Some((target, optimal)) = self.target.take() else {
+            return;
+        };
+        let correct = cells(&locked.piece, locked.pos) == cells(&target.piece, target.pos);
+        let clean = correct && self.inputs <= optimal;
+        self.verdict = Some(match (correct, clean) {
+            (false, _) => ("Not where it was meant to go".to_owned(), Color::RED),
+            (true, true) => ("Perfect finesse".to_owned(), Color::GREEN),
+            (true, false) => (format!("{} inputs, {optimal} would do", self.inputs), Color::YELLOW),
+        });
+        self.session.record(correct, clean);
+        self.stats.pieces.entry(format!("{:?}", locked.piece.kind)).or_default().record(correct, clean);
+        if let Err(e) = self.stats.save(&*state.storage, &state.data.dir) {
+            warn!("Could not save finesse statistics: {e}");
+        }
+    }
+}
+
+impl Scene for FinesseScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        let mut inputs = self.controls.take();
+        inputs.retain(|&(action, _)| action != Action::Hold);
+        let moves = inputs.iter().filter(|&&(action, pressed)| pressed && matches!(action, Action::Left | Action::Right | Action::RotLeft | Action::RotRight));
+        self.inputs += moves.count() as u32;
+        let result = self.board.tick(&inputs);
+        if let Some(locked) = &result.locked_piece {
+            self.judge(state, locked);
+            // Every piece starts on an empty board
+            self.board.grid = Grid::with_size(self.board.grid.size());
+            self.board.gameover = false;
+            self.inputs = 0;
+        }
+        if let (None, Some(piece)) = (&self.target, &self.board.cur_piece) {
+            let kind = piece.piece.kind;
+            let placements = placements(&self.board, kind, piece.pos.x);
+            if !placements.is_empty() {
+                let target = placements[self.rng.rand_range(0..placements.len() as u32) as usize].clone();
+                let optimal = optimal_inputs(&self.board, kind, piece.pos.x, &target).unwrap_or(0);
+                self.target = Some((target, optimal));
+            }
+        }
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        render::draw_game(r, &self.board, 0., &state.theme);
+        if let Some((target, _)) = &self.target {
+            render::draw_outline(r, &self.board, target, &state.theme);
+        }
+        r.draw_text("Finesse trainer", 32., [16., 16.], Color::WHITE);
+        r.draw_text("Drop each piece in the outline with as few inputs as you can", 16., [16., 60.], Color::WHITE);
+        if let Some((verdict, colour)) = &self.verdict {
+            r.draw_text(verdict, 24., [16., 96.], *colour);
+        }
+        let session = &self.session;
+        let summary = format!("This session: {} placed, {}% clean", session.placed, session.accuracy());
+        r.draw_text(&summary, 20., [16., 136.], Color::WHITE);
+        for (i, kind) in Tetromino::ALL.iter().enumerate() {
+            let name = format!("{kind:?}");
+            let line = match self.stats.pieces.get(&name) {
+                Some(piece) => format!("{name}: {}% of {}", piece.accuracy(), piece.placed),
+                None => format!("{name}: -"),
+            };
+            r.draw_text(&line, 16., [SCREEN_SIZE.0 - 136., 16. + 22. * i as f32], Color::WHITE);
+        }
+        r.draw_text("Back: leave", 16., [16., SCREEN_SIZE.1 - 24.], Color::WHITE);
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, repeated: bool) -> Transition {
+        if repeated {
+            return Transition::None;
+        }
+        match self.controls.key_down(keycode) {
+            Some(Action::Pause | Action::MenuBack) => Transition::Pop(1),
+            Some(_) => Transition::None,
+            None => match MenuInput::from_key(keycode, mods, &self.controls.bindings) {
+                Some(input) => self.menu_input(state, ctx, input),
+                None => Transition::None,
+            },
+        }
+    }
+    fn key_up(&mut self, _state: &mut GameState, keycode: KeyCode) {
+        self.controls.key_up(keycode);
+    }
+    fn menu_input(&mut self, _state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        match input {
+            MenuInput::Back => Transition::Pop(1),
+            _ => Transition::None,
+        }
+    }
+    fn gamepad_button_down(&mut self, state: &mut GameState, _ctx: &mut Context, btn: Button, _id: GamepadId) -> Transition {
+        match state.config.profile().buttons.action(btn) {
+            Some(Action::Pause) => return Transition::Pop(1),
+            Some(action) => self.controls.push(action, true),
+            None => (),
+        }
+        Transition::None
+    }
+    fn gamepad_button_up(&mut self, state: &mut GameState, btn: Button, _id: GamepadId) {
+        if let Some(action) = state.config.profile().buttons.action(btn).filter(|&a| a != Action::Pause) {
+            self.controls.push(action, false);
+        }
+    }
+}
@@ -0,0 +1,586 @@
+use oorandom::Rand32;
+
+use crate::{
+    grid::{Cell, Grid, Pos},
+    piece::{MovingPiece, Piece, Tetromino},
+    config::{GameConfig, Handling},
+    mode::Mode,
+    rules::{Game, TickResult},
+    ruleset::{Memoryless, Randomizer},
+};
+
+/// Where fumens can be looked at, with the fumen after the `?`.
+pub const VIEWER: &str = "https://fumen.zui.jp/?";
+/// The version of the format written, which starts every fumen.
+const VERSION: &str = "v115@";
+const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// How wide a fumen's field is, which only boards as wide can be written as.
+const WIDTH: usize = 10;
+/// How many rows of a fumen's field are above its bottom row of rising garbage.
+const HEIGHT: usize = 23;
+/// How many cells a fumen's field has, counting the row of rising garbage.
+const CELLS: usize = WIDTH * (HEIGHT + 1);
+
+/// The characters comments are written with, which are the printable ASCII ones.
+const COMMENT_CHARS: u32 = 96;
+
+/// A fumen's pieces, as it numbers them.
+fn piece_number(kind: Tetromino) -> u32 {
+    match kind {
+        Tetromino::I => 1,
+        Tetromino::L => 2,
+        Tetromino::O => 3,
+        Tetromino::Z => 4,
+        Tetromino::T => 5,
+        Tetromino::J => 6,
+        Tetromino::S => 7,
+    }
+}
+
+/// The piece a fumen numbers `n`, if it is one.
+fn piece_of(n: u32) -> Option<Tetromino> {
+    Tetromino::ALL.into_iter().find(|&kind| piece_number(kind) == n)
+}
+
+/// The cells of a piece around its centre, as `(x, y)` with y going up, facing the way it spawns.
+fn shape(kind: Tetromino) -> [(i32, i32); 4] {
+    match kind {
+        Tetromino::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        Tetromino::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        Tetromino::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        Tetromino::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        Tetromino::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        Tetromino::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        Tetromino::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    }
+}
+
+/// The ways a piece can face, as a fumen numbers them: spawn, right, reverse and left.
+const ROTATIONS: [u32; 4] = [2, 1, 0, 3];
+
+/// A cell of a shape turned to face the way `rotation` does.
+fn turn(rotation: u32, (x, y): (i32, i32)) -> (i32, i32) {
+    match rotation {
+        0 => (-x, -y),
+        1 => (y, -x),
+        3 => (-y, x),
+        _ => (x, y),
+    }
+}
+
+/// The field as a fumen lays it out: a cell number for each cell from the top left,
+/// with the board at the bottom of the rows above the rising garbage.
+fn field(grid: &Grid) -> Result<[u32; CELLS], String> {
+    let (width, height) = (grid.size().0 as usize, grid.size().1 as usize);
+    if width != WIDTH || height > HEIGHT {
+        return Err(format!("A fumen can only show a board {WIDTH} wide and up to {HEIGHT} high, not {width} by {height}"));
+    }
+    let mut field = [0; CELLS];
+    for (y, row) in grid.rows().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            field[(HEIGHT - height + y) * WIDTH + x] = match cell {
+                Cell::Empty => 0,
+                Cell::Filled(kind) => piece_number(kind),
+                Cell::Garbage => 8,
+            };
+        }
+    }
+    Ok(field)
+}
+
+/// Where `piece` is in a fumen's field, with which way it faces as a fumen has it,
+/// found from its cells so it doesn't matter how the rotation system turns it.
+fn locate(piece: &MovingPiece, board_height: usize) -> Result<(u32, usize), String> {
+    // With y going up from the bottom of the field
+    let mut cells: Vec<(i32, i32)> = piece
+        .piece
+        .points(piece.pos)
+        .map(|p| (p.x as i32, board_height as i32 - 1 - p.y as i32))
+        .collect();
+    cells.sort_unstable();
+    for rotation in ROTATIONS {
+        let shape = shape(piece.piece.kind).map(|cell| turn(rotation, cell));
+        // The centre is wherever it has to be for the shape's first cell to land on one of the piece's
+        for &(cx, cy) in &cells {
+            let (x, y) = (cx - shape[0].0, cy - shape[0].1);
+            let mut placed: Vec<_> = shape.iter().map(|&(dx, dy)| (x + dx, y + dy)).collect();
+            placed.sort_unstable();
+            if placed != cells {
+                continue;
+            }
+            // The centres a fumen uses for these differ from the ones the shapes are turned around
+            let (x, y) = match (piece.piece.kind, rotation) {
+                (Tetromino::O, 3) => (x - 1, y + 1),
+                (Tetromino::O, 0) | (Tetromino::I, 0) | (Tetromino::Z, 3) => (x - 1, y),
+                (Tetromino::O, 2) | (Tetromino::I, 3) | (Tetromino::S, 2) | (Tetromino::Z, 2) => (x, y + 1),
+                (Tetromino::S, 1) => (x + 1, y),
+                _ => (x, y),
+            };
+            if !(0..WIDTH as i32).contains(&x) || !(0..HEIGHT as i32).contains(&y) {
+                return Err("A piece is outside what a fumen can show".to_owned());
+            }
+            return Ok((rotation, (HEIGHT - 1 - y as usize) * WIDTH + x as usize));
+        }
+    }
+    Err("A piece has a shape a fumen doesn't know".to_owned())
+}
+
+/// `field` with `piece` locked into it and the full rows cleared, as a fumen goes from one page to the next.
+fn lock(mut field: [u32; CELLS], piece: Option<&MovingPiece>, board_height: usize) -> [u32; CELLS] {
+    for p in piece.into_iter().flat_map(|piece| piece.piece.points(piece.pos)) {
+        let row = HEIGHT as i32 - board_height as i32 + p.y as i32;
+        if (0..HEIGHT as i32).contains(&row) && (0..WIDTH as i32).contains(&(p.x as i32)) {
+            field[row as usize * WIDTH + p.x as usize] = piece.map_or(0, |piece| piece_number(piece.piece.kind));
+        }
+    }
+    after_lock(field, false, false)
+}
+
+/// `field` with the full rows cleared, and then pushed up by the row of rising garbage if `rise`
+/// and flipped if `mirror`, as a fumen does once a piece locks.
+fn after_lock(field: [u32; CELLS], rise: bool, mirror: bool) -> [u32; CELLS] {
+    let kept: Vec<&[u32]> = field[..HEIGHT * WIDTH].chunks(WIDTH).filter(|row| row.contains(&0)).collect();
+    let mut cleared = [0; CELLS];
+    let top = HEIGHT - kept.len();
+    for (i, row) in kept.iter().enumerate() {
+        cleared[(top + i) * WIDTH..(top + i + 1) * WIDTH].copy_from_slice(row);
+    }
+    if rise {
+        cleared.copy_within(WIDTH.., 0);
+        cleared[HEIGHT * WIDTH..].fill(0);
+    } else {
+        cleared[HEIGHT * WIDTH..].copy_from_slice(&field[HEIGHT * WIDTH..]);
+    }
+    if mirror {
+        for row in cleared[..HEIGHT * WIDTH].chunks_mut(WIDTH) {
+            row.reverse();
+        }
+    }
+    cleared
+}
+
+/// `s` escaped as JavaScript's `escape` does, which is how a fumen keeps its comments.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '@' | '*' | '_' | '+' | '-' | '.' | '/' => escaped.push(c),
+            c if (c as u32) < 0x100 => escaped += &format!("%{:02X}", c as u32),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped += &format!("%u{unit:04X}");
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// `s` with what `escape` escaped put back.
+fn unescape(s: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        let code = |hex: Option<&str>| hex.and_then(|hex| u16::from_str_radix(hex, 16).ok());
+        if let Some(unit) = rest.strip_prefix("%u").and_then(|after| code(after.get(..4))) {
+            units.push(unit);
+            rest = &rest[6..];
+        } else if let Some(unit) = rest.strip_prefix('%').and_then(|after| code(after.get(..2))) {
+            units.push(unit);
+            rest = &rest[3..];
+        } else {
+            units.extend(c.encode_utf16(&mut [0; 2]).iter());
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// A fumen's numbers, each written as base 64 digits, lowest first.
+#[derive(Default)]
+struct Writer {
+    digits: Vec<u32>,
+}
+
+impl Writer {
+    fn push(&mut self, mut value: u32, digits: usize) {
+        for _ in 0..digits {
+            self.digits.push(value % 64);
+            value /= 64;
+        }
+    }
+}
+
+/// Takes a fumen's numbers off the front of its text.
+struct Reader<'a>(std::slice::Iter<'a, u8>);
+
+impl Reader<'_> {
+    fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+    fn take(&mut self, digits: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..digits {
+            let &c = self.0.next().ok_or("The fumen ends too soon")?;
+            let digit = DIGITS.iter().position(|&d| d == c).ok_or_else(|| format!("A fumen has no {}", c as char))?;
+            value += digit as u32 * 64u32.pow(i);
+        }
+        Ok(value)
+    }
+}
+
+/// A board with the piece placed on it, one page of a fumen.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub grid: Grid,
+    pub piece: Option<MovingPiece>,
+    /// What is written under the board.
+    pub comment: Option<String>,
+}
+
+/// Writes `pages` as a fumen, the text boards are shared as, which `VIEWER` shows.
+pub fn encode(pages: &[Page]) -> Result<String, String> {
+    let mut out = Writer::default();
+    // Each page's field is written as how it differs from the last one's once its piece is locked
+    let mut previous = [0; CELLS];
+    // Where the count of pages in a row the same as the one before them is, to add to it
+    let mut repeats: Option<usize> = None;
+    let mut last_comment = None;
+    for (i, page) in pages.iter().enumerate() {
+        let field = field(&page.grid)?;
+        let mut runs = Vec::new();
+        for (&now, &before) in field.iter().zip(&previous) {
+            let change = now + 8 - before;
+            match runs.last_mut() {
+                Some((last, count)) if *last == change => *count += 1,
+                _ => runs.push((change, 1)),
+            }
+        }
+        let unchanged = runs == [(8, CELLS as u32)];
+        match repeats {
+            Some(at) if unchanged && out.digits[at] < 63 => out.digits[at] += 1,
+            _ => {
+                for (change, count) in runs {
+                    out.push(change * CELLS as u32 + count - 1, 2);
+                }
+                repeats = unchanged.then(|| {
+                    out.push(0, 1);
+                    out.digits.len() - 1
+                });
+            }
+        }
+
+        let height = page.grid.size().1 as usize;
+        let (kind, rotation, at) = match &page.piece {
+            Some(piece) => {
+                let (rotation, at) = locate(piece, height)?;
+                (piece_number(piece.piece.kind), rotation, at)
+            }
+            None => (0, 0, 0),
+        };
+        let comment = page.comment.as_deref().filter(|&comment| Some(comment) != last_comment);
+        // Whether the piece doesn't lock, has a comment, is in the guideline colours, is mirrored and raises the garbage
+        let flags = [false, comment.is_some(), i == 0, false, false];
+        let flags = flags.iter().fold(0, |value, &flag| value * 2 + flag as u32);
+        out.push(((flags * CELLS as u32 + at as u32) * 4 + rotation) * 8 + kind, 3);
+        if let Some(comment) = comment {
+            let escaped: Vec<u32> = escape(comment).bytes().take(4095).map(|b| (b - b' ') as u32).collect();
+            out.push(escaped.len() as u32, 2);
+            for chunk in escaped.chunks(4) {
+                out.push(chunk.iter().rev().fold(0, |value, &c| value * COMMENT_CHARS + c), 5);
+            }
+            last_comment = page.comment.as_deref();
+        }
+
+        previous = lock(field, page.piece.as_ref(), height);
+    }
+    let text: String = out.digits.iter().map(|&digit| DIGITS[digit as usize] as char).collect();
+    // Viewers skip the `?`s, which break it up for places that wrap long words
+    let mut fumen = VERSION.to_owned() + &text[..text.len().min(42)];
+    for chunk in text.as_bytes().get(42..).unwrap_or_default().chunks(47) {
+        fumen.push('?');
+        fumen += std::str::from_utf8(chunk).expect("Fumens are ASCII");
+    }
+    Ok(fumen)
+}
+
+/// Keeps the pieces placed in a game as they lock, with the board each went onto, to share as a fumen.
+#[derive(Debug, Clone)]
+pub struct Placements {
+    pages: Vec<Page>,
+    /// The board at the start of the tick, for the page of a piece locked during it.
+    board: Grid,
+}
+
+impl Placements {
+    pub fn new(game: &Game) -> Self {
+        Placements { pages: Vec::new(), board: game.grid.clone() }
+    }
+    /// Notes the piece locked in the tick `game` has just been through, if one was.
+    pub fn record(&mut self, game: &Game, result: &TickResult) {
+        if let Some(piece) = &result.locked_piece {
+            self.pages.push(Page { grid: self.board.clone(), piece: Some(piece.clone()), comment: None });
+        }
+        self.board = game.grid.clone();
+    }
+    /// The game so far as a fumen: a page for each piece placed, and the board as it is now
+    /// with the falling piece where it is and the pieces to come written under it.
+    pub fn encode(&self, game: &Game) -> Result<String, String> {
+        let name = |kind: Tetromino| format!("{kind:?}");
+        let mut queue = game.cur_piece.iter().map(|piece| name(piece.piece.kind)).collect::<Vec<_>>();
+        queue.push(name(game.next_piece.kind));
+        let hold = game.hold_piece.as_ref().map_or("none".to_owned(), |piece| name(piece.kind));
+        let now = Page {
+            grid: game.grid.clone(),
+            piece: game.cur_piece.clone(),
+            comment: Some(format!("{}: pieces {}, hold {hold}", game.mode, queue.join(" "))),
+        };
+        encode(&[&self.pages[..], &[now]].concat())
+    }
+}
+
+/// A page of a fumen as it is read, with the field before its piece.
+struct DecodedPage {
+    field: [u32; CELLS],
+    /// The piece placed on the page, with its cells as `(x, y)` with y going up from the bottom of the field.
+    piece: Option<(Tetromino, Vec<(i32, i32)>)>,
+    /// The comment of the page, or the last one before it.
+    comment: String,
+}
+
+/// Reads the pages of the fumen in `text`, which may be a link to it.
+fn decode(text: &str) -> Result<Vec<DecodedPage>, String> {
+    let start = text
+        .match_indices("115@")
+        .find(|&(i, _)| i > 0 && matches!(text.as_bytes()[i - 1], b'v' | b'm' | b'd'))
+        .map(|(i, _)| i + 4)
+        .ok_or("That isn't a fumen of the version that can be read, v115")?;
+    let data: Vec<u8> = text[start..].bytes().filter(|&c| c != b'?' && !c.is_ascii_whitespace()).collect();
+    let mut reader = Reader(data.iter());
+    let mut pages = Vec::new();
+    let mut previous = [0; CELLS];
+    let mut repeats = 0;
+    let mut comment = String::new();
+    while !reader.is_empty() {
+        let mut field = previous;
+        if repeats > 0 {
+            repeats -= 1;
+        } else {
+            let mut i = 0;
+            let mut unchanged = false;
+            while i < CELLS {
+                let run = reader.take(2)?;
+                let (change, count) = (run / CELLS as u32, run % CELLS as u32 + 1);
+                unchanged = change == 8 && count == CELLS as u32;
+                for cell in field.iter_mut().skip(i).take(count as usize) {
+                    *cell = (*cell + change).checked_sub(8).filter(|&n| n <= 8).ok_or("The fumen has a cell that isn't one")?;
+                }
+                i += count as usize;
+            }
+            if unchanged {
+                repeats = reader.take(1)?;
+            }
+        }
+
+        let mut action = reader.take(3)?;
+        let mut next = |n: u32| {
+            let part = action % n;
+            action /= n;
+            part
+        };
+        let (kind, rotation, at) = (next(8), next(4), next(CELLS as u32));
+        let [rise, mirror, _colour, has_comment, no_lock] = [(); 5].map(|_| next(2) == 1);
+        let piece = match piece_of(kind) {
+            Some(kind) => {
+                let (x, y) = ((at as usize % WIDTH) as i32, HEIGHT as i32 - 1 - (at as usize / WIDTH) as i32);
+                // The other way round from `locate`
+                let (x, y) = match (kind, rotation) {
+                    (Tetromino::O, 3) => (x + 1, y - 1),
+                    (Tetromino::O, 0) | (Tetromino::I, 0) | (Tetromino::Z, 3) => (x + 1, y),
+                    (Tetromino::O, 2) | (Tetromino::I, 3) | (Tetromino::S, 2) | (Tetromino::Z, 2) => (x, y - 1),
+                    (Tetromino::S, 1) => (x - 1, y),
+                    _ => (x, y),
+                };
+                Some((kind, shape(kind).map(|cell| turn(rotation, cell)).iter().map(|&(dx, dy)| (x + dx, y + dy)).collect()))
+            }
+            None => None,
+        };
+        if has_comment {
+            let len = reader.take(2)?;
+            let mut escaped = String::new();
+            for _ in 0..len.div_ceil(4) {
+                let mut chunk = reader.take(5)?;
+                for _ in 0..4 {
+                    escaped.push(char::from(b' ' + (chunk % COMMENT_CHARS) as u8));
+                    chunk /= COMMENT_CHARS;
+                }
+            }
+            escaped.truncate(len as usize);
+            comment = unescape(&escaped);
+        }
+
+        previous = field;
+        if !no_lock {
+            if let Some((kind, cells)) = &piece {
+                for &(x, y) in cells {
+                    if (0..WIDTH as i32).contains(&x) && (0..HEIGHT as i32).contains(&y) {
+                        previous[(HEIGHT - 1 - y as usize) * WIDTH + x as usize] = piece_number(*kind);
+                    }
+                }
+            }
+            previous = after_lock(previous, rise, mirror);
+        }
+        pages.push(DecodedPage { field, piece, comment: comment.clone() });
+    }
+    Ok(pages)
+}
+
+/// A board and the pieces to build on it with, read from a fumen, to drill in Practice.
+#[derive(Debug, Clone)]
+pub struct Setup {
+    /// The board of the first page.
+    pub grid: Grid,
+    /// The pieces dealt, in order, after which they are random.
+    pub queue: Vec<Tetromino>,
+    pub hold: Option<Tetromino>,
+}
+
+impl Setup {
+    /// The first page of the fumen in `text` on a board `size` big, with the pieces placed on the pages as the queue,
+    /// or the ones of its quiz if it is one: a comment like `#Q=[I](T)SZO` for the held piece, the current one and the rest.
+    pub fn from_fumen(text: &str, size: (i8, i8)) -> Result<Self, String> {
+        let pages = decode(text.trim())?;
+        let first = pages.first().ok_or("The fumen has no pages")?;
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        if width != WIDTH || height > HEIGHT {
+            return Err(format!("A fumen's board can't be played on one {width} by {height}"));
+        }
+        let top = HEIGHT - height;
+        if first.field[..top * WIDTH].iter().any(|&n| n != 0) {
+            return Err(format!("The fumen's board is higher than {height} rows"));
+        }
+        let mut grid = Grid::with_size(size);
+        for (i, &n) in first.field[top * WIDTH..HEIGHT * WIDTH].iter().enumerate() {
+            let cell = match n {
+                0 => continue,
+                8 => Cell::Garbage,
+                n => piece_of(n).map_or(Cell::Garbage, Cell::Filled),
+            };
+            grid.set(Pos::new((i % WIDTH) as i8, (i / WIDTH) as i8), cell);
+        }
+        let letters = |s: &str| -> Vec<Tetromino> { s.chars().filter_map(|c| Tetromino::ALL.into_iter().find(|kind| format!("{kind:?}").starts_with(c))).collect() };
+        let quiz = first.comment.strip_prefix("#Q=").and_then(|quiz| {
+            let (hold, rest) = quiz.strip_prefix('[')?.split_once(']')?;
+            let (current, next) = rest.strip_prefix('(')?.split_once(')')?;
+            Some((letters(hold).first().copied(), letters(current).into_iter().chain(letters(next)).collect()))
+        });
+        let (hold, queue) = quiz.unwrap_or_else(|| (None, pages.iter().filter_map(|page| page.piece.as_ref().map(|&(kind, _)| kind)).collect()));
+        Ok(Setup { grid, queue, hold })
+    }
+    /// A Practice game starting from the setup, with its pieces dealt first.
+    pub fn game(&self, seed: u64, handling: Handling, config: &GameConfig) -> Game {
+        let mut game = Game::with_config(Mode::Practice, Mode::Practice.rules(), seed, handling, config);
+        game.set_randomizer(Box::new(Queue { pieces: self.queue.clone(), dealt: 0 }));
+        game.grid = self.grid.clone();
+        game.hold_piece = self.hold.map(Piece::new);
+        game
+    }
+}
+
+/// Deals the pieces of a setup in order, and random ones once they have all been dealt.
+#[derive(Debug, Clone)]
+struct Queue {
+    pieces: Vec<Tetromino>,
+    dealt: usize,
+}
+
+impl Randomizer for Queue {
+    fn next(&mut self, rng: &mut Rand32) -> Tetromino {
+        let piece = self.pieces.get(self.dealt).copied();
+        self.dealt += 1;
+        piece.unwrap_or_else(|| Memoryless.next(rng))
+    }
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::Rotation;
+
+    /// A piece of `kind` turned right `turns` times, with its spawn block at `(x, y)`.
+    fn placed(kind: Tetromino, turns: usize, x: i8, y: i8) -> MovingPiece {
+        let mut piece = Piece::new(kind);
+        for _ in 0..turns {
+            piece.rotate(Rotation::Right, (0, 0));
+        }
+        MovingPiece::new(piece, Pos::new(x, y))
+    }
+
+    fn rows(grid: &Grid) -> Vec<Vec<Cell>> {
+        grid.rows().map(<[Cell]>::to_vec).collect()
+    }
+
+    #[test]
+    fn an_empty_board_is_the_usual_empty_fumen() {
+        let page = Page { grid: Grid::with_size((10, 20)), piece: None, comment: None };
+        assert_eq!(encode(&[page]).unwrap(), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn pieces_are_read_back_where_they_were_placed() {
+        for kind in Tetromino::ALL {
+            for turns in 0..4 {
+                let piece = placed(kind, turns, 4, 10);
+                let page = Page { grid: Grid::with_size((10, 20)), piece: Some(piece.clone()), comment: None };
+                let pages = decode(&encode(&[page]).unwrap()).unwrap();
+                let (read, mut cells) = pages[0].piece.clone().unwrap();
+                let mut expected: Vec<_> = piece.piece.points(piece.pos).map(|p| (p.x as i32, 19 - p.y as i32)).collect();
+                cells.sort_unstable();
+                expected.sort_unstable();
+                assert_eq!((read, cells), (kind, expected), "{kind:?} turned {turns} times");
+            }
+        }
+    }
+
+    #[test]
+    fn boards_and_comments_are_read_back() {
+        let mut grid = Grid::with_size((10, 20));
+        for x in 1..10 {
+            grid.set(Pos::new(x, 19), Cell::Garbage);
+            grid.set(Pos::new(x - 1, 18), Cell::Filled(Tetromino::S));
+        }
+        let pages = [
+            Page { grid: grid.clone(), piece: Some(placed(Tetromino::T, 2, 4, 5)), comment: Some("Opener: 50% ✓".to_owned()) },
+            Page { grid: grid.clone(), piece: Some(placed(Tetromino::I, 1, 0, 10)), comment: Some("Opener: 50% ✓".to_owned()) },
+            Page { grid, piece: None, comment: Some("Done".to_owned()) },
+        ];
+        let fumen = encode(&pages).unwrap();
+        let read = decode(&format!("{VIEWER}{fumen}")).unwrap();
+        let comments: Vec<_> = read.iter().map(|page| page.comment.as_str()).collect();
+        assert_eq!(comments, ["Opener: 50% ✓", "Opener: 50% ✓", "Done"]);
+        let setup = Setup::from_fumen(&fumen, (10, 20)).unwrap();
+        assert_eq!(rows(&setup.grid), rows(&pages[0].grid));
+        assert_eq!((setup.queue, setup.hold), (vec![Tetromino::T, Tetromino::I], None));
+    }
+
+    #[test]
+    fn quizzes_deal_their_own_pieces() {
+        let page = Page { grid: Grid::with_size((10, 20)), piece: None, comment: Some("#Q=[I](T)SZO".to_owned()) };
+        let setup = Setup::from_fumen(&encode(&[page]).unwrap(), (10, 20)).unwrap();
+        assert_eq!(setup.hold, Some(Tetromino::I));
+        assert_eq!(setup.queue, [Tetromino::T, Tetromino::S, Tetromino::Z, Tetromino::O]);
+    }
+
+    #[test]
+    fn what_a_fumen_cant_hold_is_refused() {
+        let wide = Page { grid: Grid::with_size((12, 20)), piece: None, comment: None };
+        assert!(encode(&[wide]).is_err());
+        assert!(Setup::from_fumen("not a fumen", (10, 20)).is_err());
+        assert!(Setup::from_fumen("v115@vhA", (10, 20)).is_err());
+        assert!(Setup::from_fumen("v115@vhAAgH", (12, 20)).is_err());
+    }
+}
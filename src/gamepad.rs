@@ -0,0 +1,149 @@
+use ggez::event::Button;
+use serde::{Deserialize, Serialize};
+
+use crate::{input::Action, settings::MenuInput};
+
+/// How the left stick moves pieces.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StickSettings {
+    /// How far the stick has to be pushed (from 0 to 1) before it does anything.
+    pub deadzone: f32,
+    /// Time between shifts with the stick just past the deadzone (ms).
+    pub slowest_repeat: u32,
+    /// Time between shifts with the stick pushed all the way (ms).
+    pub fastest_repeat: u32,
+    /// How the repeat rate ramps up between the two: 1 is linear,
+    /// higher values keep it slow until the stick is pushed further.
+    pub curve: f32,
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        StickSettings {
+            deadzone: 0.25,
+            slowest_repeat: 250,
+            fastest_repeat: 33,
+            curve: 2.,
+        }
+    }
+}
+
+impl StickSettings {
+    fn repeat_ms(&self, deflection: f32) -> u32 {
+        let t = ((deflection - self.deadzone) / (1. - self.deadzone)).clamp(0., 1.).powf(self.curve);
+        let (slow, fast) = (self.slowest_repeat as f32, self.fastest_repeat as f32);
+        (slow + (fast - slow) * t) as u32
+    }
+}
+
+/// Which gamepad buttons trigger which action, like `Keybindings` for the keyboard.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ButtonBindings {
+    pub left: Vec<Button>,
+    pub right: Vec<Button>,
+    pub rotate_left: Vec<Button>,
+    pub rotate_right: Vec<Button>,
+    pub soft_drop: Vec<Button>,
+    pub hard_drop: Vec<Button>,
+    pub hold: Vec<Button>,
+    pub restart: Vec<Button>,
+    pub pause: Vec<Button>,
+    pub menu_confirm: Vec<Button>,
+    pub menu_back: Vec<Button>,
+    pub undo: Vec<Button>,
+    pub redo: Vec<Button>,
+}
+
+impl Default for ButtonBindings {
+    fn default() -> Self {
+        ButtonBindings {
+            left: vec![Button::DPadLeft],
+            right: vec![Button::DPadRight],
+            rotate_left: vec![Button::East],
+            rotate_right: vec![Button::South],
+            soft_drop: vec![Button::DPadDown],
+            hard_drop: vec![Button::DPadUp],
+            hold: vec![Button::LeftTrigger, Button::RightTrigger],
+            restart: Vec::new(),
+            pause: vec![Button::Start],
+            menu_confirm: vec![Button::South],
+            menu_back: vec![Button::East],
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+}
+
+impl ButtonBindings {
+    pub fn action(&self, button: Button) -> Option<Action> {
+        [
+            (&self.left, Action::Left),
+            (&self.right, Action::Right),
+            (&self.rotate_left, Action::RotLeft),
+            (&self.rotate_right, Action::RotRight),
+            (&self.soft_drop, Action::SoftDrop),
+            (&self.hard_drop, Action::HardDrop),
+            (&self.hold, Action::Hold),
+            (&self.restart, Action::Restart),
+            (&self.pause, Action::Pause),
+            (&self.menu_confirm, Action::MenuConfirm),
+            (&self.menu_back, Action::MenuBack),
+            (&self.undo, Action::Undo),
+            (&self.redo, Action::Redo),
+        ]
+        .into_iter()
+        .find_map(|(buttons, action)| buttons.contains(&button).then_some(action))
+    }
+    /// Moving around menus is always on the D-pad, like it is always on the arrow keys.
+    pub fn menu_input(&self, button: Button) -> Option<MenuInput> {
+        Some(if self.menu_back.contains(&button) || self.pause.contains(&button) {
+            MenuInput::Back
+        } else if self.menu_confirm.contains(&button) {
+            MenuInput::Confirm
+        } else {
+            match button {
+                Button::DPadUp => MenuInput::Up,
+                Button::DPadDown => MenuInput::Down,
+                Button::DPadLeft => MenuInput::Adjust(-1),
+                Button::DPadRight => MenuInput::Adjust(1),
+                Button::North => MenuInput::NewProfile,
+                _ => return None,
+            }
+        })
+    }
+}
+
+/// Turns the horizontal position of the left stick into shifts,
+/// repeating faster the further it is pushed.
+#[derive(Debug, Default)]
+pub struct Stick {
+    x: f32,
+    /// Time since the last shift, or `None` if the stick is within the deadzone.
+    since_shift_ms: Option<u32>,
+}
+
+impl Stick {
+    pub fn set_x(&mut self, x: f32) {
+        self.x = x;
+    }
+    /// Advances the stick's repeat timer, returning a direction when it is time to shift.
+    pub fn tick(&mut self, settings: &StickSettings, ms: u32) -> Option<Action> {
+        let deflection = self.x.abs();
+        if deflection <= settings.deadzone {
+            self.since_shift_ms = None;
+            return None;
+        }
+        let since_shift_ms = match self.since_shift_ms {
+            Some(t) if t + ms < settings.repeat_ms(deflection) => {
+                self.since_shift_ms = Some(t + ms);
+                return None;
+            }
+            // Pushing the stick out of the deadzone shifts straight away
+            Some(_) | None => 0,
+        };
+        self.since_shift_ms = Some(since_shift_ms);
+        Some(if self.x < 0. { Action::Left } else { Action::Right })
+    }
+}
@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{bitgrid::BitGrid, piece::Tetromino};
+
+pub const GAME_GRID_WIDTH: usize = 10;
+pub const GAME_GRID_HEIGHT: usize = 20;
+pub const GAME_GRID_SIZE: (i8, i8) = (GAME_GRID_WIDTH as i8, GAME_GRID_HEIGHT as i8);
+/// The widest a grid can be, as a row of `BitGrid` is a `u16`.
+pub const MAX_GRID_WIDTH: usize = 16;
+pub const MAX_GRID_HEIGHT: usize = 40;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Pos {
+    pub x: i8,
+    pub y: i8,
+}
+
+impl Pos {
+    pub const fn new(x: i8, y: i8) -> Self {
+        Pos { x, y }
+    }
+}
+
+/// And here we implement `From` again to allow us to easily convert between
+/// `(i8, i8)` and a `Pos`.
+impl From<(i8, i8)> for Pos {
+    fn from(pos: (i8, i8)) -> Self {
+        Pos { x: pos.0, y: pos.1 }
+    }
+}
+
+/// What is in a cell of the grid.
+/// Saved as a byte, the colour of the piece or 255 when empty, as grids were before there were cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
+pub enum Cell {
+    #[default]
+    Empty,
+    /// A block left by a piece.
+    Filled(Tetromino),
+    /// A block that didn't come from a piece.
+    Garbage,
+}
+
+impl Cell {
+    pub fn is_empty(self) -> bool {
+        self == Cell::Empty
+    }
+}
+
+impl From<u8> for Cell {
+    fn from(c: u8) -> Self {
+        match c {
+            254 => Cell::Garbage,
+            c => Tetromino::try_from(c).map_or(Cell::Empty, Cell::Filled),
+        }
+    }
+}
+
+impl From<Cell> for u8 {
+    fn from(cell: Cell) -> Self {
+        match cell {
+            Cell::Empty => 255,
+            Cell::Filled(t) => t.into(),
+            Cell::Garbage => 254,
+        }
+    }
+}
+
+/// The playing field, row 0 at the top, `GAME_GRID_SIZE` unless made otherwise.
+/// What is in each cell is kept for drawing, and which cells are filled is kept as a `BitGrid` for checking.
+/// Both are kept at the largest size a grid can be, so a grid is cheap to copy whatever its size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SavedGrid", into = "SavedGrid")]
+pub struct Grid {
+    grid: [[Cell; MAX_GRID_WIDTH]; MAX_GRID_HEIGHT],
+    bits: BitGrid,
+}
+
+/// How a grid is saved, only the cells as the rest follows from them.
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedGrid {
+    grid: Vec<Vec<Cell>>,
+}
+
+impl TryFrom<SavedGrid> for Grid {
+    type Error = String;
+
+    fn try_from(SavedGrid { grid: rows }: SavedGrid) -> Result<Self, Self::Error> {
+        let width = rows.first().map_or(0, Vec::len);
+        if !(1..=MAX_GRID_WIDTH).contains(&width) || !(1..=MAX_GRID_HEIGHT).contains(&rows.len()) {
+            return Err(format!("a grid can't be {width} by {} cells", rows.len()));
+        }
+        if rows.iter().any(|row| row.len() != width) {
+            return Err("the rows of the grid aren't all as wide".to_owned());
+        }
+        let mut grid = Grid::with_size((width as i8, rows.len() as i8));
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, c) in row.into_iter().enumerate() {
+                grid.set(Pos::new(x as i8, y as i8), c);
+            }
+        }
+        Ok(grid)
+    }
+}
+
+impl From<Grid> for SavedGrid {
+    fn from(grid: Grid) -> Self {
+        SavedGrid {
+            grid: grid.rows().map(<[Cell]>::to_vec).collect(),
+        }
+    }
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid::new()
+    }
+}
+
+impl Grid {
+    pub const fn new() -> Self {
+        Grid::with_size(GAME_GRID_SIZE)
+    }
+    /// An empty grid `width` by `height` cells, at most `MAX_GRID_WIDTH` by `MAX_GRID_HEIGHT`.
+    pub const fn with_size(size: (i8, i8)) -> Self {
+        Grid {
+            grid: [[Cell::Empty; MAX_GRID_WIDTH]; MAX_GRID_HEIGHT],
+            bits: BitGrid::with_size(size),
+        }
+    }
+
+    /// How many cells across and down the grid is.
+    pub fn size(&self) -> (i8, i8) {
+        self.bits.size()
+    }
+    /// The rows from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        let (width, height) = self.size();
+        self.grid[..height as usize].iter().map(move |row| &row[..width as usize])
+    }
+    /// Which cells are filled.
+    pub fn bits(&self) -> &BitGrid {
+        &self.bits
+    }
+    /// How high each column is stacked, counting from the bottom up to its highest block.
+    pub fn column_heights(&self) -> Vec<usize> {
+        self.bits.column_heights()
+    }
+    /// How many empty cells have a block somewhere above them.
+    pub fn count_holes(&self) -> usize {
+        self.bits.count_holes()
+    }
+    /// Removes every full row, moving the rows above down to fill the gaps,
+    /// and returns where the removed rows were, from the top.
+    pub fn clear_full_rows(&mut self) -> Vec<usize> {
+        let full: Vec<usize> = self.bits.full_rows().collect();
+        if full.is_empty() {
+            return full;
+        }
+        let mut to = self.size().1 as usize;
+        for from in (0..to).rev() {
+            if !full.contains(&from) {
+                to -= 1;
+                self.grid[to] = self.grid[from];
+            }
+        }
+        for row in &mut self.grid[..to] {
+            *row = [Cell::Empty; MAX_GRID_WIDTH];
+        }
+        self.bits.clear_full_rows();
+        full
+    }
+    /// Pushes everything up to make room for `rows` at the bottom, e.g. garbage sent by an opponent.
+    /// Only as much of each row as the grid is wide is used.
+    /// Returns `false` if blocks were pushed off the top.
+    pub fn insert_rows_at_bottom(&mut self, rows: &[[Cell; MAX_GRID_WIDTH]]) -> bool {
+        let (width, height) = (self.size().0 as usize, self.size().1 as usize);
+        let n = rows.len().min(height);
+        self.grid[..height].rotate_left(n);
+        for (to, row) in self.grid[height - n..height].iter_mut().zip(&rows[rows.len() - n..]) {
+            to[..width].copy_from_slice(&row[..width]);
+        }
+        let masks: Vec<u16> = rows
+            .iter()
+            .map(|row| row[..width].iter().enumerate().filter(|(_, c)| !c.is_empty()).fold(0, |mask, (x, _)| mask | 1 << x))
+            .collect();
+        self.bits.insert_rows_at_bottom(&masks)
+    }
+    pub fn is_free_or_above(&self, pos: Pos) -> bool {
+        self.bits.is_free_or_above(pos)
+    }
+    /// Puts `c` at `pos`, returning `false` if `pos` is outside the grid.
+    pub fn set(&mut self, pos: Pos, c: Cell) -> bool {
+        let (width, height) = self.size();
+        if pos.x >= width || pos.y >= height {
+            return false;
+        }
+        if let Some(g) = self.grid
+            .get_mut(pos.y as usize)
+            .and_then(|row| row.get_mut(pos.x as usize)) {
+                *g = c;
+                self.bits.set(pos, !c.is_empty())
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4 by 4 grid with `#` in `rows` filled, from the top.
+    fn grid(rows: [&str; 4]) -> Grid {
+        let mut grid = Grid::with_size((4, 4));
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    grid.set(Pos::new(x as i8, y as i8), Cell::Garbage);
+                }
+            }
+        }
+        grid
+    }
+
+    fn drawn(grid: &Grid) -> Vec<String> {
+        grid.rows().map(|row| row.iter().map(|c| if c.is_empty() { '.' } else { '#' }).collect()).collect()
+    }
+
+    #[test]
+    fn rows_are_as_big_as_the_grid() {
+        let grid = grid(["#...", "....", "....", "...#"]);
+        assert_eq!(grid.rows().count(), 4);
+        assert!(grid.rows().all(|row| row.len() == 4));
+        assert_eq!(drawn(&grid), ["#...", "....", "....", "...#"]);
+    }
+
+    #[test]
+    fn column_heights_of_empty_and_full_grids() {
+        assert_eq!(grid(["....", "....", "....", "...."]).column_heights(), [0, 0, 0, 0]);
+        assert_eq!(grid(["####", "####", "####", "####"]).column_heights(), [4, 4, 4, 4]);
+        assert_eq!(grid(["....", ".#..", ".#..", "#..."]).column_heights(), [1, 3, 0, 0]);
+    }
+
+    #[test]
+    fn holes_under_overhangs() {
+        assert_eq!(grid(["....", "....", "....", "#.##"]).count_holes(), 0);
+        assert_eq!(grid(["....", "###.", "....", "#..#"]).count_holes(), 5);
+    }
+
+    #[test]
+    fn clearing_rows_that_are_not_next_to_each_other() {
+        let mut grid = grid(["#...", "####", ".#..", "####"]);
+        assert_eq!(grid.clear_full_rows(), [1, 3]);
+        assert_eq!(drawn(&grid), ["....", "....", "#...", ".#.."]);
+        assert_eq!(grid.column_heights(), [2, 1, 0, 0]);
+        assert!(grid.clear_full_rows().is_empty());
+    }
+
+    #[test]
+    fn inserting_rows_at_the_bottom() {
+        let mut row = [Cell::Empty; MAX_GRID_WIDTH];
+        row[..3].fill(Cell::Garbage);
+        let mut grid = grid(["....", "....", "....", ".#.."]);
+        assert!(grid.insert_rows_at_bottom(&[row, row]));
+        assert_eq!(drawn(&grid), ["....", ".#..", "###.", "###."]);
+        assert_eq!(grid.count_holes(), 0);
+    }
+
+    #[test]
+    fn inserting_rows_that_push_the_stack_off_the_top() {
+        let mut row = [Cell::Empty; MAX_GRID_WIDTH];
+        row[1] = Cell::Garbage;
+        let mut grid = grid(["....", "#...", "#...", "#..."]);
+        assert!(!grid.insert_rows_at_bottom(&[row, row]));
+    }
+}
@@ -0,0 +1,135 @@
+use ggez::{graphics::Color, Context};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::GameState,
+    match_replay::MatchReplay,
+    render::{Renderer, SCREEN_SIZE},
+    scene::{Scene, Transition},
+    settings::MenuInput,
+    versus::VersusScene,
+};
+
+/// The most rows of garbage a board can be handicapped to start with.
+const MAX_GARBAGE: u32 = 10;
+/// The most times slower a board's pieces can be handicapped to fall.
+const MAX_GRAVITY: u8 = 4;
+/// The parts of a handicap that can be changed, in the order they are listed.
+pub const FIELDS: [&str; 3] = ["attack", "garbage", "gravity"];
+
+/// What a player of a versus game is held back or helped by, so that friends who play
+/// at different levels can still have close games.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handicap {
+    /// How much of the garbage their clears would send is sent, in percent.
+    pub attack: u32,
+    /// How many rows of garbage their board starts with.
+    pub garbage: u32,
+    /// How many times slower than usual their pieces fall.
+    pub gravity: u8,
+}
+
+impl Default for Handicap {
+    fn default() -> Self {
+        Handicap { attack: 100, garbage: 0, gravity: 1 }
+    }
+}
+
+impl Handicap {
+    /// Changes field `field` of `FIELDS` a step in the direction of `step`.
+    pub fn adjust(&mut self, field: usize, step: i32) {
+        let step = step.signum();
+        match field {
+            0 => self.attack = (self.attack as i32 + 25 * step).clamp(25, 200) as u32,
+            1 => self.garbage = (self.garbage as i32 + step).clamp(0, MAX_GARBAGE as i32) as u32,
+            _ => self.gravity = (self.gravity as i32 + step).clamp(1, MAX_GRAVITY as i32) as u8,
+        }
+    }
+    /// What field `field` of `FIELDS` is set to, for showing it.
+    pub fn value(&self, field: usize) -> String {
+        match field {
+            0 => format!("{}%", self.attack),
+            1 => format!("{} rows", self.garbage),
+            _ if self.gravity == 1 => "normal".to_owned(),
+            _ => format!("{}x slower", self.gravity),
+        }
+    }
+    /// `rows` of garbage scaled by the attack handicap, to the nearest row.
+    pub fn scale_attack(&self, rows: u32) -> u32 {
+        (rows * self.attack + 50) / 100
+    }
+    /// Checks the handicap could have been picked here, e.g. before playing by one from the other player.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(25..=200).contains(&self.attack) {
+            return Err(format!("an attack of {}%", self.attack));
+        }
+        if self.garbage > MAX_GARBAGE {
+            return Err(format!("{} rows of starting garbage", self.garbage));
+        }
+        if !(1..=MAX_GRAVITY).contains(&self.gravity) {
+            return Err(format!("gravity {} times slower", self.gravity));
+        }
+        Ok(())
+    }
+}
+
+/// Sets the handicaps of the two players of a local versus game before it starts,
+/// the second of which is the computer if `computer`, along with how well the computer plays.
+/// They are kept in the config for the next game.
+pub struct HandicapScene {
+    race: bool,
+    computer: bool,
+    selected: usize,
+}
+
+impl HandicapScene {
+    pub fn new(race: bool, computer: bool) -> Self {
+        HandicapScene { race, computer, selected: 0 }
+    }
+    /// How many things there are to set, the last of which is the difficulty against the computer.
+    fn entries(&self) -> usize {
+        2 * FIELDS.len() + self.computer as usize
+    }
+}
+
+impl Scene for HandicapScene {
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        r.draw_text("Handicaps", 48., [64., 64.], Color::WHITE);
+        for (player, handicap) in state.config.handicaps.iter().enumerate() {
+            let y = 160. + 200. * player as f32;
+            let name = if self.computer && player == 1 { "Computer".to_owned() } else { format!("Player {}", player + 1) };
+            r.draw_text(&name, 32., [64., y], Color::WHITE);
+            for (field, name) in FIELDS.iter().enumerate() {
+                let i = player * FIELDS.len() + field;
+                let colour = if i == self.selected { Color::YELLOW } else { Color::WHITE };
+                r.draw_text(&format!("{name}: {}", handicap.value(field)), 24., [96., y + 48. + 36. * field as f32], colour);
+            }
+        }
+        if self.computer {
+            let colour = if self.selected == 2 * FIELDS.len() { Color::YELLOW } else { Color::WHITE };
+            r.draw_text(&format!("Difficulty: {}", state.config.difficulty), 32., [64., 560.], colour);
+        }
+        let help = "Up/Down: select  Left/Right: change  Confirm: play  Back: choose mode";
+        r.draw_text(help, 16., [64., SCREEN_SIZE.1 - 48.], Color::WHITE);
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        let n = self.entries();
+        match input {
+            MenuInput::Up => self.selected = (self.selected + n - 1) % n,
+            MenuInput::Down => self.selected = (self.selected + 1) % n,
+            MenuInput::Adjust(step) if self.selected == 2 * FIELDS.len() => state.config.difficulty = state.config.difficulty.cycle(step > 0),
+            MenuInput::Adjust(step) => state.config.handicaps[self.selected / FIELDS.len()].adjust(self.selected % FIELDS.len(), step as i32),
+            MenuInput::Confirm => {
+                if let Err(e) = state.config.save(&*state.storage) {
+                    warn!("Could not save config: {e}");
+                }
+                return Transition::Replace(Box::new(VersusScene::new(state, self.race, self.computer, MatchReplay::new())));
+            }
+            MenuInput::Back => return Transition::Pop(1),
+            MenuInput::NewProfile => (),
+        }
+        Transition::None
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    time::Duration,
+};
+
+use log::info;
+
+use crate::{
+    ai::Ai,
+    app::random_seed,
+    config::{Config, GameConfig, Handling},
+    fumen::{Placements, VIEWER},
+    handicap::Handicap,
+    mode::Mode,
+    replay::{Playback, Replay},
+    rules::Game,
+    versus::{decide_match, Outcome, Side},
+};
+
+/// How long a game without a window goes on for at most, in game time, as the AI can keep some modes going for ever.
+const MAX_GAME_MS: u32 = 10 * 60 * 1000;
+/// How long the AI is given to decide where a piece goes before the game is played on without it.
+const THINKING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The replay in the file `arg`, or `arg` itself.
+fn load_replay(arg: &str) -> Result<Replay, String> {
+    let replay = match std::fs::read_to_string(arg) {
+        Ok(s) => Replay::decode(&s),
+        Err(_) => Replay::decode(arg),
+    };
+    replay.map_err(|e| e.to_string())
+}
+
+/// Plays the replay in the file `arg`, or `arg` itself, and says whether it ends the way it says it does.
+pub fn verify(arg: &str, config: &GameConfig) -> bool {
+    match load_replay(arg).and_then(|replay| replay.verify(config)) {
+        Ok(()) => {
+            println!("The replay holds up");
+            true
+        }
+        Err(e) => {
+            println!("{e}");
+            false
+        }
+    }
+}
+
+/// Plays the replay in the file `arg`, or `arg` itself, up to its last input and prints the pieces placed
+/// as a link to them as a fumen.
+pub fn fumen(arg: &str, config: &GameConfig) -> bool {
+    let encoded = load_replay(arg).and_then(|replay| {
+        let mut game = Game::with_config(replay.mode, replay.mode.rules(), replay.seed, replay.handling, config);
+        game.start_level = replay.level;
+        let mut playback = Playback::new(&replay);
+        let mut placements = Placements::new(&game);
+        let last_input = replay.events.last().map_or(0, |&(tick, ..)| tick);
+        while !game.gameover && game.tick < last_input {
+            let inputs: Vec<_> = playback.inputs(game.tick.wrapping_add(1)).collect();
+            let result = game.tick(&inputs);
+            placements.record(&game, &result);
+        }
+        placements.encode(&game)
+    });
+    match encoded {
+        Ok(fumen) => {
+            println!("{VIEWER}{fumen}");
+            true
+        }
+        Err(e) => {
+            println!("{e}");
+            false
+        }
+    }
+}
+
+/// The seed of game `i` of a run, counting up from `seed` so the run can be played again, or random.
+fn seed_of(seed: Option<u64>, i: u32) -> u64 {
+    seed.map_or_else(random_seed, |seed| seed.wrapping_add(i as u64))
+}
+
+/// Has the AI play `games` games of `mode` as fast as it can, printing how each went,
+/// to look for games that go wrong over a long run. Says whether every game played out without panicking.
+pub fn soak(mode: Mode, games: u32, seed: Option<u64>, level: u32, config: &Config, game_config: &GameConfig) -> bool {
+    let mut ok = true;
+    for i in 0..games {
+        let seed = seed_of(seed, i);
+        info!("Soaking {mode} with seed {seed}");
+        let played = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut game = Game::with_config(mode, mode.rules(), seed, Handling::default(), game_config);
+            game.start_level = level;
+            let mut ai = Ai::with_weights(config.difficulty, config.ai_weights);
+            while !game.gameover && game.ms() < MAX_GAME_MS {
+                ai.wait_for_plan(&game, THINKING_TIMEOUT);
+                let inputs = ai.inputs(&game);
+                game.tick(&inputs);
+            }
+            game
+        }));
+        match played {
+            Ok(game) => {
+                let end = if game.gameover { "ended" } else { "stopped" };
+                println!(
+                    "Game {}: {mode} with seed {seed} {end} after {:.1} s with {} points, {} lines and {} pieces",
+                    i + 1,
+                    game.ms() as f32 / 1000.,
+                    game.score,
+                    game.lines,
+                    game.pieces
+                );
+            }
+            Err(_) => {
+                println!("Game {}: {mode} with seed {seed} panicked", i + 1);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// Has the AI play `games` versus games against itself, printing who won each.
+pub fn versus(games: u32, seed: Option<u64>, config: &Config, game_config: &GameConfig) -> bool {
+    let mode = Mode::Marathon;
+    let mut wins = [0; 2];
+    for i in 0..games {
+        let seed = seed_of(seed, i);
+        info!("Playing the AI against itself with seed {seed}");
+        let mut sides = [0, 1].map(|player| Side::new(seed, player, mode, Handling::default(), game_config, Handicap::default()));
+        let mut ais = [0, 1].map(|_| Ai::with_weights(config.difficulty, config.ai_weights));
+        let mut ended = [None; 2];
+        let mut ticks = 0;
+        let outcome = loop {
+            ticks += 1;
+            let sent = [0, 1].map(|i| {
+                let game = &sides[i].board.game;
+                ais[i].wait_for_plan(game, THINKING_TIMEOUT);
+                let inputs = ais[i].inputs(game);
+                sides[i].tick(&inputs, &config.attack, true)
+            });
+            for (from, garbage) in sent.into_iter().enumerate() {
+                if let Some((rows, hole)) = garbage {
+                    sides[1 - from].board.game.receive_garbage(rows, hole);
+                }
+            }
+            for (side, ended) in sides.iter().zip(&mut ended) {
+                if side.board.game.gameover && ended.is_none() {
+                    *ended = Some(ticks);
+                }
+            }
+            let outcome = decide_match(false, [&sides[0].board, &sides[1].board], ended, [ticks; 2]);
+            if outcome.is_some() || sides[0].board.game.ms() >= MAX_GAME_MS {
+                break outcome;
+            }
+        };
+        let secs = sides[0].board.game.ms() as f32 / 1000.;
+        match outcome {
+            Some(Outcome::Winner(winner)) => {
+                wins[winner] += 1;
+                println!("Game {}: seed {seed}, player {} won after {secs:.1} s", i + 1, winner + 1);
+            }
+            Some(Outcome::Draw) => println!("Game {}: seed {seed}, a draw after {secs:.1} s", i + 1),
+            None => println!("Game {}: seed {seed}, no winner after {secs:.1} s", i + 1),
+        }
+    }
+    println!("Player 1 won {}, player 2 won {}", wins[0], wins[1]);
+    true
+}
@@ -0,0 +1,63 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// How long a request may take to connect, and to send or receive anything.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// The most of a response that is read, in bytes, far more than a leaderboard needs.
+const MAX_RESPONSE: u64 = 4 * 1024 * 1024;
+
+/// Fetches `url`, returning the body of the response.
+pub fn get(url: &str) -> Result<String, String> {
+    request("GET", url, None)
+}
+
+/// Posts `body` as JSON to `url`, returning the body of the response.
+pub fn post_json(url: &str, body: &str) -> Result<String, String> {
+    request("POST", url, Some(body))
+}
+
+/// Makes a plain HTTP/1.0 request, which is all the leaderboard needs: one request per connection,
+/// with the whole response read until the server closes it, so there's no chunked encoding to undo.
+/// Only `http://` addresses can be reached this way.
+fn request(method: &str, url: &str, body: Option<&str>) -> Result<String, String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Only http:// addresses are supported, not {url}"))?;
+    let (authority, path) = rest.find('/').map_or((rest, "/"), |i| rest.split_at(i));
+    let host = authority.rsplit_once(':').map_or(authority, |(host, _)| host);
+    let address = if authority.contains(':') { authority.to_owned() } else { format!("{authority}:80") };
+
+    let addr = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Could not find {authority}: {e}"))?
+        .next()
+        .ok_or_else(|| format!("Could not find {authority}"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(|e| format!("Could not reach {authority}: {e}"))?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let mut request = format!("{method} {path} HTTP/1.0\r\nHost: {host}\r\nUser-Agent: tetris/{}\r\n", env!("CARGO_PKG_VERSION"));
+    if let Some(body) = body {
+        request += &format!("Content-Type: application/json\r\nContent-Length: {}\r\n", body.len());
+    }
+    request += "\r\n";
+    request += body.unwrap_or("");
+    stream.write_all(request.as_bytes()).map_err(|e| format!("Could not send to {authority}: {e}"))?;
+
+    let mut response = Vec::new();
+    // One byte more than the limit is read, to tell a response that is too big from one just big enough
+    stream.take(MAX_RESPONSE + 1).read_to_end(&mut response).map_err(|e| format!("Could not read from {authority}: {e}"))?;
+    if response.len() as u64 > MAX_RESPONSE {
+        return Err(format!("{authority} sent a response over {MAX_RESPONSE} bytes long"));
+    }
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response.split_once("\r\n\r\n").ok_or("The response had no end to its headers")?;
+    let status = head.lines().next().and_then(|line| line.split(' ').nth(1)).unwrap_or("");
+    if !status.starts_with('2') {
+        return Err(format!("{authority} answered {}", head.lines().next().unwrap_or("nothing")));
+    }
+    Ok(body.to_owned())
+}
@@ -0,0 +1,275 @@
+use ggez::input::keyboard::KeyCode;
+use serde::{Deserialize, Serialize};
+
+/// Something the player can do, independent of which key does it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Left,
+    Right,
+    RotLeft,
+    RotRight,
+    SoftDrop,
+    HardDrop,
+    Hold,
+    /// Held to start over with a new game.
+    Restart,
+    Pause,
+    MenuConfirm,
+    MenuBack,
+    /// Takes back the last placement, in modes that allow it.
+    Undo,
+    /// Puts back the last placement taken back.
+    Redo,
+}
+
+impl Action {
+    /// Replays save actions as their place in here, so new ones go at the end.
+    pub const ALL: [Action; 13] = [
+        Action::Left,
+        Action::Right,
+        Action::RotLeft,
+        Action::RotRight,
+        Action::SoftDrop,
+        Action::HardDrop,
+        Action::Hold,
+        Action::Restart,
+        Action::Pause,
+        Action::MenuConfirm,
+        Action::MenuBack,
+        Action::Undo,
+        Action::Redo,
+    ];
+}
+
+/// An action being pressed or released on a given tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub tick: u32,
+    pub action: Action,
+    pub pressed: bool,
+}
+
+/// Something that wants to see every input the game applies, in order, such as a replay recorder.
+/// Feeding the same events back on the same ticks plays the game out the same way.
+pub trait InputListener {
+    fn input(&mut self, event: InputEvent);
+    /// Called at the end of every tick with `Game::state_checksum`, to check another run of the game against.
+    fn tick_end(&mut self, _tick: u32, _checksum: u64) {}
+    /// Called once the game is over, with how it went and a checksum of the final state.
+    fn game_over(&mut self, _score: u32, _lines: u32, _checksum: u64, _personal_best: bool) {}
+    /// The game played so far as a replay, if this listener records one.
+    fn replay(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Which keys trigger which action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keybindings {
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub rotate_left: Vec<KeyCode>,
+    pub rotate_right: Vec<KeyCode>,
+    pub soft_drop: Vec<KeyCode>,
+    pub hard_drop: Vec<KeyCode>,
+    pub hold: Vec<KeyCode>,
+    pub restart: Vec<KeyCode>,
+    pub pause: Vec<KeyCode>,
+    pub menu_confirm: Vec<KeyCode>,
+    pub menu_back: Vec<KeyCode>,
+    pub undo: Vec<KeyCode>,
+    pub redo: Vec<KeyCode>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Preset::Guideline.bindings()
+    }
+}
+
+/// Built-in sets of keybindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// The layout most other clients use: arrows to move, Up/X and Z to rotate, C to hold.
+    Guideline,
+    /// WASD-style movement with Q and E to rotate.
+    Current,
+    /// Everything under the right hand: IJKL to move and drop, U and O to rotate.
+    Ijkl,
+    /// Everything on the numpad.
+    Numpad,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [Preset::Guideline, Preset::Current, Preset::Ijkl, Preset::Numpad];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Guideline => "Guideline",
+            Preset::Current => "Current",
+            Preset::Ijkl => "IJKL",
+            Preset::Numpad => "Numpad",
+        }
+    }
+    pub fn bindings(self) -> Keybindings {
+        match self {
+            Preset::Guideline => Keybindings {
+                left: vec![KeyCode::Left],
+                right: vec![KeyCode::Right],
+                rotate_left: vec![KeyCode::Z, KeyCode::LControl],
+                rotate_right: vec![KeyCode::Up, KeyCode::X],
+                soft_drop: vec![KeyCode::Down],
+                hard_drop: vec![KeyCode::Space],
+                hold: vec![KeyCode::C, KeyCode::LShift],
+                restart: vec![KeyCode::R],
+                pause: vec![KeyCode::Escape],
+                menu_confirm: vec![KeyCode::Return],
+                menu_back: vec![KeyCode::Escape, KeyCode::Back],
+                undo: vec![KeyCode::Y],
+                redo: vec![KeyCode::H],
+            },
+            Preset::Current => Keybindings {
+                left: vec![KeyCode::A, KeyCode::Left],
+                right: vec![KeyCode::D, KeyCode::Right],
+                rotate_left: vec![KeyCode::Q],
+                rotate_right: vec![KeyCode::E],
+                soft_drop: vec![KeyCode::S, KeyCode::Down],
+                hard_drop: vec![KeyCode::Space],
+                hold: vec![KeyCode::C],
+                restart: vec![KeyCode::R],
+                pause: vec![KeyCode::Escape],
+                menu_confirm: vec![KeyCode::Return],
+                menu_back: vec![KeyCode::Escape, KeyCode::Back],
+                undo: vec![KeyCode::Y],
+                redo: vec![KeyCode::H],
+            },
+            Preset::Ijkl => Keybindings {
+                left: vec![KeyCode::J],
+                right: vec![KeyCode::L],
+                rotate_left: vec![KeyCode::U],
+                rotate_right: vec![KeyCode::O],
+                soft_drop: vec![KeyCode::K],
+                hard_drop: vec![KeyCode::I],
+                hold: vec![KeyCode::Semicolon],
+                restart: vec![KeyCode::P],
+                pause: vec![KeyCode::Escape],
+                menu_confirm: vec![KeyCode::Return],
+                menu_back: vec![KeyCode::Escape, KeyCode::Back],
+                undo: vec![KeyCode::Y],
+                redo: vec![KeyCode::H],
+            },
+            Preset::Numpad => Keybindings {
+                left: vec![KeyCode::Numpad4],
+                right: vec![KeyCode::Numpad6],
+                rotate_left: vec![KeyCode::Numpad7],
+                rotate_right: vec![KeyCode::Numpad9, KeyCode::Numpad8],
+                soft_drop: vec![KeyCode::Numpad5, KeyCode::Numpad2],
+                hard_drop: vec![KeyCode::Numpad0],
+                hold: vec![KeyCode::NumpadAdd],
+                restart: vec![KeyCode::NumpadSubtract],
+                pause: vec![KeyCode::Escape, KeyCode::NumpadMultiply],
+                menu_confirm: vec![KeyCode::Return, KeyCode::NumpadEnter],
+                menu_back: vec![KeyCode::Escape, KeyCode::NumpadDecimal],
+                undo: vec![KeyCode::Y],
+                redo: vec![KeyCode::H],
+            },
+        }
+    }
+    pub fn cycle(self, forward: bool) -> Self {
+        let i = Self::ALL.iter().position(|&p| p == self).unwrap_or(0);
+        let n = Self::ALL.len();
+        Self::ALL[if forward { (i + 1) % n } else { (i + n - 1) % n }]
+    }
+    /// The preset the bindings are exactly the same as, if any.
+    pub fn matching(bindings: &Keybindings) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| preset.bindings() == *bindings)
+    }
+}
+
+/// Keys that replace the profile's keybindings for some actions, e.g. in a specific mode.
+/// Actions that are left out keep the profile's keys, and an empty list unbinds the action.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BindingOverrides {
+    pub left: Option<Vec<KeyCode>>,
+    pub right: Option<Vec<KeyCode>>,
+    pub rotate_left: Option<Vec<KeyCode>>,
+    pub rotate_right: Option<Vec<KeyCode>>,
+    pub soft_drop: Option<Vec<KeyCode>>,
+    pub hard_drop: Option<Vec<KeyCode>>,
+    pub hold: Option<Vec<KeyCode>>,
+    pub restart: Option<Vec<KeyCode>>,
+    pub pause: Option<Vec<KeyCode>>,
+    pub menu_confirm: Option<Vec<KeyCode>>,
+    pub menu_back: Option<Vec<KeyCode>>,
+    pub undo: Option<Vec<KeyCode>>,
+    pub redo: Option<Vec<KeyCode>>,
+}
+
+impl Keybindings {
+    pub fn with_overrides(&self, overrides: &BindingOverrides) -> Self {
+        let pick = |keys: &Vec<KeyCode>, over: &Option<Vec<KeyCode>>| over.as_ref().unwrap_or(keys).clone();
+        Keybindings {
+            left: pick(&self.left, &overrides.left),
+            right: pick(&self.right, &overrides.right),
+            rotate_left: pick(&self.rotate_left, &overrides.rotate_left),
+            rotate_right: pick(&self.rotate_right, &overrides.rotate_right),
+            soft_drop: pick(&self.soft_drop, &overrides.soft_drop),
+            hard_drop: pick(&self.hard_drop, &overrides.hard_drop),
+            hold: pick(&self.hold, &overrides.hold),
+            restart: pick(&self.restart, &overrides.restart),
+            pause: pick(&self.pause, &overrides.pause),
+            menu_confirm: pick(&self.menu_confirm, &overrides.menu_confirm),
+            menu_back: pick(&self.menu_back, &overrides.menu_back),
+            undo: pick(&self.undo, &overrides.undo),
+            redo: pick(&self.redo, &overrides.redo),
+        }
+    }
+    pub fn action(&self, keycode: KeyCode) -> Option<Action> {
+        [
+            (&self.left, Action::Left),
+            (&self.right, Action::Right),
+            (&self.rotate_left, Action::RotLeft),
+            (&self.rotate_right, Action::RotRight),
+            (&self.soft_drop, Action::SoftDrop),
+            (&self.hard_drop, Action::HardDrop),
+            (&self.hold, Action::Hold),
+            (&self.restart, Action::Restart),
+            (&self.pause, Action::Pause),
+            (&self.menu_confirm, Action::MenuConfirm),
+            (&self.menu_back, Action::MenuBack),
+            (&self.undo, Action::Undo),
+            (&self.redo, Action::Redo),
+        ]
+        .into_iter()
+        .find_map(|(keys, action)| keys.contains(&keycode).then_some(action))
+    }
+}
+
+/// Every action currently held down, in the order they were pressed.
+/// An action held by several keys (or touches) at once is in here once for each of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeldActions(Vec<Action>);
+
+impl HeldActions {
+    pub fn press(&mut self, action: Action) {
+        self.0.push(action);
+    }
+    pub fn release(&mut self, action: Action) {
+        if let Some(i) = self.0.iter().rposition(|&a| a == action) {
+            self.0.remove(i);
+        }
+    }
+    /// The actions held, in the order they were pressed.
+    pub fn iter(&self) -> impl Iterator<Item = Action> + '_ {
+        self.0.iter().copied()
+    }
+    pub fn is_held(&self, action: Action) -> bool {
+        self.0.contains(&action)
+    }
+    /// The most recently pressed of `actions` that is still held.
+    pub fn latest(&self, actions: &[Action]) -> Option<Action> {
+        self.0.iter().rev().copied().find(|a| actions.contains(a))
+    }
+}
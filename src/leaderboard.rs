@@ -0,0 +1,176 @@
+use ggez::{graphics::Color, Context};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    app::GameState,
+    http,
+    render::{Renderer, SCREEN_SIZE},
+    scene::{Scene, Transition},
+    scores::{Board, ScoreEntry},
+    settings::MenuInput,
+    worker::Worker,
+};
+
+/// How many entries the global leaderboards show.
+pub const TOP_ENTRIES: usize = 100;
+/// The boards games are sent to the global leaderboard for.
+pub const BOARDS: [Board; 2] = [Board::SprintTime, Board::UltraScore];
+/// How many entries fit on the screen at once.
+const ROWS: usize = 30;
+
+/// A game sent to the global leaderboard, with its replay for the server to play back and check.
+#[derive(Debug, Serialize)]
+struct Submission {
+    board: &'static str,
+    #[serde(flatten)]
+    entry: ScoreEntry,
+    replay: String,
+}
+
+enum Job {
+    Submit { url: String, body: String },
+    Fetch { url: String, board: Board },
+}
+
+enum Reply {
+    Submitted(Result<(), String>),
+    Fetched(Board, Result<Vec<ScoreEntry>, String>),
+}
+
+/// What is known of a global leaderboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fetched {
+    Loading,
+    Loaded(Vec<ScoreEntry>),
+    Failed(String),
+}
+
+/// Sends games to the global leaderboards at the address in the config and fetches their top entries,
+/// on a worker so a slow server never holds up the game. The server is expected to take games posted as JSON
+/// to `<address>/submit` and to list the best of a board as a JSON array at `<address>/top/<board>`.
+#[derive(Default)]
+pub struct Leaderboard {
+    worker: Option<Worker<Job, Reply>>,
+    boards: Vec<(Board, Fetched)>,
+}
+
+impl Leaderboard {
+    fn send(&mut self, job: Job) {
+        let worker = self.worker.get_or_insert_with(|| {
+            Worker::spawn("leaderboard", |job, _| match job {
+                Job::Submit { url, body } => Reply::Submitted(http::post_json(&url, &body).map(|_| ())),
+                Job::Fetch { url, board } => {
+                    let top = http::get(&url).and_then(|body| serde_json::from_str(&body).map_err(|e| e.to_string()));
+                    Reply::Fetched(board, top)
+                }
+            })
+        });
+        worker.send(job);
+    }
+    /// Sends a game on `board` to the leaderboard at `address`, if it is one that goes there.
+    pub fn submit(&mut self, address: &str, board: Board, entry: ScoreEntry, replay: String) {
+        if !BOARDS.contains(&board) || !board.qualifies(&entry) {
+            return;
+        }
+        let submission = Submission { board: board.key(), entry, replay };
+        let body = serde_json::to_string(&submission).expect("Submissions can always be written");
+        let url = format!("{}/submit", address.trim_end_matches('/'));
+        info!("Submitting to {url}");
+        self.send(Job::Submit { url, body });
+    }
+    /// Starts fetching the top of `board` from the leaderboard at `address`.
+    pub fn fetch(&mut self, address: &str, board: Board) {
+        let url = format!("{}/top/{}", address.trim_end_matches('/'), board.key());
+        self.set(board, Fetched::Loading);
+        self.send(Job::Fetch { url, board });
+    }
+    pub fn board(&self, board: Board) -> Option<&Fetched> {
+        self.boards.iter().find(|(b, _)| *b == board).map(|(_, fetched)| fetched)
+    }
+    fn set(&mut self, board: Board, fetched: Fetched) {
+        match self.boards.iter_mut().find(|(b, _)| *b == board) {
+            Some((_, old)) => *old = fetched,
+            None => self.boards.push((board, fetched)),
+        }
+    }
+    /// Takes in what the worker has done since last time, returning what to tell the player about submissions.
+    pub fn update(&mut self) -> Vec<String> {
+        let replies: Vec<Reply> = self.worker.as_ref().map_or_else(Vec::new, |worker| worker.results().collect());
+        let mut messages = Vec::new();
+        for reply in replies {
+            match reply {
+                Reply::Submitted(Ok(())) => messages.push("Sent to the global leaderboard".to_owned()),
+                Reply::Submitted(Err(e)) => {
+                    warn!("Could not submit to the global leaderboard: {e}");
+                    messages.push(format!("Could not send to the global leaderboard: {e}"));
+                }
+                Reply::Fetched(board, Ok(mut top)) => {
+                    top.truncate(TOP_ENTRIES);
+                    self.set(board, Fetched::Loaded(top));
+                }
+                Reply::Fetched(board, Err(e)) => {
+                    warn!("Could not fetch the global leaderboard: {e}");
+                    self.set(board, Fetched::Failed(e));
+                }
+            }
+        }
+        messages
+    }
+}
+
+/// The best games everyone has sent to the global leaderboards.
+pub struct LeaderboardScene {
+    board: usize,
+    scroll: usize,
+}
+
+impl LeaderboardScene {
+    pub fn new(state: &mut GameState) -> Self {
+        let scene = LeaderboardScene { board: 0, scroll: 0 };
+        scene.fetch(state);
+        scene
+    }
+    fn fetch(&self, state: &mut GameState) {
+        if let Some(address) = &state.config.leaderboard {
+            state.leaderboard.fetch(address, BOARDS[self.board]);
+        }
+    }
+}
+
+impl Scene for LeaderboardScene {
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let board = BOARDS[self.board];
+        r.draw_text(&format!("Global {}", board.title().to_lowercase()), 32., [32., 32.], Color::WHITE);
+        match state.leaderboard.board(board) {
+            None | Some(Fetched::Loading) => r.draw_text("Loading...", 24., [32., 96.], Color::WHITE),
+            Some(Fetched::Failed(e)) => r.draw_text(&format!("Could not load: {e}"), 20., [32., 96.], Color::RED),
+            Some(Fetched::Loaded(top)) if top.is_empty() => r.draw_text("Nobody is on it yet", 24., [32., 96.], Color::WHITE),
+            Some(Fetched::Loaded(top)) => {
+                for (row, (i, entry)) in top.iter().enumerate().skip(self.scroll).take(ROWS).enumerate() {
+                    let colour = if entry.name == state.config.profile { Color::YELLOW } else { Color::WHITE };
+                    r.draw_text(&board.row(i, entry), 18., [32., 96. + 26. * row as f32], colour);
+                }
+            }
+        }
+        r.draw_text("Up/Down: scroll  Left/Right: board  Back: choose mode", 16., [32., SCREEN_SIZE.1 - 36.], Color::WHITE);
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        let entries = match state.leaderboard.board(BOARDS[self.board]) {
+            Some(Fetched::Loaded(top)) => top.len(),
+            _ => 0,
+        };
+        match input {
+            MenuInput::Up => self.scroll = self.scroll.saturating_sub(1),
+            MenuInput::Down => self.scroll = (self.scroll + 1).min(entries.saturating_sub(ROWS)),
+            MenuInput::Adjust(step) => {
+                self.board = (self.board as isize + step.signum() as isize).rem_euclid(BOARDS.len() as isize) as usize;
+                self.scroll = 0;
+                self.fetch(state);
+            }
+            MenuInput::Back => return Transition::Pop(1),
+            MenuInput::Confirm | MenuInput::NewProfile => (),
+        }
+        Transition::None
+    }
+}
@@ -0,0 +1,64 @@
+//! Tetris, with the game logic in `rules` kept apart from the ggez frontend in `app` and `scene`
+//! so it can be tested and driven by other frontends.
+
+pub mod ai;
+pub mod analysis;
+pub mod app;
+pub mod attack;
+pub mod bot;
+pub mod chat;
+pub mod bitgrid;
+pub mod cli;
+pub mod clip;
+pub mod clipboard;
+pub mod config;
+pub mod demo;
+pub mod discord;
+pub mod effects;
+pub mod finesse;
+pub mod fumen;
+pub mod gamepad;
+pub mod grid;
+pub mod handicap;
+pub mod headless;
+pub mod http;
+pub mod input;
+pub mod leaderboard;
+pub mod lobby;
+pub mod logging;
+pub mod match_replay;
+pub mod mode;
+pub mod net;
+pub mod online;
+pub mod opening;
+pub mod overlay;
+pub mod piece;
+pub mod profile;
+#[cfg(feature = "macroquad")]
+pub mod quad;
+pub mod reload;
+pub mod render;
+pub mod replay;
+pub mod rotation;
+pub mod royale;
+pub mod rules;
+pub mod ruleset;
+pub mod save;
+pub mod scene;
+pub mod scores;
+pub mod scoring;
+pub mod script;
+pub mod screenshot;
+pub mod server;
+pub mod settings;
+pub mod stats;
+pub mod stats_window;
+pub mod storage;
+pub mod theme;
+pub mod touch;
+pub mod tui;
+pub mod twitch;
+pub mod versioned;
+pub mod versus;
+pub mod widget;
+pub mod worker;
@@ -0,0 +1,291 @@
+use ggez::{graphics::Color, input::keyboard::KeyCode};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    attack::AttackTable,
+    handicap::{self, Handicap},
+    match_replay::MatchReplay,
+    chat::{Chat, ChatKey},
+    config::{GameConfig, Handling},
+    mode::Mode,
+    net::{check_handshake, Connection, Message},
+    render::{Renderer, SCREEN_SIZE},
+    ruleset::SPRINT_LINES,
+    settings::MenuInput,
+};
+
+/// What the host of an online game has decided the games are played by.
+/// It is sent along with the start of every game, and both ends play both boards by it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomRules {
+    pub attack: AttackTable,
+    /// The shortest DAS a board may be played with (ms).
+    pub min_das: u32,
+    /// The shortest ARR a board may be played with (ms).
+    pub min_arr: u32,
+    /// How many games the match is played over, the first to win more than half of them winning it.
+    pub best_of: u32,
+    /// Whether the games are races to clear the lines of Sprint first on the same pieces, with no garbage.
+    pub race: bool,
+    /// Whether clears cancel out garbage waiting to come in before what is left is sent on.
+    pub cancel: bool,
+    /// The handicap of each player, the host's first.
+    pub handicaps: [Handicap; 2],
+}
+
+impl Default for RoomRules {
+    fn default() -> Self {
+        RoomRules {
+            attack: AttackTable::default(),
+            min_das: 0,
+            min_arr: 0,
+            best_of: 1,
+            race: false,
+            cancel: true,
+            handicaps: [Handicap::default(); 2],
+        }
+    }
+}
+
+impl RoomRules {
+    /// `handling` held to the limits of the room.
+    pub fn cap(&self, handling: Handling) -> Handling {
+        Handling {
+            das: handling.das.max(self.min_das),
+            arr: handling.arr.max(self.min_arr),
+            ..handling
+        }
+    }
+    pub fn wins_needed(&self) -> u32 {
+        self.best_of / 2 + 1
+    }
+    /// The mode the boards are played as.
+    pub fn mode(&self) -> Mode {
+        if self.race {
+            Mode::Sprint
+        } else {
+            Mode::Marathon
+        }
+    }
+    /// Checks that the games can be played by the rules, e.g. before playing by rules from the host.
+    pub fn validate(&self) -> Result<(), String> {
+        self.attack.validate().map_err(|e| format!("The attack table can't be played by: {e}"))?;
+        for handicap in &self.handicaps {
+            handicap.validate().map_err(|e| format!("A handicap can't be played with: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The rules the host can change, in the order they are listed, followed by each player's handicap.
+const RULES: [&str; 6] = ["Game", "Best of", "Min DAS", "Min ARR", "Garbage cancelling", "Attack table"];
+/// How many things the host can select to change: the rules, then the fields of both handicaps.
+const SELECTABLE: usize = RULES.len() + 2 * handicap::FIELDS.len();
+
+/// Where the players of an online game wait between games: the host sets the rules,
+/// and the next game starts once both players say they are ready.
+/// The connection, the games won so far and the chat are kept here while a game is being played.
+pub struct Lobby {
+    pub connection: Connection,
+    pub hosting: bool,
+    /// The other player's name and handling, once they have said hello.
+    pub opponent: Option<(String, Handling)>,
+    /// Whether the other end's handshake has come in and matched this game's.
+    shaken: bool,
+    pub rules: RoomRules,
+    /// The games each player has won, this player first.
+    pub wins: [u32; 2],
+    /// Whether each player is ready to start, this player first.
+    ready: [bool; 2],
+    /// The rule the host is changing.
+    selected: usize,
+    /// The tables the host can pick the attack table from: the presets, and the one they started with
+    /// if it is their own.
+    attack_tables: Vec<(&'static str, AttackTable)>,
+    pub chat: Chat,
+    /// The games of the match so far.
+    pub replay: MatchReplay,
+}
+
+impl Lobby {
+    pub fn new(mut connection: Connection, hosting: bool, rules: RoomRules) -> Self {
+        if hosting {
+            connection.send(&Message::Rules { rules: rules.clone() });
+        }
+        let mut attack_tables = AttackTable::presets().to_vec();
+        if rules.attack.preset_name().is_none() {
+            attack_tables.push(("Custom", rules.attack.clone()));
+        }
+        Lobby {
+            connection,
+            hosting,
+            opponent: None,
+            shaken: false,
+            rules,
+            wins: [0; 2],
+            ready: [false; 2],
+            selected: 0,
+            attack_tables,
+            chat: Chat::default(),
+            replay: MatchReplay::new(),
+        }
+    }
+    /// Adds a chat message from the other player.
+    pub fn chat_received(&mut self, text: &str) {
+        let from = self.opponent.as_ref().map_or("The other player", |(name, _)| name.as_str());
+        self.chat.add(from, text);
+    }
+    /// Passes a key on to the chat, sending what the player here, `name`, says. Returns whether the chat took it.
+    pub fn chat_key(&mut self, name: &str, keycode: KeyCode) -> bool {
+        match self.chat.key_down(keycode) {
+            ChatKey::Ignored => false,
+            ChatKey::Handled => true,
+            ChatKey::Send(text) => {
+                self.chat.add(name, &text);
+                self.connection.send(&Message::Chat { text });
+                true
+            }
+        }
+    }
+    /// The player who has won the match, if one has won enough games.
+    pub fn match_winner(&self) -> Option<usize> {
+        self.wins.iter().position(|&w| w >= self.rules.wins_needed())
+    }
+    /// Handles what the other end has sent, returning the seed and which of its boards to play once a game starts.
+    /// A host starts the game with `seed` as soon as both players are ready.
+    /// Fails if the other end's game can't play with this one, played with `config`.
+    pub fn update(&mut self, config: &GameConfig, seed: impl FnOnce() -> u64) -> Result<Option<(u64, usize)>, String> {
+        let mut start = None;
+        while let Some(message) = self.connection.receive() {
+            match message {
+                Message::Handshake { version, rules, engine, .. } => {
+                    check_handshake(version, rules, engine, config)?;
+                    self.shaken = true;
+                }
+                Message::Refused { reason } => return Err(reason),
+                Message::Hello { name, handling } => self.opponent = Some((name, handling)),
+                Message::Rules { rules } if !self.hosting => {
+                    rules.validate()?;
+                    self.rules = rules;
+                    self.ready = [false; 2];
+                }
+                Message::Ready { ready } => self.ready[1] = ready,
+                Message::Chat { text } => self.chat_received(&text),
+                Message::Start { seed, rules, player } if !self.hosting => {
+                    rules.validate()?;
+                    self.rules = rules;
+                    start = Some((seed, player));
+                    // What comes after is for the match
+                    break;
+                }
+                // The rest of a game that is already over
+                Message::Tick { .. } | Message::Result { .. } => (),
+                message => warn!("Unexpected message in the lobby: {message:?}"),
+            }
+        }
+        if self.hosting && self.shaken && self.opponent.is_some() && self.ready == [true; 2] {
+            let seed = seed();
+            // The host plays the first board of the seed
+            self.connection.send(&Message::Start { seed, rules: self.rules.clone(), player: 1 });
+            start = Some((seed, 0));
+        }
+        if start.is_some() {
+            self.ready = [false; 2];
+            // A new match starts after one has been won
+            if self.match_winner().is_some() {
+                self.wins = [0; 2];
+                self.replay = MatchReplay::new();
+            }
+        }
+        Ok(start)
+    }
+    pub fn menu_input(&mut self, input: MenuInput) {
+        match input {
+            MenuInput::Confirm => {
+                self.ready[0] = !self.ready[0];
+                self.connection.send(&Message::Ready { ready: self.ready[0] });
+            }
+            MenuInput::Up if self.hosting => self.selected = (self.selected + SELECTABLE - 1) % SELECTABLE,
+            MenuInput::Down if self.hosting => self.selected = (self.selected + 1) % SELECTABLE,
+            MenuInput::Adjust(step) if self.hosting => {
+                let step = step as i32;
+                let rules = &mut self.rules;
+                match self.selected {
+                    0 => rules.race = !rules.race,
+                    1 => rules.best_of = (rules.best_of as i32 + 2 * step.signum()).clamp(1, 9) as u32,
+                    2 => rules.min_das = (rules.min_das as i32 + 10 * step).clamp(0, 500) as u32,
+                    3 => rules.min_arr = (rules.min_arr as i32 + 5 * step).clamp(0, 200) as u32,
+                    4 => rules.cancel = !rules.cancel,
+                    5 => {
+                        let tables = &self.attack_tables;
+                        let current = tables.iter().position(|(_, table)| *table == rules.attack).unwrap_or(0);
+                        let next = (current as i32 + step.signum()).rem_euclid(tables.len() as i32) as usize;
+                        rules.attack = tables[next].1.clone();
+                    }
+                    i => {
+                        let i = i - RULES.len();
+                        rules.handicaps[i / handicap::FIELDS.len()].adjust(i % handicap::FIELDS.len(), step);
+                    }
+                }
+                // Nobody agreed to play by the new rules yet
+                self.ready = [false; 2];
+                self.connection.send(&Message::Rules { rules: self.rules.clone() });
+            }
+            _ => (),
+        }
+    }
+    pub fn draw(&self, name: &str, r: &mut dyn Renderer) {
+        r.draw_text("Lobby", 48., [64., 64.], Color::WHITE);
+        let opponent = self.opponent.as_ref().map(|(name, _)| name.as_str());
+        let players = [Some(name), opponent];
+        for (i, player) in players.into_iter().enumerate() {
+            let y = 160. + 40. * i as f32;
+            match player {
+                Some(player) => {
+                    let (status, colour) = if self.ready[i] { ("ready", Color::GREEN) } else { ("not ready", Color::WHITE) };
+                    r.draw_text(&format!("{player}: {status}"), 32., [64., y], colour);
+                }
+                None => r.draw_text("Waiting for the other player...", 32., [64., y], Color::WHITE),
+            }
+        }
+        if self.rules.best_of > 1 || self.wins != [0; 2] {
+            r.draw_text(&format!("Games won: {} - {}", self.wins[0], self.wins[1]), 24., [64., 260.], Color::WHITE);
+        }
+        if let Some(winner) = self.match_winner() {
+            let winner = players[winner].unwrap_or("The other player");
+            r.draw_text(&format!("{winner} wins the match!"), 32., [64., 296.], Color::YELLOW);
+        }
+
+        let rules = &self.rules;
+        let game = if rules.race { "Race" } else { "Versus" };
+        let cancel = if rules.cancel { "On" } else { "Off" };
+        let table = rules.attack.preset_name().unwrap_or("Custom");
+        let values = [game.to_owned(), rules.best_of.to_string(), format!("{} ms", rules.min_das), format!("{} ms", rules.min_arr), cancel.to_owned(), table.to_owned()];
+        for (i, (rule, value)) in RULES.iter().zip(values).enumerate() {
+            let colour = if self.hosting && i == self.selected { Color::YELLOW } else { Color::WHITE };
+            r.draw_text(&format!("{rule}: {value}"), 24., [64., 340. + 36. * i as f32], colour);
+        }
+        for (player, handicap) in rules.handicaps.iter().enumerate() {
+            let who = if player == 0 { "Host" } else { "Guest" };
+            for (field, name) in handicap::FIELDS.iter().enumerate() {
+                let i = player * handicap::FIELDS.len() + field;
+                let colour = if self.hosting && RULES.len() + i == self.selected { Color::YELLOW } else { Color::WHITE };
+                r.draw_text(&format!("{who} {name}: {}", handicap.value(field)), 20., [368., 340. + 36. * i as f32], colour);
+            }
+        }
+        let attack = &rules.attack;
+        let sends = if rules.race {
+            format!("No garbage, the first to clear {SPRINT_LINES} lines wins")
+        } else {
+            format!("Tetris sends {}, T-spin double {}, perfect clear {} more", attack.lines[4], attack.t_spin[2], attack.perfect_clear)
+        };
+        r.draw_text(&sends, 24., [64., 340. + 36. * RULES.len() as f32], Color::WHITE);
+
+        self.chat.draw(r, (64., 600.));
+
+        let help = if self.hosting { "Up/Down: select  Left/Right: change  Confirm: ready  Back: leave" } else { "Confirm: ready  Back: leave" };
+        r.draw_text(help, 16., [64., SCREEN_SIZE.1 - 48.], Color::WHITE);
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// What the log file is called, in the user data directory.
+pub const LOG_FILE: &str = "tetris.log";
+
+/// Writes each message as a line to the log file, and warnings and errors to stderr as well.
+struct FileLogger {
+    file: Mutex<Option<File>>,
+    start: Instant,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "[{:9.3}] {:<5} {}: {}",
+            self.start.elapsed().as_secs_f32(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if record.level() <= Level::Warn {
+            eprintln!("{}", record.args());
+        }
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(f) = &mut *file {
+                // Nowhere left to report failing to write the log
+                let _ = writeln!(f, "{line}");
+            }
+        }
+    }
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(f) = &mut *file {
+                let _ = f.flush();
+            }
+        }
+    }
+}
+
+/// Starts logging messages up to `level` to `path`, keeping the log of the last run beside it with `.old` on the end,
+/// so it can be sent along with a bug report after a crash.
+pub fn init(path: &Path, level: LevelFilter) {
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::rename(path, path.with_extension("log.old"));
+    let file = File::create(path)
+        .inspect_err(|e| eprintln!("Could not create log file {}: {e}", path.display()))
+        .ok();
+    let logger = FileLogger {
+        file: Mutex::new(file),
+        start: Instant::now(),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -0,0 +1,227 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ggez::{graphics::Color, Context, GameError, GameResult};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::GameState,
+    config::Handling,
+    input::Action,
+    lobby::RoomRules,
+    render::{Renderer, SCREEN_SIZE},
+    scene::{Scene, Transition},
+    settings::MenuInput,
+    storage::Storage,
+    versus::{draw_match, draw_race_progress, Outcome, Side},
+};
+
+const MATCH_REPLAY_DIR: &str = "match-replays";
+pub const MATCH_REPLAY_EXTENSION: &str = "match";
+/// How many of the latest match replays are kept.
+const KEEP_MATCH_REPLAYS: usize = 20;
+
+/// Everything one player did in a game of a match: the inputs of each tick, and the garbage that landed before it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Track {
+    pub name: String,
+    pub handling: Handling,
+    /// The inputs as `(tick, action, pressed)`, counting ticks from 1.
+    inputs: Vec<(u32, Action, bool)>,
+    /// The garbage as `(tick, rows, hole)`, landing before the inputs of the tick.
+    garbage: Vec<(u32, u32, usize)>,
+    /// How many ticks the board played.
+    ticks: u32,
+}
+
+impl Track {
+    pub fn new(name: &str, handling: Handling) -> Self {
+        Track {
+            name: name.to_owned(),
+            handling,
+            ..Track::default()
+        }
+    }
+    /// Adds tick `tick`, the one after the last, played with `garbage` landing before `inputs`.
+    pub fn record(&mut self, tick: u32, garbage: &[(u32, usize)], inputs: &[(Action, bool)]) {
+        self.garbage.extend(garbage.iter().map(|&(rows, hole)| (tick, rows, hole)));
+        self.inputs.extend(inputs.iter().map(|&(action, pressed)| (tick, action, pressed)));
+        self.ticks = tick;
+    }
+    /// Adds garbage landing before tick `tick`, for when it is sent after the tick before has been recorded.
+    pub fn land(&mut self, tick: u32, rows: u32, hole: usize) {
+        self.garbage.push((tick, rows, hole));
+    }
+    // Both are recorded in tick order, so each tick's are found by searching
+    fn garbage(&self, tick: u32) -> &[(u32, u32, usize)] {
+        let start = self.garbage.partition_point(|&(t, ..)| t < tick);
+        let end = self.garbage.partition_point(|&(t, ..)| t <= tick);
+        &self.garbage[start..end]
+    }
+    fn inputs(&self, tick: u32) -> Vec<(Action, bool)> {
+        let start = self.inputs.partition_point(|&(t, ..)| t < tick);
+        let end = self.inputs.partition_point(|&(t, ..)| t <= tick);
+        self.inputs[start..end].iter().map(|&(_, action, pressed)| (action, pressed)).collect()
+    }
+}
+
+/// One game of a match, with a track for each of the seed's boards.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Round {
+    pub seed: u64,
+    pub rules: RoomRules,
+    pub tracks: [Track; 2],
+    /// Which of the seed's boards won, or `None` for a draw.
+    pub winner: Option<usize>,
+}
+
+impl Round {
+    pub fn new(seed: u64, rules: RoomRules, tracks: [Track; 2]) -> Self {
+        Round { seed, rules, tracks, winner: None }
+    }
+}
+
+/// Both boards of every game of a versus match, to be watched side by side afterwards.
+/// Unlike a single game's `Replay`, which only needs the inputs, each board also needs the garbage
+/// that landed on it, as that came from the other player.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchReplay {
+    /// When the match started, in seconds since the Unix epoch, which names the file it is kept in.
+    started: u64,
+    pub rounds: Vec<Round>,
+}
+
+impl MatchReplay {
+    pub fn new() -> Self {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        MatchReplay { started, rounds: Vec::new() }
+    }
+    fn dir(profile_dir: &str) -> String {
+        format!("{profile_dir}/{MATCH_REPLAY_DIR}")
+    }
+    /// The keys of the saved match replays, newest first.
+    pub fn list(storage: &dyn Storage, profile_dir: &str) -> Vec<String> {
+        let mut keys = storage.list(&Self::dir(profile_dir)).unwrap_or_else(|e| {
+            warn!("Could not list match replays: {e}");
+            Vec::new()
+        });
+        keys.retain(|key| key.ends_with(&format!(".{MATCH_REPLAY_EXTENSION}")));
+        keys.sort();
+        keys.reverse();
+        keys
+    }
+    pub fn load(storage: &dyn Storage, key: &str) -> GameResult<Self> {
+        let data = storage
+            .read(key)?
+            .ok_or_else(|| GameError::CustomError(format!("There is no match replay {key}")))?;
+        serde_json::from_slice(&data).map_err(|e| GameError::CustomError(format!("Invalid match replay: {e}")))
+    }
+    /// Saves the match as it is so far, over what was saved of it before, dropping the oldest replays beyond the latest few.
+    pub fn save(&self, storage: &dyn Storage, profile_dir: &str) -> GameResult {
+        let Some(round) = self.rounds.first() else {
+            return Ok(());
+        };
+        let dir = Self::dir(profile_dir);
+        let kind = if round.rules.race { "race" } else { "versus" };
+        let data = serde_json::to_vec(self).map_err(|e| GameError::CustomError(e.to_string()))?;
+        storage.write(&format!("{dir}/{}-{kind}.{MATCH_REPLAY_EXTENSION}", self.started), &data)?;
+
+        let mut latest = Self::list(storage, profile_dir);
+        for key in latest.split_off(KEEP_MATCH_REPLAYS.min(latest.len())) {
+            storage.remove(&key)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for MatchReplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Plays a match replay back a game at a time, with both boards side by side.
+pub struct MatchReplayScene {
+    replay: MatchReplay,
+    round: usize,
+    sides: [Side; 2],
+    tick: u32,
+    /// The games each board has won up to and including the one being watched, once it is over.
+    wins: [u32; 2],
+}
+
+impl MatchReplayScene {
+    pub fn new(state: &GameState, replay: MatchReplay) -> Self {
+        info!("Watching a match replay of {} games", replay.rounds.len());
+        let sides = Self::sides(state, &replay.rounds[0]);
+        MatchReplayScene { replay, round: 0, sides, tick: 0, wins: [0; 2] }
+    }
+    fn sides(state: &GameState, round: &Round) -> [Side; 2] {
+        let rules = &round.rules;
+        [0, 1].map(|i| Side::new(round.seed, i, rules.mode(), round.tracks[i].handling, &state.game_config, rules.handicaps[i]))
+    }
+    fn current(&self) -> &Round {
+        &self.replay.rounds[self.round]
+    }
+    fn finished(&self) -> bool {
+        self.current().tracks.iter().all(|track| self.tick >= track.ticks)
+    }
+}
+
+impl Scene for MatchReplayScene {
+    fn update(&mut self, _state: &mut GameState, _ctx: &mut Context) -> Transition {
+        if self.finished() {
+            return Transition::None;
+        }
+        self.tick += 1;
+        let round = &self.replay.rounds[self.round];
+        for (side, track) in self.sides.iter_mut().zip(&round.tracks) {
+            if self.tick > track.ticks {
+                continue;
+            }
+            for &(_, rows, hole) in track.garbage(self.tick) {
+                side.board.game.receive_garbage(rows, hole);
+            }
+            // What a board sent is already in the other board's garbage
+            side.tick(&track.inputs(self.tick), &round.rules.attack, round.rules.cancel);
+        }
+        if self.finished() {
+            if let Some(winner) = round.winner {
+                self.wins[winner] += 1;
+            }
+        }
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let round = self.current();
+        let outcome = self.finished().then(|| round.winner.map_or(Outcome::Draw, Outcome::Winner));
+        let boards = [0, 1].map(|i| (round.tracks[i].name.as_str(), &self.sides[i].board));
+        draw_match(r, boards, 0, outcome, &state.theme);
+        if round.rules.race {
+            draw_race_progress(r, boards.map(|(_, board)| board.game.lines));
+        }
+        let footer = [16., SCREEN_SIZE.1 - 32.];
+        let games = format!("Game {} of {}  Games won: {} - {}", self.round + 1, self.replay.rounds.len(), self.wins[0], self.wins[1]);
+        r.draw_text(&games, 16., [16., SCREEN_SIZE.1 - 56.], Color::WHITE);
+        let help = if self.round + 1 < self.replay.rounds.len() { "Confirm: next game  Back: stop watching" } else { "Back: stop watching" };
+        r.draw_text(help, 16., footer, Color::WHITE);
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        match input {
+            MenuInput::Confirm if self.round + 1 < self.replay.rounds.len() => {
+                // A game skipped before it ended still counts towards the games won
+                if !self.finished() {
+                    if let Some(winner) = self.current().winner {
+                        self.wins[winner] += 1;
+                    }
+                }
+                self.round += 1;
+                self.sides = Self::sides(state, self.current());
+                self.tick = 0;
+            }
+            MenuInput::Back => return Transition::Pop(1),
+            _ => (),
+        }
+        Transition::None
+    }
+}
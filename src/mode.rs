@@ -0,0 +1,56 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ruleset::{GameRules, Marathon, PLUGINS};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Play until topping out.
+    #[default]
+    Marathon,
+    /// Clear 40 lines as fast as possible.
+    Sprint,
+    /// Score as much as possible in two minutes.
+    Ultra,
+    /// Play without an end, taking back placements. Not ranked.
+    Practice,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 4] = [Mode::Marathon, Mode::Sprint, Mode::Ultra, Mode::Practice];
+
+    /// The name used for this mode's sections in the config.
+    pub fn key(self) -> &'static str {
+        match self {
+            Mode::Marathon => "marathon",
+            Mode::Sprint => "sprint",
+            Mode::Ultra => "ultra",
+            Mode::Practice => "practice",
+        }
+    }
+    pub fn cycle(self, forward: bool) -> Self {
+        let i = Self::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        let n = Self::ALL.len();
+        Self::ALL[if forward { (i + 1) % n } else { (i + n - 1) % n }]
+    }
+    /// The rules games of this mode are played by, from its plugin.
+    pub fn rules(self) -> &'static dyn GameRules {
+        PLUGINS
+            .iter()
+            .find(|&&(mode, _)| mode == self)
+            .map_or(&Marathon, |&(_, rules)| rules)
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Marathon => "Marathon",
+            Mode::Sprint => "Sprint",
+            Mode::Ultra => "Ultra",
+            Mode::Practice => "Practice",
+        })
+    }
+}
@@ -0,0 +1,457 @@
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{GameConfig, Handling},
+    input::Action,
+    lobby::RoomRules,
+    mode::Mode,
+    royale::Targeting,
+    rules::{Game, TICKS_PER_SECOND},
+    versus::SideSnapshot,
+};
+
+/// The port games are hosted on unless another is given.
+pub const DEFAULT_PORT: u16 = 7777;
+/// The version of the messages, raised whenever they change in a way an older game could not follow.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// What this game can do beyond what the protocol version promises, offered in the handshake
+/// so later additions can be used only with the games that know them.
+pub const CAPABILITIES: [&str; 0] = [];
+/// How long to try reaching a host for before giving up.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long sending a message may wait on the other end to take it before it is given up on.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many messages may be waiting to go out before the other end is taken to have stopped taking them.
+pub const MAX_QUEUED: usize = 1000;
+/// The longest a message can be, in bytes, far longer than even the boards sent to get back into a match.
+const MAX_LINE: u64 = 1 << 20;
+/// How long a server's match waits for a player whose connection dropped to get back into it before they lose.
+pub const RECONNECT_GRACE: Duration = Duration::from_secs(30);
+/// How often a player sends a server a checksum of their board, in ticks.
+pub const CHECKSUM_INTERVAL: u32 = TICKS_PER_SECOND;
+/// The port games hosted on the local network are announced on.
+pub const DISCOVERY_PORT: u16 = 7778;
+/// How often a host announces its game.
+const BEACON_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a host is listed for after it was last heard from.
+const DISCOVERED_FOR: Duration = Duration::from_secs(4);
+/// Tells announcements of games apart from anything else sent to the port.
+const BEACON_GAME: &str = "tetris";
+
+/// What the two ends of an online match send each other, as a line of JSON each.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// Sent by both ends first thing on connecting, so games that would not play out the same fail straight away
+    /// rather than falling out of step in the middle of a match.
+    Handshake {
+        version: u32,
+        /// `GameConfig::rules_hash` of the config the boards are played with.
+        rules: u64,
+        /// The checksum of a game played the same way everywhere, which only comes out the same if the game logic is.
+        engine: u64,
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
+    /// Sent by a server to someone it won't play with, e.g. after a handshake that doesn't match, before hanging up.
+    Refused { reason: String },
+    /// Sent by both ends on connecting, after the handshake, with the handling their board is played with.
+    Hello { name: String, handling: Handling },
+    /// Sent by the host when it changes the rules in the lobby.
+    Rules { rules: RoomRules },
+    /// Sent by both ends in the lobby when their player is ready to start, or no longer is.
+    Ready { ready: bool },
+    /// Sent by the host or server to start a game, with the rules it is played by
+    /// and which of the seed's two boards the receiver plays.
+    Start { seed: u64, rules: RoomRules, player: usize },
+    /// Tick `tick` of the sender's board: the garbage that arrived on it before the tick, and the inputs played on it.
+    Tick {
+        tick: u32,
+        garbage: Vec<(u32, usize)>,
+        inputs: Vec<(Action, bool)>,
+    },
+    /// Sent to a server instead of `Hello` to watch its matches rather than play.
+    Watch,
+    /// Sent by a server to someone watching, with what they need to follow the match being played.
+    Spectate { seed: u64, rules: RoomRules, players: [(String, Handling); 2] },
+    /// A tick of player `player`'s board, sent by a server to someone watching, a few seconds after it was played,
+    /// or to the other players of a battle royale as soon as it comes in.
+    Watched {
+        player: usize,
+        tick: u32,
+        garbage: Vec<(u32, usize)>,
+        inputs: Vec<(Action, bool)>,
+    },
+    /// Sent by a server when it has decided the match, with the board that won, or `None` for a draw.
+    /// Its word is final, e.g. when the other player left or sent something that could not happen.
+    Result { winner: Option<usize> },
+    /// Sent to a server instead of `Hello` to play in its next battle royale.
+    JoinRoyale { name: String, handling: Handling },
+    /// Sent by a server to start a battle royale between `players`, with which of them the receiver is.
+    RoyaleStart {
+        seed: u64,
+        rules: RoomRules,
+        player: usize,
+        players: Vec<(String, Handling)>,
+    },
+    /// Sent by a player of a battle royale to change who the garbage they send goes to.
+    Target { targeting: Targeting },
+    /// Sent by a server to the player of a battle royale that player `from` sent garbage to.
+    /// It lands before the receiver's next tick and goes in that tick's garbage, like garbage in a versus match.
+    Attack { from: usize, rows: u32, hole: usize },
+    /// Sent by a server to everyone in a battle royale when a player is out, with the place they came in
+    /// and who knocked them out, who gets a badge for it.
+    KnockedOut { player: usize, place: usize, by: Option<usize> },
+    /// A chat message from the sender's player, sent in the lobby or between games.
+    Chat { text: String },
+    /// Sent by a server along with the start of a match, for the player to get back into it with
+    /// if their connection drops.
+    Session { token: u64 },
+    /// Sent to a server instead of `Hello` to get back into the match `token` is for.
+    Rejoin { token: u64 },
+    /// Sent by a server to a player getting back into a match: both boards as of the last ticks it played of them,
+    /// the first the player's, and the garbage sent to the player that has yet to land.
+    Resync {
+        rules: RoomRules,
+        player: usize,
+        opponent: (String, Handling),
+        sides: Box<[SideSnapshot; 2]>,
+        ticks: [u32; 2],
+        incoming: Vec<(u32, usize)>,
+    },
+    /// Sent by a server when the other player's connection has dropped, and it is waiting for them to come back.
+    Dropped,
+    /// Sent by a server when the other player has got back into the match.
+    Rejoined,
+    /// Sent by a player to a server every `CHECKSUM_INTERVAL` ticks and when they top out: their board as it was
+    /// after tick `tick`, for the server to check against its own copy of the game.
+    Checksum { tick: u32, score: u32, lines: u32, checksum: u64 },
+}
+
+impl Message {
+    /// The handshake of this game, playing with `config`.
+    pub fn handshake(config: &GameConfig) -> Self {
+        Message::Handshake {
+            version: PROTOCOL_VERSION,
+            rules: config.rules_hash(),
+            engine: engine_checksum(),
+            capabilities: CAPABILITIES.iter().map(|&c| c.to_owned()).collect(),
+        }
+    }
+    /// The checksum of `game` as it is after tick `tick`.
+    pub fn checksum(tick: u32, game: &Game) -> Self {
+        Message::Checksum { tick, score: game.score, lines: game.lines, checksum: game.state_checksum() }
+    }
+    /// Whether a player sends the checksum of their board after tick `tick`.
+    pub fn checksum_due(tick: u32, game: &Game) -> bool {
+        tick.is_multiple_of(CHECKSUM_INTERVAL) || game.gameover
+    }
+}
+
+/// Checks the other end's handshake against this game's, playing with `config`,
+/// returning why they can't play together if they can't.
+pub fn check_handshake(version: u32, rules: u64, engine: u64, config: &GameConfig) -> Result<(), String> {
+    if version != PROTOCOL_VERSION {
+        return Err(format!("The other game speaks version {version} of the protocol, this one speaks {PROTOCOL_VERSION}. Update both to the same version."));
+    }
+    if engine != engine_checksum() {
+        return Err("The other game plays differently from this one. Update both to the same version.".to_owned());
+    }
+    if rules != config.rules_hash() {
+        return Err("The other game has a different board size or speed. Both need the same settings.".to_owned());
+    }
+    Ok(())
+}
+
+/// The checksum of a short game played the same way every time, to tell whether two builds of the game
+/// play out the same from the same inputs.
+fn engine_checksum() -> u64 {
+    static CHECKSUM: OnceLock<u64> = OnceLock::new();
+    *CHECKSUM.get_or_init(|| {
+        let mut game = Game::with_config(Mode::Marathon, Mode::Marathon.rules(), 0, Handling::default(), &GameConfig::default());
+        let moves = [Action::Left, Action::RotRight, Action::Right, Action::Hold, Action::RotLeft, Action::HardDrop];
+        for tick in 0..400 {
+            let action = moves[tick % moves.len()];
+            game.tick(&[(action, true), (action, false), (Action::HardDrop, tick % 3 == 0)]);
+        }
+        game.state_checksum()
+    })
+}
+
+/// A connection to the other player of an online match.
+/// Messages are read on a thread of their own and picked up with `receive`, so waiting on them never holds up a tick.
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Receiver<Message>,
+    /// Hands messages to the thread writing them out, so sending never waits on the other end.
+    /// Dropping it lets that thread finish what is queued and then hang up.
+    outgoing: Option<Sender<String>>,
+    /// How many messages have been sent that have not been written out yet.
+    queued: Arc<AtomicUsize>,
+    closed: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        // Inputs are small and go out every tick, so they shouldn't wait to be sent together
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(false)?;
+        // An other end that stops taking messages is hung up on rather than keeping the writing thread forever
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        let (outgoing, lines) = mpsc::channel::<String>();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let written = Arc::clone(&queued);
+        thread::Builder::new().name("net writer".to_owned()).spawn(move || {
+            for line in lines {
+                if let Err(e) = writer.write_all(line.as_bytes()) {
+                    warn!("Could not send to the other end: {e}");
+                    break;
+                }
+                written.fetch_sub(1, Ordering::Relaxed);
+            }
+            // Ends the reading thread, and tells the other end we're gone
+            let _ = writer.shutdown(Shutdown::Both);
+        })?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (sender, incoming) = mpsc::channel();
+        thread::Builder::new().name("net".to_owned()).spawn(move || {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                match (&mut reader).take(MAX_LINE).read_line(&mut line) {
+                    Ok(_) if line.ends_with('\n') => (),
+                    Ok(read) => {
+                        if read as u64 == MAX_LINE {
+                            warn!("Hanging up on a connection that sent a message over {MAX_LINE} bytes long");
+                            let _ = reader.get_ref().shutdown(Shutdown::Both);
+                        } else {
+                            debug!("Connection closed");
+                        }
+                        break;
+                    }
+                    Err(e) => {
+                        debug!("Connection closed: {e}");
+                        break;
+                    }
+                }
+                let line = line.trim_end();
+                match serde_json::from_str(line) {
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("Ignoring message that could not be read ({e}): {line}"),
+                }
+            }
+        })?;
+        Ok(Connection {
+            stream,
+            incoming,
+            outgoing: Some(outgoing),
+            queued,
+            closed: false,
+        })
+    }
+    /// Connects to a game hosted at `address`, taking the default port if it has none.
+    /// This blocks for up to a few seconds per address it resolves to, so it belongs on a `Worker`.
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let address = address.trim();
+        let addrs: Vec<SocketAddr> = match address.to_socket_addrs() {
+            Ok(addrs) => addrs.collect(),
+            Err(_) => (address, DEFAULT_PORT).to_socket_addrs()?.collect(),
+        };
+        let mut error = io::Error::new(ErrorKind::NotFound, format!("{address} could not be found"));
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(stream) => return Connection::new(stream),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+    /// Queues `message` to be sent, hanging up at once if the other end has stopped taking them.
+    pub fn send(&mut self, message: &Message) {
+        let Some(outgoing) = self.outgoing.as_ref().filter(|_| !self.closed) else {
+            return;
+        };
+        if self.queued.load(Ordering::Relaxed) >= MAX_QUEUED {
+            warn!("Hanging up on a connection that has stopped taking messages");
+            let _ = self.stream.shutdown(Shutdown::Both);
+            self.close();
+            return;
+        }
+        let mut line = serde_json::to_string(message).expect("Messages can always be written");
+        line.push('\n');
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        if outgoing.send(line).is_err() {
+            // The writing thread has given up on the other end
+            self.close();
+        }
+    }
+    /// How many messages sent have not gone out yet.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+    /// The next message that has come in, if any.
+    pub fn receive(&mut self) -> Option<Message> {
+        match self.incoming.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+    /// Hangs up on the other end once what has been sent has gone out, e.g. after it has been told why.
+    pub fn close(&mut self) {
+        self.outgoing = None;
+        self.closed = true;
+    }
+    /// Whether the other end has gone, once everything it sent has been received.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Waits for another player to join a game hosted here.
+#[derive(Debug)]
+pub struct Host {
+    listener: TcpListener,
+}
+
+impl Host {
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Host { listener })
+    }
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map_or(0, |addr| addr.port())
+    }
+    /// The player who has joined since last time, if any.
+    pub fn accept(&self) -> io::Result<Option<Connection>> {
+        match self.listener.accept() {
+            Ok((stream, addr)) => {
+                debug!("{addr} joined");
+                Connection::new(stream).map(Some)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// What a host broadcasts to the local network so it can be joined without typing in its address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Announcement {
+    game: String,
+    name: String,
+    port: u16,
+}
+
+/// Announces a game hosted here to the local network every so often, to be listed by `Discovery`.
+#[derive(Debug)]
+pub struct Beacon {
+    socket: UdpSocket,
+    announcement: Vec<u8>,
+    last: Option<Instant>,
+}
+
+impl Beacon {
+    /// Announces the game `name` hosts on `port`.
+    pub fn new(name: &str, port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        let announcement = Announcement { game: BEACON_GAME.to_owned(), name: name.to_owned(), port };
+        Ok(Beacon {
+            socket,
+            announcement: serde_json::to_vec(&announcement).expect("Announcements can always be written"),
+            last: None,
+        })
+    }
+    /// Sends the announcement again if it has been long enough.
+    pub fn update(&mut self) {
+        if self.last.is_some_and(|last| last.elapsed() < BEACON_INTERVAL) {
+            return;
+        }
+        self.last = Some(Instant::now());
+        if let Err(e) = self.socket.send_to(&self.announcement, (Ipv4Addr::BROADCAST, DISCOVERY_PORT)) {
+            debug!("Could not announce the game: {e}");
+        }
+    }
+}
+
+/// A game announced on the local network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discovered {
+    pub name: String,
+    pub address: SocketAddr,
+    seen: Instant,
+}
+
+/// Listens for games announced on the local network by a `Beacon`.
+#[derive(Debug)]
+pub struct Discovery {
+    socket: UdpSocket,
+    hosts: Vec<Discovered>,
+}
+
+impl Discovery {
+    pub fn listen() -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))?;
+        socket.set_nonblocking(true)?;
+        Ok(Discovery { socket, hosts: Vec::new() })
+    }
+    /// Takes in the announcements that have come in, and forgets the hosts that have gone quiet.
+    pub fn update(&mut self) {
+        let mut buf = [0; 512];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug!("Could not listen for games: {e}");
+                    break;
+                }
+            };
+            let Ok(announcement) = serde_json::from_slice::<Announcement>(&buf[..len]) else {
+                continue;
+            };
+            if announcement.game != BEACON_GAME {
+                continue;
+            }
+            let address = SocketAddr::new(from.ip(), announcement.port);
+            let seen = Instant::now();
+            match self.hosts.iter_mut().find(|host| host.address == address) {
+                Some(host) => {
+                    host.name = announcement.name;
+                    host.seen = seen;
+                }
+                None => self.hosts.push(Discovered { name: announcement.name, address, seen }),
+            }
+        }
+        self.hosts.retain(|host| host.seen.elapsed() < DISCOVERED_FOR);
+    }
+    /// The games heard from lately, in the order they were first heard.
+    pub fn hosts(&self) -> &[Discovered] {
+        &self.hosts
+    }
+}
@@ -0,0 +1,811 @@
+use std::{io, thread, time::{Duration, Instant}};
+
+use ggez::{
+    event::{Button, GamepadId},
+    graphics::{Color, Rect},
+    input::keyboard::{KeyCode, KeyMods},
+    Context,
+};
+use log::{info, warn};
+
+use crate::{
+    app::{random_seed, GameState},
+    chat::Chat,
+    config::Handling,
+    input::Action,
+    lobby::{Lobby, RoomRules},
+    match_replay::{Round, Track},
+    mode::Mode,
+    net::{check_handshake, Beacon, Connection, Discovered, Discovery, Host, Message, DEFAULT_PORT, RECONNECT_GRACE},
+    render::{Renderer, SCREEN_SIZE},
+    royale::RoyaleScene,
+    scene::{Scene, Transition},
+    settings::MenuInput,
+    versus::{decide_match, draw_match, draw_race_progress, Controls, Outcome, Side, SideSnapshot, COUNTDOWN_MS},
+    worker::Worker,
+};
+
+/// How far ahead of the last inputs from the other player their board is guessed, in ticks.
+const MAX_PREDICTION_TICKS: u32 = 12;
+/// How long to wait between tries at getting back to a server after the connection dropped.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+const HOST: usize = 0;
+const WATCH: usize = 2;
+const ROYALE: usize = 3;
+const ENTRIES: usize = 4;
+
+/// Picks whether to host an online versus game, or join or watch one at an address typed in,
+/// or play in a battle royale on a server. Games hosted on the local network are listed after these to join.
+pub struct OnlineMenuScene {
+    selected: usize,
+    address: String,
+    /// Listens for games on the local network, unless the port could not be listened on.
+    discovery: Option<Discovery>,
+}
+
+impl OnlineMenuScene {
+    pub fn new() -> Self {
+        let discovery = Discovery::listen().map_err(|e| warn!("Could not listen for games on the local network: {e}")).ok();
+        OnlineMenuScene {
+            selected: HOST,
+            address: format!("127.0.0.1:{DEFAULT_PORT}"),
+            discovery,
+        }
+    }
+    fn discovered(&self) -> &[Discovered] {
+        self.discovery.as_ref().map_or(&[], Discovery::hosts)
+    }
+    fn entries(&self) -> usize {
+        ENTRIES + self.discovered().len()
+    }
+    /// Whether what is typed goes into the address.
+    fn typing(&self) -> bool {
+        self.selected != HOST && self.selected < ENTRIES
+    }
+}
+
+impl Default for OnlineMenuScene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scene for OnlineMenuScene {
+    fn update(&mut self, _state: &mut GameState, _ctx: &mut Context) -> Transition {
+        if let Some(discovery) = &mut self.discovery {
+            discovery.update();
+        }
+        // The game selected may have stopped being announced
+        self.selected = self.selected.min(self.entries() - 1);
+        Transition::None
+    }
+    fn draw(&self, _state: &GameState, r: &mut dyn Renderer) {
+        r.draw_text("Online versus", 48., [64., 64.], Color::WHITE);
+        let entries = [
+            format!("Host on port {DEFAULT_PORT}"),
+            "Join".to_owned(),
+            "Watch a server's match".to_owned(),
+            "Battle royale on a server".to_owned(),
+        ];
+        for (i, entry) in entries.iter().enumerate() {
+            let colour = if i == self.selected { Color::YELLOW } else { Color::WHITE };
+            r.draw_text(entry, 32., [64., 160. + 48. * i as f32], colour);
+        }
+        let colour = if self.typing() { Color::YELLOW } else { Color::WHITE };
+        r.draw_text(&format!("Address: {}_", self.address), 24., [64., 176. + 48. * ENTRIES as f32], colour);
+
+        let top = 256. + 48. * ENTRIES as f32;
+        match &self.discovery {
+            Some(discovery) if discovery.hosts().is_empty() => r.draw_text("No games found on this network", 24., [64., top], Color::WHITE),
+            Some(discovery) => {
+                r.draw_text("Games on this network:", 24., [64., top], Color::WHITE);
+                for (i, host) in discovery.hosts().iter().enumerate() {
+                    let colour = if self.selected == ENTRIES + i { Color::YELLOW } else { Color::WHITE };
+                    r.draw_text(&format!("Join {} ({})", host.name, host.address.ip()), 32., [64., top + 40. + 44. * i as f32], colour);
+                }
+            }
+            None => (),
+        }
+        r.draw_text("Up/Down: select  Type: address  Confirm: start  Back: choose mode", 16., [64., SCREEN_SIZE.1 - 48.], Color::WHITE);
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        let entries = self.entries();
+        match input {
+            MenuInput::Up => self.selected = (self.selected + entries - 1) % entries,
+            MenuInput::Down => self.selected = (self.selected + 1) % entries,
+            MenuInput::Confirm if self.selected == HOST => return Transition::Push(Box::new(OnlineVersusScene::host(&state.config.profile))),
+            MenuInput::Confirm if self.selected >= ENTRIES => {
+                let address = self.discovered()[self.selected - ENTRIES].address.to_string();
+                return Transition::Push(Box::new(OnlineVersusScene::join(&address)));
+            }
+            MenuInput::Confirm if self.selected == WATCH => return Transition::Push(Box::new(SpectateScene::new(&self.address))),
+            MenuInput::Confirm if self.selected == ROYALE => return Transition::Push(Box::new(RoyaleScene::join(&self.address))),
+            MenuInput::Confirm => return Transition::Push(Box::new(OnlineVersusScene::join(&self.address))),
+            MenuInput::Back => return Transition::Pop(1),
+            MenuInput::Adjust(_) | MenuInput::NewProfile => (),
+        }
+        Transition::None
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, _repeated: bool) -> Transition {
+        if self.typing() {
+            match keycode {
+                KeyCode::Back => {
+                    self.address.pop();
+                    return Transition::None;
+                }
+                // These are typed into the address rather than moving around the menu
+                KeyCode::W | KeyCode::A | KeyCode::S | KeyCode::D | KeyCode::N => return Transition::None,
+                _ => (),
+            }
+        }
+        match MenuInput::from_key(keycode, mods, &state.bindings) {
+            Some(input) => self.menu_input(state, ctx, input),
+            None => Transition::None,
+        }
+    }
+    fn text_input(&mut self, _state: &mut GameState, character: char) {
+        if self.typing() && !character.is_control() && !character.is_whitespace() {
+            self.address.push(character);
+        }
+    }
+}
+
+/// How far an online match has got.
+enum Phase {
+    /// Waiting for someone to join, announcing the game to the local network meanwhile.
+    Listening(Host, Option<Beacon>),
+    Connecting(Worker<String, io::Result<Connection>>),
+    Lobby(Box<Lobby>),
+    Playing(Box<Match>),
+    Failed(String),
+}
+
+/// A versus game against another player over the network.
+/// Each end plays its own board and sends the inputs of every tick to the other,
+/// which plays them out on a copy of that board, so the boards only need the seed to agree.
+/// The garbage each board sends is worked out by the copy of it at the other end, where it lands,
+/// and sent back along with the next tick's inputs so that both copies get it on the same tick.
+pub struct OnlineVersusScene {
+    phase: Phase,
+    /// Where the game was joined, to get back to if the connection drops.
+    address: Option<String>,
+}
+
+impl OnlineVersusScene {
+    /// Hosts a game, announced to the local network as `name`'s.
+    pub fn host(name: &str) -> Self {
+        let port = DEFAULT_PORT;
+        let phase = match Host::listen(port) {
+            Ok(host) => {
+                let beacon = Beacon::new(name, host.port()).map_err(|e| warn!("Could not announce the game to the local network: {e}")).ok();
+                Phase::Listening(host, beacon)
+            }
+            Err(e) => {
+                warn!("Could not host on port {port}: {e}");
+                Phase::Failed(format!("Could not host on port {port}: {e}"))
+            }
+        };
+        OnlineVersusScene { phase, address: None }
+    }
+    pub fn join(address: &str) -> Self {
+        let worker = Worker::spawn("connect", |address: String, _| Connection::connect(&address));
+        worker.send(address.to_owned());
+        OnlineVersusScene {
+            phase: Phase::Connecting(worker),
+            address: Some(address.to_owned()),
+        }
+    }
+    /// The lobby, when its chat can be used: in the lobby itself and once a game is over.
+    fn chatting(&mut self) -> Option<&mut Lobby> {
+        match &mut self.phase {
+            Phase::Lobby(lobby) => Some(lobby),
+            Phase::Playing(game) if game.outcome.is_some() => Some(&mut game.lobby),
+            _ => None,
+        }
+    }
+    /// Tells the other end which game this is, and who is playing it.
+    fn greet(state: &GameState, connection: &mut Connection) {
+        connection.send(&Message::handshake(&state.game_config));
+        connection.send(&Message::Hello {
+            name: state.config.profile.clone(),
+            handling: state.config.profile().handling,
+        });
+    }
+}
+
+impl Scene for OnlineVersusScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        let phase = std::mem::replace(&mut self.phase, Phase::Failed(String::new()));
+        self.phase = match phase {
+            Phase::Listening(host, mut beacon) => match host.accept() {
+                Ok(Some(mut connection)) => {
+                    Self::greet(state, &mut connection);
+                    let rules = RoomRules { attack: state.config.attack.clone(), ..RoomRules::default() };
+                    Phase::Lobby(Box::new(Lobby::new(connection, true, rules)))
+                }
+                Ok(None) => {
+                    if let Some(beacon) = &mut beacon {
+                        beacon.update();
+                    }
+                    Phase::Listening(host, beacon)
+                }
+                Err(e) => Phase::Failed(format!("Could not accept a player: {e}")),
+            },
+            Phase::Connecting(worker) => {
+                let result = worker.results().next();
+                match result {
+                    Some(Ok(mut connection)) => {
+                        Self::greet(state, &mut connection);
+                        Phase::Lobby(Box::new(Lobby::new(connection, false, RoomRules::default())))
+                    }
+                    Some(Err(e)) => Phase::Failed(format!("Could not connect: {e}")),
+                    None => Phase::Connecting(worker),
+                }
+            }
+            Phase::Lobby(mut lobby) => match lobby.update(&state.game_config, || state.seed.unwrap_or_else(random_seed)) {
+                Ok(Some((seed, player))) => Phase::Playing(Box::new(Match::new(state, *lobby, seed, player))),
+                Ok(None) if lobby.connection.is_closed() => Phase::Failed("The other player left".to_owned()),
+                Ok(None) => Phase::Lobby(lobby),
+                Err(e) => {
+                    warn!("Could not play with the other player: {e}");
+                    Phase::Failed(e)
+                }
+            },
+            Phase::Playing(mut game) => {
+                let over = game.outcome.is_some();
+                game.update(state, self.address.as_deref());
+                if !over && game.outcome.is_some() {
+                    if let Err(e) = game.lobby.replay.save(&*state.storage, &state.data.dir) {
+                        warn!("Could not save the match replay: {e}");
+                    }
+                }
+                Phase::Playing(game)
+            }
+            Phase::Failed(message) => Phase::Failed(message),
+        };
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let middle = [SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 / 2.];
+        let back = [16., SCREEN_SIZE.1 - 32.];
+        match &self.phase {
+            Phase::Listening(host, _) => {
+                r.draw_text_centred(&format!("Waiting for a player on port {}", host.port()), 32., middle, Color::WHITE);
+                r.draw_text("Back: cancel", 16., back, Color::WHITE);
+            }
+            Phase::Connecting(_) => {
+                r.draw_text_centred("Connecting...", 32., middle, Color::WHITE);
+                r.draw_text("Back: cancel", 16., back, Color::WHITE);
+            }
+            Phase::Lobby(lobby) => lobby.draw(&state.config.profile, r),
+            Phase::Playing(game) => game.draw(state, r),
+            Phase::Failed(message) => {
+                r.draw_text_centred(message, 24., middle, Color::RED);
+                r.draw_text("Back: choose again", 16., back, Color::WHITE);
+            }
+        }
+    }
+    fn menu_input(&mut self, _state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        if input == MenuInput::Back {
+            return Transition::Pop(1);
+        }
+        let phase = std::mem::replace(&mut self.phase, Phase::Failed(String::new()));
+        self.phase = match phase {
+            Phase::Lobby(mut lobby) => {
+                lobby.menu_input(input);
+                Phase::Lobby(lobby)
+            }
+            Phase::Playing(game) if input == MenuInput::Confirm && game.outcome.is_some() => Phase::Lobby(Box::new(game.lobby)),
+            phase => phase,
+        };
+        Transition::None
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, repeated: bool) -> Transition {
+        if let Phase::Playing(game) = &mut self.phase {
+            if game.playing() {
+                if !repeated && game.controls.key_down(keycode) == Some(Action::Pause) {
+                    // Leaving gives up the match
+                    return Transition::Pop(1);
+                }
+                return Transition::None;
+            }
+        }
+        if let Some(lobby) = self.chatting() {
+            if lobby.chat_key(&state.config.profile, keycode) {
+                return Transition::None;
+            }
+        }
+        match MenuInput::from_key(keycode, mods, &state.bindings) {
+            Some(input) => self.menu_input(state, ctx, input),
+            None => Transition::None,
+        }
+    }
+    fn key_up(&mut self, _state: &mut GameState, keycode: KeyCode) {
+        if let Phase::Playing(game) = &mut self.phase {
+            game.controls.key_up(keycode);
+        }
+    }
+    fn text_input(&mut self, _state: &mut GameState, character: char) {
+        if let Some(lobby) = self.chatting() {
+            lobby.chat.text_input(character);
+        }
+    }
+    fn gamepad_button_down(&mut self, state: &mut GameState, ctx: &mut Context, btn: Button, _id: GamepadId) -> Transition {
+        if let Phase::Playing(game) = &mut self.phase {
+            if game.playing() {
+                match state.config.profile().buttons.action(btn) {
+                    Some(Action::Pause) => return Transition::Pop(1),
+                    Some(action) => game.controls.push(action, true),
+                    None => (),
+                }
+                return Transition::None;
+            }
+        }
+        match state.config.profile().buttons.menu_input(btn) {
+            Some(input) => self.menu_input(state, ctx, input),
+            None => Transition::None,
+        }
+    }
+    fn gamepad_button_up(&mut self, state: &mut GameState, btn: Button, _id: GamepadId) {
+        if let Phase::Playing(game) = &mut self.phase {
+            if let Some(action) = state.config.profile().buttons.action(btn).filter(|&a| a != Action::Pause) {
+                game.controls.push(action, false);
+            }
+        }
+    }
+}
+
+/// An online match under way, with the board played here first and the other player's second.
+///
+/// The other player's inputs take a while to get here, so what is shown of their board is a guess at where
+/// it has got to by now: a copy of it played on from their last inputs as if they were still holding the
+/// same keys. When their real inputs arrive, the guess is thrown away and played again from the board as
+/// those inputs leave it. Only the real board decides the garbage and the winner, so guessing wrong
+/// never changes how the match goes, only what is seen of it for a moment.
+///
+/// A match on a server can be got back into if the connection drops: this board stops until the server
+/// has sent both boards back as it last played them, and carries on from there.
+struct Match {
+    lobby: Lobby,
+    names: [String; 2],
+    /// Which of the seed's boards is played here.
+    player: usize,
+    sides: [Side; 2],
+    /// The other player's board guessed ahead to this board's tick, as far as `MAX_PREDICTION_TICKS` allows.
+    predicted: Side,
+    predicted_ticks: u32,
+    controls: Controls,
+    countdown_ms: u32,
+    /// How many ticks each board has played.
+    ticks: [u32; 2],
+    /// The tick each board topped out on.
+    topped_out: [Option<u32>; 2],
+    /// Garbage sent by the other board, to land on this one before its next tick.
+    incoming: Vec<(u32, usize)>,
+    outcome: Option<Outcome>,
+    /// What the server said to get back into the match with, if it is played on one.
+    session: Option<u64>,
+    reconnect: Option<Reconnect>,
+    /// Whether the server is waiting for the other player to get back into the match.
+    opponent_dropped: bool,
+    /// Whether the game is being recorded in the lobby's replay of the match.
+    recording: bool,
+}
+
+/// How getting back into a match on a server after the connection dropped is going.
+enum Reconnect {
+    Connecting(Worker<String, io::Result<Connection>>),
+    /// Connected again, and waiting for the server to send the boards back.
+    Resyncing(Connection),
+    Failed(String),
+}
+
+impl Match {
+    fn new(state: &GameState, mut lobby: Lobby, seed: u64, player: usize) -> Self {
+        let (name, handling) = lobby.opponent.clone().expect("A game only starts once the other player has said hello");
+        info!("Starting an online versus game against {name} with seed {seed}");
+        let profile = state.config.profile();
+        // The replay has the boards in the seed's order
+        let mut tracks = [Track::new(&state.config.profile, lobby.rules.cap(profile.handling)), Track::new(&name, lobby.rules.cap(handling))];
+        if player == 1 {
+            tracks.reverse();
+        }
+        lobby.replay.rounds.push(Round::new(seed, lobby.rules.clone(), tracks));
+        let rules = &lobby.rules;
+        // Both ends play the same board of the seed for each player, so they agree on the gaps in the garbage
+        let (local, remote) = (player, 1 - player);
+        let remote = Side::new(seed, remote, rules.mode(), rules.cap(handling), &state.game_config, rules.handicaps[remote]);
+        Match {
+            names: [state.config.profile.clone(), name],
+            player,
+            predicted: remote.clone(),
+            predicted_ticks: 0,
+            sides: [Side::new(seed, local, rules.mode(), rules.cap(profile.handling), &state.game_config, rules.handicaps[local]), remote],
+            controls: Controls::new(profile.bindings(Mode::Marathon)),
+            lobby,
+            countdown_ms: COUNTDOWN_MS,
+            ticks: [0; 2],
+            topped_out: [None; 2],
+            incoming: Vec::new(),
+            outcome: None,
+            session: None,
+            reconnect: None,
+            opponent_dropped: false,
+            recording: true,
+        }
+    }
+    fn playing(&self) -> bool {
+        self.countdown_ms == 0 && self.outcome.is_none() && self.reconnect.is_none() && !self.lobby.connection.is_closed()
+    }
+    fn update(&mut self, state: &GameState, address: Option<&str>) {
+        // What comes in after the game is over is for the lobby
+        if self.outcome.is_some() {
+            return;
+        }
+        if let Some(reconnect) = self.reconnect.take() {
+            self.reconnect = self.update_reconnect(state, reconnect);
+            return;
+        }
+        if self.lobby.connection.is_closed() {
+            if let (Some(_), Some(address)) = (self.session, address) {
+                info!("Lost the connection to the server, reconnecting");
+                self.reconnect = Some(Reconnect::Connecting(Self::connect(address)));
+                return;
+            }
+        }
+        if self.countdown_ms > 0 {
+            self.countdown_ms = self.countdown_ms.saturating_sub(self.sides[0].board.game.ms_per_tick());
+        } else if self.topped_out[0].is_none() {
+            let garbage = std::mem::take(&mut self.incoming);
+            let inputs = self.controls.take();
+            self.record(self.player, self.ticks[0] + 1, &garbage, &inputs);
+            let side = &mut self.sides[0];
+            for &(rows, hole) in &garbage {
+                side.board.game.receive_garbage(rows, hole);
+            }
+            // What this board sends is worked out at the other end
+            side.tick(&inputs, &self.lobby.rules.attack, self.lobby.rules.cancel);
+            self.ticks[0] += 1;
+            if side.board.game.gameover {
+                self.topped_out[0] = Some(self.ticks[0]);
+            }
+            self.lobby.connection.send(&Message::Tick { tick: self.ticks[0], garbage, inputs });
+            // Only a server checks them, a player hosting plays out the other board themselves
+            let game = &self.sides[0].board.game;
+            if self.session.is_some() && Message::checksum_due(self.ticks[0], game) {
+                self.lobby.connection.send(&Message::checksum(self.ticks[0], game));
+            }
+            self.end(self.decide());
+        }
+
+        let confirmed = self.ticks[1];
+        while self.outcome.is_none() {
+            let Some(message) = self.lobby.connection.receive() else {
+                break;
+            };
+            match message {
+                Message::Tick { tick, garbage, inputs } if tick == self.ticks[1] + 1 && self.topped_out[1].is_none() => {
+                    self.record(1 - self.player, tick, &garbage, &inputs);
+                    let side = &mut self.sides[1];
+                    for (rows, hole) in garbage {
+                        side.board.game.receive_garbage(rows, hole);
+                    }
+                    let sent = side.tick(&inputs, &self.lobby.rules.attack, self.lobby.rules.cancel);
+                    if !self.lobby.rules.race {
+                        self.incoming.extend(sent);
+                    }
+                    self.ticks[1] = tick;
+                    if side.board.game.gameover {
+                        self.topped_out[1] = Some(tick);
+                    }
+                    self.end(self.decide());
+                }
+                Message::Result { winner } => self.end(Some(winner.map_or(Outcome::Draw, |w| Outcome::Winner(usize::from(w != self.player))))),
+                Message::Session { token } => self.session = Some(token),
+                Message::Dropped => self.opponent_dropped = true,
+                Message::Rejoined => self.opponent_dropped = false,
+                // Shown once the game is over
+                Message::Chat { text } => self.lobby.chat_received(&text),
+                message => warn!("Unexpected message during the match: {message:?}"),
+            }
+        }
+
+        self.predict(self.ticks[1] != confirmed);
+    }
+    /// Starts connecting to `address` on a worker, trying every `RECONNECT_INTERVAL` until the server
+    /// would have given up on this player.
+    fn connect(address: &str) -> Worker<String, io::Result<Connection>> {
+        let since = Instant::now();
+        let worker = Worker::spawn("reconnect", move |address: String, stop| loop {
+            thread::sleep(RECONNECT_INTERVAL);
+            match Connection::connect(&address) {
+                Err(e) if since.elapsed() < RECONNECT_GRACE && !stop.requested() => warn!("Could not reconnect: {e}"),
+                result => return result,
+            }
+        });
+        worker.send(address.to_owned());
+        worker
+    }
+    /// Carries on getting back into the match, returning how it is going unless it is back.
+    fn update_reconnect(&mut self, state: &GameState, reconnect: Reconnect) -> Option<Reconnect> {
+        let token = self.session.expect("Only matches with a session reconnect");
+        match reconnect {
+            Reconnect::Connecting(worker) => {
+                let result = worker.results().next();
+                match result {
+                Some(Ok(mut connection)) => {
+                    connection.send(&Message::handshake(&state.game_config));
+                    connection.send(&Message::Rejoin { token });
+                    Some(Reconnect::Resyncing(connection))
+                }
+                Some(Err(e)) => Some(Reconnect::Failed(format!("Could not get back to the server: {e}"))),
+                None => Some(Reconnect::Connecting(worker)),
+                }
+            }
+            Reconnect::Resyncing(mut connection) => {
+                while let Some(message) = connection.receive() {
+                    match message {
+                        Message::Handshake { version, rules, engine, .. } => {
+                            if let Err(e) = check_handshake(version, rules, engine, &state.game_config) {
+                                return Some(Reconnect::Failed(e));
+                            }
+                        }
+                        Message::Refused { reason } => return Some(Reconnect::Failed(reason)),
+                        Message::Resync { rules, player, sides, ticks, incoming, .. } => {
+                            self.resync(connection, rules, player, *sides, ticks, incoming);
+                            return None;
+                        }
+                        message => warn!("Unexpected message while reconnecting: {message:?}"),
+                    }
+                }
+                if connection.is_closed() {
+                    return Some(Reconnect::Failed("The server closed the connection".to_owned()));
+                }
+                Some(Reconnect::Resyncing(connection))
+            }
+            Reconnect::Failed(reason) => Some(Reconnect::Failed(reason)),
+        }
+    }
+    /// Carries on the match on `connection` from the boards the server sent back.
+    fn resync(&mut self, connection: Connection, rules: RoomRules, player: usize, sides: [SideSnapshot; 2], ticks: [u32; 2], incoming: Vec<(u32, usize)>) {
+        info!("Back in the match at tick {}", ticks[0]);
+        // The inputs that got the server's boards to where they are never all came here, so the game can't be played back
+        if self.recording {
+            self.lobby.replay.rounds.pop();
+            self.recording = false;
+        }
+        self.lobby.connection = connection;
+        self.lobby.rules = rules;
+        self.player = player;
+        self.sides = sides.map(Side::from_snapshot);
+        self.ticks = ticks;
+        self.topped_out = [0, 1].map(|i| self.sides[i].board.game.gameover.then_some(ticks[i]));
+        self.incoming = incoming;
+        self.countdown_ms = 0;
+        self.opponent_dropped = false;
+        self.predict(true);
+        self.end(self.decide());
+    }
+    fn decide(&self) -> Option<Outcome> {
+        decide_match(self.lobby.rules.race, [&self.sides[0].board, &self.sides[1].board], self.topped_out, self.ticks)
+    }
+    fn end(&mut self, outcome: Option<Outcome>) {
+        let Some(outcome) = outcome else {
+            return;
+        };
+        info!("Online versus game over: {outcome:?}");
+        self.outcome = Some(outcome);
+        if let Outcome::Winner(i) = outcome {
+            self.lobby.wins[i] += 1;
+        }
+        if let Some(round) = self.lobby.replay.rounds.last_mut().filter(|_| self.recording) {
+            round.winner = match outcome {
+                Outcome::Winner(0) => Some(self.player),
+                Outcome::Winner(_) => Some(1 - self.player),
+                Outcome::Draw => None,
+            };
+        }
+    }
+    /// Adds tick `tick` of the seed's board `board` to the replay.
+    fn record(&mut self, board: usize, tick: u32, garbage: &[(u32, usize)], inputs: &[(Action, bool)]) {
+        if let Some(round) = self.lobby.replay.rounds.last_mut().filter(|_| self.recording) {
+            round.tracks[board].record(tick, garbage, inputs);
+        }
+    }
+    /// Plays the guess at the other player's board on to this board's tick,
+    /// first going back to their real board if new inputs of theirs have come in.
+    fn predict(&mut self, rollback: bool) {
+        if rollback {
+            self.predicted = self.sides[1].clone();
+            self.predicted_ticks = self.ticks[1];
+        }
+        let until = self.ticks[0].min(self.ticks[1] + MAX_PREDICTION_TICKS);
+        while self.predicted_ticks < until && !self.predicted.board.game.gameover {
+            // With no inputs, the keys held at their last tick stay held
+            self.predicted.tick(&[], &self.lobby.rules.attack, self.lobby.rules.cancel);
+            self.predicted_ticks += 1;
+        }
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        // A guess is never shown topping out, as only the real board can say that
+        let remote = if self.outcome.is_some() || self.predicted.board.game.gameover { &self.sides[1] } else { &self.predicted };
+        let boards = [(self.names[0].as_str(), &self.sides[0].board), (self.names[1].as_str(), &remote.board)];
+        draw_match(r, boards, self.countdown_ms, self.outcome, &state.theme);
+        if self.lobby.rules.race {
+            draw_race_progress(r, boards.map(|(_, board)| board.game.lines));
+        }
+        let footer = [16., SCREEN_SIZE.1 - 32.];
+        if self.outcome.is_some() {
+            let top = SCREEN_SIZE.1 - 48. - Chat::height();
+            r.draw_panel(Rect::new(0., top - 8., SCREEN_SIZE.0, Chat::height() + 8.), Color::new(0., 0., 0., 0.8));
+            self.lobby.chat.draw(r, (16., top));
+            r.draw_text("Confirm: back to the lobby  Back: leave", 16., footer, Color::WHITE);
+        } else if let Some(reconnect) = &self.reconnect {
+            let middle = [SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 / 2.];
+            match reconnect {
+                Reconnect::Failed(reason) => r.draw_text_centred(reason, 24., middle, Color::RED),
+                _ => r.draw_text_centred("Reconnecting...", 48., middle, Color::YELLOW),
+            }
+            r.draw_text("Back: choose mode", 16., footer, Color::WHITE);
+        } else if self.lobby.connection.is_closed() {
+            r.draw_text_centred(&format!("{} left", self.names[1]), 48., [SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 / 2.], Color::YELLOW);
+            r.draw_text("Back: choose mode", 16., footer, Color::WHITE);
+        } else if self.opponent_dropped {
+            let waiting = format!("{} lost their connection, waiting for them", self.names[1]);
+            r.draw_text_centred(&waiting, 24., [SCREEN_SIZE.0 / 2., 48.], Color::YELLOW);
+        }
+    }
+}
+
+/// How far watching a server's match has got.
+enum SpectatePhase {
+    Connecting(Worker<String, io::Result<Connection>>),
+    /// Waiting for the server to start a match.
+    Waiting(Connection),
+    Watching(Box<Spectated>),
+    Failed(String),
+}
+
+/// Watches a match played on a server, a few seconds behind the players.
+pub struct SpectateScene {
+    phase: SpectatePhase,
+}
+
+impl SpectateScene {
+    pub fn new(address: &str) -> Self {
+        let worker = Worker::spawn("connect", |address: String, _| Connection::connect(&address));
+        worker.send(address.to_owned());
+        SpectateScene {
+            phase: SpectatePhase::Connecting(worker),
+        }
+    }
+}
+
+impl Scene for SpectateScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        let phase = std::mem::replace(&mut self.phase, SpectatePhase::Failed(String::new()));
+        self.phase = match phase {
+            SpectatePhase::Connecting(worker) => {
+                let result = worker.results().next();
+                match result {
+                    Some(Ok(mut connection)) => {
+                        connection.send(&Message::handshake(&state.game_config));
+                        connection.send(&Message::Watch);
+                        SpectatePhase::Waiting(connection)
+                    }
+                    Some(Err(e)) => SpectatePhase::Failed(format!("Could not connect: {e}")),
+                    None => SpectatePhase::Connecting(worker),
+                }
+            }
+            SpectatePhase::Waiting(mut connection) => match connection.receive() {
+                Some(Message::Spectate { seed, rules, players }) => SpectatePhase::Watching(Box::new(Spectated::new(state, connection, seed, rules, players))),
+                Some(Message::Handshake { version, rules, engine, .. }) => match check_handshake(version, rules, engine, &state.game_config) {
+                    Ok(()) => SpectatePhase::Waiting(connection),
+                    Err(e) => SpectatePhase::Failed(e),
+                },
+                Some(Message::Refused { reason }) => SpectatePhase::Failed(reason),
+                Some(message) => {
+                    warn!("Unexpected message while waiting to watch: {message:?}");
+                    SpectatePhase::Waiting(connection)
+                }
+                None if connection.is_closed() => SpectatePhase::Failed("The server closed the connection".to_owned()),
+                None => SpectatePhase::Waiting(connection),
+            },
+            SpectatePhase::Watching(mut spectated) => {
+                spectated.update();
+                SpectatePhase::Watching(spectated)
+            }
+            SpectatePhase::Failed(message) => SpectatePhase::Failed(message),
+        };
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let middle = [SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 / 2.];
+        let back = [16., SCREEN_SIZE.1 - 32.];
+        match &self.phase {
+            SpectatePhase::Connecting(_) => r.draw_text_centred("Connecting...", 32., middle, Color::WHITE),
+            SpectatePhase::Waiting(_) => r.draw_text_centred("Waiting for a match to start", 32., middle, Color::WHITE),
+            SpectatePhase::Watching(spectated) => spectated.draw(state, r),
+            SpectatePhase::Failed(message) => r.draw_text_centred(message, 24., middle, Color::RED),
+        }
+        r.draw_text("Back: stop watching", 16., back, Color::WHITE);
+    }
+    fn menu_input(&mut self, _state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        match input {
+            MenuInput::Back => Transition::Pop(1),
+            _ => Transition::None,
+        }
+    }
+}
+
+/// A match being watched, with both boards played out from the ticks the server sends.
+struct Spectated {
+    connection: Connection,
+    names: [String; 2],
+    sides: [Side; 2],
+    rules: RoomRules,
+    ticks: [u32; 2],
+    topped_out: [Option<u32>; 2],
+    /// The rows of garbage each board has sent.
+    sent: [u32; 2],
+    outcome: Option<Outcome>,
+}
+
+impl Spectated {
+    fn new(state: &GameState, connection: Connection, seed: u64, rules: RoomRules, players: [(String, Handling); 2]) -> Self {
+        let [(first, first_handling), (second, second_handling)] = players;
+        info!("Watching {first} against {second}");
+        let handling = [first_handling, second_handling];
+        Spectated {
+            connection,
+            names: [first, second],
+            sides: [0, 1].map(|i| Side::new(seed, i, rules.mode(), rules.cap(handling[i]), &state.game_config, rules.handicaps[i])),
+            rules,
+            ticks: [0; 2],
+            topped_out: [None; 2],
+            sent: [0; 2],
+            outcome: None,
+        }
+    }
+    fn update(&mut self) {
+        while let Some(message) = self.connection.receive() {
+            match message {
+                Message::Watched { player, tick, garbage, inputs } if player < 2 && tick == self.ticks[player] + 1 => {
+                    let side = &mut self.sides[player];
+                    for (rows, hole) in garbage {
+                        side.board.game.receive_garbage(rows, hole);
+                    }
+                    if let Some((rows, _)) = side.tick(&inputs, &self.rules.attack, self.rules.cancel) {
+                        self.sent[player] += rows;
+                    }
+                    self.ticks[player] = tick;
+                    if side.board.game.gameover {
+                        self.topped_out[player] = Some(tick);
+                    }
+                }
+                Message::Result { winner } => self.outcome = Some(winner.map_or(Outcome::Draw, Outcome::Winner)),
+                message => warn!("Unexpected message while watching: {message:?}"),
+            }
+        }
+        if self.outcome.is_none() {
+            self.outcome = decide_match(self.rules.race, [&self.sides[0].board, &self.sides[1].board], self.topped_out, self.ticks);
+        }
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let boards = [(self.names[0].as_str(), &self.sides[0].board), (self.names[1].as_str(), &self.sides[1].board)];
+        draw_match(r, boards, 0, self.outcome, &state.theme);
+        if self.rules.race {
+            draw_race_progress(r, boards.map(|(_, board)| board.game.lines));
+        } else {
+            let half = SCREEN_SIZE.0 / 2.;
+            for (i, side) in self.sides.iter().enumerate() {
+                let attack = format!("Sent {}  Incoming {}", self.sent[i], side.board.game.pending_garbage());
+                r.draw_text_centred(&attack, 20., [half * i as f32 + half / 2., 68.], Color::WHITE);
+            }
+        }
+        if self.outcome.is_none() && self.connection.is_closed() {
+            r.draw_text_centred("The server closed the connection", 32., [SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 / 2.], Color::YELLOW);
+        }
+    }
+}
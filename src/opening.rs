@@ -0,0 +1,324 @@
+use ggez::{
+    event::{Button, GamepadId},
+    graphics::Color,
+    input::keyboard::{KeyCode, KeyMods},
+    Context,
+};
+use log::info;
+use oorandom::Rand32;
+
+use crate::{
+    app::{random_seed, GameState},
+    grid::Pos,
+    input::Action,
+    mode::Mode,
+    piece::{MovingPiece, Tetromino},
+    render::{self, Renderer, Shifted, SCREEN_SIZE},
+    rotation::{Kicks, RotationSystem},
+    rules::Game,
+    ruleset::{GameRules, Memoryless, Randomizer},
+    scene::{Scene, Transition},
+    settings::MenuInput,
+    versus::Controls,
+};
+
+/// A setup to build at the start of a game, one piece at a time in the order they are dealt.
+///
+/// Each stage is the bottom of the board from the top down as it should look once its steps are done,
+/// with the cells of each step's piece marked with the step: `0` to `9` and then `a` to `z`, in the order of `pieces`.
+/// A new stage starts where lines are cleared, and marks what is left from before with `#`.
+#[derive(Debug)]
+pub struct Opening {
+    pub name: &'static str,
+    /// The pieces dealt, one letter each, which are the pieces of the steps in turn.
+    pub pieces: &'static str,
+    pub stages: &'static [&'static [&'static str]],
+}
+
+/// The setups the trainer teaches.
+pub static OPENINGS: [Opening; 2] = [
+    Opening {
+        name: "Perfect clear",
+        pieces: "ILOSZJTTLJ",
+        stages: &[
+            &[
+                ".........4",
+                "22......44",
+                "22555.3341",
+                "0000533111",
+            ],
+            &[
+                ".........#",
+                "##..666.##",
+                "#####6####",
+            ],
+            &[
+                "999888777#",
+                "##98###7##",
+            ],
+        ],
+    },
+    Opening {
+        name: "T-spin double into triple",
+        pieces: "OJISLOZZSILJTT",
+        stages: &[
+            &[
+                "..bb..8...",
+                "..b...88..",
+                "aab6ccc871",
+                "9a663c4771",
+                "9a6.334711",
+                "955..34400",
+                "955.222200",
+            ],
+            &[
+                "..##..#...",
+                "..#...##..",
+                "###d######",
+                "###dd#####",
+                "###d######",
+            ],
+        ],
+    },
+];
+
+impl Opening {
+    fn step_mark(step: usize) -> Option<char> {
+        char::from_digit(step as u32, 36)
+    }
+    /// The piece placed in `step`, if there is one.
+    pub fn kind(&self, step: usize) -> Option<Tetromino> {
+        let letter = self.pieces.chars().nth(step)?;
+        Tetromino::ALL.into_iter().find(|kind| format!("{kind:?}").starts_with(letter))
+    }
+    pub fn steps(&self) -> usize {
+        self.pieces.len()
+    }
+    /// The stage `step` is done in.
+    fn stage(&self, step: usize) -> Option<&'static [&'static str]> {
+        let mark = Self::step_mark(step)?;
+        self.stages.iter().copied().find(|stage| stage.iter().any(|row| row.contains(mark)))
+    }
+    /// The cells of the steps from `step` to the end of its stage, as `(step, cell)`, on a board `height` rows high.
+    pub fn remaining(&self, step: usize, height: i8) -> Vec<(usize, Pos)> {
+        let Some(stage) = self.stage(step) else {
+            return Vec::new();
+        };
+        let top = height - stage.len() as i8;
+        let mut cells = Vec::new();
+        for (y, row) in stage.iter().enumerate() {
+            for (x, mark) in row.chars().enumerate() {
+                if let Some(marked) = mark.to_digit(36).map(|d| d as usize).filter(|&marked| marked >= step) {
+                    cells.push((marked, Pos::new(x as i8, top + y as i8)));
+                }
+            }
+        }
+        cells
+    }
+    /// The cells the piece of `step` goes in, sorted.
+    pub fn target(&self, step: usize, height: i8) -> Vec<(i8, i8)> {
+        let mut cells: Vec<_> = self.remaining(step, height).into_iter().filter(|&(marked, _)| marked == step).map(|(_, pos)| (pos.x, pos.y)).collect();
+        cells.sort_unstable();
+        cells
+    }
+}
+
+impl GameRules for Opening {
+    fn randomizer(&self) -> Box<dyn Randomizer> {
+        Box::new(Deal { opening: self.pieces, dealt: 0 })
+    }
+    fn rotation_system(&self) -> Box<dyn RotationSystem> {
+        // The setups are built with the spins of the modern games
+        Box::new(Kicks::SRS)
+    }
+    /// Enough to take back a piece put in the wrong place.
+    fn undo_limit(&self) -> usize {
+        1
+    }
+    fn is_finished(&self, _lines: u32, _ms: u32) -> bool {
+        false
+    }
+}
+
+/// Deals the pieces of an opening in order, and random ones once they have all been dealt.
+#[derive(Debug, Clone, Copy)]
+struct Deal {
+    opening: &'static str,
+    dealt: usize,
+}
+
+impl Randomizer for Deal {
+    fn next(&mut self, rng: &mut Rand32) -> Tetromino {
+        let letter = self.opening.chars().nth(self.dealt);
+        self.dealt += 1;
+        letter
+            .and_then(|letter| Tetromino::ALL.into_iter().find(|kind| format!("{kind:?}").starts_with(letter)))
+            .unwrap_or_else(|| Memoryless.next(rng))
+    }
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(*self)
+    }
+}
+
+fn cells(piece: &MovingPiece) -> Vec<(i8, i8)> {
+    let mut cells: Vec<_> = piece.piece.points(piece.pos).map(|pos| (pos.x, pos.y)).collect();
+    cells.sort_unstable();
+    cells
+}
+
+/// Picks the opening to practise.
+#[derive(Debug, Default)]
+pub struct OpeningMenuScene {
+    selected: usize,
+}
+
+impl Scene for OpeningMenuScene {
+    fn draw(&self, _state: &GameState, r: &mut dyn Renderer) {
+        r.draw_text("Opening trainer", 48., [64., 64.], Color::WHITE);
+        for (i, opening) in OPENINGS.iter().enumerate() {
+            let colour = if i == self.selected { Color::YELLOW } else { Color::WHITE };
+            r.draw_text(opening.name, 32., [64., 160. + 48. * i as f32], colour);
+        }
+        r.draw_text("Up/Down: select  Confirm: start  Back: choose mode", 16., [64., SCREEN_SIZE.1 - 48.], Color::WHITE);
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        let n = OPENINGS.len();
+        match input {
+            MenuInput::Up => self.selected = (self.selected + n - 1) % n,
+            MenuInput::Down => self.selected = (self.selected + 1) % n,
+            MenuInput::Confirm => return Transition::Push(Box::new(OpeningScene::new(state, &OPENINGS[self.selected]))),
+            MenuInput::Back => return Transition::Pop(1),
+            _ => (),
+        }
+        Transition::None
+    }
+}
+
+/// Teaches an opening: its pieces are dealt in order with where each goes shown over the board,
+/// and a piece put anywhere else is taken back to try again. Pieces don't fall, and can't be held.
+pub struct OpeningScene {
+    opening: &'static Opening,
+    board: Game,
+    controls: Controls,
+    /// The step the current piece is for.
+    step: usize,
+    /// Whether the last piece is to be taken back on the next tick.
+    take_back: bool,
+    /// What was said about the last piece.
+    verdict: Option<(String, Color)>,
+    /// How many times the opening has been built.
+    built: u32,
+}
+
+impl OpeningScene {
+    pub fn new(state: &GameState, opening: &'static Opening) -> Self {
+        info!("Practising the {} opening", opening.name);
+        OpeningScene {
+            opening,
+            board: Self::game(state, opening),
+            controls: Controls::new(state.config.profile().bindings(Mode::Practice)),
+            step: 0,
+            take_back: false,
+            verdict: None,
+            built: 0,
+        }
+    }
+    fn game(state: &GameState, opening: &'static Opening) -> Game {
+        let mut config = state.game_config.clone();
+        config.frames_per_row = Some(u8::MAX);
+        Game::with_config(Mode::Practice, opening, random_seed(), state.config.profile().handling, &config)
+    }
+    /// Checks `locked` against the step it was for, going on to the next step or taking it back.
+    fn judge(&mut self, state: &GameState, locked: &MovingPiece) {
+        let height = self.board.grid.size().1;
+        if cells(locked) != self.opening.target(self.step, height) {
+            let kind = self.opening.kind(self.step).unwrap_or(locked.piece.kind);
+            self.verdict = Some((format!("That isn't where the {kind:?} goes, try again"), Color::RED));
+            self.take_back = true;
+            return;
+        }
+        self.step += 1;
+        self.verdict = None;
+        if self.step == self.opening.steps() {
+            self.built += 1;
+            self.verdict = Some(("Built! Once more from the start".to_owned(), Color::GREEN));
+            self.board = Self::game(state, self.opening);
+            self.step = 0;
+        }
+    }
+}
+
+impl Scene for OpeningScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        let mut inputs = self.controls.take();
+        // The pieces come in the order they are placed in
+        inputs.retain(|&(action, _)| !matches!(action, Action::Hold | Action::Undo | Action::Redo));
+        if std::mem::take(&mut self.take_back) {
+            inputs.extend([(Action::Undo, true), (Action::Undo, false)]);
+        }
+        let result = self.board.tick(&inputs);
+        if let Some(locked) = &result.locked_piece {
+            self.judge(state, locked);
+        }
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        render::draw_game(r, &self.board, 0., &state.theme);
+        {
+            let grid = &self.board.grid;
+            let r = &mut Shifted { inner: r, by: render::board_offset(grid.size()) };
+            for (step, pos) in self.opening.remaining(self.step, grid.size().1) {
+                let Some(kind) = self.opening.kind(step).filter(|_| grid.is_free_or_above(pos)) else {
+                    continue;
+                };
+                let alpha = if step == self.step { 0.6 } else { 0.25 };
+                r.draw_cell(pos.x as f32, pos.y as f32, Color { a: alpha, ..state.theme.colour(kind.colour()) });
+            }
+        }
+        r.draw_text(self.opening.name, 32., [16., 16.], Color::WHITE);
+        if let Some(kind) = self.opening.kind(self.step) {
+            let step = format!("Step {} of {}: the {kind:?}", self.step + 1, self.opening.steps());
+            r.draw_text(&step, 20., [16., 60.], Color::WHITE);
+        }
+        if let Some((verdict, colour)) = &self.verdict {
+            r.draw_text(verdict, 20., [16., 92.], *colour);
+        }
+        r.draw_text(&format!("Built {} times", self.built), 20., [16., 124.], Color::WHITE);
+        r.draw_text("Back: leave", 16., [16., SCREEN_SIZE.1 - 24.], Color::WHITE);
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, repeated: bool) -> Transition {
+        if repeated {
+            return Transition::None;
+        }
+        match self.controls.key_down(keycode) {
+            Some(Action::Pause | Action::MenuBack) => Transition::Pop(1),
+            Some(_) => Transition::None,
+            None => match MenuInput::from_key(keycode, mods, &self.controls.bindings) {
+                Some(input) => self.menu_input(state, ctx, input),
+                None => Transition::None,
+            },
+        }
+    }
+    fn key_up(&mut self, _state: &mut GameState, keycode: KeyCode) {
+        self.controls.key_up(keycode);
+    }
+    fn menu_input(&mut self, _state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        match input {
+            MenuInput::Back => Transition::Pop(1),
+            _ => Transition::None,
+        }
+    }
+    fn gamepad_button_down(&mut self, state: &mut GameState, _ctx: &mut Context, btn: Button, _id: GamepadId) -> Transition {
+        match state.config.profile().buttons.action(btn) {
+            Some(Action::Pause) => return Transition::Pop(1),
+            Some(action) => self.controls.push(action, true),
+            None => (),
+        }
+        Transition::None
+    }
+    fn gamepad_button_up(&mut self, state: &mut GameState, btn: Button, _id: GamepadId) {
+        if let Some(action) = state.config.profile().buttons.action(btn).filter(|&a| a != Action::Pause) {
+            self.controls.push(action, false);
+        }
+    }
+}
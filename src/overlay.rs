@@ -0,0 +1,44 @@
+use ggez::graphics::{Color, Rect};
+
+use crate::{
+    input::{Action, HeldActions},
+    render::{Renderer, GRID_CELL_SIZE, SCREEN_SIZE},
+};
+
+const KEY_SIZE: f32 = 1.5 * GRID_CELL_SIZE.0 as f32;
+const KEY_GAP: f32 = 0.25 * GRID_CELL_SIZE.0 as f32;
+
+/// The keys shown, by row and column.
+const KEYS: [(Action, &str, (f32, f32)); 7] = [
+    (Action::Hold, "H", (0., 0.)),
+    (Action::RotLeft, "<)", (1., 0.)),
+    (Action::HardDrop, "^", (2., 0.)),
+    (Action::RotRight, "(>", (3., 0.)),
+    (Action::Left, "<", (1., 1.)),
+    (Action::SoftDrop, "v", (2., 1.)),
+    (Action::Right, ">", (3., 1.)),
+];
+
+/// Draws the action keys in the top right corner, lighting up the ones being held.
+pub fn draw_key_overlay(r: &mut dyn Renderer, held: &HeldActions) {
+    draw_keys(r, held, [SCREEN_SIZE.0 - 4. * (KEY_SIZE + KEY_GAP), KEY_GAP]);
+}
+
+/// Draws the action keys with their top left corner at `(origin_x, origin_y)`, lighting up the ones being held.
+pub fn draw_keys(r: &mut dyn Renderer, held: &HeldActions, [origin_x, origin_y]: [f32; 2]) {
+    for &(action, label, (col, row)) in &KEYS {
+        let rect = Rect::new(
+            origin_x + col * (KEY_SIZE + KEY_GAP),
+            origin_y + row * (KEY_SIZE + KEY_GAP),
+            KEY_SIZE,
+            KEY_SIZE,
+        );
+        let (fill, text_colour) = if held.is_held(action) {
+            (Color::WHITE, Color::BLACK)
+        } else {
+            (Color::new(1., 1., 1., 0.15), Color::WHITE)
+        };
+        r.draw_panel(rect, fill);
+        r.draw_text_centred(label, 24., [rect.x + KEY_SIZE / 2., rect.y + KEY_SIZE / 2.], text_colour);
+    }
+}
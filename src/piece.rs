@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grid::{Grid, Pos},
+    rotation::Rotation,
+};
+
+pub const NUM_COLOURS: usize = Tetromino::ALL.len();
+
+/// The seven pieces. They are saved as their place in `ALL`, which is also their colour in the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum Tetromino {
+    I,
+    O,
+    T,
+    S,
+    Z,
+    J,
+    L,
+}
+
+impl Tetromino {
+    /// In the order the randomiser picks them, which replays depend on.
+    pub const ALL: [Tetromino; 7] = [
+        Tetromino::L,
+        Tetromino::I,
+        Tetromino::T,
+        Tetromino::S,
+        Tetromino::Z,
+        Tetromino::O,
+        Tetromino::J,
+    ];
+
+    /// Which colour of the theme's palette the piece has.
+    pub fn colour(self) -> usize {
+        match self {
+            Tetromino::L => 0,
+            Tetromino::I => 1,
+            Tetromino::T => 2,
+            Tetromino::S => 3,
+            Tetromino::Z => 4,
+            Tetromino::O => 5,
+            Tetromino::J => 6,
+        }
+    }
+    /// The blocks of the piece in its spawn orientation, around the point it rotates about.
+    pub fn offsets(self) -> [Pos; 4] {
+        match self {
+            Tetromino::L => [Pos::new(-1, -1), Pos::new(0, -1), Pos::new(1, -1), Pos::new(-1, 0)],
+            Tetromino::I => [Pos::new(-1, 0), Pos::new(0, 0), Pos::new(1, 0), Pos::new(2, 0)],
+            Tetromino::T => [Pos::new(-1, -1), Pos::new(0, -1), Pos::new(1, -1), Pos::new(0, 0)],
+            Tetromino::S => [Pos::new(0, -1), Pos::new(1, -1), Pos::new(-1, 0), Pos::new(0, 0)],
+            Tetromino::Z => [Pos::new(-1, -1), Pos::new(0, -1), Pos::new(0, 0), Pos::new(1, 0)],
+            Tetromino::O => [Pos::new(-1, -1), Pos::new(0, -1), Pos::new(-1, 0), Pos::new(0, 0)],
+            Tetromino::J => [Pos::new(-1, -1), Pos::new(0, -1), Pos::new(1, -1), Pos::new(1, 0)],
+        }
+    }
+}
+
+impl TryFrom<u8> for Tetromino {
+    type Error = String;
+
+    fn try_from(i: u8) -> Result<Self, Self::Error> {
+        Self::ALL.get(i as usize).copied().ok_or_else(|| format!("no piece number {i}"))
+    }
+}
+
+impl From<Tetromino> for u8 {
+    fn from(t: Tetromino) -> Self {
+        t.colour() as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Piece {
+    #[serde(rename = "colour")]
+    pub kind: Tetromino,
+    pub offsets: [Pos; 4],
+    /// How many quarter turns right of its spawn orientation the piece is.
+    #[serde(default)]
+    pub rotation: u8,
+}
+
+impl Piece {
+    /// The piece in its spawn orientation.
+    pub fn new(kind: Tetromino) -> Self {
+        Piece {
+            kind,
+            offsets: kind.offsets(),
+            rotation: 0,
+        }
+    }
+    /// Turns the piece a quarter turn about `centre`, which is in half cells so it can be between cells.
+    pub fn rotate(&mut self, dir: Rotation, (cx, cy): (i8, i8)) {
+        for offset in &mut self.offsets {
+            let (x, y) = (2 * offset.x - cx, 2 * offset.y - cy);
+            let (x, y) = match dir {
+                Rotation::Left => (y, -x),
+                Rotation::Right => (-y, x),
+            };
+            *offset = Pos::new((x + cx) / 2, (y + cy) / 2);
+        }
+        self.rotation = match dir {
+            Rotation::Left => (self.rotation + 3) % 4,
+            Rotation::Right => (self.rotation + 1) % 4,
+        };
+    }
+    /// The cells the piece covers when placed at `offset`.
+    pub fn points<'a>(&'a self, offset: Pos) -> impl Iterator<Item=Pos> + use<'a> {
+        self.offsets.iter().map(move |p| Pos::new(offset.x + p.x, offset.y + p.y))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MovingPiece {
+    pub pos: Pos,
+    pub piece: Piece,
+}
+
+impl MovingPiece {
+    /// The piece spawning at `spawn`, usually just above the grid.
+    pub fn new(piece: Piece, spawn: Pos) -> Self {
+        MovingPiece { pos: spawn, piece }
+    }
+    /// Whether the piece is clear of the blocks and walls of `grid`.
+    pub fn fits(&self, grid: &Grid) -> bool {
+        self.piece.points(self.pos).all(|pos| grid.is_free_or_above(pos))
+    }
+}
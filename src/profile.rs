@@ -0,0 +1,42 @@
+use log::warn;
+
+use crate::{
+    save::{SavedGame, Slot},
+    scores::HighScores,
+    stats::Stats,
+    storage::Storage,
+};
+
+/// What each profile keeps besides its settings, in a storage directory of its own
+/// along with its saved games and replays.
+#[derive(Debug, Default)]
+pub struct ProfileData {
+    pub dir: String,
+    pub high_scores: HighScores,
+    pub stats: Stats,
+}
+
+impl ProfileData {
+    /// Loads the profile data in `dir`, starting afresh with whatever can't be loaded.
+    pub fn load(storage: &dyn Storage, dir: String) -> Self {
+        let high_scores = HighScores::load(storage, &dir).unwrap_or_else(|e| {
+            warn!("Could not load high scores: {e}");
+            HighScores::default()
+        });
+        let stats = Stats::load(storage, &dir).unwrap_or_else(|e| {
+            warn!("Could not load statistics: {e}");
+            Stats::default()
+        });
+        ProfileData { dir, high_scores, stats }
+    }
+    /// The game left in progress last time, if any.
+    pub fn saved_game(&self, storage: &dyn Storage) -> Option<SavedGame> {
+        // An autosave is only left behind by a crash, and is newer than any game saved on quitting
+        [Slot::Autosave, Slot::Quit].into_iter().find_map(|slot| {
+            SavedGame::load(storage, &self.dir, slot).unwrap_or_else(|e| {
+                warn!("Could not load saved game: {e}");
+                None
+            })
+        })
+    }
+}
@@ -0,0 +1,179 @@
+use ggez::{
+    graphics::{Color, Rect},
+    input::keyboard::KeyCode,
+};
+use log::info;
+use macroquad::{
+    color::Color as QuadColor,
+    input::{self as quad_input, KeyCode as QuadKey},
+    shapes, text, time, window,
+};
+
+use crate::{
+    app::GameState,
+    input::Action,
+    render::{self, Renderer, SCREEN_SIZE},
+};
+
+/// The most ticks played in one frame to catch up, so a frame that took very long isn't followed by a burst of them.
+const MAX_TICKS_PER_FRAME: u32 = 5;
+
+/// Draws the screens with macroquad, fitting the screen into the window wherever it is resized to.
+#[derive(Debug, Default)]
+pub struct Quad {
+    scale: f32,
+    /// Where the top left corner of the screen is in the window, leaving equal margins either side of it.
+    offset: (f32, f32),
+}
+
+impl Quad {
+    /// Fits the screen into the window as it is now.
+    fn fit(&mut self) {
+        let (w, h) = (window::screen_width(), window::screen_height());
+        self.scale = (w / SCREEN_SIZE.0).min(h / SCREEN_SIZE.1);
+        self.offset = ((w - SCREEN_SIZE.0 * self.scale) / 2., (h - SCREEN_SIZE.1 * self.scale) / 2.);
+    }
+    fn to_window(&self, [x, y]: [f32; 2]) -> (f32, f32) {
+        (self.offset.0 + x * self.scale, self.offset.1 + y * self.scale)
+    }
+}
+
+fn colour(colour: Color) -> QuadColor {
+    QuadColor::new(colour.r, colour.g, colour.b, colour.a)
+}
+
+impl Renderer for Quad {
+    fn draw_text(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color) {
+        let size = (scale * self.scale).round() as u16;
+        let (x, y) = self.to_window(at);
+        // Text is drawn from its baseline, so it is moved down by how far that is from the top
+        let measured = text::measure_text(text, None, size, 1.);
+        text::draw_text(text, x, y + measured.offset_y, size as f32, self::colour(colour));
+    }
+    fn draw_text_centred(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color) {
+        let size = (scale * self.scale).round() as u16;
+        let (x, y) = self.to_window(at);
+        let measured = text::measure_text(text, None, size, 1.);
+        let (x, y) = (x - measured.width / 2., y - measured.height / 2. + measured.offset_y);
+        text::draw_text(text, x, y, size as f32, self::colour(colour));
+    }
+    fn draw_panel(&mut self, rect: Rect, colour: Color) {
+        let (x, y) = self.to_window([rect.x, rect.y]);
+        shapes::draw_rectangle(x, y, rect.w * self.scale, rect.h * self.scale, self::colour(colour));
+    }
+}
+
+/// The key of the window `key` is, for the keys that can be bound.
+fn key(key: QuadKey) -> Option<KeyCode> {
+    const LETTERS: [(QuadKey, KeyCode); 26] = [
+        (QuadKey::A, KeyCode::A), (QuadKey::B, KeyCode::B), (QuadKey::C, KeyCode::C), (QuadKey::D, KeyCode::D),
+        (QuadKey::E, KeyCode::E), (QuadKey::F, KeyCode::F), (QuadKey::G, KeyCode::G), (QuadKey::H, KeyCode::H),
+        (QuadKey::I, KeyCode::I), (QuadKey::J, KeyCode::J), (QuadKey::K, KeyCode::K), (QuadKey::L, KeyCode::L),
+        (QuadKey::M, KeyCode::M), (QuadKey::N, KeyCode::N), (QuadKey::O, KeyCode::O), (QuadKey::P, KeyCode::P),
+        (QuadKey::Q, KeyCode::Q), (QuadKey::R, KeyCode::R), (QuadKey::S, KeyCode::S), (QuadKey::T, KeyCode::T),
+        (QuadKey::U, KeyCode::U), (QuadKey::V, KeyCode::V), (QuadKey::W, KeyCode::W), (QuadKey::X, KeyCode::X),
+        (QuadKey::Y, KeyCode::Y), (QuadKey::Z, KeyCode::Z),
+    ];
+    const OTHERS: [(QuadKey, KeyCode); 24] = [
+        (QuadKey::Key0, KeyCode::Key0), (QuadKey::Key1, KeyCode::Key1), (QuadKey::Key2, KeyCode::Key2),
+        (QuadKey::Key3, KeyCode::Key3), (QuadKey::Key4, KeyCode::Key4), (QuadKey::Key5, KeyCode::Key5),
+        (QuadKey::Key6, KeyCode::Key6), (QuadKey::Key7, KeyCode::Key7), (QuadKey::Key8, KeyCode::Key8),
+        (QuadKey::Key9, KeyCode::Key9), (QuadKey::Left, KeyCode::Left), (QuadKey::Right, KeyCode::Right),
+        (QuadKey::Up, KeyCode::Up), (QuadKey::Down, KeyCode::Down), (QuadKey::Space, KeyCode::Space),
+        (QuadKey::Enter, KeyCode::Return), (QuadKey::Escape, KeyCode::Escape), (QuadKey::Backspace, KeyCode::Back),
+        (QuadKey::Tab, KeyCode::Tab), (QuadKey::LeftShift, KeyCode::LShift), (QuadKey::RightShift, KeyCode::RShift),
+        (QuadKey::LeftControl, KeyCode::LControl), (QuadKey::RightControl, KeyCode::RControl),
+        (QuadKey::LeftAlt, KeyCode::LAlt),
+    ];
+    LETTERS.iter().chain(&OTHERS).find(|&&(quad, _)| quad == key).map(|&(_, key)| key)
+}
+
+fn draw(state: &GameState, quad: &mut Quad, fall: f32) {
+    quad.fit();
+    let [r, g, b] = state.theme.background;
+    window::clear_background(QuadColor::from_rgba(r / 2, g / 2, b / 2, 255));
+    let game = &state.game;
+    render::draw_game(quad, game, fall, &state.theme);
+    let secs = game.ms() / 1000;
+    let lines = [
+        game.mode.to_string(),
+        String::new(),
+        format!("Score {}", game.score),
+        format!("Lines {}", game.lines),
+        format!("Level {}", game.level()),
+        format!("Time {}:{:02}", secs / 60, secs % 60),
+    ];
+    let x = SCREEN_SIZE.0 - 5. * 32.;
+    for (i, line) in lines.iter().enumerate() {
+        quad.draw_text(line, 24., [x, (8 + i) as f32 * 32.], Color::WHITE);
+    }
+    quad.draw_text("Esc: quit", 24., [x, SCREEN_SIZE.1 - 48.], Color::WHITE);
+    if game.gameover {
+        let middle = SCREEN_SIZE.0 / 2.;
+        quad.draw_text_centred("Game over", 48., [middle, SCREEN_SIZE.1 / 2.], Color::RED);
+        quad.draw_text_centred("Enter: again", 32., [middle, SCREEN_SIZE.1 / 2. + 48.], Color::WHITE);
+    }
+}
+
+/// Plays in a macroquad window until Escape is pressed or the window is closed, the same way the terminal does
+/// but with keys that can be held, saving the game in progress to be continued next time as the ggez window does.
+pub async fn run(mut state: GameState) {
+    quad_input::prevent_quit();
+    let mut quad = Quad::default();
+    // How long it has been since the last tick was played, and what has been pressed since
+    let mut unticked: f32 = 0.;
+    let mut inputs = Vec::new();
+    loop {
+        // Pressed first, for keys tapped within a frame
+        let pressed = quad_input::get_keys_pressed().into_iter().filter_map(key).map(|key| (key, true));
+        let released = quad_input::get_keys_released().into_iter().filter_map(key).map(|key| (key, false));
+        for (key, down) in pressed.chain(released) {
+            let action = state.bindings.action(key);
+            if !down {
+                // Let go of even when the game no longer takes input, so nothing is left held down
+                inputs.extend(action.map(|action| (action, false)));
+                continue;
+            }
+            match (key, action) {
+                (KeyCode::Escape, _) | (_, Some(Action::Pause)) => {
+                    info!("Leaving the macroquad window");
+                    state.save_on_exit();
+                    return;
+                }
+                (_, Some(Action::Restart)) => {
+                    state.reset(state.game.mode);
+                    inputs.clear();
+                }
+                (KeyCode::Return, _) if state.game.gameover => {
+                    state.reset(state.game.mode);
+                    inputs.clear();
+                }
+                (_, Some(action)) if state.accepts_input() => inputs.push((action, true)),
+                _ => (),
+            }
+        }
+        if quad_input::is_quit_requested() {
+            info!("Leaving the macroquad window");
+            state.save_on_exit();
+            return;
+        }
+
+        let ms_per_tick = state.game.ms_per_tick() as f32;
+        unticked = (unticked + time::get_frame_time() * 1000.).min(MAX_TICKS_PER_FRAME as f32 * ms_per_tick);
+        while unticked >= ms_per_tick && !state.game.gameover {
+            unticked -= ms_per_tick;
+            let result = state.game.tick(&std::mem::take(&mut inputs));
+            if state.keeps_records() {
+                for event in result.events {
+                    state.data.stats.record(event);
+                }
+            }
+            if result.game_over {
+                state.record_score();
+            }
+        }
+        let fall = if state.game.gameover { 0. } else { state.game.fall_progress(unticked / ms_per_tick) };
+        draw(&state, &mut quad, fall);
+        window::next_frame().await;
+    }
+}
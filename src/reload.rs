@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use ggez::Context;
+
+use crate::{config::CONFIG_FILE, storage::Storage, theme::Theme};
+
+/// How often the files are looked at.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Notices the config and the theme in use being edited outside the game,
+/// so handling, bindings and colours can be tuned without restarting.
+#[derive(Debug, Default)]
+pub struct Reloader {
+    config: Option<SystemTime>,
+    /// The theme looked at last time, and when it was changed.
+    theme: (Option<String>, Option<SystemTime>),
+    last_check: Option<Instant>,
+}
+
+/// Which files have changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Changed {
+    pub config: bool,
+    pub theme: bool,
+}
+
+impl Reloader {
+    /// Which of the files have changed since they were last looked at, looking at most once a second.
+    /// Nothing has changed the first time, when there is nothing to compare with,
+    /// and switching to another theme isn't the theme changing.
+    pub fn poll(&mut self, ctx: &Context, storage: &dyn Storage, theme: Option<&str>) -> Changed {
+        if self.last_check.is_some_and(|last| last.elapsed() < CHECK_INTERVAL) {
+            return Changed::default();
+        }
+        let first = self.last_check.is_none();
+        self.last_check = Some(Instant::now());
+        let config = storage.modified(CONFIG_FILE);
+        let theme = (theme.map(str::to_owned), Theme::modified(ctx, theme));
+        let changed = Changed {
+            config: !first && config != self.config,
+            theme: !first && theme.0 == self.theme.0 && theme.1 != self.theme.1,
+        };
+        self.config = config;
+        self.theme = theme;
+        changed
+    }
+}
@@ -0,0 +1,331 @@
+use ggez::{
+    conf::WindowMode,
+    graphics::{self, Canvas, Color, DrawParam, InstanceArray, Rect},
+    winit::dpi::LogicalSize,
+    Context,
+};
+
+use crate::{
+    grid::{Cell, Grid, Pos, GAME_GRID_SIZE},
+    piece::{MovingPiece, Piece, NUM_COLOURS},
+    rules::Game,
+    theme::Theme,
+};
+
+pub const FULL_GRID_SIZE: (i8, i8) = (20, 30);
+pub const GRID_CELL_SIZE: (i8, i8) = (32, 32);
+
+// Next we define how large we want our actual window to be by multiplying
+// the components of our grid size by its corresponding pixel size.
+pub const SCREEN_SIZE: (f32, f32) = (
+    FULL_GRID_SIZE.0 as f32 * GRID_CELL_SIZE.0 as f32,
+    FULL_GRID_SIZE.1 as f32 * GRID_CELL_SIZE.1 as f32,
+);
+
+/// The part of the layout shown during a game in the portrait layout: the board with the pieces above it
+/// and the touch buttons below, without the margins beside it.
+pub const PORTRAIT_VIEW: Rect = Rect {
+    x: 4. * GRID_CELL_SIZE.0 as f32,
+    y: 4. * GRID_CELL_SIZE.1 as f32,
+    w: 12. * GRID_CELL_SIZE.0 as f32,
+    h: 30. * GRID_CELL_SIZE.1 as f32,
+};
+
+pub const COLOURS: [Color; NUM_COLOURS] = [
+    Color::new(0.5, 0., 0.5, 1.),
+    Color::RED,
+    Color::YELLOW,
+    Color::GREEN,
+    Color::CYAN,
+    Color::BLUE,
+    Color::WHITE,
+];
+const GARBAGE_COLOUR: Color = Color::new(0.5, 0.5, 0.5, 1.);
+
+/// Everything the screens are drawn with, so the same game and menu layouts can be drawn by other frontends than ggez.
+/// Positions are in pixels of a screen `SCREEN_SIZE` big.
+pub trait Renderer {
+    /// Fills a cell of the board at `(x, y)` cells from its top left corner, or outside it for the pieces beside it.
+    /// The cells can be between rows, for pieces on their way down.
+    fn draw_cell(&mut self, x: f32, y: f32, colour: Color) {
+        self.draw_panel(cell_rect(x, y), colour);
+    }
+    /// Draws `text` with its top left corner at `at`, `scale` pixels high.
+    fn draw_text(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color);
+    /// Draws `text` with its middle at `at`, `scale` pixels high.
+    fn draw_text_centred(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color);
+    /// Fills a rectangle, such as a button or the backdrop of a menu.
+    fn draw_panel(&mut self, rect: Rect, colour: Color);
+}
+
+impl Renderer for Canvas {
+    fn draw_text(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color) {
+        let mut text = graphics::Text::new(text);
+        text.set_scale(scale);
+        self.draw(&text, DrawParam::new().dest(at).color(colour));
+    }
+    fn draw_text_centred(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color) {
+        let mut text = graphics::Text::new(text);
+        text.set_scale(scale);
+        self.draw(&text, DrawParam::new().dest(at).offset([0.5, 0.5]).color(colour));
+    }
+    fn draw_panel(&mut self, rect: Rect, colour: Color) {
+        self.draw(&graphics::Quad, DrawParam::new().dest_rect(rect).color(colour));
+    }
+}
+
+/// The panels drawn each frame, batched into `InstanceArray`s so the hundreds of cells of a board
+/// take a few draw calls rather than one each. The arrays are kept between frames,
+/// and only uploaded again when what one of them holds has changed, e.g. when a piece moves.
+#[derive(Debug, Default)]
+pub struct QuadBatch {
+    /// One array for each run of panels between pieces of text, in the order they were drawn.
+    arrays: Vec<InstanceArray>,
+}
+
+impl QuadBatch {
+    /// Starts drawing a frame onto `canvas`.
+    pub fn frame<'a>(&'a mut self, ctx: &'a Context, canvas: &'a mut Canvas) -> Batched<'a> {
+        Batched {
+            batch: self,
+            ctx,
+            canvas,
+            pending: Vec::new(),
+            used: 0,
+        }
+    }
+}
+
+/// A frame being drawn by a `QuadBatch`. The last of the panels are drawn when it is dropped.
+pub struct Batched<'a> {
+    batch: &'a mut QuadBatch,
+    ctx: &'a Context,
+    canvas: &'a mut Canvas,
+    pending: Vec<DrawParam>,
+    used: usize,
+}
+
+impl Batched<'_> {
+    /// Draws the panels drawn since the last text, so the text goes on top of them.
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if self.batch.arrays.len() <= self.used {
+            self.batch.arrays.push(InstanceArray::new(self.ctx, None));
+        }
+        let array = &mut self.batch.arrays[self.used];
+        if array.instances() != self.pending {
+            array.set(self.pending.drain(..));
+        }
+        self.pending.clear();
+        self.canvas.draw(array, DrawParam::new());
+        self.used += 1;
+    }
+}
+
+impl Renderer for Batched<'_> {
+    fn draw_text(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color) {
+        self.flush();
+        self.canvas.draw_text(text, scale, at, colour);
+    }
+    fn draw_text_centred(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color) {
+        self.flush();
+        self.canvas.draw_text_centred(text, scale, at, colour);
+    }
+    fn draw_panel(&mut self, rect: Rect, colour: Color) {
+        self.pending.push(DrawParam::new().dest_rect(rect).color(colour));
+    }
+}
+
+impl Drop for Batched<'_> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Draws everything through `inner` moved `by` pixels, e.g. to shake the board.
+pub struct Shifted<'a> {
+    pub inner: &'a mut dyn Renderer,
+    pub by: [f32; 2],
+}
+
+impl Renderer for Shifted<'_> {
+    fn draw_text(&mut self, text: &str, scale: f32, [x, y]: [f32; 2], colour: Color) {
+        self.inner.draw_text(text, scale, [x + self.by[0], y + self.by[1]], colour);
+    }
+    fn draw_text_centred(&mut self, text: &str, scale: f32, [x, y]: [f32; 2], colour: Color) {
+        self.inner.draw_text_centred(text, scale, [x + self.by[0], y + self.by[1]], colour);
+    }
+    fn draw_panel(&mut self, rect: Rect, colour: Color) {
+        self.inner.draw_panel(Rect { x: rect.x + self.by[0], y: rect.y + self.by[1], ..rect }, colour);
+    }
+}
+
+/// Draws a layout made for the part `from` of the screen into `to` instead, scaled to fit and centred.
+pub struct Viewport<'a> {
+    inner: &'a mut dyn Renderer,
+    from: Rect,
+    origin: [f32; 2],
+    scale: f32,
+}
+
+impl<'a> Viewport<'a> {
+    pub fn new(inner: &'a mut dyn Renderer, from: Rect, to: Rect) -> Self {
+        let scale = (to.w / from.w).min(to.h / from.h);
+        Viewport {
+            inner,
+            from,
+            origin: [to.x + (to.w - from.w * scale) / 2., to.y + (to.h - from.h * scale) / 2.],
+            scale,
+        }
+    }
+    fn point(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        [self.origin[0] + (x - self.from.x) * self.scale, self.origin[1] + (y - self.from.y) * self.scale]
+    }
+}
+
+impl Renderer for Viewport<'_> {
+    fn draw_text(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color) {
+        let at = self.point(at);
+        self.inner.draw_text(text, scale * self.scale, at, colour);
+    }
+    fn draw_text_centred(&mut self, text: &str, scale: f32, at: [f32; 2], colour: Color) {
+        let at = self.point(at);
+        self.inner.draw_text_centred(text, scale * self.scale, at, colour);
+    }
+    fn draw_panel(&mut self, rect: Rect, colour: Color) {
+        let [x, y] = self.point([rect.x, rect.y]);
+        self.inner.draw_panel(Rect::new(x, y, rect.w * self.scale, rect.h * self.scale), colour);
+    }
+}
+
+/// Where on the screen the cell `(x, y)` cells from the top left corner of the board is.
+pub fn cell_rect(x: f32, y: f32) -> Rect {
+    const START_X: f32 = (FULL_GRID_SIZE.0 - GAME_GRID_SIZE.0) as f32 / 2.;
+    const START_Y: f32 = (FULL_GRID_SIZE.1 - GAME_GRID_SIZE.1) as f32;
+    Rect::new(
+        (START_X + x) * GRID_CELL_SIZE.0 as f32,
+        (START_Y + y) * GRID_CELL_SIZE.1 as f32,
+        GRID_CELL_SIZE.0 as f32,
+        GRID_CELL_SIZE.1 as f32,
+    )
+}
+
+pub fn draw_grid(r: &mut dyn Renderer, grid: &Grid, theme: &Theme) {
+    for (y, row) in grid.rows().enumerate() {
+        for (x, &c) in row.iter().enumerate() {
+            let colour = match c {
+                Cell::Empty => theme.background(),
+                Cell::Filled(t) => theme.colour(t.colour()),
+                Cell::Garbage => GARBAGE_COLOUR,
+            };
+            r.draw_cell(x as f32, y as f32, colour);
+        }
+    }
+}
+
+pub fn draw_piece(r: &mut dyn Renderer, piece: &Piece, at: Pos, theme: &Theme) {
+    draw_piece_lowered(r, piece, at, 0., theme);
+}
+
+/// Draws the piece `fall` of a row below `at`.
+fn draw_piece_lowered(r: &mut dyn Renderer, piece: &Piece, at: Pos, fall: f32, theme: &Theme) {
+    let colour = theme.colour(piece.kind.colour());
+    for pos in piece.points(at) {
+        r.draw_cell(pos.x as f32, pos.y as f32 + fall, colour);
+    }
+}
+
+pub fn draw_moving_piece(r: &mut dyn Renderer, piece: &MovingPiece, theme: &Theme) {
+    draw_piece(r, &piece.piece, piece.pos, theme);
+}
+
+/// Outlines where `piece` would go on the board of `game`, e.g. to suggest a placement.
+pub fn draw_outline(r: &mut dyn Renderer, game: &Game, piece: &MovingPiece, theme: &Theme) {
+    draw_piece_outline(&mut Shifted { inner: r, by: board_offset(game.grid.size()) }, piece, theme);
+}
+
+/// Outlines the cells of `piece` in its colour.
+pub fn draw_piece_outline(r: &mut dyn Renderer, piece: &MovingPiece, theme: &Theme) {
+    let colour = theme.colour(piece.piece.kind.colour());
+    let width = 3.;
+    for pos in piece.piece.points(piece.pos) {
+        let cell = cell_rect(pos.x as f32, pos.y as f32);
+        r.draw_panel(Rect::new(cell.x, cell.y, cell.w, width), colour);
+        r.draw_panel(Rect::new(cell.x, cell.bottom() - width, cell.w, width), colour);
+        r.draw_panel(Rect::new(cell.x, cell.y, width, cell.h), colour);
+        r.draw_panel(Rect::new(cell.right() - width, cell.y, width, cell.h), colour);
+    }
+}
+
+/// How far a board of `size` is moved from where the usual board is drawn by `cell_rect`,
+/// to keep it in the middle and at the bottom of the screen.
+pub fn board_offset((width, height): (i8, i8)) -> [f32; 2] {
+    [
+        (GAME_GRID_SIZE.0 - width) as f32 / 2. * GRID_CELL_SIZE.0 as f32,
+        (GAME_GRID_SIZE.1 - height) as f32 * GRID_CELL_SIZE.1 as f32,
+    ]
+}
+
+/// Draws a bar up the left edge of the board as high as the rows of garbage waiting to come in.
+fn draw_garbage_meter(r: &mut dyn Renderer, game: &Game) {
+    let rows = game.pending_garbage();
+    if rows == 0 {
+        return;
+    }
+    let height = game.grid.size().1 as f32;
+    let top = cell_rect(-0.25, height - rows as f32);
+    let bottom = cell_rect(-0.25, height);
+    r.draw_panel(Rect::new(top.x, top.y, top.w / 4., bottom.y - top.y), Color::RED);
+}
+
+/// Draws the board with the falling piece, and the next and held pieces beside it.
+/// The falling piece is drawn `fall` of a row lower than where it is, to smooth out its falling.
+pub fn draw_game(r: &mut dyn Renderer, game: &Game, fall: f32, theme: &Theme) {
+    draw_game_with_pieces_at(r, game, fall, theme, Pos::new(-3, -3), Pos::new(-3, 2));
+}
+
+/// Draws the board with the falling piece, and the held and next pieces side by side above it, for the portrait layout.
+pub fn draw_game_portrait(r: &mut dyn Renderer, game: &Game, fall: f32, theme: &Theme) {
+    draw_game_with_pieces_at(r, game, fall, theme, Pos::new(7, -3), Pos::new(2, -3));
+}
+
+fn draw_game_with_pieces_at(r: &mut dyn Renderer, game: &Game, fall: f32, theme: &Theme, next: Pos, hold: Pos) {
+    {
+        let r = &mut Shifted { inner: r, by: board_offset(game.grid.size()) };
+        draw_piece(r, &game.next_piece, next, theme);
+        if let Some(piece) = &game.hold_piece {
+            draw_piece(r, piece, hold, theme);
+        }
+    }
+    draw_board(r, game, fall, theme);
+}
+
+/// A resizable window `width` by `height` pixels on a monitor that isn't scaled, and as many times bigger as a monitor
+/// is scaled, so everything is as big on a hi-DPI monitor as on any other. Moved to a monitor scaled differently,
+/// it is resized to match. What is drawn in it is laid out to fit its actual size in pixels, see `fit`.
+pub fn window_mode((width, height): (f32, f32)) -> WindowMode {
+    WindowMode {
+        logical_size: Some(LogicalSize::new(width, height)),
+        ..WindowMode::default().dimensions(width, height).resizable(true).resize_on_scale_factor_change(true)
+    }
+}
+
+/// Where `view` goes in a window `size` big: as big as fits without stretching it, in the middle.
+pub fn fit(view: Rect, (width, height): (f32, f32)) -> Rect {
+    let scale = (width / view.w).min(height / view.h);
+    let (w, h) = (view.w * scale, view.h * scale);
+    Rect::new((width - w) / 2., (height - h) / 2., w, h)
+}
+
+/// Draws just the board with the falling piece and the garbage waiting to come in, without the pieces beside it.
+pub fn draw_board(r: &mut dyn Renderer, game: &Game, fall: f32, theme: &Theme) {
+    let r = &mut Shifted { inner: r, by: board_offset(game.grid.size()) };
+    draw_grid(r, &game.grid, theme);
+    draw_garbage_meter(r, game);
+
+    if let Some(p) = &game.cur_piece {
+        draw_piece_lowered(r, &p.piece, p.pos, fall, theme);
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ggez::{GameError, GameResult};
+use log::warn;
+
+use crate::{
+    config::{GameConfig, Handling},
+    input::{Action, InputEvent, InputListener},
+    mode::Mode,
+    rules::{Game, TICKS_PER_SECOND},
+    storage::Storage,
+};
+
+const REPLAY_DIR: &str = "replays";
+const REPLAY_EXTENSION: &str = "replay";
+/// How many of the latest replays are kept, besides the personal bests.
+const KEEP_REPLAYS: usize = 20;
+/// The version of the share format written by `Replay::encode`.
+const FORMAT_VERSION: u8 = 4;
+/// How long a game being verified is played on for after its last input before it is given up on.
+const VERIFY_AFTER_INPUTS: u32 = 10 * 60 * TICKS_PER_SECOND;
+
+/// Everything needed to play a game out again exactly as it went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub mode: Mode,
+    pub seed: u64,
+    pub level: u32,
+    pub handling: Handling,
+    /// The inputs as `(tick, action, pressed)`.
+    pub events: Vec<(u32, Action, bool)>,
+    pub score: u32,
+    pub lines: u32,
+    /// The checksum of the final state of the game, to tell whether playing it back went the same way.
+    pub checksum: u64,
+    /// The lowest byte of `Game::state_checksum` at the end of each tick from the first on,
+    /// to tell which tick playing it back went differently at. Empty in replays from before there were any,
+    /// and in those from before version 4, whose checksums left out some of the game and can't be checked any more.
+    pub tick_checksums: Vec<u8>,
+}
+
+impl Replay {
+    /// The directory replays are kept in, inside the profile's directory.
+    fn dir(profile_dir: &str) -> String {
+        format!("{profile_dir}/{REPLAY_DIR}")
+    }
+    /// The keys of the saved replays, personal bests first and then newest first.
+    pub fn list(storage: &dyn Storage, profile_dir: &str) -> Vec<String> {
+        let mut keys = storage.list(&Self::dir(profile_dir)).unwrap_or_else(|e| {
+            warn!("Could not list replays: {e}");
+            Vec::new()
+        });
+        keys.retain(|key| key.ends_with(&format!(".{REPLAY_EXTENSION}")));
+        keys.sort();
+        keys.reverse();
+        keys
+    }
+    pub fn load(storage: &dyn Storage, key: &str) -> GameResult<Self> {
+        let data = storage
+            .read(key)?
+            .ok_or_else(|| GameError::CustomError(format!("There is no replay {key}")))?;
+        Self::decode(&String::from_utf8_lossy(&data))
+    }
+
+    /// Encodes the replay as text that can be shared.
+    ///
+    /// The text is the URL-safe base64 (without padding) of the following, with all numbers little endian:
+    ///
+    /// | Bytes  | Contents                                                   |
+    /// |--------|------------------------------------------------------------|
+    /// | 1      | Format version, currently 4                                |
+    /// | 1      | Mode: 0 Marathon, 1 Sprint, 2 Ultra                        |
+    /// | 8      | Seed                                                       |
+    /// | 4      | Starting level                                             |
+    /// | 4      | DAS (ms)                                                   |
+    /// | 4      | ARR (ms)                                                   |
+    /// | 1      | SDF                                                        |
+    /// | 4      | Misdrop guard (ms)                                         |
+    /// | 4      | Final score                                                |
+    /// | 4      | Final lines                                                |
+    /// | 8      | Checksum of the final state                                |
+    /// | 4      | Number of inputs                                           |
+    /// | 6 each | Inputs: tick (4), action (1, index in `Action::ALL`), pressed (1) |
+    /// | 4      | Number of ticks                                            |
+    /// | 1 each | Lowest byte of the state checksum after each tick          |
+    ///
+    /// Version 3 is the same with tick checksums of less of the game, version 2 also without the starting level,
+    /// and version 1 also without the tick checksums at the end.
+    pub fn encode(&self) -> String {
+        let mut bytes = vec![FORMAT_VERSION, Mode::ALL.iter().position(|&m| m == self.mode).unwrap_or(0) as u8];
+        bytes.extend(self.seed.to_le_bytes());
+        bytes.extend(self.level.to_le_bytes());
+        bytes.extend(self.handling.das.to_le_bytes());
+        bytes.extend(self.handling.arr.to_le_bytes());
+        bytes.push(self.handling.sdf);
+        bytes.extend(self.handling.misdrop_guard.to_le_bytes());
+        bytes.extend(self.score.to_le_bytes());
+        bytes.extend(self.lines.to_le_bytes());
+        bytes.extend(self.checksum.to_le_bytes());
+        bytes.extend((self.events.len() as u32).to_le_bytes());
+        for &(tick, action, pressed) in &self.events {
+            bytes.extend(tick.to_le_bytes());
+            bytes.push(Action::ALL.iter().position(|&a| a == action).unwrap_or(0) as u8);
+            bytes.push(pressed as u8);
+        }
+        bytes.extend((self.tick_checksums.len() as u32).to_le_bytes());
+        bytes.extend(&self.tick_checksums);
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+    /// Decodes a replay written by `encode`.
+    pub fn decode(s: &str) -> GameResult<Self> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(s.trim())
+            .map_err(|e| GameError::CustomError(format!("Invalid replay: {e}")))?;
+        let mut reader = Reader(&bytes);
+        let version = reader.u8()?;
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(GameError::CustomError(format!("Unsupported replay version {version}")));
+        }
+        let mode = *Mode::ALL.get(reader.u8()? as usize).ok_or_else(|| invalid("mode"))?;
+        let seed = reader.u64()?;
+        let level = if version >= 3 { reader.u32()? } else { 0 };
+        let handling = Handling {
+            das: reader.u32()?,
+            arr: reader.u32()?,
+            sdf: reader.u8()?,
+            misdrop_guard: reader.u32()?,
+        };
+        let score = reader.u32()?;
+        let lines = reader.u32()?;
+        let checksum = reader.u64()?;
+        let num_events = reader.u32()?;
+        let events = (0..num_events)
+            .map(|_| {
+                let tick = reader.u32()?;
+                let action = *Action::ALL.get(reader.u8()? as usize).ok_or_else(|| invalid("action"))?;
+                Ok((tick, action, reader.u8()? != 0))
+            })
+            .collect::<GameResult<_>>()?;
+        let tick_checksums = if version >= 2 {
+            let num_ticks = reader.u32()?;
+            let tick_checksums: Vec<u8> = (0..num_ticks).map(|_| reader.u8()).collect::<GameResult<_>>()?;
+            if version >= 4 { tick_checksums } else { Vec::new() }
+        } else {
+            Vec::new()
+        };
+        Ok(Replay {
+            mode,
+            seed,
+            level,
+            handling,
+            events,
+            score,
+            lines,
+            checksum,
+            tick_checksums,
+        })
+    }
+    /// Plays the replay out without drawing anything, checking that it ends with the score, lines and board it says it does,
+    /// as one sent to a leaderboard has to.
+    pub fn verify(&self, config: &GameConfig) -> Result<(), String> {
+        let mut game = Game::with_config(self.mode, self.mode.rules(), self.seed, self.handling, config);
+        game.start_level = self.level;
+        let mut playback = Playback::new(self);
+        let last_input = self.events.last().map_or(0, |&(tick, ..)| tick);
+        while !game.gameover {
+            if game.tick > last_input.saturating_add(VERIFY_AFTER_INPUTS) {
+                return Err(format!("The game was still going {} s after the last input", VERIFY_AFTER_INPUTS / TICKS_PER_SECOND));
+            }
+            let inputs: Vec<_> = playback.inputs(game.tick.wrapping_add(1)).collect();
+            game.tick(&inputs);
+            playback.check(game.tick, game.state_checksum());
+        }
+        if let Some(tick) = playback.desync_tick {
+            return Err(format!("The game went differently from the recording at tick {tick}"));
+        }
+        if (game.score, game.lines) != (self.score, self.lines) {
+            return Err(format!("The game ended with {} points and {} lines, not {} and {}", game.score, game.lines, self.score, self.lines));
+        }
+        if game.checksum() != self.checksum {
+            return Err("The game ended with a different board".to_owned());
+        }
+        Ok(())
+    }
+}
+
+fn invalid(what: &str) -> GameError {
+    GameError::CustomError(format!("Invalid replay: bad {what}"))
+}
+
+/// Reads little endian numbers off the front of a byte slice.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> GameResult<[u8; N]> {
+        if self.0.len() < N {
+            return Err(invalid("length"));
+        }
+        let (bytes, rest) = self.0.split_at(N);
+        self.0 = rest;
+        let mut out = [0; N];
+        out.copy_from_slice(bytes);
+        Ok(out)
+    }
+    fn u8(&mut self) -> GameResult<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+    fn u32(&mut self) -> GameResult<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+    fn u64(&mut self) -> GameResult<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+}
+
+/// Feeds the inputs of a replay back in on the ticks they were made on.
+#[derive(Debug)]
+pub struct Playback {
+    events: Vec<(u32, Action, bool)>,
+    next: usize,
+    /// The checksum the game should end with.
+    pub checksum: u64,
+    tick_checksums: Vec<u8>,
+    /// The first tick that played out differently from how it was recorded, if one has.
+    pub desync_tick: Option<u32>,
+}
+
+impl Playback {
+    pub fn new(replay: &Replay) -> Self {
+        Playback {
+            events: replay.events.clone(),
+            next: 0,
+            checksum: replay.checksum,
+            tick_checksums: replay.tick_checksums.clone(),
+            desync_tick: None,
+        }
+    }
+    /// Checks the state the game was in at the end of `tick` against the recording.
+    pub fn check(&mut self, tick: u32, checksum: u64) {
+        let recorded = tick.checked_sub(1).and_then(|i| self.tick_checksums.get(i as usize));
+        if self.desync_tick.is_none() && recorded.is_some_and(|&c| c != checksum as u8) {
+            warn!("Replay desynced at tick {tick}");
+            self.desync_tick = Some(tick);
+        }
+    }
+    /// The inputs made up to and including `tick` that haven't been played yet.
+    pub fn inputs(&mut self, tick: u32) -> impl Iterator<Item = (Action, bool)> + '_ {
+        let start = self.next;
+        while self.events.get(self.next).is_some_and(|&(t, _, _)| t <= tick) {
+            self.next += 1;
+        }
+        self.events[start..self.next].iter().map(|&(_, action, pressed)| (action, pressed))
+    }
+}
+
+/// Records the inputs of a game and saves the replay when it ends.
+/// The latest games are kept along with the best one of each mode.
+#[derive(Debug)]
+pub struct ReplayRecorder {
+    storage: Rc<dyn Storage>,
+    dir: String,
+    replay: Replay,
+}
+
+impl ReplayRecorder {
+    pub fn new(storage: Rc<dyn Storage>, profile_dir: &str, mode: Mode, seed: u64, level: u32, handling: Handling) -> Self {
+        ReplayRecorder {
+            storage,
+            dir: Replay::dir(profile_dir),
+            replay: Replay {
+                mode,
+                seed,
+                level,
+                handling,
+                events: Vec::new(),
+                score: 0,
+                lines: 0,
+                checksum: 0,
+                tick_checksums: Vec::new(),
+            },
+        }
+    }
+    fn save(&self, personal_best: bool) -> GameResult {
+        let s = self.replay.encode();
+        let dir = &self.dir;
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mode = self.replay.mode.key();
+        self.storage.write(&format!("{dir}/{time}-{mode}.{REPLAY_EXTENSION}"), s.as_bytes())?;
+        if personal_best {
+            self.storage.write(&format!("{dir}/best-{mode}.{REPLAY_EXTENSION}"), s.as_bytes())?;
+        }
+
+        // The timestamp prefix makes the names sort oldest first
+        let mut latest = self.storage.list(dir)?;
+        latest.retain(|key| !key.starts_with(&format!("{dir}/best-")));
+        latest.sort();
+        let too_many = latest.len().saturating_sub(KEEP_REPLAYS);
+        for key in &latest[..too_many] {
+            self.storage.remove(key)?;
+        }
+        Ok(())
+    }
+}
+
+impl InputListener for ReplayRecorder {
+    fn input(&mut self, event: InputEvent) {
+        self.replay.events.push((event.tick, event.action, event.pressed));
+    }
+    fn tick_end(&mut self, _tick: u32, checksum: u64) {
+        self.replay.tick_checksums.push(checksum as u8);
+    }
+    fn game_over(&mut self, score: u32, lines: u32, checksum: u64, personal_best: bool) {
+        self.replay.score = score;
+        self.replay.lines = lines;
+        self.replay.checksum = checksum;
+        if let Err(e) = self.save(personal_best) {
+            warn!("Could not save replay: {e}");
+        }
+    }
+    fn replay(&self) -> Option<String> {
+        Some(self.replay.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Marathon game recorded as it is played, hard dropping every ten ticks with a turn and a shift in between,
+    /// until it tops out.
+    fn recorded() -> Replay {
+        let handling = Handling::default();
+        let mut game = Game::with_config(Mode::Marathon, Mode::Marathon.rules(), 7, handling, &GameConfig::default());
+        let mut events = Vec::new();
+        let mut tick_checksums = Vec::new();
+        while !game.gameover {
+            assert!(game.tick < 100_000, "The game should top out");
+            let tick = game.tick + 1;
+            let inputs = match tick % 10 {
+                0 => vec![(Action::HardDrop, true)],
+                1 => vec![(Action::HardDrop, false)],
+                4 => vec![(Action::RotRight, true)],
+                5 => vec![(Action::RotRight, false)],
+                7 => vec![(Action::Left, true)],
+                8 => vec![(Action::Left, false)],
+                _ => Vec::new(),
+            };
+            events.extend(inputs.iter().map(|&(action, pressed)| (tick, action, pressed)));
+            game.tick(&inputs);
+            tick_checksums.push(game.state_checksum() as u8);
+        }
+        Replay {
+            mode: Mode::Marathon,
+            seed: 7,
+            level: 0,
+            handling,
+            events,
+            score: game.score,
+            lines: game.lines,
+            checksum: game.checksum(),
+            tick_checksums,
+        }
+    }
+
+    /// The bytes of an encoded replay, to write older or broken ones with.
+    fn bytes(replay: &Replay) -> Vec<u8> {
+        URL_SAFE_NO_PAD.decode(replay.encode()).unwrap()
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let replay = recorded();
+        assert!(!replay.events.is_empty());
+        assert_eq!(Replay::decode(&replay.encode()).unwrap(), replay);
+        assert_eq!(Replay::decode(&format!("  {}\n", replay.encode())).unwrap(), replay);
+    }
+
+    #[test]
+    fn older_versions_still_decode() {
+        let replay = recorded();
+        let mut v3 = bytes(&replay);
+        v3[0] = 3;
+        let decoded = Replay::decode(&URL_SAFE_NO_PAD.encode(&v3)).unwrap();
+        assert_eq!(decoded, Replay { tick_checksums: Vec::new(), ..replay.clone() });
+
+        let mut v1 = bytes(&replay);
+        v1[0] = 1;
+        v1.drain(10..14);
+        v1.truncate(v1.len() - 4 - replay.tick_checksums.len());
+        let decoded = Replay::decode(&URL_SAFE_NO_PAD.encode(&v1)).unwrap();
+        assert_eq!(decoded, Replay { tick_checksums: Vec::new(), ..replay });
+    }
+
+    #[test]
+    fn broken_replays_are_refused() {
+        let replay = recorded();
+        assert!(Replay::decode("not a replay!").is_err());
+        let mut truncated = bytes(&replay);
+        truncated.pop();
+        assert!(Replay::decode(&URL_SAFE_NO_PAD.encode(&truncated)).is_err());
+        for version in [0, FORMAT_VERSION + 1] {
+            let mut unknown = bytes(&replay);
+            unknown[0] = version;
+            assert!(Replay::decode(&URL_SAFE_NO_PAD.encode(&unknown)).is_err());
+        }
+        let mut bad_mode = bytes(&replay);
+        bad_mode[1] = u8::MAX;
+        assert!(Replay::decode(&URL_SAFE_NO_PAD.encode(&bad_mode)).is_err());
+    }
+
+    #[test]
+    fn verifying_checks_how_the_game_went() {
+        let config = GameConfig::default();
+        let replay = recorded();
+        assert_eq!(replay.verify(&config), Ok(()));
+        assert!(Replay { score: replay.score + 100, ..replay.clone() }.verify(&config).is_err());
+        assert!(Replay { seed: 8, ..replay.clone() }.verify(&config).is_err());
+        let mut tick_checksums = replay.tick_checksums.clone();
+        tick_checksums[20] ^= 1;
+        let desynced = Replay { tick_checksums, ..replay };
+        assert_eq!(desynced.verify(&config), Err("The game went differently from the recording at tick 21".to_owned()));
+    }
+}
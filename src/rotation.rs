@@ -0,0 +1,225 @@
+use crate::{
+    grid::{Grid, Pos},
+    piece::{MovingPiece, Tetromino},
+};
+
+/// Which way to turn a piece.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Left,
+    Right,
+}
+
+/// Decides where a piece ends up when it is turned, if it can be turned at all.
+pub trait RotationSystem {
+    fn rotate(&self, piece: &MovingPiece, dir: Rotation, grid: &Grid) -> Option<MovingPiece>;
+}
+
+/// Turns pieces about the middle of their spawn position's block, only if they fit right there.
+/// This is how the game has always rotated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoKicks;
+
+impl RotationSystem for NoKicks {
+    fn rotate(&self, piece: &MovingPiece, dir: Rotation, grid: &Grid) -> Option<MovingPiece> {
+        let mut turned = piece.clone();
+        turned.piece.rotate(dir, (0, 0));
+        turned.fits(grid).then_some(turned)
+    }
+}
+
+/// The names modes can choose their rotation system by, such as scripted ones with `rotation()`.
+pub const NAMES: [&str; 3] = ["none", "srs", "ars"];
+
+/// The rotation system called `name`, one of `NAMES`.
+pub fn by_name(name: &str) -> Option<Box<dyn RotationSystem>> {
+    Some(match name {
+        "none" => Box::new(NoKicks),
+        "srs" => Box::new(Kicks::SRS),
+        "ars" => Box::new(Kicks::ARS),
+        _ => return None,
+    })
+}
+
+/// The offsets to try in turn, `(x, y)` with y going up, for each rotation.
+/// They are indexed by `2 * from + 0` for turning right and `2 * from + 1` for turning left,
+/// where `from` is the orientation before turning: 0 for spawn, 1 for right, 2 for flipped and 3 for left.
+type KickTable = [&'static [(i8, i8)]; 8];
+
+/// Turns pieces about their centre, and if they don't fit there, tries moving them by each of a list of offsets.
+#[derive(Debug, Clone, Copy)]
+pub struct Kicks {
+    pub jlstz: KickTable,
+    pub i: KickTable,
+    /// How many quarter turns right of the table's spawn orientation each piece's spawn orientation is, in `Tetromino::ALL` order.
+    pub spawn_rotation: [u8; 7],
+}
+
+impl Kicks {
+    /// The Super Rotation System of the modern guideline games.
+    pub const SRS: Kicks = Kicks {
+        jlstz: [
+            &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+            &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+            &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+            &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+            &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+            &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+            &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+            &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+        ],
+        i: [
+            &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+            &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+            &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+            &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+            &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+            &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+            &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+            &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+        ],
+        // The L, T and J spawn upside down compared to the guideline
+        spawn_rotation: [2, 0, 2, 0, 0, 0, 2],
+    };
+    /// The Arika Rotation System of the TGM games, kicking one step right and then left, but never the I.
+    pub const ARS: Kicks = Kicks {
+        jlstz: [&[(0, 0), (1, 0), (-1, 0)]; 8],
+        i: [&[(0, 0)]; 8],
+        spawn_rotation: [0; 7],
+    };
+
+    /// Where the piece turns about, in half cells.
+    fn centre(kind: Tetromino) -> (i8, i8) {
+        match kind {
+            Tetromino::L | Tetromino::T | Tetromino::J => (0, -2),
+            Tetromino::S | Tetromino::Z => (0, 0),
+            Tetromino::I => (1, 1),
+            Tetromino::O => (-1, -1),
+        }
+    }
+}
+
+impl RotationSystem for Kicks {
+    fn rotate(&self, piece: &MovingPiece, dir: Rotation, grid: &Grid) -> Option<MovingPiece> {
+        let kind = piece.piece.kind;
+        let mut turned = piece.clone();
+        turned.piece.rotate(dir, Self::centre(kind));
+        let from = (piece.piece.rotation + self.spawn_rotation[kind.colour()]) % 4;
+        let i = 2 * from as usize + (dir == Rotation::Left) as usize;
+        let kicks = match kind {
+            Tetromino::I => self.i[i],
+            Tetromino::O => &[(0, 0)],
+            _ => self.jlstz[i],
+        };
+        kicks.iter().find_map(|&(dx, dy)| {
+            let mut kicked = turned.clone();
+            kicked.pos = Pos::new(piece.pos.x + dx, piece.pos.y - dy);
+            kicked.fits(grid).then_some(kicked)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid::Cell, piece::Piece};
+
+    fn empty() -> Grid {
+        Grid::with_size((10, 20))
+    }
+
+    /// `kind` at `pos` after turning it `turns` times with `system`, which has to fit each time.
+    fn turned(system: &dyn RotationSystem, kind: Tetromino, turns: &[Rotation], pos: (i8, i8)) -> MovingPiece {
+        let mut piece = MovingPiece::new(Piece::new(kind), Pos::new(4, 10));
+        for &dir in turns {
+            piece = system.rotate(&piece, dir, &empty()).unwrap();
+        }
+        piece.pos = Pos::new(pos.0, pos.1);
+        piece
+    }
+
+    fn cells(piece: &MovingPiece) -> Vec<(i8, i8)> {
+        let mut cells: Vec<_> = piece.piece.points(piece.pos).map(|p| (p.x, p.y)).collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn no_kicks_turns_in_place_as_it_always_has() {
+        for kind in Tetromino::ALL {
+            let mut piece = MovingPiece::new(Piece::new(kind), Pos::new(4, 10));
+            for dir in [Rotation::Right, Rotation::Right, Rotation::Left, Rotation::Right, Rotation::Right] {
+                let mut expected = piece.clone();
+                expected.piece.rotate(dir, (0, 0));
+                piece = NoKicks.rotate(&piece, dir, &empty()).unwrap();
+                assert_eq!(piece, expected);
+            }
+        }
+        let i = turned(&NoKicks, Tetromino::I, &[Rotation::Right], (0, 10));
+        assert_eq!(cells(&i), [(0, 9), (0, 10), (0, 11), (0, 12)]);
+        assert!(NoKicks.rotate(&i, Rotation::Left, &empty()).is_none());
+    }
+
+    #[test]
+    fn wall_kicks() {
+        let t = turned(&Kicks::SRS, Tetromino::T, &[Rotation::Left], (0, 10));
+        assert_eq!(cells(&t), [(0, 8), (0, 9), (0, 10), (1, 9)]);
+        for system in [Kicks::SRS, Kicks::ARS] {
+            let kicked = system.rotate(&t, Rotation::Right, &empty()).unwrap();
+            assert_eq!(kicked.pos, Pos::new(1, 10));
+            assert_eq!(cells(&kicked), [(0, 9), (1, 9), (1, 10), (2, 9)]);
+        }
+        let j = turned(&Kicks::SRS, Tetromino::J, &[Rotation::Right], (9, 10));
+        let kicked = Kicks::SRS.rotate(&j, Rotation::Left, &empty()).unwrap();
+        assert_eq!(cells(&kicked), [(7, 9), (8, 9), (9, 9), (9, 10)]);
+    }
+
+    #[test]
+    fn the_i_kicks_by_its_own_table() {
+        let i = turned(&Kicks::SRS, Tetromino::I, &[Rotation::Right], (-1, 10));
+        assert_eq!(cells(&i), [(0, 9), (0, 10), (0, 11), (0, 12)]);
+        let kicked = Kicks::SRS.rotate(&i, Rotation::Left, &empty()).unwrap();
+        assert_eq!(kicked.pos, Pos::new(1, 10));
+        assert_eq!(cells(&kicked), [(0, 10), (1, 10), (2, 10), (3, 10)]);
+        // The Arika system never kicks the I
+        assert!(Kicks::ARS.rotate(&i, Rotation::Left, &empty()).is_none());
+    }
+
+    #[test]
+    fn floor_kicks() {
+        let i = MovingPiece::new(Piece::new(Tetromino::I), Pos::new(4, 19));
+        let right = Kicks::SRS.rotate(&i, Rotation::Right, &empty()).unwrap();
+        assert_eq!(cells(&right), [(6, 16), (6, 17), (6, 18), (6, 19)]);
+        let left = Kicks::SRS.rotate(&i, Rotation::Left, &empty()).unwrap();
+        assert_eq!(cells(&left), [(3, 16), (3, 17), (3, 18), (3, 19)]);
+        assert!(NoKicks.rotate(&i, Rotation::Right, &empty()).is_none());
+    }
+
+    #[test]
+    fn the_o_stays_put() {
+        // In a well just wide enough, with nowhere to be kicked to
+        let mut grid = empty();
+        for y in 16..20 {
+            for x in [0, 1, 2, 5, 6, 7, 8, 9] {
+                grid.set(Pos::new(x, y), Cell::Garbage);
+            }
+        }
+        let o = MovingPiece::new(Piece::new(Tetromino::O), Pos::new(4, 19));
+        assert_eq!(cells(&o), [(3, 18), (3, 19), (4, 18), (4, 19)]);
+        for dir in [Rotation::Left, Rotation::Right] {
+            let turned = Kicks::SRS.rotate(&o, dir, &grid).unwrap();
+            assert_eq!((turned.pos, cells(&turned)), (o.pos, cells(&o)));
+        }
+    }
+
+    #[test]
+    fn every_name_picks_a_system() {
+        for name in NAMES {
+            assert!(by_name(name).is_some(), "{name}");
+        }
+        let i = turned(&Kicks::SRS, Tetromino::I, &[Rotation::Right], (-1, 10));
+        assert!(by_name("srs").unwrap().rotate(&i, Rotation::Left, &empty()).is_some());
+        assert!(by_name("none").unwrap().rotate(&i, Rotation::Left, &empty()).is_none());
+        assert!(by_name("dtet").is_none());
+    }
+}
@@ -0,0 +1,385 @@
+use std::{fmt, io};
+
+use ggez::{
+    event::{Button, GamepadId},
+    graphics::{Color, Rect},
+    input::keyboard::{KeyCode, KeyMods},
+    Context,
+};
+use log::{info, warn};
+use oorandom::Rand32;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::GameState,
+    config::Handling,
+    handicap::Handicap,
+    input::Action,
+    lobby::RoomRules,
+    mode::Mode,
+    net::{check_handshake, Connection, Message},
+    render::{Renderer, SCREEN_SIZE},
+    scene::{Scene, Transition},
+    settings::MenuInput,
+    versus::{Controls, Side, COUNTDOWN_MS},
+    worker::Worker,
+};
+
+/// The fewest players a battle royale is started with.
+pub const MIN_PLAYERS: usize = 3;
+/// The most players a battle royale is started with.
+pub const MAX_PLAYERS: usize = 8;
+/// Room above the boards for the player's name and targeting.
+const HEADER_HEIGHT: f32 = 96.;
+
+/// Who the garbage a player of a battle royale sends goes to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Targeting {
+    /// Anyone still in, picked anew for every attack.
+    #[default]
+    Random,
+    /// Whoever last sent garbage to the player, to get back at them.
+    Attacker,
+    /// Whoever has knocked out the most players, to stop them getting further ahead.
+    Badges,
+}
+
+impl Targeting {
+    pub const ALL: [Targeting; 3] = [Targeting::Random, Targeting::Attacker, Targeting::Badges];
+}
+
+impl fmt::Display for Targeting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Targeting::Random => "Random",
+            Targeting::Attacker => "Attacker",
+            Targeting::Badges => "Badges",
+        })
+    }
+}
+
+/// Who the garbage player `from` sends goes to by `targeting`, among those still in.
+/// When the strategy has nobody to pick, e.g. nobody has attacked `from` yet, someone is picked at random.
+pub fn pick_target(from: usize, targeting: Targeting, alive: &[bool], badges: &[u32], attacked_by: Option<usize>, rng: &mut Rand32) -> Option<usize> {
+    let others: Vec<usize> = (0..alive.len()).filter(|&i| i != from && alive[i]).collect();
+    let picked = match targeting {
+        Targeting::Random => None,
+        Targeting::Attacker => attacked_by.filter(|i| others.contains(i)),
+        Targeting::Badges => {
+            let most = others.iter().map(|&i| badges[i]).max().unwrap_or(0);
+            let leaders: Vec<usize> = others.iter().copied().filter(|&i| badges[i] == most).collect();
+            (most > 0).then(|| leaders[rng.rand_range(0..leaders.len() as u32) as usize])
+        }
+    };
+    picked.or_else(|| (!others.is_empty()).then(|| others[rng.rand_range(0..others.len() as u32) as usize]))
+}
+
+/// How far joining a battle royale has got.
+enum Phase {
+    Connecting(Worker<String, io::Result<Connection>>),
+    /// Waiting for the server to find enough players.
+    Waiting(Connection),
+    Playing(Box<Royale>),
+    Failed(String),
+}
+
+/// A battle royale on a server: everyone plays the same pieces, and the garbage each player sends goes to
+/// one of the others by the targeting they have picked, until only one is left.
+pub struct RoyaleScene {
+    phase: Phase,
+}
+
+impl RoyaleScene {
+    pub fn join(address: &str) -> Self {
+        let worker = Worker::spawn("connect", |address: String, _| Connection::connect(&address));
+        worker.send(address.to_owned());
+        RoyaleScene {
+            phase: Phase::Connecting(worker),
+        }
+    }
+}
+
+impl Scene for RoyaleScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        let phase = std::mem::replace(&mut self.phase, Phase::Failed(String::new()));
+        self.phase = match phase {
+            Phase::Connecting(worker) => {
+                let result = worker.results().next();
+                match result {
+                    Some(Ok(mut connection)) => {
+                        connection.send(&Message::handshake(&state.game_config));
+                        connection.send(&Message::JoinRoyale {
+                            name: state.config.profile.clone(),
+                            handling: state.config.profile().handling,
+                        });
+                        Phase::Waiting(connection)
+                    }
+                    Some(Err(e)) => Phase::Failed(format!("Could not connect: {e}")),
+                    None => Phase::Connecting(worker),
+                }
+            }
+            Phase::Waiting(mut connection) => match connection.receive() {
+                Some(Message::RoyaleStart { seed, rules, player, players }) => Phase::Playing(Box::new(Royale::new(state, connection, seed, rules, player, players))),
+                Some(Message::Handshake { version, rules, engine, .. }) => match check_handshake(version, rules, engine, &state.game_config) {
+                    Ok(()) => Phase::Waiting(connection),
+                    Err(e) => Phase::Failed(e),
+                },
+                Some(Message::Refused { reason }) => Phase::Failed(reason),
+                Some(message) => {
+                    warn!("Unexpected message while waiting for a battle royale: {message:?}");
+                    Phase::Waiting(connection)
+                }
+                None if connection.is_closed() => Phase::Failed("The server closed the connection".to_owned()),
+                None => Phase::Waiting(connection),
+            },
+            Phase::Playing(mut royale) => {
+                royale.update();
+                Phase::Playing(royale)
+            }
+            Phase::Failed(message) => Phase::Failed(message),
+        };
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let middle = [SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 / 2.];
+        let back = [16., SCREEN_SIZE.1 - 32.];
+        match &self.phase {
+            Phase::Connecting(_) => {
+                r.draw_text_centred("Connecting...", 32., middle, Color::WHITE);
+                r.draw_text("Back: cancel", 16., back, Color::WHITE);
+            }
+            Phase::Waiting(_) => {
+                r.draw_text_centred(&format!("Waiting for at least {MIN_PLAYERS} players"), 32., middle, Color::WHITE);
+                r.draw_text("Back: cancel", 16., back, Color::WHITE);
+            }
+            Phase::Playing(royale) => royale.draw(state, r),
+            Phase::Failed(message) => {
+                r.draw_text_centred(message, 24., middle, Color::RED);
+                r.draw_text("Back: choose again", 16., back, Color::WHITE);
+            }
+        }
+    }
+    fn menu_input(&mut self, _state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        match (input, &self.phase) {
+            (MenuInput::Back, _) => Transition::Pop(1),
+            (MenuInput::Confirm, Phase::Playing(royale)) if royale.winner.is_some() => Transition::Pop(1),
+            _ => Transition::None,
+        }
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, repeated: bool) -> Transition {
+        if let Phase::Playing(royale) = &mut self.phase {
+            if royale.playing() {
+                let targeting = match keycode {
+                    KeyCode::Key1 => Some(Targeting::Random),
+                    KeyCode::Key2 => Some(Targeting::Attacker),
+                    KeyCode::Key3 => Some(Targeting::Badges),
+                    _ => None,
+                };
+                if let Some(targeting) = targeting {
+                    royale.target(targeting);
+                } else if !repeated && royale.controls.key_down(keycode) == Some(Action::Pause) {
+                    // Leaving knocks the player out
+                    return Transition::Pop(1);
+                }
+                return Transition::None;
+            }
+        }
+        match MenuInput::from_key(keycode, mods, &state.bindings) {
+            Some(input) => self.menu_input(state, ctx, input),
+            None => Transition::None,
+        }
+    }
+    fn key_up(&mut self, _state: &mut GameState, keycode: KeyCode) {
+        if let Phase::Playing(royale) = &mut self.phase {
+            royale.controls.key_up(keycode);
+        }
+    }
+    fn gamepad_button_down(&mut self, state: &mut GameState, ctx: &mut Context, btn: Button, _id: GamepadId) -> Transition {
+        if let Phase::Playing(royale) = &mut self.phase {
+            if royale.playing() {
+                match state.config.profile().buttons.action(btn) {
+                    Some(Action::Pause) => return Transition::Pop(1),
+                    Some(action) => royale.controls.push(action, true),
+                    // The targeting goes round with the spare shoulder button
+                    None if btn == Button::LeftTrigger2 => {
+                        let next = Targeting::ALL.iter().position(|&t| t == royale.targeting).map_or(0, |i| (i + 1) % Targeting::ALL.len());
+                        royale.target(Targeting::ALL[next]);
+                    }
+                    None => (),
+                }
+                return Transition::None;
+            }
+        }
+        match state.config.profile().buttons.menu_input(btn) {
+            Some(input) => self.menu_input(state, ctx, input),
+            None => Transition::None,
+        }
+    }
+    fn gamepad_button_up(&mut self, state: &mut GameState, btn: Button, _id: GamepadId) {
+        if let Phase::Playing(royale) = &mut self.phase {
+            if let Some(action) = state.config.profile().buttons.action(btn).filter(|&a| a != Action::Pause) {
+                royale.controls.push(action, false);
+            }
+        }
+    }
+}
+
+/// A battle royale under way. This board is played here and its ticks sent to the server,
+/// which sends back everyone else's ticks to be played out on copies of their boards,
+/// along with the garbage sent here and who has been knocked out.
+struct Royale {
+    connection: Connection,
+    /// Which of the players is played here.
+    player: usize,
+    names: Vec<String>,
+    sides: Vec<Side>,
+    rules: RoomRules,
+    /// How many ticks each board has played.
+    ticks: Vec<u32>,
+    /// The place each player came in, once they are out.
+    places: Vec<Option<usize>>,
+    /// How many players each player has knocked out.
+    badges: Vec<u32>,
+    controls: Controls,
+    targeting: Targeting,
+    countdown_ms: u32,
+    /// Garbage sent to this board, to land before its next tick.
+    incoming: Vec<(u32, usize)>,
+    /// Who won, once the server has said, or `None` inside if nobody did.
+    winner: Option<Option<usize>>,
+}
+
+impl Royale {
+    fn new(state: &GameState, connection: Connection, seed: u64, rules: RoomRules, player: usize, players: Vec<(String, Handling)>) -> Self {
+        info!("Starting a battle royale of {} players with seed {seed}", players.len());
+        let sides = players.iter().enumerate().map(|(i, (_, handling))| Side::new(seed, i, Mode::Marathon, rules.cap(*handling), &state.game_config, Handicap::default())).collect();
+        let count = players.len();
+        Royale {
+            connection,
+            player,
+            names: players.into_iter().map(|(name, _)| name).collect(),
+            sides,
+            rules,
+            ticks: vec![0; count],
+            places: vec![None; count],
+            badges: vec![0; count],
+            controls: Controls::new(state.config.profile().bindings(Mode::Marathon)),
+            targeting: Targeting::default(),
+            countdown_ms: COUNTDOWN_MS,
+            incoming: Vec::new(),
+            winner: None,
+        }
+    }
+    fn playing(&self) -> bool {
+        self.countdown_ms == 0 && self.winner.is_none() && !self.connection.is_closed()
+    }
+    fn target(&mut self, targeting: Targeting) {
+        if targeting != self.targeting {
+            self.targeting = targeting;
+            self.connection.send(&Message::Target { targeting });
+        }
+    }
+    fn update(&mut self) {
+        if self.winner.is_some() {
+            return;
+        }
+        let me = self.player;
+        if self.countdown_ms > 0 {
+            self.countdown_ms = self.countdown_ms.saturating_sub(self.sides[me].board.game.ms_per_tick());
+        } else if !self.sides[me].board.game.gameover {
+            let garbage = std::mem::take(&mut self.incoming);
+            let inputs = self.controls.take();
+            let side = &mut self.sides[me];
+            for &(rows, hole) in &garbage {
+                side.board.game.receive_garbage(rows, hole);
+            }
+            // Where what this board sends goes is up to the server
+            side.tick(&inputs, &self.rules.attack, self.rules.cancel);
+            self.ticks[me] += 1;
+            self.connection.send(&Message::Tick { tick: self.ticks[me], garbage, inputs });
+            if Message::checksum_due(self.ticks[me], &side.board.game) {
+                self.connection.send(&Message::checksum(self.ticks[me], &side.board.game));
+            }
+        }
+
+        while let Some(message) = self.connection.receive() {
+            match message {
+                Message::Watched { player, tick, garbage, inputs } if player < self.sides.len() && player != me && tick == self.ticks[player] + 1 => {
+                    let side = &mut self.sides[player];
+                    for (rows, hole) in garbage {
+                        side.board.game.receive_garbage(rows, hole);
+                    }
+                    side.tick(&inputs, &self.rules.attack, self.rules.cancel);
+                    self.ticks[player] = tick;
+                }
+                Message::Attack { rows, hole, .. } => {
+                    if !self.sides[me].board.game.gameover {
+                        self.incoming.push((rows, hole));
+                    }
+                }
+                Message::KnockedOut { player, place, by } if player < self.places.len() => {
+                    self.places[player] = Some(place);
+                    if let Some(by) = by.filter(|&by| by < self.badges.len()) {
+                        self.badges[by] += 1;
+                    }
+                }
+                Message::Result { winner } => {
+                    info!("Battle royale over, won by {:?}", winner.and_then(|w| self.names.get(w)));
+                    self.winner = Some(winner);
+                    if let Some(w) = winner.filter(|&w| w < self.places.len()) {
+                        self.places[w] = Some(1);
+                    }
+                    break;
+                }
+                message => warn!("Unexpected message during the battle royale: {message:?}"),
+            }
+        }
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let me = self.player;
+        let badges = |i: usize| "*".repeat(self.badges[i] as usize);
+
+        // This board in the middle, the rest small down the sides
+        let centre = Rect::new(SCREEN_SIZE.0 / 4., HEADER_HEIGHT, SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 - HEADER_HEIGHT - 48.);
+        r.draw_text_centred(&format!("{} {}", self.names[me], badges(me)), 32., [SCREEN_SIZE.0 / 2., 24.], Color::WHITE);
+        let targeting = format!("Targeting: {}  (1: random  2: attacker  3: badges)", self.targeting);
+        r.draw_text_centred(&targeting, 16., [SCREEN_SIZE.0 / 2., 64.], Color::WHITE);
+        self.sides[me].board.draw(r, centre, 0., &state.theme);
+
+        let others: Vec<usize> = (0..self.sides.len()).filter(|&i| i != me).collect();
+        let per_column = others.len().div_ceil(2).max(1);
+        let height = (SCREEN_SIZE.1 - HEADER_HEIGHT - 48.) / per_column as f32;
+        let width = SCREEN_SIZE.0 / 4. - 16.;
+        for (n, &i) in others.iter().enumerate() {
+            let x = if n % 2 == 0 { 8. } else { SCREEN_SIZE.0 * 3. / 4. + 8. };
+            let y = HEADER_HEIGHT + height * (n / 2) as f32;
+            let colour = if self.places[i].is_some() { Color::new(0.5, 0.5, 0.5, 1.) } else { Color::WHITE };
+            r.draw_text_centred(&format!("{} {}", self.names[i], badges(i)), 14., [x + width / 2., y + 8.], colour);
+            self.sides[i].board.draw_mini(r, Rect::new(x, y + 20., width, height - 28.), &state.theme);
+            if let Some(place) = self.places[i] {
+                r.draw_text_centred(&format!("#{place}"), 32., [x + width / 2., y + height / 2.], Color::YELLOW);
+            }
+        }
+
+        let middle = [SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 / 2.];
+        if self.countdown_ms > 0 {
+            r.draw_text_centred(&self.countdown_ms.div_ceil(1000).to_string(), 128., middle, Color::YELLOW);
+        }
+        let ended = match (self.winner, self.places[me]) {
+            (Some(Some(w)), _) => Some(format!("{} wins!", self.names[w])),
+            (Some(None), _) => Some("Nobody wins!".to_owned()),
+            (None, Some(place)) => Some(format!("Knocked out, #{place} of {}", self.sides.len())),
+            (None, None) if self.connection.is_closed() => Some("The server closed the connection".to_owned()),
+            (None, None) => None,
+        };
+        if let Some(text) = ended {
+            r.draw_panel(Rect::new(0., middle[1] - 48., SCREEN_SIZE.0, 96.), Color::new(0., 0., 0., 0.8));
+            r.draw_text_centred(&text, 40., middle, Color::YELLOW);
+        }
+        let footer = [16., SCREEN_SIZE.1 - 32.];
+        if self.winner.is_some() {
+            r.draw_text("Confirm or Back: choose mode", 16., footer, Color::WHITE);
+        }
+    }
+}
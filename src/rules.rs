@@ -0,0 +1,929 @@
+use std::collections::VecDeque;
+
+use oorandom::Rand32;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    config::{GameConfig, Handling},
+    grid::{Cell, Grid, Pos, MAX_GRID_WIDTH},
+    input::{Action, HeldActions, InputEvent, InputListener},
+    mode::Mode,
+    piece::{MovingPiece, Piece, Tetromino},
+    rotation::{Rotation, RotationSystem},
+    ruleset::{GameRules, Randomizer, RulesAction},
+    save::SavedGame,
+    scoring::{Clear, ScoringSystem},
+};
+
+/// How many times a second the game logic runs by default, however often the screen is drawn.
+/// Replays and saves count time in ticks, so changing this would break them.
+pub const TICKS_PER_SECOND: u32 = 24;
+pub const MS_PER_TICK: u32 = 1000 / TICKS_PER_SECOND;
+
+/// How long an input pressed while no piece is in play is kept around
+/// before it is thrown away.
+const INPUT_BUFFER_MS: u32 = 500;
+
+/// Something that happened during a tick, for the frontend, the statistics or whatever else is driving the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEvent {
+    PieceLocked { lines: u32, t_spin: bool },
+    GameOver { mode: Mode, score: u32, lines: u32, pieces: u32, ms: u32, efficiency: Efficiency },
+}
+
+/// How cleanly the pieces of a game were placed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Efficiency {
+    /// Placements that covered up empty cells, which are most likely misdrops.
+    pub misdrops: u32,
+    /// Turns beyond the fewest that would have left the pieces facing the way they were locked.
+    pub wasted_rotations: u32,
+}
+
+/// Everything that happened in a tick, so whatever is driving the game can react without looking inside it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TickResult {
+    pub events: Vec<GameEvent>,
+    /// The piece that was locked into the grid, where it was locked.
+    pub locked_piece: Option<MovingPiece>,
+    /// Which rows were cleared, counting from the top before they were removed.
+    pub cleared_rows: Vec<usize>,
+    /// Whether the game ended in this tick.
+    pub game_over: bool,
+}
+
+/// A single game, with everything that decides how it plays out and nothing about how it's shown.
+/// It only moves on when `tick` is called, so a bot, a server or a test can run it as fast as it likes.
+pub struct Game {
+    pub mode: Mode,
+    pub grid: Grid,
+    pub gameover: bool,
+    move_frames: u8,
+    pub score: u32,
+    pub lines: u32,
+    /// The level the game started at, going up one every 10 lines from there.
+    pub start_level: u32,
+    /// The mode's rules, which everything below that they decide is taken from.
+    pub rules: &'static dyn GameRules,
+    /// Decides what clears are worth, `GameRules::scoring` unless changed.
+    pub scoring: Box<dyn ScoringSystem>,
+    /// Decides how pieces turn, `GameRules::rotation_system` unless changed.
+    pub rotation: Box<dyn RotationSystem>,
+    /// How many pieces have been locked this game.
+    pub pieces: u32,
+    /// Whether the last thing the current piece did was rotate, for spotting T-spins.
+    last_move_rotated: bool,
+    pub efficiency: Efficiency,
+    /// How many times the current piece has been turned.
+    turns: u32,
+    pub seed: u64,
+    rng: Rand32,
+    randomizer: Box<dyn Randomizer>,
+    pub next_piece: Piece,
+    pub cur_piece: Option<MovingPiece>,
+    pub hold_piece: Option<Piece>,
+    /// Whether the current piece may still be swapped with the held piece.
+    can_hold: bool,
+    /// The handling this game is played with, which a replay brings its own of.
+    pub handling: Handling,
+    /// Where new pieces appear.
+    spawn: Pos,
+    /// How many frames a piece waits before falling a row, if not what the rules say.
+    frames_per_row: Option<u8>,
+    ms_per_tick: u32,
+    auto_shift: Option<AutoShift>,
+    pub held: HeldActions,
+    pub tick: u32,
+    /// The last tick the current piece spawned or shifted sideways on, for the misdrop guard.
+    last_shift_tick: u32,
+    /// Gets every input as it is applied, e.g. to record a replay.
+    pub input_listener: Option<Box<dyn InputListener>>,
+    /// Moves not yet applied, stamped with the tick they were made on.
+    /// Moves made while there is no current piece stay here until the next one spawns.
+    move_queue: VecDeque<(u32, Move)>,
+    /// The game as it was before each of the last placements, the latest at the back, up to `GameRules::undo_limit`.
+    undo: VecDeque<Snapshot>,
+    /// The placements that were undone, the latest undone at the back, until another piece is placed.
+    redo: Vec<Snapshot>,
+    /// Garbage sent by an opponent as `(rows, hole)`, oldest first, waiting to be pushed in under the stack.
+    garbage: VecDeque<(u32, usize)>,
+}
+
+/// Everything a placement changes, to go back to with undo.
+struct Snapshot {
+    grid: Grid,
+    score: u32,
+    lines: u32,
+    pieces: u32,
+    rng: Rand32,
+    randomizer: Box<dyn Randomizer>,
+    /// The piece that was in play, back at the spawn.
+    piece: Option<Piece>,
+    next_piece: Piece,
+    hold_piece: Option<Piece>,
+    can_hold: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Move {
+    Left, Right, RotLeft, RotRight, HardDrop, Hold, Undo, Redo,
+}
+
+/// A direction that is being held down, counting towards auto shift.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AutoShift {
+    mv: Move,
+    held_ms: u32,
+    shifts: u32,
+}
+
+impl Game {
+    /// Sets up a game whose pieces come from the given seed.
+    pub fn new(mode: Mode, seed: u64, handling: Handling) -> Self {
+        Self::with_rules(mode, mode.rules(), seed, handling)
+    }
+    /// Sets up a game of `mode` played by other rules than its own, such as a script's.
+    pub fn with_rules(mode: Mode, rules: &'static dyn GameRules, seed: u64, handling: Handling) -> Self {
+        Self::with_config(mode, rules, seed, handling, &GameConfig::default())
+    }
+    /// Sets up a game with a board, speed and so on other than the usual.
+    pub fn with_config(mode: Mode, rules: &'static dyn GameRules, seed: u64, handling: Handling, config: &GameConfig) -> Self {
+        let mut rng = Rand32::new(seed);
+        let mut randomizer = rules.randomizer();
+
+        Game {
+            mode,
+            grid: Grid::with_size(config.grid_size),
+            gameover: false,
+            next_piece: Piece::new(randomizer.next(&mut rng)),
+            cur_piece: None,
+            hold_piece: None,
+            can_hold: true,
+            move_frames: 0,
+            score: 0,
+            lines: 0,
+            start_level: 0,
+            rules,
+            scoring: rules.scoring(),
+            rotation: rules.rotation_system(),
+            pieces: 0,
+            last_move_rotated: false,
+            efficiency: Efficiency::default(),
+            turns: 0,
+            seed,
+            rng,
+            randomizer,
+            handling,
+            spawn: config.spawn,
+            frames_per_row: config.frames_per_row(),
+            ms_per_tick: config.ms_per_tick(),
+            auto_shift: None,
+            held: HeldActions::default(),
+            tick: 0,
+            last_shift_tick: 0,
+            input_listener: None,
+            move_queue: VecDeque::new(),
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            garbage: VecDeque::new(),
+        }
+    }
+    /// Deals the pieces with `randomizer` from the start of the game, instead of with the rules' own.
+    pub fn set_randomizer(&mut self, randomizer: Box<dyn Randomizer>) {
+        self.rng = Rand32::new(self.seed);
+        self.randomizer = randomizer;
+        self.next_piece = Piece::new(self.randomizer.next(&mut self.rng));
+    }
+    /// Advances the game by one tick, first pressing (`true`) or releasing the given actions,
+    /// and returns what happened in it.
+    pub fn tick(&mut self, actions: &[(Action, bool)]) -> TickResult {
+        let mut result = TickResult::default();
+        self.tick = self.tick.wrapping_add(1);
+        self.apply_inputs(actions);
+        let move_frame = {
+            self.move_frames = self.move_frames.saturating_add(self.gravity());
+            let frames_per_row = self.frames_per_row();
+            if self.move_frames > frames_per_row {
+                self.move_frames %= frames_per_row;
+                true
+            } else {
+                false
+            }
+        };
+
+        if !self.gameover {
+            self.apply_queued_moves(&mut result);
+            self.apply_auto_shift(&mut result);
+            if self.cur_piece.is_some() {
+                if move_frame && !self.step_down() {
+                    self.lock_piece(&mut result);
+                }
+            } else {
+                let piece = self.take_next_piece();
+                self.cur_piece = Some(MovingPiece::new(piece, self.spawn));
+                self.last_shift_tick = self.tick;
+                self.apply_queued_moves(&mut result);
+            }
+            for action in self.rules.after_tick(self, &result.events) {
+                self.apply_rules_action(action);
+            }
+            if self.rules.is_finished(self.lines, self.ms()) {
+                self.gameover = true;
+            }
+            if self.gameover {
+                result.game_over = true;
+                result.events.push(GameEvent::GameOver {
+                    mode: self.mode,
+                    score: self.score,
+                    lines: self.lines,
+                    pieces: self.pieces,
+                    ms: self.ms(),
+                    efficiency: self.efficiency,
+                });
+            }
+        }
+        if self.input_listener.is_some() {
+            let checksum = self.state_checksum();
+            if let Some(listener) = &mut self.input_listener {
+                listener.tick_end(self.tick, checksum);
+            }
+        }
+        result
+    }
+    /// How far the current piece is towards falling another row, from 0 to 1, if it has room to,
+    /// `between_ticks` of the way to the next tick.
+    pub fn fall_progress(&self, between_ticks: f32) -> f32 {
+        let Some(mut below) = self.cur_piece.clone() else {
+            return 0.;
+        };
+        below.pos.y += 1;
+        if below.fits(&self.grid) {
+            let frames = self.move_frames as f32 + self.gravity() as f32 * between_ticks;
+            (frames / (self.frames_per_row() as f32 + 1.)).min(1.)
+        } else {
+            0.
+        }
+    }
+    fn apply_rules_action(&mut self, action: RulesAction) {
+        match action {
+            RulesAction::AddGarbage { hole } => self.add_garbage(1, hole),
+            RulesAction::AddScore(points) => self.score = self.score.saturating_add(points),
+            RulesAction::EndGame => self.gameover = true,
+        }
+    }
+    /// Pushes `rows` rows of garbage with a gap in column `hole` in under the stack straight away,
+    /// unlike `receive_garbage`.
+    pub fn add_garbage(&mut self, rows: u32, hole: usize) {
+        let mut row = [Cell::Garbage; MAX_GRID_WIDTH];
+        if let Some(c) = row.get_mut(hole) {
+            *c = Cell::Empty;
+        }
+        if !self.grid.insert_rows_at_bottom(&vec![row; rows as usize]) {
+            self.gameover = true;
+        }
+        // The falling piece is pushed up along with the stack if it's in the way
+        if let Some(mp) = &mut self.cur_piece {
+            for _ in 0..rows {
+                if !mp.fits(&self.grid) {
+                    mp.pos.y -= 1;
+                }
+            }
+        }
+    }
+    /// Queues up `rows` rows of garbage from an opponent, all with the gap in column `hole`.
+    /// They are pushed in under the stack the next time a piece locks without clearing anything,
+    /// so clears in between can still cancel them with `cancel_garbage`.
+    pub fn receive_garbage(&mut self, rows: u32, hole: usize) {
+        if rows > 0 {
+            self.garbage.push_back((rows, hole));
+        }
+    }
+    /// Cancels up to `rows` of the garbage waiting, oldest first, as when a clear counters an attack,
+    /// returning how many of `rows` were left to send on.
+    pub fn cancel_garbage(&mut self, mut rows: u32) -> u32 {
+        while let Some((waiting, _)) = self.garbage.front_mut() {
+            if rows == 0 {
+                break;
+            }
+            let cancelled = rows.min(*waiting);
+            *waiting -= cancelled;
+            rows -= cancelled;
+            if *waiting == 0 {
+                self.garbage.pop_front();
+            }
+        }
+        rows
+    }
+    /// How many rows of garbage are waiting to come in.
+    pub fn pending_garbage(&self) -> u32 {
+        self.garbage.iter().map(|&(rows, _)| rows).sum()
+    }
+    /// Whether the current piece may still be swapped with the held piece.
+    pub fn can_hold(&self) -> bool {
+        self.can_hold
+    }
+    /// Takes the next piece, picking a new one to come after it.
+    fn take_next_piece(&mut self) -> Piece {
+        let piece = Piece::new(self.randomizer.next(&mut self.rng));
+        std::mem::replace(&mut self.next_piece, piece)
+    }
+    /// At least 1, whatever the rules or a save say.
+    fn frames_per_row(&self) -> u8 {
+        self.frames_per_row.unwrap_or_else(|| self.rules.frames_per_row(self.level())).max(1)
+    }
+    /// How many frames closer to falling the current piece gets each tick.
+    fn gravity(&self) -> u8 {
+        if self.held.is_held(Action::SoftDrop) { self.handling.sdf } else { 1 }
+    }
+    pub fn level(&self) -> u32 {
+        self.start_level + self.lines / 10
+    }
+    /// How long the game has been going.
+    pub fn ms(&self) -> u32 {
+        self.tick.saturating_mul(self.ms_per_tick)
+    }
+    /// How long a tick of this game is.
+    pub fn ms_per_tick(&self) -> u32 {
+        self.ms_per_tick
+    }
+    fn mv(&mut self, mv: Move, result: &mut TickResult) {
+        let Some(mp) = &self.cur_piece else {
+            return;
+        };
+        let moved = match mv {
+            Move::Left | Move::Right => {
+                let mut new_mp = mp.clone();
+                new_mp.pos.x += if mv == Move::Left { -1 } else { 1 };
+                Some(new_mp).filter(|p| p.fits(&self.grid))
+            }
+            Move::RotLeft => self.rotation.rotate(mp, Rotation::Left, &self.grid),
+            Move::RotRight => self.rotation.rotate(mp, Rotation::Right, &self.grid),
+            Move::HardDrop => {
+                let since_shift = self.tick.wrapping_sub(self.last_shift_tick).saturating_mul(self.ms_per_tick);
+                if since_shift >= self.handling.misdrop_guard {
+                    self.hard_drop(result);
+                }
+                return;
+            }
+            Move::Hold => return self.hold(),
+            Move::Undo => return self.undo(),
+            Move::Redo => return self.redo(),
+        };
+        let Some(new_mp) = moved else {
+            return;
+        };
+        self.cur_piece = Some(new_mp);
+        if let Move::Left | Move::Right = mv {
+            self.last_shift_tick = self.tick;
+        }
+        self.last_move_rotated = matches!(mv, Move::RotLeft | Move::RotRight);
+        self.turns += self.last_move_rotated as u32;
+    }
+    /// Moves the current piece one row down, returning `false` if it is blocked.
+    fn step_down(&mut self) -> bool {
+        let Some(cur_piece) = &mut self.cur_piece else {
+            return false;
+        };
+        let new_pos = Pos {x: cur_piece.pos.x, y: cur_piece.pos.y + 1};
+        for pos in cur_piece.piece.points(new_pos) {
+            if !self.grid.is_free_or_above(pos) {
+                return false;
+            }
+        }
+        cur_piece.pos = new_pos;
+        self.last_move_rotated = false;
+        true
+    }
+    /// Swaps the current piece with the held one, or the next one if nothing is held yet.
+    fn hold(&mut self) {
+        if !self.can_hold {
+            return;
+        }
+        let Some(cur_piece) = self.cur_piece.take() else {
+            return;
+        };
+        let held = Piece::new(cur_piece.piece.kind);
+        let piece = match self.hold_piece.replace(held) {
+            Some(piece) => piece,
+            None => self.take_next_piece(),
+        };
+        self.cur_piece = Some(MovingPiece::new(piece, self.spawn));
+        self.can_hold = false;
+        self.turns = 0;
+    }
+    fn hard_drop(&mut self, result: &mut TickResult) {
+        while self.step_down() {}
+        self.lock_piece(result);
+    }
+    /// Whether the current piece is a T rotated into a spot with at least three of its corners blocked.
+    fn is_t_spin(&self) -> bool {
+        let Some(cur_piece) = &self.cur_piece else {
+            return false;
+        };
+        if !self.last_move_rotated || cur_piece.piece.kind != Tetromino::T {
+            return false;
+        }
+        // The centre of the T is the block touching all the others
+        let points: Vec<_> = cur_piece.piece.points(cur_piece.pos).collect();
+        let Some(centre) = points
+            .iter()
+            .find(|p| points.iter().filter(|q| (q.x - p.x).abs() + (q.y - p.y).abs() == 1).count() == 3)
+        else {
+            return false;
+        };
+        let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+        corners
+            .into_iter()
+            .filter(|&(dx, dy)| !self.grid.is_free_or_above(Pos::new(centre.x + dx, centre.y + dy)))
+            .count()
+            >= 3
+    }
+    /// Counts what was wasted on `placed`, which was locked onto a grid with `holes_before` holes.
+    fn judge_placement(&mut self, placed: &MovingPiece, holes_before: usize) {
+        self.efficiency.misdrops += (self.grid.bits().count_holes() > holes_before) as u32;
+        let turned = placed.piece.rotation as u32;
+        let fewest = if placed.piece.kind == Tetromino::O { 0 } else { turned.min(4 - turned) };
+        self.efficiency.wasted_rotations += std::mem::take(&mut self.turns).saturating_sub(fewest);
+    }
+    /// Puts the current piece into the grid and clears the lines it completes.
+    fn lock_piece(&mut self, result: &mut TickResult) {
+        let Some(cur_piece) = self.cur_piece.clone() else {
+            return;
+        };
+        let limit = self.rules.undo_limit();
+        if limit > 0 {
+            if self.undo.len() >= limit {
+                self.undo.pop_front();
+            }
+            self.undo.push_back(self.snapshot());
+            self.redo.clear();
+        }
+        let t_spin = self.is_t_spin();
+        let holes = self.grid.bits().count_holes();
+        let mut out_of_bounds = false;
+        for pos in cur_piece.piece.points(cur_piece.pos) {
+            if !self.grid.set(pos, Cell::Filled(cur_piece.piece.kind)) {
+                out_of_bounds = true;
+                break;
+            }
+        }
+        if out_of_bounds {
+            self.gameover = true;
+        } else {
+            self.cur_piece = None;
+            self.can_hold = true;
+            let cleared_rows = self.grid.clear_full_rows();
+            let num_cleared = cleared_rows.len() as u32;
+            self.score += self.scoring.score(Clear { lines: num_cleared, t_spin, level: self.level() });
+            self.lines += num_cleared;
+            self.pieces += 1;
+            self.judge_placement(&cur_piece, holes);
+            result.events.push(GameEvent::PieceLocked { lines: num_cleared, t_spin });
+            result.locked_piece = Some(cur_piece);
+            result.cleared_rows = cleared_rows;
+            if num_cleared == 0 {
+                while let Some((rows, hole)) = self.garbage.pop_front() {
+                    self.add_garbage(rows, hole);
+                }
+            }
+        }
+    }
+
+    fn press(&mut self, action: Action) {
+        self.held.press(action);
+        match action {
+            Action::Left => self.press_shift(Move::Left),
+            Action::Right => self.press_shift(Move::Right),
+            Action::RotLeft => self.queue_move(Move::RotLeft),
+            Action::RotRight => self.queue_move(Move::RotRight),
+            Action::HardDrop => self.queue_move(Move::HardDrop),
+            Action::Hold => self.queue_move(Move::Hold),
+            Action::Undo => self.queue_move(Move::Undo),
+            Action::Redo => self.queue_move(Move::Redo),
+            Action::SoftDrop | Action::Restart | Action::Pause | Action::MenuConfirm | Action::MenuBack => (),
+        }
+    }
+    fn release(&mut self, action: Action) {
+        self.held.release(action);
+        if let Action::Left | Action::Right = action {
+            self.release_shift();
+        }
+    }
+    pub fn to_save(&self) -> SavedGame {
+        SavedGame {
+            mode: self.mode,
+            seed: Some(self.seed),
+            gameover: self.gameover,
+            grid: self.grid.clone(),
+            score: self.score,
+            lines: self.lines,
+            start_level: self.start_level,
+            pieces: self.pieces,
+            rng: self.rng.state(),
+            next_piece: self.next_piece,
+            cur_piece: self.cur_piece.clone(),
+            hold_piece: self.hold_piece,
+            can_hold: self.can_hold,
+            move_frames: self.move_frames,
+            tick: self.tick,
+            randomizer: self.randomizer.state(),
+            handling: Some(self.handling),
+            spawn: self.spawn,
+            frames_per_row: self.frames_per_row,
+            ms_per_tick: self.ms_per_tick,
+            held: self.held.clone(),
+            auto_shift: self.auto_shift,
+            last_shift_tick: Some(self.last_shift_tick),
+            last_move_rotated: self.last_move_rotated,
+            efficiency: self.efficiency,
+            turns: self.turns,
+            move_queue: self.move_queue.iter().copied().collect(),
+            garbage: self.garbage.iter().copied().collect(),
+        }
+    }
+    /// A game that carries on from `save`, played by its mode's rules.
+    pub fn from_save(save: SavedGame) -> Self {
+        let handling = save.handling.unwrap_or_default();
+        let mut game = Game::new(save.mode, save.seed.unwrap_or(0), handling);
+        game.restore(save);
+        game
+    }
+    /// Continues a saved game.
+    pub fn restore(&mut self, save: SavedGame) {
+        self.mode = save.mode;
+        self.rules = save.mode.rules();
+        self.randomizer = self.rules.randomizer();
+        self.scoring = self.rules.scoring();
+        self.rotation = self.rules.rotation_system();
+        self.grid = save.grid;
+        self.score = save.score;
+        self.lines = save.lines;
+        self.start_level = save.start_level;
+        self.pieces = save.pieces;
+        self.rng = Rand32::from_state(save.rng);
+        self.next_piece = save.next_piece;
+        self.cur_piece = save.cur_piece;
+        self.hold_piece = save.hold_piece;
+        self.can_hold = save.can_hold;
+        self.move_frames = save.move_frames;
+        self.tick = save.tick;
+        self.randomizer.set_state(&save.randomizer);
+        if let Some(seed) = save.seed {
+            self.seed = seed;
+        }
+        if let Some(handling) = save.handling {
+            self.handling = handling;
+        }
+        self.gameover = save.gameover;
+        self.spawn = save.spawn;
+        self.frames_per_row = save.frames_per_row;
+        self.ms_per_tick = save.ms_per_tick.max(1);
+        self.held = save.held;
+        self.auto_shift = save.auto_shift;
+        self.last_shift_tick = save.last_shift_tick.unwrap_or(save.tick);
+        self.last_move_rotated = save.last_move_rotated;
+        self.efficiency = save.efficiency;
+        self.turns = save.turns;
+        self.move_queue = save.move_queue.into();
+        self.garbage = save.garbage.into();
+        self.undo.clear();
+        self.redo.clear();
+    }
+    /// How many placements can be undone and redone.
+    pub fn history(&self) -> (usize, usize) {
+        (self.undo.len(), self.redo.len())
+    }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            grid: self.grid.clone(),
+            score: self.score,
+            lines: self.lines,
+            pieces: self.pieces,
+            rng: self.rng,
+            randomizer: self.randomizer.clone_box(),
+            piece: self.cur_piece.as_ref().map(|mp| Piece::new(mp.piece.kind)),
+            next_piece: self.next_piece,
+            hold_piece: self.hold_piece,
+            can_hold: self.can_hold,
+        }
+    }
+    /// Goes back to `snapshot`, with its piece falling from the top again.
+    fn go_to(&mut self, snapshot: Snapshot) {
+        self.grid = snapshot.grid;
+        self.score = snapshot.score;
+        self.lines = snapshot.lines;
+        self.pieces = snapshot.pieces;
+        self.rng = snapshot.rng;
+        self.randomizer = snapshot.randomizer;
+        self.cur_piece = snapshot.piece.map(|piece| MovingPiece::new(piece, self.spawn));
+        self.next_piece = snapshot.next_piece;
+        self.hold_piece = snapshot.hold_piece;
+        self.can_hold = snapshot.can_hold;
+        self.move_frames = 0;
+        self.last_move_rotated = false;
+        self.turns = 0;
+        self.last_shift_tick = self.tick;
+    }
+    /// Takes back the last placement.
+    fn undo(&mut self) {
+        if let Some(before) = self.undo.pop_back() {
+            self.redo.push(self.snapshot());
+            self.go_to(before);
+        }
+    }
+    /// Puts back the last placement that was undone.
+    fn redo(&mut self) {
+        if let Some(after) = self.redo.pop() {
+            self.undo.push_back(self.snapshot());
+            self.go_to(after);
+        }
+    }
+    /// A hash of the grid, score and lines, for checking that a replay played out the same way.
+    pub fn checksum(&self) -> u64 {
+        let bytes = self.grid.rows().flatten().map(|&c| u8::from(c));
+        fnv1a(bytes.chain(self.score.to_le_bytes()).chain(self.lines.to_le_bytes()))
+    }
+    /// A cheap checksum of everything that decides how the game goes on from here, for spotting the tick
+    /// two runs of the same game stop agreeing at.
+    pub fn state_checksum(&self) -> u64 {
+        let piece_bytes = |piece: Option<&Piece>, pos: Pos| {
+            piece.map_or([255; 4], |p| [p.kind.into(), p.rotation, pos.x as u8, pos.y as u8])
+        };
+        let cur = self.cur_piece.as_ref();
+        let (rng_state, rng_inc) = self.rng.state();
+        // Each list goes in with its length first, so two that differ only in where one ends and the next starts don't agree
+        let garbage = self.garbage.iter().flat_map(|&(rows, hole)| rows.to_le_bytes().into_iter().chain([hole as u8]));
+        let moves = self.move_queue.iter().flat_map(|&(tick, mv)| tick.to_le_bytes().into_iter().chain([mv as u8]));
+        let held = self.held.iter().map(|action| action as u8);
+        let auto_shift = match self.auto_shift {
+            Some(shift) => [shift.mv as u8].into_iter().chain(shift.held_ms.to_le_bytes()).chain(shift.shifts.to_le_bytes()).collect(),
+            None => vec![255],
+        };
+        let bag = self.randomizer.state();
+        let bytes = self.grid.bits().rows().iter().flat_map(|row| row.to_le_bytes())
+            .chain(piece_bytes(cur.map(|mp| &mp.piece), cur.map_or(Pos::new(0, 0), |mp| mp.pos)))
+            .chain(piece_bytes(Some(&self.next_piece), Pos::new(0, 0)))
+            .chain(piece_bytes(self.hold_piece.as_ref(), Pos::new(0, 0)))
+            .chain([self.move_frames, self.gameover as u8, self.can_hold as u8])
+            .chain(self.score.to_le_bytes())
+            .chain(self.lines.to_le_bytes())
+            .chain(self.start_level.to_le_bytes())
+            .chain(self.pieces.to_le_bytes())
+            .chain(self.tick.to_le_bytes())
+            .chain(rng_state.to_le_bytes())
+            .chain(rng_inc.to_le_bytes())
+            .chain((self.garbage.len() as u32).to_le_bytes())
+            .chain(garbage)
+            .chain((self.move_queue.len() as u32).to_le_bytes())
+            .chain(moves)
+            .chain((self.held.iter().count() as u32).to_le_bytes())
+            .chain(held)
+            .chain(auto_shift)
+            .chain([self.last_move_rotated as u8])
+            .chain(self.last_shift_tick.to_le_bytes())
+            .chain((bag.len() as u32).to_le_bytes())
+            .chain(bag.into_iter().map(u8::from));
+        fnv1a(bytes)
+    }
+    fn apply_inputs(&mut self, actions: &[(Action, bool)]) {
+        for &(action, pressed) in actions {
+            if let Some(listener) = &mut self.input_listener {
+                listener.input(InputEvent { tick: self.tick, action, pressed });
+            }
+            if pressed {
+                self.press(action);
+            } else {
+                self.release(action);
+            }
+        }
+    }
+
+    fn press_shift(&mut self, mv: Move) {
+        self.queue_move(mv);
+        self.auto_shift = Some(AutoShift { mv, held_ms: 0, shifts: 0 });
+    }
+    /// Continues auto shifting in whichever direction is still held, if any.
+    fn release_shift(&mut self) {
+        let mv = match self.held.latest(&[Action::Left, Action::Right]) {
+            Some(Action::Left) => Move::Left,
+            Some(Action::Right) => Move::Right,
+            _ => {
+                self.auto_shift = None;
+                return;
+            }
+        };
+        if self.auto_shift.is_none_or(|s| s.mv != mv) {
+            // The direction has been held all along, so it goes straight to auto repeating
+            let held_ms = self.handling.das;
+            self.auto_shift = Some(AutoShift { mv, held_ms, shifts: 0 });
+        }
+    }
+    /// Repeats the held shift once it has been held longer than DAS, every ARR after that.
+    fn apply_auto_shift(&mut self, result: &mut TickResult) {
+        let Some(shift) = &mut self.auto_shift else {
+            return;
+        };
+        let handling = self.handling;
+        shift.held_ms += self.ms_per_tick;
+        if shift.held_ms < handling.das {
+            return;
+        }
+        let mv = shift.mv;
+        let shifts = match (shift.held_ms - handling.das).checked_div(handling.arr) {
+            Some(n) => {
+                let due = n + 1;
+                let shifts = due.saturating_sub(shift.shifts);
+                shift.shifts = due;
+                shifts
+            }
+            // An ARR of 0 means going straight to the wall
+            None => self.grid.size().0 as u32,
+        };
+        for _ in 0..shifts {
+            self.mv(mv, result);
+        }
+    }
+
+    fn queue_move(&mut self, mv: Move) {
+        self.move_queue.push_back((self.tick, mv));
+    }
+    /// Applies the queued moves if there is a piece to apply them to,
+    /// otherwise keeps them buffered until they get too old.
+    fn apply_queued_moves(&mut self, result: &mut TickResult) {
+        while self.cur_piece.is_some() {
+            let Some((_, mv)) = self.move_queue.pop_front() else {
+                break;
+            };
+            self.mv(mv, result);
+        }
+        let (tick, buffer_ticks) = (self.tick, INPUT_BUFFER_MS / self.ms_per_tick);
+        self.move_queue.retain(|&(t, _)| tick.wrapping_sub(t) <= buffer_ticks);
+    }
+}
+
+/// A game is saved as everything in its `SavedGame`, except the placements that can be undone
+/// and who is listening to its inputs.
+impl Serialize for Game {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_save().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Game {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        SavedGame::deserialize(deserializer).map(Game::from_save)
+    }
+}
+
+/// A copy of the game to play on from separately, e.g. to guess ahead of an opponent's inputs and go back when
+/// they arrive. Nothing is written out, so it is cheap enough to take every tick. The copy has no input listener,
+/// and takes its scoring and rotation from the rules again, as restoring a save does.
+impl Clone for Game {
+    fn clone(&self) -> Self {
+        Game {
+            mode: self.mode,
+            grid: self.grid.clone(),
+            gameover: self.gameover,
+            move_frames: self.move_frames,
+            score: self.score,
+            lines: self.lines,
+            start_level: self.start_level,
+            rules: self.rules,
+            scoring: self.rules.scoring(),
+            rotation: self.rules.rotation_system(),
+            pieces: self.pieces,
+            last_move_rotated: self.last_move_rotated,
+            efficiency: self.efficiency,
+            turns: self.turns,
+            seed: self.seed,
+            rng: self.rng,
+            randomizer: self.randomizer.clone_box(),
+            next_piece: self.next_piece,
+            cur_piece: self.cur_piece.clone(),
+            hold_piece: self.hold_piece,
+            can_hold: self.can_hold,
+            handling: self.handling,
+            spawn: self.spawn,
+            frames_per_row: self.frames_per_row,
+            ms_per_tick: self.ms_per_tick,
+            auto_shift: self.auto_shift,
+            held: self.held.clone(),
+            tick: self.tick,
+            last_shift_tick: self.last_shift_tick,
+            input_listener: None,
+            move_queue: self.move_queue.clone(),
+            undo: self.undo.clone(),
+            redo: self.redo.clone(),
+            garbage: self.garbage.clone(),
+        }
+    }
+}
+
+impl Clone for Snapshot {
+    fn clone(&self) -> Self {
+        Snapshot {
+            grid: self.grid.clone(),
+            randomizer: self.randomizer.clone_box(),
+            ..*self
+        }
+    }
+}
+
+/// 64-bit FNV-1a
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(seed: u64) -> Game {
+        Game::new(Mode::Marathon, seed, Handling::default())
+    }
+
+    /// Inputs for tick `tick`, turning, shifting and dropping now and then.
+    fn inputs(tick: u32) -> Vec<(Action, bool)> {
+        match tick % 12 {
+            0 => vec![(Action::HardDrop, true)],
+            1 => vec![(Action::HardDrop, false), (Action::Left, true)],
+            6 => vec![(Action::Left, false), (Action::RotRight, true)],
+            7 => vec![(Action::RotRight, false)],
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn configs_out_of_range_are_played_within_it() {
+        for (ticks_per_second, frames_per_row) in [(0, Some(0)), (5000, Some(0)), (u32::MAX, Some(u8::MAX)), (24, None)] {
+            let config = GameConfig { ticks_per_second, frames_per_row, ..GameConfig::default() };
+            assert!(config.ms_per_tick() >= 1);
+            let mut game = Game::with_config(Mode::Marathon, Mode::Marathon.rules(), 5, Handling::default(), &config);
+            for tick in 1..200 {
+                game.tick(&inputs(tick));
+                game.fall_progress(0.5);
+            }
+            assert!(game.ms() > 0);
+        }
+    }
+
+    #[test]
+    fn the_same_game_agrees_every_tick() {
+        let (mut a, mut b) = (game(5), game(5));
+        for tick in 1..400 {
+            a.tick(&inputs(tick));
+            b.tick(&inputs(tick));
+            assert_eq!(a.state_checksum(), b.state_checksum(), "tick {tick}");
+        }
+        assert_eq!(a.clone().state_checksum(), a.state_checksum());
+    }
+
+    #[test]
+    fn games_that_went_differently_disagree() {
+        assert_ne!(game(5).state_checksum(), game(6).state_checksum());
+        let (mut a, mut b) = (game(5), game(5));
+        a.tick(&[]);
+        assert_ne!(a.state_checksum(), b.state_checksum());
+        b.tick(&[(Action::SoftDrop, true)]);
+        assert_ne!(a.state_checksum(), b.state_checksum());
+    }
+
+    #[test]
+    fn queued_garbage_and_held_keys_count() {
+        let (mut a, mut b) = (game(5), game(5));
+        a.tick(&[]);
+        b.tick(&[]);
+        a.receive_garbage(2, 3);
+        assert_ne!(a.state_checksum(), b.state_checksum());
+        b.receive_garbage(2, 4);
+        assert_ne!(a.state_checksum(), b.state_checksum());
+
+        let (mut a, mut b) = (game(5), game(5));
+        a.tick(&[(Action::Hold, true)]);
+        b.tick(&[(Action::Hold, true), (Action::Hold, false)]);
+        assert_eq!(a.hold_piece, b.hold_piece);
+        assert_ne!(a.state_checksum(), b.state_checksum());
+    }
+
+    #[test]
+    fn clears_cancel_the_garbage_waiting_first() {
+        let mut game = game(5);
+        game.receive_garbage(3, 1);
+        game.receive_garbage(0, 2);
+        game.receive_garbage(2, 4);
+        assert_eq!(game.pending_garbage(), 5);
+        assert_eq!(game.cancel_garbage(4), 0);
+        assert_eq!(game.pending_garbage(), 1);
+        assert_eq!(game.garbage, [(1, 4)]);
+        // What is left over once nothing is waiting is sent on
+        assert_eq!(game.cancel_garbage(3), 2);
+        assert_eq!(game.pending_garbage(), 0);
+        assert_eq!(game.cancel_garbage(2), 2);
+    }
+}
@@ -0,0 +1,129 @@
+use oorandom::Rand32;
+
+use crate::{
+    mode::Mode,
+    piece::Tetromino,
+    rotation::{NoKicks, RotationSystem},
+    rules::{Game, GameEvent},
+    scoring::{ScoreTable, ScoringSystem},
+};
+
+/// How many frames of gravity it has always taken a piece to fall a row.
+const FRAMES_PER_ROW: u8 = 18;
+pub const SPRINT_LINES: u32 = 40;
+const ULTRA_MS: u32 = 2 * 60 * 1000;
+/// How many placements can be taken back in practice.
+const PRACTICE_UNDOS: usize = 50;
+
+/// Picks the pieces.
+pub trait Randomizer {
+    /// The next piece, drawing from the game's `rng` so it is saved and replayed along with the game.
+    fn next(&mut self, rng: &mut Rand32) -> Tetromino;
+    /// A copy of the randomiser as it is now, to go back to when a placement is undone.
+    fn clone_box(&self) -> Box<dyn Randomizer>;
+    /// The pieces the randomiser remembers, such as what is left of a bag, for saving it.
+    fn state(&self) -> Vec<Tetromino> {
+        Vec::new()
+    }
+    /// Picks up where a randomiser with `state` left off.
+    fn set_state(&mut self, _state: &[Tetromino]) {}
+}
+
+/// Picks every piece independently of the ones before.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Memoryless;
+
+impl Randomizer for Memoryless {
+    fn next(&mut self, rng: &mut Rand32) -> Tetromino {
+        Tetromino::ALL[rng.rand_range(0..Tetromino::ALL.len() as u32) as usize]
+    }
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(*self)
+    }
+}
+
+/// Something rules can make happen besides what the game does by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RulesAction {
+    /// Pushes the stack up by a row of garbage with a gap in column `hole`.
+    AddGarbage { hole: usize },
+    AddScore(u32),
+    EndGame,
+}
+
+/// Everything that makes one mode play differently from another.
+/// The defaults are the rules every mode has always been played by.
+pub trait GameRules {
+    /// How many frames of gravity it takes the piece to fall a row at `level`.
+    fn frames_per_row(&self, _level: u32) -> u8 {
+        FRAMES_PER_ROW
+    }
+    fn randomizer(&self) -> Box<dyn Randomizer> {
+        Box::new(Memoryless)
+    }
+    fn rotation_system(&self) -> Box<dyn RotationSystem> {
+        // Kicks would change how existing replays play out
+        Box::new(NoKicks)
+    }
+    fn scoring(&self) -> Box<dyn ScoringSystem> {
+        // Existing high scores and replays depend on the original points
+        Box::new(ScoreTable::CLASSIC)
+    }
+    /// How many of the last placements can be undone. Only for modes that aren't ranked.
+    fn undo_limit(&self) -> usize {
+        0
+    }
+    /// Whether the game has been won, given the lines cleared and time played so far.
+    fn is_finished(&self, lines: u32, ms: u32) -> bool;
+    /// Called at the end of every tick with what happened in it, for rules that do more than the game does by itself.
+    fn after_tick(&self, _game: &Game, _events: &[GameEvent]) -> Vec<RulesAction> {
+        Vec::new()
+    }
+}
+
+/// Play until topping out.
+pub struct Marathon;
+
+impl GameRules for Marathon {
+    fn is_finished(&self, _lines: u32, _ms: u32) -> bool {
+        false
+    }
+}
+
+/// Clear 40 lines as fast as possible.
+pub struct Sprint;
+
+impl GameRules for Sprint {
+    fn is_finished(&self, lines: u32, _ms: u32) -> bool {
+        lines >= SPRINT_LINES
+    }
+}
+
+/// Score as much as possible in two minutes.
+pub struct Ultra;
+
+impl GameRules for Ultra {
+    fn is_finished(&self, _lines: u32, ms: u32) -> bool {
+        ms >= ULTRA_MS
+    }
+}
+
+/// Play without an end, taking back placements.
+pub struct Practice;
+
+impl GameRules for Practice {
+    fn undo_limit(&self) -> usize {
+        PRACTICE_UNDOS
+    }
+    fn is_finished(&self, _lines: u32, _ms: u32) -> bool {
+        false
+    }
+}
+
+/// The rules each mode is played by. A new variant only needs its rules added here.
+pub static PLUGINS: [(Mode, &(dyn GameRules + Sync)); 4] = [
+    (Mode::Marathon, &Marathon),
+    (Mode::Sprint, &Sprint),
+    (Mode::Ultra, &Ultra),
+    (Mode::Practice, &Practice),
+];
@@ -0,0 +1,114 @@
+use ggez::GameResult;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{GameConfig, Handling},
+    grid::{Grid, Pos},
+    input::HeldActions,
+    mode::Mode,
+    piece::{MovingPiece, Piece, Tetromino},
+    rules::{AutoShift, Efficiency, Move, MS_PER_TICK},
+    storage::Storage,
+    versioned::{self, Versioned},
+};
+
+/// Where a game in progress is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// Saved when quitting.
+    Quit,
+    /// Saved every few seconds while playing and deleted on a clean exit,
+    /// so finding it at startup means the game didn't get to quit properly.
+    Autosave,
+}
+
+impl Slot {
+    fn file_name(self) -> &'static str {
+        match self {
+            Slot::Quit => "save.json",
+            Slot::Autosave => "autosave.json",
+        }
+    }
+}
+
+/// A game in progress, saved so it can be continued next time, or sent to someone else to carry on with.
+/// Games saved before a field was added get what a game used to be.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub mode: Mode,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub gameover: bool,
+    pub grid: Grid,
+    pub score: u32,
+    pub lines: u32,
+    #[serde(default)]
+    pub start_level: u32,
+    #[serde(default)]
+    pub pieces: u32,
+    /// The state of the piece randomiser, from `Rand32::state`.
+    pub rng: (u64, u64),
+    pub next_piece: Piece,
+    pub cur_piece: Option<MovingPiece>,
+    pub hold_piece: Option<Piece>,
+    pub can_hold: bool,
+    pub move_frames: u8,
+    pub tick: u32,
+    /// What the randomiser remembers, from `Randomizer::state`.
+    #[serde(default)]
+    pub randomizer: Vec<Tetromino>,
+    /// The handling the game was played with, or `None` to keep the player's.
+    #[serde(default)]
+    pub handling: Option<Handling>,
+    #[serde(default = "default_spawn")]
+    pub spawn: Pos,
+    #[serde(default)]
+    pub frames_per_row: Option<u8>,
+    #[serde(default = "default_ms_per_tick")]
+    pub ms_per_tick: u32,
+    /// The actions being held and the move repeating because of it, so a game can be picked up mid-move.
+    #[serde(default)]
+    pub held: HeldActions,
+    #[serde(default)]
+    pub(crate) auto_shift: Option<AutoShift>,
+    #[serde(default)]
+    pub last_shift_tick: Option<u32>,
+    #[serde(default)]
+    pub last_move_rotated: bool,
+    #[serde(default)]
+    pub efficiency: Efficiency,
+    #[serde(default)]
+    pub turns: u32,
+    #[serde(default)]
+    pub(crate) move_queue: Vec<(u32, Move)>,
+    /// Garbage from an opponent that hasn't come in yet, as `(rows, hole)`.
+    #[serde(default)]
+    pub garbage: Vec<(u32, usize)>,
+}
+
+/// Version 1 is the same as the unversioned saves before it.
+impl Versioned for SavedGame {
+    const VERSION: u32 = 1;
+}
+
+fn default_spawn() -> Pos {
+    GameConfig::default().spawn
+}
+
+fn default_ms_per_tick() -> u32 {
+    MS_PER_TICK
+}
+
+impl SavedGame {
+    pub fn load(storage: &dyn Storage, dir: &str, slot: Slot) -> GameResult<Option<Self>> {
+        versioned::load(storage, &format!("{dir}/{}", slot.file_name()))
+    }
+    pub fn save(&self, storage: &dyn Storage, dir: &str, slot: Slot) -> GameResult {
+        versioned::save(storage, &format!("{dir}/{}", slot.file_name()), self, false)
+    }
+    pub fn delete(storage: &dyn Storage, dir: &str, slot: Slot) -> GameResult {
+        storage.remove(&format!("{dir}/{}", slot.file_name()))?;
+        Ok(())
+    }
+}
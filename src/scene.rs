@@ -0,0 +1,618 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use ggez::{
+    event::{Button, GamepadId},
+    graphics::{Color, Rect},
+    input::keyboard::{KeyCode, KeyMods},
+    Context,
+};
+use log::{debug, info, warn};
+
+use crate::{
+    ai::Hinter,
+    analysis::{self, Mistake, Review},
+    app::GameState,
+    clipboard,
+    demo::{self, DemoScene},
+    effects::RowFlash,
+    finesse::FinesseScene,
+    opening::OpeningMenuScene,
+    input::Action,
+    mode::Mode,
+    overlay,
+    render::{self, Renderer, Shifted, PORTRAIT_VIEW, SCREEN_SIZE},
+    replay::Replay,
+    rules::GameEvent,
+    save::{SavedGame, Slot},
+    scores::Board,
+    settings::{MenuInput, MenuResult, SettingsMenu},
+    stats,
+    theme::Theme,
+    leaderboard::LeaderboardScene,
+    online::OnlineMenuScene,
+    handicap::HandicapScene,
+    match_replay::{MatchReplay, MatchReplayScene, MATCH_REPLAY_EXTENSION},
+};
+
+/// How long the restart key has to be held to start a new game (ms).
+const RESTART_HOLD_MS: u32 = 500;
+/// How often the game in progress is saved in case the game crashes (ms).
+const AUTOSAVE_MS: u32 = 5000;
+
+/// What to do with the scene stack after a scene has handled something.
+pub enum Transition {
+    None,
+    /// Put a scene on top of this one.
+    Push(Box<dyn Scene>),
+    /// Go back this many scenes.
+    Pop(usize),
+    /// Swap this scene for another.
+    Replace(Box<dyn Scene>),
+    Quit,
+}
+
+/// A screen of the game, like the title screen or the game itself.
+/// Only the scene on top of the stack gets updated and gets input.
+pub trait Scene {
+    /// Called every tick while the scene is on top.
+    fn update(&mut self, _state: &mut GameState, _ctx: &mut Context) -> Transition {
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer);
+    /// Handles navigating the scene's menu, from whichever input device.
+    fn menu_input(&mut self, _state: &mut GameState, _ctx: &mut Context, _input: MenuInput) -> Transition {
+        Transition::None
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, _repeated: bool) -> Transition {
+        match MenuInput::from_key(keycode, mods, &state.bindings) {
+            Some(input) => self.menu_input(state, ctx, input),
+            None => Transition::None,
+        }
+    }
+    /// Releasing a key, for scenes that keep track of the keys held themselves.
+    fn key_up(&mut self, _state: &mut GameState, _keycode: KeyCode) {}
+    fn gamepad_button_down(&mut self, state: &mut GameState, ctx: &mut Context, btn: Button, _id: GamepadId) -> Transition {
+        match state.config.profile().buttons.menu_input(btn) {
+            Some(input) => self.menu_input(state, ctx, input),
+            None => Transition::None,
+        }
+    }
+    /// Releasing a gamepad button, for scenes with a gamepad for each player.
+    fn gamepad_button_up(&mut self, _state: &mut GameState, _btn: Button, _id: GamepadId) {}
+    /// A character typed, for scenes with something to type into.
+    fn text_input(&mut self, _state: &mut GameState, _character: char) {}
+    /// Whether the scene is shown over the one below it instead of covering it.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+    /// Whether this is where the game is played.
+    fn plays_game(&self) -> bool {
+        false
+    }
+}
+
+/// Darkens the screen down to `height` for a scene shown over the game.
+fn dim(r: &mut dyn Renderer, height: f32) {
+    r.draw_panel(Rect::new(0., 0., SCREEN_SIZE.0, height), Color::new(0., 0., 0., 0.8));
+}
+
+fn draw_text(r: &mut dyn Renderer, text: &str, scale: f32, dest: [f32; 2]) {
+    r.draw_text(text, scale, dest, Color::WHITE);
+}
+
+/// What the game starts on. Left alone long enough, it shows a demo.
+#[derive(Debug, Default)]
+pub struct TitleScene {
+    idle_ms: u32,
+}
+
+impl Scene for TitleScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        self.idle_ms += state.game.ms_per_tick();
+        if self.idle_ms >= demo::IDLE_MS {
+            self.idle_ms = 0;
+            return Transition::Push(Box::new(DemoScene::new(state)));
+        }
+        Transition::None
+    }
+    fn draw(&self, _state: &GameState, r: &mut dyn Renderer) {
+        draw_text(r, "TETRIS", 96., [64., 160.]);
+        draw_text(r, "Confirm: play\nBack: quit", 32., [64., 400.]);
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        self.idle_ms = 0;
+        match input {
+            MenuInput::Confirm if state.config.profiles.len() > 1 => Transition::Push(Box::new(ProfileSelectScene)),
+            MenuInput::Confirm => {
+                state.load_profile();
+                Transition::Push(Box::new(ModeSelectScene::new(state.config.mode)))
+            }
+            MenuInput::Back => Transition::Quit,
+            _ => Transition::None,
+        }
+    }
+}
+
+/// Asks who is playing, when there is more than one profile.
+pub struct ProfileSelectScene;
+
+impl Scene for ProfileSelectScene {
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        draw_text(
+            r,
+            &format!("Who is playing?\n\n< {} >\n\nLeft/Right: change profile\nConfirm: play", state.config.profile),
+            32.,
+            [64., 160.],
+        );
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        match input {
+            MenuInput::Up => state.config.cycle_profile(false),
+            MenuInput::Down => state.config.cycle_profile(true),
+            MenuInput::Adjust(step) => state.config.cycle_profile(step > 0),
+            MenuInput::Confirm => {
+                if let Err(e) = state.config.save(&*state.storage) {
+                    warn!("Could not save config: {e}");
+                }
+                state.load_profile();
+                return Transition::Replace(Box::new(ModeSelectScene::new(state.config.mode)));
+            }
+            MenuInput::Back => return Transition::Pop(1),
+            MenuInput::NewProfile => (),
+        }
+        Transition::None
+    }
+}
+
+/// Picks the mode to play, or a versus game or race, local or online, after the modes.
+/// The global leaderboards are listed last when there is a server for them in the config.
+pub struct ModeSelectScene {
+    selected: usize,
+}
+
+/// What is listed after the modes.
+const EXTRAS: [&str; 8] = [
+    "Versus (2 players)",
+    "Race (2 players)",
+    "Versus the computer",
+    "Online versus",
+    "Finesse trainer",
+    "Opening trainer",
+    "Fumen from the clipboard",
+    "Global leaderboards",
+];
+
+/// How many of `EXTRAS` there are to pick from.
+fn extras(state: &GameState) -> usize {
+    if state.config.leaderboard.is_some() {
+        EXTRAS.len()
+    } else {
+        EXTRAS.len() - 1
+    }
+}
+
+impl ModeSelectScene {
+    pub fn new(mode: Mode) -> Self {
+        ModeSelectScene {
+            selected: Mode::ALL.iter().position(|&m| m == mode).unwrap_or(0),
+        }
+    }
+}
+
+impl Scene for ModeSelectScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        if state.resume.is_some() {
+            return Transition::Push(Box::new(ResumeScene { over_game: false }));
+        }
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        draw_text(r, "Choose a mode", 48., [64., 64.]);
+        let names = Mode::ALL.map(|mode| mode.to_string());
+        for (i, name) in names.iter().map(String::as_str).chain(EXTRAS.into_iter().take(extras(state))).enumerate() {
+            let colour = if i == self.selected { Color::YELLOW } else { Color::WHITE };
+            r.draw_text(name, 32., [64., 160. + 48. * i as f32], colour);
+        }
+        draw_text(r, "Up/Down: select  Confirm: play  Back: title", 16., [64., SCREEN_SIZE.1 - 48.]);
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        let n = Mode::ALL.len() + extras(state);
+        // The leaderboards may have gone from the config since they were selected
+        self.selected = self.selected.min(n - 1);
+        match input {
+            MenuInput::Up | MenuInput::Adjust(..0) => self.selected = (self.selected + n - 1) % n,
+            MenuInput::Down | MenuInput::Adjust(_) => self.selected = (self.selected + 1) % n,
+            MenuInput::Confirm if self.selected == Mode::ALL.len() => return Transition::Push(Box::new(HandicapScene::new(false, false))),
+            MenuInput::Confirm if self.selected == Mode::ALL.len() + 1 => return Transition::Push(Box::new(HandicapScene::new(true, false))),
+            MenuInput::Confirm if self.selected == Mode::ALL.len() + 2 => return Transition::Push(Box::new(HandicapScene::new(false, true))),
+            MenuInput::Confirm if self.selected == Mode::ALL.len() + 3 => return Transition::Push(Box::new(OnlineMenuScene::new())),
+            MenuInput::Confirm if self.selected == Mode::ALL.len() + 4 => return Transition::Push(Box::new(FinesseScene::new(state))),
+            MenuInput::Confirm if self.selected == Mode::ALL.len() + 5 => return Transition::Push(Box::<OpeningMenuScene>::default()),
+            MenuInput::Confirm if self.selected == Mode::ALL.len() + 6 => {
+                match clipboard::paste().and_then(|text| state.practise(&text)) {
+                    Ok(()) => return Transition::Push(Box::new(GameScene)),
+                    Err(e) => {
+                        warn!("Could not practise the fumen on the clipboard: {e}");
+                        state.show_toast(format!("Could not practise the fumen on the clipboard: {e}"));
+                    }
+                }
+            }
+            MenuInput::Confirm if self.selected == Mode::ALL.len() + 7 => return Transition::Push(Box::new(LeaderboardScene::new(state))),
+            MenuInput::Confirm => {
+                // Picking a mode leaves the custom one and the fumen
+                state.script = None;
+                state.setup = None;
+                state.reset(Mode::ALL[self.selected]);
+                if let Err(e) = state.config.save(&*state.storage) {
+                    warn!("Could not save config: {e}");
+                }
+                return Transition::Push(Box::new(GameScene));
+            }
+            MenuInput::Back => return Transition::Pop(1),
+            MenuInput::NewProfile => (),
+        }
+        Transition::None
+    }
+}
+
+/// Asks whether to continue the game saved last time.
+pub struct ResumeScene {
+    /// Whether a new game is already being played underneath, rather than the game starting after this.
+    over_game: bool,
+}
+
+impl Scene for ResumeScene {
+    fn draw(&self, _state: &GameState, r: &mut dyn Renderer) {
+        dim(r, SCREEN_SIZE.1);
+        draw_text(r, "Continue the saved game?\n\nConfirm: continue\nBack: new game", 32., [64., 160.]);
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        match input {
+            MenuInput::Confirm => {
+                state.answer_resume(true);
+                if self.over_game {
+                    Transition::Pop(1)
+                } else {
+                    Transition::Replace(Box::new(GameScene))
+                }
+            }
+            MenuInput::Back => {
+                state.answer_resume(false);
+                Transition::Pop(1)
+            }
+            _ => Transition::None,
+        }
+    }
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+/// The game itself.
+pub struct GameScene;
+
+impl Scene for GameScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        if let Some(held_ms) = &mut state.restart_held_ms {
+            *held_ms += state.game.ms_per_tick();
+            if *held_ms >= RESTART_HOLD_MS {
+                state.reset(state.game.mode);
+            }
+        }
+        if let Some(action) = state.stick.tick(&state.config.profile().stick, state.game.ms_per_tick()) {
+            if state.accepts_input() {
+                state.input_queue.extend([(action, true), (action, false)]);
+            }
+        }
+        if let Some(playback) = &mut state.playback {
+            state.input_queue.extend(playback.inputs(state.game.tick.wrapping_add(1)));
+        }
+        let inputs = std::mem::take(&mut state.input_queue);
+        // A bug in the rules shouldn't take the whole program down, the last autosave is still there to resume
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| state.game.tick(&inputs))) {
+            Ok(result) => result,
+            Err(panic) => {
+                state.report_bug(panic);
+                return Transition::Pop(1);
+            }
+        };
+        if let Some(playback) = &mut state.playback {
+            playback.check(state.game.tick, state.game.state_checksum());
+        }
+        state.placements.record(&state.game, &result);
+        for event in result.events {
+            match event {
+                GameEvent::GameOver { .. } => info!("{event:?}"),
+                _ => debug!("{event:?}"),
+            }
+            if let GameEvent::PieceLocked { lines, t_spin } = event {
+                state.effects.spawn_clear(lines, t_spin);
+                // Between pieces, so nothing changes under the falling one
+                if let Some(votes) = &mut state.twitch {
+                    if state.playback.is_none() && votes.apply_won(&mut state.game, &mut state.theme) {
+                        state.chat_changed_game = true;
+                    }
+                }
+            }
+            if state.keeps_records() {
+                state.data.stats.record(event);
+            }
+        }
+        if !result.cleared_rows.is_empty() {
+            state.effects.spawn(RowFlash::new(result.cleared_rows, state.game.grid.size()));
+        }
+        let mut transition = Transition::None;
+        if result.game_over {
+            state.record_score();
+            if let Err(e) = SavedGame::delete(&*state.storage, &state.data.dir, Slot::Autosave) {
+                warn!("Could not delete autosave: {e}");
+            }
+            transition = Transition::Push(Box::new(ResultsScene::new(state)));
+        }
+        if state.shows_hints() {
+            state.hinter.get_or_insert_with(Hinter::default).update(&state.game);
+        }
+        if state.accepts_input() && state.keeps_records() && state.game.tick.is_multiple_of(AUTOSAVE_MS / state.game.ms_per_tick()) {
+            if let Err(e) = state.game.to_save().save(&*state.storage, &state.data.dir, Slot::Autosave) {
+                warn!("Could not autosave: {e}");
+            }
+        }
+        transition
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let fall = if state.config.smooth_fall && !state.game.gameover {
+            state.game.fall_progress(state.tick_progress)
+        } else {
+            0.
+        };
+        let shaken = &mut Shifted { inner: r, by: state.effects.shake() };
+        if state.config.portrait {
+            render::draw_game_portrait(shaken, &state.game, fall, &state.theme);
+        } else {
+            render::draw_game(shaken, &state.game, fall, &state.theme);
+        }
+        if let Some(hint) = state.hinter.as_ref().and_then(Hinter::hint).filter(|_| state.shows_hints()) {
+            render::draw_outline(shaken, &state.game, hint, &state.theme);
+        }
+        if state.game.rules.undo_limit() > 0 {
+            let (undos, redos) = state.game.history();
+            // In the corner of what is shown of the layout
+            let corner = if state.config.portrait { [PORTRAIT_VIEW.x + 16., PORTRAIT_VIEW.y + 16.] } else { [16., 16.] };
+            draw_text(r, &format!("Undo: {undos}  Redo: {redos}"), 20., corner);
+        }
+        if state.config.touch_buttons {
+            state.touch.draw(r, state.config.portrait);
+        }
+        if state.config.key_overlay {
+            overlay::draw_key_overlay(r, &state.game.held);
+        }
+        if let Some(votes) = &state.twitch {
+            votes.draw(r, (16., SCREEN_SIZE.1 - 128.));
+        }
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, _mods: KeyMods, repeated: bool) -> Transition {
+        // Holding keys is handled by auto shift and soft drop instead of key repeat
+        if repeated || state.held_keys.contains_key(&keycode) {
+            return Transition::None;
+        }
+
+        match state.bindings.action(keycode) {
+            Some(Action::Pause) => return Transition::Push(Box::new(PauseScene::new(state, ctx))),
+            Some(Action::Restart) => state.restart_held_ms = Some(0),
+            Some(Action::MenuConfirm | Action::MenuBack) | None => (),
+            Some(action) if state.accepts_input() => {
+                state.held_keys.insert(keycode, action);
+                state.input_queue.push((action, true));
+            }
+            _ => (),
+        }
+        Transition::None
+    }
+    fn gamepad_button_down(&mut self, state: &mut GameState, ctx: &mut Context, btn: Button, _id: GamepadId) -> Transition {
+        match state.config.profile().buttons.action(btn) {
+            Some(Action::Pause) => return Transition::Push(Box::new(PauseScene::new(state, ctx))),
+            Some(action) if state.accepts_input() => state.input_queue.push((action, true)),
+            _ => (),
+        }
+        Transition::None
+    }
+    fn plays_game(&self) -> bool {
+        true
+    }
+}
+
+/// The settings menu, opened with the pause key. The game is paused while it is open.
+pub struct PauseScene {
+    menu: SettingsMenu,
+}
+
+impl PauseScene {
+    pub fn new(state: &GameState, ctx: &Context) -> Self {
+        PauseScene {
+            menu: SettingsMenu::new(&*state.storage, &state.data.dir, Theme::list(ctx), state.seed),
+        }
+    }
+}
+
+impl Scene for PauseScene {
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        self.menu.draw(r, &state.config, &state.data.stats);
+    }
+    fn menu_input(&mut self, state: &mut GameState, ctx: &mut Context, input: MenuInput) -> Transition {
+        let menu = &mut self.menu;
+        match menu.input(input, &mut state.config) {
+            MenuResult::Open => (),
+            MenuResult::Close => {
+                state.apply_settings(ctx, menu.seed);
+                // Switching profiles may have turned up a saved game
+                return if state.resume.is_some() {
+                    Transition::Replace(Box::new(ResumeScene { over_game: true }))
+                } else {
+                    Transition::Pop(1)
+                };
+            }
+            MenuResult::InstallThemes => {
+                let results = Theme::import_all(ctx);
+                menu.themes = Theme::list(ctx);
+                menu.message = Some(if results.is_empty() {
+                    "No themes to install".to_owned()
+                } else {
+                    results.join("\n")
+                });
+            }
+            MenuResult::ExportTheme => {
+                menu.message = Some(match state.theme.export(ctx, state.config.theme.as_deref()) {
+                    Ok(path) => format!("Exported to {}", path.display()),
+                    Err(e) => format!("Could not export theme: {e}"),
+                });
+            }
+            MenuResult::ToggleSeed => {
+                menu.seed = match menu.seed {
+                    Some(_) => None,
+                    None => Some(state.game.seed),
+                };
+            }
+            MenuResult::ExportStats => {
+                let base = format!("{}/stats-export", state.data.dir);
+                let result = ["json", "csv"].into_iter().try_for_each(|ext| {
+                    let key = format!("{base}.{ext}");
+                    let s = state.data.stats.export(&key)?;
+                    state.storage.write(&key, s.as_bytes()).map_err(ggez::GameError::from)
+                });
+                menu.message = Some(match result {
+                    Ok(()) => format!("Exported to {}.json/.csv", state.storage.location(&base)),
+                    Err(e) => format!("Could not export statistics: {e}"),
+                });
+            }
+            MenuResult::WatchReplay(key) if key.ends_with(&format!(".{MATCH_REPLAY_EXTENSION}")) => {
+                state.apply_settings(ctx, menu.seed);
+                match MatchReplay::load(&*state.storage, &key) {
+                    Ok(replay) if !replay.rounds.is_empty() => return Transition::Replace(Box::new(MatchReplayScene::new(state, replay))),
+                    Ok(_) => warn!("The match replay {key} has no games"),
+                    Err(e) => warn!("Could not load match replay: {e}"),
+                }
+                return Transition::Pop(1);
+            }
+            MenuResult::WatchReplay(key) => {
+                state.apply_settings(ctx, menu.seed);
+                match Replay::load(&*state.storage, &key) {
+                    Ok(replay) => state.watch(replay),
+                    Err(e) => warn!("Could not load replay: {e}"),
+                }
+                return Transition::Pop(1);
+            }
+        }
+        Transition::None
+    }
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+/// The leaderboards once a game is over, and a review of the worst placements in it.
+pub struct ResultsScene {
+    review: Option<Review>,
+    /// Which mistake is being looked at, if any.
+    showing: Option<usize>,
+}
+
+impl ResultsScene {
+    pub fn new(state: &GameState) -> Self {
+        // Only a game that was recorded can be played over again to review it
+        let replay = state.game.input_listener.as_ref().and_then(|listener| listener.replay());
+        let replay = replay.and_then(|replay| Replay::decode(&replay).map_err(|e| warn!("Could not review the game: {e}")).ok());
+        ResultsScene {
+            review: replay.map(|replay| Review::start(replay, &state.game_config)),
+            showing: None,
+        }
+    }
+    fn mistakes(&self) -> &[Mistake] {
+        self.review.as_ref().and_then(Review::found).unwrap_or_default()
+    }
+    fn draw_mistake(&self, state: &GameState, r: &mut dyn Renderer, i: usize) {
+        let mistakes = self.mistakes();
+        let mistake = &mistakes[i];
+        dim(r, SCREEN_SIZE.1);
+        draw_text(r, &format!("Mistake {} of {}", i + 1, mistakes.len()), 32., [32., 32.]);
+        let when = format!("{:.1} s in, the {:?}", mistake.ms as f32 / 1000., mistake.played.piece.kind);
+        draw_text(r, &when, 20., [32., 84.]);
+        let loss = format!("left the board {:.1} worse than the outline", mistake.loss);
+        draw_text(r, &loss, 20., [32., 112.]);
+        analysis::draw_mistake(r, mistake, Rect::new(32., 160., 320., 640.), &state.theme);
+        draw_text(r, "Left/Right: other mistakes  Back: results", 16., [32., SCREEN_SIZE.1 - 36.]);
+    }
+}
+
+impl Scene for ResultsScene {
+    fn update(&mut self, _state: &mut GameState, _ctx: &mut Context) -> Transition {
+        if let Some(review) = &mut self.review {
+            review.update();
+        }
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        if let Some(i) = self.showing {
+            return self.draw_mistake(state, r, i);
+        }
+        let boards = Board::of(state.game.mode);
+        let height = 500. + 340. * (boards.len() - 1) as f32;
+        dim(r, height);
+        for (i, &board) in boards.iter().enumerate() {
+            let rank = state.high_score_ranks.get(i).copied().flatten();
+            state.data.high_scores.draw(r, board, rank, (32., 32. + 340. * i as f32));
+        }
+        if let Some(playback) = state.playback.as_ref().filter(|p| p.desync_tick.is_some() || p.checksum != state.game.checksum()) {
+            let message = match playback.desync_tick {
+                Some(tick) => format!("This replay went differently from the recording {:.1} s in", (tick * state.game.ms_per_tick()) as f32 / 1000.),
+                None => "This replay played out differently from how it was recorded".to_owned(),
+            };
+            r.draw_text(&message, 20., [32., height - 130.], Color::RED);
+        }
+        let (efficiency, pieces) = (state.game.efficiency, state.game.pieces as u64);
+        let misdrops = stats::per_100(efficiency.misdrops as u64, pieces);
+        let rotations = stats::per_100(efficiency.wasted_rotations as u64, pieces);
+        draw_text(r, &format!("Misdrops: {misdrops}  Wasted rotations: {rotations}"), 20., [32., height - 100.]);
+        let review = match &self.review {
+            Some(review) if review.found().is_none() => "Reviewing the game...".to_owned(),
+            Some(_) if self.mistakes().is_empty() => "No big mistakes".to_owned(),
+            Some(_) => format!("{} big mistakes, Left/Right to see them", self.mistakes().len()),
+            None => String::new(),
+        };
+        draw_text(r, &format!("Seed: {}  {review}", state.game.seed), 20., [32., height - 70.]);
+        draw_text(r, "Confirm: play again  Back: choose mode  S: save the results as an image", 16., [32., height - 36.]);
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, _repeated: bool) -> Transition {
+        if keycode == KeyCode::S && self.showing.is_none() {
+            state.results_card = true;
+            return Transition::None;
+        }
+        match MenuInput::from_key(keycode, mods, &state.bindings) {
+            Some(input) => self.menu_input(state, ctx, input),
+            None => Transition::None,
+        }
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        let n = self.mistakes().len();
+        match input {
+            MenuInput::Adjust(step) if n > 0 => {
+                let i = self.showing.map_or(0, |i| (i as isize + step.signum() as isize).rem_euclid(n as isize) as usize);
+                self.showing = Some(i);
+                Transition::None
+            }
+            MenuInput::Back if self.showing.is_some() => {
+                self.showing = None;
+                Transition::None
+            }
+            MenuInput::Confirm => {
+                state.reset(state.game.mode);
+                Transition::Pop(1)
+            }
+            // Back past the game to picking a mode
+            MenuInput::Back => Transition::Pop(2),
+            _ => Transition::None,
+        }
+    }
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
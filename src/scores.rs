@@ -0,0 +1,199 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ggez::{graphics::Color, GameResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    mode::Mode,
+    render::Renderer,
+    storage::Storage,
+    versioned::{self, Versioned},
+};
+
+const SCORES_FILE: &str = "scores.json";
+/// How many scores are kept for each mode.
+const MAX_SCORES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub lines: u32,
+    /// How long the game lasted.
+    #[serde(default)]
+    pub ms: u32,
+    /// When the game was played, in seconds since the Unix epoch.
+    pub date: u64,
+}
+
+impl ScoreEntry {
+    pub fn new(name: String, score: u32, lines: u32, ms: u32) -> Self {
+        let date = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        ScoreEntry { name, score, lines, ms, date }
+    }
+}
+
+/// A leaderboard, ranking games of one mode by what matters in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Board {
+    MarathonScore,
+    /// How long Marathon games lasted.
+    MarathonSurvival,
+    /// The fastest finished Sprints.
+    SprintTime,
+    UltraScore,
+}
+
+impl Board {
+    /// The boards a mode's games go on, the main one first.
+    pub fn of(mode: Mode) -> &'static [Board] {
+        match mode {
+            Mode::Marathon => &[Board::MarathonScore, Board::MarathonSurvival],
+            Mode::Sprint => &[Board::SprintTime],
+            Mode::Ultra => &[Board::UltraScore],
+            Mode::Practice => &[],
+        }
+    }
+    /// The name of the board in the scores file.
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            Board::MarathonScore => "marathon",
+            Board::MarathonSurvival => "marathon-survival",
+            Board::SprintTime => "sprint-time",
+            Board::UltraScore => "ultra",
+        }
+    }
+    pub(crate) fn title(self) -> &'static str {
+        match self {
+            Board::MarathonScore => "Marathon high scores",
+            Board::MarathonSurvival => "Marathon longest games",
+            Board::SprintTime => "Sprint best times",
+            Board::UltraScore => "Ultra high scores",
+        }
+    }
+    /// Whether a game belongs on this board at all, which for Sprint means finishing it.
+    pub(crate) fn qualifies(self, entry: &ScoreEntry) -> bool {
+        match self {
+            Board::SprintTime => Mode::Sprint.rules().is_finished(entry.lines, 0),
+            _ => true,
+        }
+    }
+    /// Whether `a` ranks above `b`.
+    fn better(self, a: &ScoreEntry, b: &ScoreEntry) -> bool {
+        match self {
+            Board::MarathonScore | Board::UltraScore => a.score > b.score,
+            Board::MarathonSurvival => a.ms > b.ms,
+            Board::SprintTime => a.ms < b.ms,
+        }
+    }
+    fn describe(self, entry: &ScoreEntry) -> String {
+        match self {
+            Board::MarathonScore | Board::UltraScore => format!("{:>7} {:>4} lines", entry.score, entry.lines),
+            Board::MarathonSurvival => format!("{:>9} {:>4} lines", format_time(entry.ms), entry.lines),
+            Board::SprintTime => format!("{:>9} {:>7}", format_time(entry.ms), entry.score),
+        }
+    }
+    /// The line `entry` is shown as at place `i` on the board, counting from 0.
+    pub(crate) fn row(self, i: usize, entry: &ScoreEntry) -> String {
+        format!("{:>3}. {:<12} {}  {}", i + 1, entry.name, self.describe(entry), format_date(entry.date))
+    }
+}
+
+/// The leaderboards, kept in the profile's directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighScores {
+    /// The entries by `Board::key`, best first.
+    boards: BTreeMap<String, Vec<ScoreEntry>>,
+}
+
+impl HighScores {
+    pub fn load(storage: &dyn Storage, dir: &str) -> GameResult<Self> {
+        Ok(versioned::load(storage, &format!("{dir}/{SCORES_FILE}"))?.unwrap_or_default())
+    }
+    pub fn save(&self, storage: &dyn Storage, dir: &str) -> GameResult {
+        versioned::save(storage, &format!("{dir}/{SCORES_FILE}"), self, true)
+    }
+    pub fn top(&self, board: Board) -> &[ScoreEntry] {
+        self.boards.get(board.key()).map_or(&[], Vec::as_slice)
+    }
+    /// Adds a game to a board, returning its place on it if it was good enough to stay there.
+    pub fn add(&mut self, board: Board, entry: ScoreEntry) -> Option<usize> {
+        if !board.qualifies(&entry) {
+            return None;
+        }
+        let scores = self.boards.entry(board.key().to_owned()).or_default();
+        let i = scores.partition_point(|e| !board.better(&entry, e));
+        if i >= MAX_SCORES {
+            return None;
+        }
+        scores.insert(i, entry);
+        scores.truncate(MAX_SCORES);
+        Some(i)
+    }
+    /// Draws `board` at `(x, y)`, highlighting the entry at `highlight`.
+    pub fn draw(&self, r: &mut dyn Renderer, board: Board, highlight: Option<usize>, (x, y): (f32, f32)) {
+        r.draw_text(board.title(), 32., [x, y], Color::WHITE);
+        for (i, entry) in self.top(board).iter().enumerate() {
+            let colour = if Some(i) == highlight { Color::YELLOW } else { Color::WHITE };
+            r.draw_text(&board.row(i, entry), 20., [x, y + 48. + 28. * i as f32], colour);
+        }
+    }
+}
+
+impl Versioned for HighScores {
+    const VERSION: u32 = 1;
+
+    /// Before version 1, scores were kept by mode in `modes` rather than by board in `boards`,
+    /// which later unversioned files also have. The old entries are ranked onto their mode's boards.
+    fn migrate(version: u32, value: &mut Value) -> Result<(), String> {
+        if version != 0 {
+            return Ok(());
+        }
+        let modes = value.as_object_mut().and_then(|fields| fields.remove("modes"));
+        let modes: BTreeMap<String, Vec<ScoreEntry>> = match modes {
+            Some(modes) => serde_json::from_value(modes).map_err(|e| e.to_string())?,
+            None => return Ok(()),
+        };
+        let mut scores: HighScores = serde_json::from_value(value.take()).map_err(|e| e.to_string())?;
+        for (key, entries) in modes {
+            let Some(mode) = Mode::ALL.into_iter().find(|m| m.key() == key) else {
+                continue;
+            };
+            for entry in entries {
+                for &board in Board::of(mode) {
+                    // How long the game lasted wasn't always kept
+                    if entry.ms > 0 || matches!(board, Board::MarathonScore | Board::UltraScore) {
+                        scores.add(board, entry.clone());
+                    }
+                }
+            }
+        }
+        *value = serde_json::to_value(scores).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Formats a duration as `m:ss.cc`.
+pub(crate) fn format_time(ms: u32) -> String {
+    format!("{}:{:02}.{:02}", ms / 60_000, ms / 1000 % 60, ms % 1000 / 10)
+}
+
+/// Formats a Unix timestamp as a `YYYY-MM-DD` date (in UTC).
+pub(crate) fn format_date(secs: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm
+    let z = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -0,0 +1,135 @@
+/// What a locked piece did, for scoring it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clear {
+    /// How many lines it cleared, which may be none.
+    pub lines: u32,
+    pub t_spin: bool,
+    /// The level the game was at, `Game::level`.
+    pub level: u32,
+}
+
+/// How many points a clear is worth.
+pub trait ScoringSystem {
+    fn score(&self, clear: Clear) -> u32;
+}
+
+/// The points of the original NES game, multiplied by the level (counting from 1).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Nes;
+
+impl ScoringSystem for Nes {
+    fn score(&self, clear: Clear) -> u32 {
+        ScoreTable::CLASSIC.score(clear) * (clear.level + 1)
+    }
+}
+
+/// The points of the modern guideline games, multiplied by the level (counting from 1),
+/// with T-spins worth more than normal clears.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Guideline;
+
+impl ScoringSystem for Guideline {
+    fn score(&self, clear: Clear) -> u32 {
+        let table = ScoreTable {
+            lines: [0, 100, 300, 500, 800],
+            t_spin: [400, 800, 1200, 1600],
+        };
+        table.score(clear) * (clear.level + 1)
+    }
+}
+
+/// A fixed number of points for each number of lines, whatever the level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScoreTable {
+    /// The points for clearing 0 to 4 lines.
+    pub lines: [u32; 5],
+    /// The points for a T-spin clearing 0 to 3 lines.
+    pub t_spin: [u32; 4],
+}
+
+impl ScoreTable {
+    /// The points the game has always given, with nothing extra for T-spins.
+    pub const CLASSIC: ScoreTable = ScoreTable {
+        lines: [0, 40, 100, 300, 1200],
+        t_spin: [0, 40, 100, 300],
+    };
+}
+
+impl ScoringSystem for ScoreTable {
+    fn score(&self, clear: Clear) -> u32 {
+        let table: &[u32] = if clear.t_spin { &self.t_spin } else { &self.lines };
+        // More lines than a piece can clear don't happen, but score as the most there is if they do
+        table.get(clear.lines as usize).or(table.last()).copied().unwrap_or(0)
+    }
+}
+
+/// The names modes can choose their scoring by, such as scripted ones with `scoring()`.
+pub const NAMES: [&str; 3] = ["classic", "nes", "guideline"];
+
+/// The scoring system called `name`, one of `NAMES`.
+pub fn by_name(name: &str) -> Option<Box<dyn ScoringSystem>> {
+    Some(match name {
+        "classic" => Box::new(ScoreTable::CLASSIC),
+        "nes" => Box::new(Nes),
+        "guideline" => Box::new(Guideline),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u32, t_spin: bool, level: u32) -> Clear {
+        Clear { lines, t_spin, level }
+    }
+
+    #[test]
+    fn classic_points_ignore_the_level() {
+        let classic = ScoreTable::CLASSIC;
+        let points: Vec<_> = (0..=4).map(|lines| classic.score(clear(lines, false, 0))).collect();
+        assert_eq!(points, [0, 40, 100, 300, 1200]);
+        assert_eq!(classic.score(clear(4, false, 15)), 1200);
+        assert_eq!(classic.score(clear(2, true, 3)), 100);
+    }
+
+    #[test]
+    fn nes_points_go_up_with_the_level() {
+        assert_eq!(Nes.score(clear(1, false, 0)), 40);
+        assert_eq!(Nes.score(clear(4, false, 0)), 1200);
+        assert_eq!(Nes.score(clear(4, false, 9)), 12000);
+        assert_eq!(Nes.score(clear(0, false, 9)), 0);
+    }
+
+    #[test]
+    fn guideline_t_spins_are_worth_more() {
+        let points: Vec<_> = (0..=4).map(|lines| Guideline.score(clear(lines, false, 0))).collect();
+        assert_eq!(points, [0, 100, 300, 500, 800]);
+        let t_spins: Vec<_> = (0..=3).map(|lines| Guideline.score(clear(lines, true, 0))).collect();
+        assert_eq!(t_spins, [400, 800, 1200, 1600]);
+        assert_eq!(Guideline.score(clear(2, true, 4)), 6000);
+    }
+
+    #[test]
+    fn tables_look_up_t_spins_apart_from_clears() {
+        let table = ScoreTable {
+            lines: [0, 1, 2, 3, 4],
+            t_spin: [10, 20, 30, 40],
+        };
+        assert_eq!(table.score(clear(3, false, 0)), 3);
+        assert_eq!(table.score(clear(3, true, 0)), 40);
+        assert_eq!(table.score(clear(0, true, 0)), 10);
+        // Clearing more lines than a piece can scores as the most there is
+        assert_eq!(table.score(clear(6, false, 0)), 4);
+        assert_eq!(table.score(clear(4, true, 0)), 40);
+    }
+
+    #[test]
+    fn every_name_picks_a_system() {
+        for name in NAMES {
+            assert!(by_name(name).is_some(), "{name}");
+        }
+        assert_eq!(by_name("nes").unwrap().score(clear(1, false, 1)), 80);
+        assert!(by_name("tgm").is_none());
+    }
+}
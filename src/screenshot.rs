@@ -0,0 +1,77 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ggez::{
+    graphics::{Canvas, Color, DrawParam, Image, ImageEncodingFormat, ImageFormat, Rect},
+    Context, GameResult,
+};
+
+use crate::{
+    render::{board_offset, cell_rect, draw_board, Renderer, Viewport},
+    rules::Game,
+    scores::{format_date, format_time},
+    theme::Theme,
+};
+
+const SCREENSHOT_DIR: &str = "/screenshots";
+/// How big the results card is, in pixels: 16:9 like most places it would be shared to.
+const CARD_SIZE: (u32, u32) = (960, 540);
+const CARD_BACKGROUND: Color = Color::new(0.08, 0.08, 0.12, 1.);
+
+/// Saves what was last drawn to the window as a PNG in the screenshots directory,
+/// returning where it went. Has to be called after the frame has been drawn.
+pub fn save(ctx: &mut Context) -> GameResult<String> {
+    // The window's own format can't always be encoded, so the frame is copied into a plain RGBA image first
+    let (width, height) = (ctx.gfx.frame().width(), ctx.gfx.frame().height());
+    let image = Image::new_canvas_image(ctx, ImageFormat::Rgba8UnormSrgb, width, height, 1);
+    let mut canvas = Canvas::from_image(ctx, image.clone(), Color::BLACK);
+    canvas.draw(ctx.gfx.frame(), DrawParam::new());
+    canvas.finish(ctx)?;
+    write(ctx, &image, "")
+}
+
+/// Saves a card summing up `game` to share once it's over, with its final board, mode, score, time,
+/// pieces per second and the date, as a PNG in the screenshots directory, returning where it went.
+/// Like `save`, it has to be called while drawing a frame.
+pub fn save_card(ctx: &mut Context, game: &Game, theme: &Theme) -> GameResult<String> {
+    let image = Image::new_canvas_image(ctx, ImageFormat::Rgba8UnormSrgb, CARD_SIZE.0, CARD_SIZE.1, 1);
+    let mut canvas = Canvas::from_image(ctx, image.clone(), CARD_BACKGROUND);
+    draw_card(&mut canvas, game, theme);
+    canvas.finish(ctx)?;
+    write(ctx, &image, "-results")
+}
+
+fn draw_card(r: &mut dyn Renderer, game: &Game, theme: &Theme) {
+    let (width, height) = (CARD_SIZE.0 as f32, CARD_SIZE.1 as f32);
+    let (columns, rows) = game.grid.size();
+    let [x, y] = board_offset(game.grid.size());
+    let top_left = cell_rect(0., 0.);
+    let board = Rect::new(top_left.x + x, top_left.y + y, columns as f32 * top_left.w, rows as f32 * top_left.h);
+    let fits_in = Rect::new(32., 32., width / 2. - 64., height - 64.);
+    draw_board(&mut Viewport::new(r, board, fits_in), game, 0., theme);
+
+    let left = width / 2.;
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let pps = if game.ms() == 0 { 0. } else { game.pieces as f32 * 1000. / game.ms() as f32 };
+    r.draw_text("Tetris", 24., [left, 48.], Color::new(0.7, 0.7, 0.7, 1.));
+    r.draw_text(&game.mode.to_string(), 48., [left, 80.], Color::WHITE);
+    let lines = [
+        format!("Score: {}", game.score),
+        format!("Lines: {}", game.lines),
+        format!("Level: {}", game.level()),
+        format!("Time: {}", format_time(game.ms())),
+        format!("Pieces per second: {pps:.2}"),
+    ];
+    for (i, line) in lines.iter().enumerate() {
+        r.draw_text(line, 28., [left, 168. + 44. * i as f32], Color::WHITE);
+    }
+    r.draw_text(&format_date(secs), 20., [left, height - 64.], Color::new(0.7, 0.7, 0.7, 1.));
+}
+
+/// Encodes `image` as a PNG named after the current time and `suffix`, returning the path it was saved to.
+fn write(ctx: &mut Context, image: &Image, suffix: &str) -> GameResult<String> {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+    let path = format!("{SCREENSHOT_DIR}/{millis}{suffix}.png");
+    ctx.fs.create_dir(SCREENSHOT_DIR)?;
+    image.encode(ctx, ImageEncodingFormat::Png, &path)?;
+    Ok(ctx.fs.user_data_dir().join(path.trim_start_matches('/')).display().to_string())
+}
@@ -0,0 +1,303 @@
+use std::{any::Any, cell::RefCell, path::Path, rc::Rc};
+
+use ggez::{GameError, GameResult};
+use log::warn;
+use oorandom::Rand32;
+use rhai::{Dynamic, Engine, FuncArgs, Scope, AST, INT};
+
+use crate::{
+    bitgrid::BitGrid,
+    grid::Pos,
+    mode::Mode,
+    rotation::{self, RotationSystem},
+    rules::{Game, GameEvent},
+    ruleset::{GameRules, Randomizer, RulesAction},
+    scoring::{self, Clear, ScoringSystem},
+};
+
+/// A custom mode written as a [Rhai](https://rhai.rs) script: a built-in mode with the script's hooks on top.
+///
+/// A script can define any of these functions:
+///
+/// - `base()`: the key of the built-in mode it builds on, like `"sprint"`. Marathon if there is none.
+/// - `on_tick(game)`: called at the end of every tick.
+/// - `on_lock(game, lines, t_spin)`: called when a piece locks, clearing `lines` lines.
+/// - `rotation()`: the name of the rotation system to use instead of the base mode's, one of `rotation::NAMES`.
+/// - `scoring()`: the name of the scoring system to use instead of the base mode's, one of `scoring::NAMES`.
+/// - `score(lines, t_spin, level, points)`: what a clear is worth, given the `points` the scoring system gives for it.
+/// - `is_finished(lines, ms)`: whether the game has been won, instead of the base mode's goal.
+///
+/// `game` has the properties `tick`, `ms`, `score`, `lines`, `level`, `pieces` and `seed`.
+/// It reads the grid with `filled(x, y)`, `height(x)` and `holes()`, picks numbers below `n` with `random(n)`,
+/// and changes the game with `add_garbage(hole)`, `add_score(points)` and `end()`.
+///
+/// For example, a garbage row every 20 seconds:
+///
+/// ```text
+/// fn on_tick(game) {
+///     if game.tick % 480 == 0 {
+///         game.add_garbage(game.random(10));
+///     }
+/// }
+/// ```
+pub struct Script(Rc<Inner>);
+
+struct Inner {
+    name: String,
+    base: Mode,
+    /// The rotation system the script picked, one of `rotation::NAMES`.
+    rotation: Option<String>,
+    /// The scoring system the script picked, one of `scoring::NAMES`.
+    scoring: Option<String>,
+    engine: Engine,
+    ast: AST,
+    /// Whether a hook has failed yet, to only log the first failure rather than one every tick.
+    failed: RefCell<bool>,
+}
+
+/// What a script sees of the game, and the actions it has asked for.
+#[derive(Clone)]
+struct ScriptGame {
+    tick: INT,
+    ms: INT,
+    score: INT,
+    lines: INT,
+    level: INT,
+    pieces: INT,
+    seed: INT,
+    bits: BitGrid,
+    actions: Rc<RefCell<Vec<RulesAction>>>,
+}
+
+impl ScriptGame {
+    fn new(game: &Game, actions: Rc<RefCell<Vec<RulesAction>>>) -> Self {
+        ScriptGame {
+            tick: game.tick as INT,
+            ms: game.ms() as INT,
+            score: game.score as INT,
+            lines: game.lines as INT,
+            level: game.level() as INT,
+            pieces: game.pieces as INT,
+            seed: game.seed as INT,
+            bits: *game.grid.bits(),
+            actions,
+        }
+    }
+    fn filled(&mut self, x: INT, y: INT) -> bool {
+        match (i8::try_from(x), i8::try_from(y)) {
+            (Ok(x), Ok(y)) => self.bits.is_filled(Pos::new(x, y)),
+            _ => false,
+        }
+    }
+    fn height(&mut self, x: INT) -> INT {
+        usize::try_from(x).ok().and_then(|x| self.bits.column_heights().get(x).copied()).unwrap_or(0) as INT
+    }
+    fn holes(&mut self) -> INT {
+        self.bits.count_holes() as INT
+    }
+    /// The same for the same game and tick, so scripted games play out the same every time.
+    fn random(&mut self, n: INT) -> INT {
+        let mut rng = Rand32::new((self.seed as u64) ^ (self.tick as u64) << 32);
+        u32::try_from(n).ok().filter(|&n| n > 0).map_or(0, |n| rng.rand_range(0..n) as INT)
+    }
+    fn add_garbage(&mut self, hole: INT) {
+        let hole = usize::try_from(hole).unwrap_or(usize::MAX);
+        self.actions.borrow_mut().push(RulesAction::AddGarbage { hole });
+    }
+    fn add_score(&mut self, points: INT) {
+        self.actions.borrow_mut().push(RulesAction::AddScore(points.clamp(0, u32::MAX as INT) as u32));
+    }
+    fn end(&mut self) {
+        self.actions.borrow_mut().push(RulesAction::EndGame);
+    }
+}
+
+impl Script {
+    /// Loads the script in the file at `path`, named after the file.
+    pub fn load(path: &Path) -> GameResult<Self> {
+        let name = path.file_stem().map_or_else(|| "Script".to_owned(), |s| s.to_string_lossy().into_owned());
+        let source = std::fs::read_to_string(path)?;
+        Self::compile(name, &source)
+    }
+    pub fn compile(name: String, source: &str) -> GameResult<Self> {
+        let mut engine = Engine::new();
+        engine
+            .register_type_with_name::<ScriptGame>("Game")
+            .register_get("tick", |g: &mut ScriptGame| g.tick)
+            .register_get("ms", |g: &mut ScriptGame| g.ms)
+            .register_get("score", |g: &mut ScriptGame| g.score)
+            .register_get("lines", |g: &mut ScriptGame| g.lines)
+            .register_get("level", |g: &mut ScriptGame| g.level)
+            .register_get("pieces", |g: &mut ScriptGame| g.pieces)
+            .register_get("seed", |g: &mut ScriptGame| g.seed)
+            .register_fn("filled", ScriptGame::filled)
+            .register_fn("height", ScriptGame::height)
+            .register_fn("holes", ScriptGame::holes)
+            .register_fn("random", ScriptGame::random)
+            .register_fn("add_garbage", ScriptGame::add_garbage)
+            .register_fn("add_score", ScriptGame::add_score)
+            .register_fn("end", ScriptGame::end);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| GameError::CustomError(format!("Could not compile script {name}: {e}")))?;
+        let mut inner = Inner {
+            name,
+            base: Mode::Marathon,
+            rotation: None,
+            scoring: None,
+            engine,
+            ast,
+            failed: RefCell::new(false),
+        };
+        if let Some(key) = inner.call::<String>("base", ()) {
+            inner.base = *Mode::ALL
+                .iter()
+                .find(|m| m.key() == key)
+                .ok_or_else(|| GameError::CustomError(format!("Script {} builds on {key}, which isn't a mode", inner.name)))?;
+        }
+        if let Some(name) = inner.call::<String>("rotation", ()) {
+            if !rotation::NAMES.contains(&name.as_str()) {
+                return Err(GameError::CustomError(format!("Script {} picks {name} rotation, which there isn't", inner.name)));
+            }
+            inner.rotation = Some(name);
+        }
+        if let Some(name) = inner.call::<String>("scoring", ()) {
+            if !scoring::NAMES.contains(&name.as_str()) {
+                return Err(GameError::CustomError(format!("Script {} picks {name} scoring, which there isn't", inner.name)));
+            }
+            inner.scoring = Some(name);
+        }
+        Ok(Script(Rc::new(inner)))
+    }
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+    /// The built-in mode the script builds on.
+    pub fn base(&self) -> Mode {
+        self.0.base
+    }
+}
+
+impl Inner {
+    /// Calls the script's function `name`, if it has one and it works.
+    fn call<T: Any>(&self, name: &str, args: impl FuncArgs) -> Option<T> {
+        if !self.ast.iter_functions().any(|f| f.name == name) {
+            return None;
+        }
+        match self.engine.call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, args) {
+            Ok(value) => value.try_cast::<T>().or_else(|| {
+                self.report(&format!("{name} returned the wrong type"));
+                None
+            }),
+            Err(e) => {
+                self.report(&e.to_string());
+                None
+            }
+        }
+    }
+    fn report(&self, error: &str) {
+        if !self.failed.replace(true) {
+            warn!("Script {} failed, later failures won't be logged: {error}", self.name);
+        }
+    }
+}
+
+impl GameRules for Script {
+    fn frames_per_row(&self, level: u32) -> u8 {
+        self.0.base.rules().frames_per_row(level)
+    }
+    fn randomizer(&self) -> Box<dyn Randomizer> {
+        self.0.base.rules().randomizer()
+    }
+    fn rotation_system(&self) -> Box<dyn RotationSystem> {
+        self.0.rotation.as_deref().and_then(rotation::by_name).unwrap_or_else(|| self.0.base.rules().rotation_system())
+    }
+    fn scoring(&self) -> Box<dyn ScoringSystem> {
+        Box::new(ScriptScoring {
+            script: self.0.clone(),
+            base: self.0.scoring.as_deref().and_then(scoring::by_name).unwrap_or_else(|| self.0.base.rules().scoring()),
+        })
+    }
+    fn undo_limit(&self) -> usize {
+        self.0.base.rules().undo_limit()
+    }
+    fn is_finished(&self, lines: u32, ms: u32) -> bool {
+        self.0
+            .call("is_finished", (lines as INT, ms as INT))
+            .unwrap_or_else(|| self.0.base.rules().is_finished(lines, ms))
+    }
+    fn after_tick(&self, game: &Game, events: &[GameEvent]) -> Vec<RulesAction> {
+        let actions = Rc::new(RefCell::new(Vec::new()));
+        let script_game = ScriptGame::new(game, actions.clone());
+        self.0.call::<Dynamic>("on_tick", (script_game.clone(),));
+        for event in events {
+            if let &GameEvent::PieceLocked { lines, t_spin } = event {
+                self.0.call::<Dynamic>("on_lock", (script_game.clone(), lines as INT, t_spin));
+            }
+        }
+        actions.take()
+    }
+}
+
+/// The base mode's scoring, with the script's `score` function on top.
+struct ScriptScoring {
+    script: Rc<Inner>,
+    base: Box<dyn ScoringSystem>,
+}
+
+impl ScoringSystem for ScriptScoring {
+    fn score(&self, clear: Clear) -> u32 {
+        let points = self.base.score(clear);
+        let args = (clear.lines as INT, clear.t_spin, clear.level as INT, points as INT);
+        self.script
+            .call::<INT>("score", args)
+            .map_or(points, |p| p.clamp(0, u32::MAX as INT) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        grid::Grid,
+        piece::{MovingPiece, Piece, Tetromino},
+        rotation::Rotation,
+    };
+
+    fn tetris(level: u32) -> Clear {
+        Clear { lines: 4, t_spin: false, level }
+    }
+
+    #[test]
+    fn scripts_can_pick_their_scoring() {
+        let script = Script::compile("nes".to_owned(), r#"fn scoring() { "nes" }"#).unwrap();
+        assert_eq!(script.scoring().score(tetris(2)), 3600);
+        let script = Script::compile("base".to_owned(), r#"fn base() { "sprint" }"#).unwrap();
+        assert_eq!(script.scoring().score(tetris(2)), 1200);
+        assert!(Script::compile("tgm".to_owned(), r#"fn scoring() { "tgm" }"#).is_err());
+    }
+
+    #[test]
+    fn scripts_can_pick_their_rotation() {
+        let grid = Grid::with_size((10, 20));
+        // A vertical I against the left wall, which only turns if it is kicked away from it
+        let mut i = MovingPiece::new(Piece::new(Tetromino::I), Pos::new(4, 10));
+        i.piece.rotate(Rotation::Right, (0, 0));
+        i.pos.x = 0;
+        let script = Script::compile("srs".to_owned(), r#"fn rotation() { "srs" }"#).unwrap();
+        assert!(script.rotation_system().rotate(&i, Rotation::Left, &grid).is_some());
+        let script = Script::compile("base".to_owned(), "").unwrap();
+        assert!(script.rotation_system().rotate(&i, Rotation::Left, &grid).is_none());
+        assert!(Script::compile("dtet".to_owned(), r#"fn rotation() { "dtet" }"#).is_err());
+    }
+
+    #[test]
+    fn score_hooks_see_the_picked_scoring() {
+        let source = r#"
+            fn scoring() { "guideline" }
+            fn score(lines, t_spin, level, points) { points + 1 }
+        "#;
+        let script = Script::compile("hooked".to_owned(), source).unwrap();
+        assert_eq!(script.scoring().score(tetris(0)), 801);
+    }
+}
@@ -0,0 +1,784 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use oorandom::Rand32;
+
+use crate::{
+    app::random_seed,
+    chat::MAX_LENGTH,
+    config::{GameConfig, Handling},
+    handicap::Handicap,
+    input::Action,
+    lobby::RoomRules,
+    net::{check_handshake, Beacon, Connection, Host, Message, MAX_QUEUED, RECONNECT_GRACE},
+    royale::{pick_target, Targeting, MAX_PLAYERS, MIN_PLAYERS},
+    rules::TICKS_PER_SECOND,
+    versus::{decide_match, Outcome, Side},
+};
+
+/// How long the server waits before looking for messages again when there were none.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+/// More inputs than anyone could make in a tick.
+const MAX_INPUTS_PER_TICK: usize = 32;
+/// How far ahead of the time since the match started a player's ticks may be, for their clock starting a little
+/// earlier than the server's or running a little fast.
+const TICK_LEAD: Duration = Duration::from_millis(500);
+/// More presses than anyone could make in a tick. Shifts aren't counted: a swipe on a touch screen makes one for each
+/// cell it crosses, and shifting faster than a person can is no help, as auto shift with no repeat delay crosses
+/// the board in a tick anyway.
+const MAX_PRESSES_PER_TICK: usize = 8;
+/// How many ticks back presses are counted, to catch a pace nobody could keep up: a second at the usual speed.
+const PRESS_WINDOW: usize = TICKS_PER_SECOND as usize;
+/// More presses than anyone could make in `PRESS_WINDOW` ticks.
+const MAX_PRESSES_PER_WINDOW: usize = 30;
+/// How far behind the players those watching are kept, so they can't tell a player what is coming.
+const SPECTATOR_DELAY: Duration = Duration::from_secs(3);
+/// How many messages may be waiting to go out to a spectator before they are sent any more,
+/// well short of the `MAX_QUEUED` that would hang up on them.
+const MAX_SPECTATOR_BACKLOG: usize = MAX_QUEUED / 2;
+/// How long someone who has connected has to send their handshake before they are hung up on.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the first player waiting for a battle royale waits for it to fill up before it starts with fewer.
+const ROYALE_WAIT: Duration = Duration::from_secs(20);
+
+/// Someone who has connected and is waiting for an opponent, or for a match to watch.
+struct Waiting {
+    connection: Connection,
+    /// Whether their handshake has come in and matched the server's.
+    shaken: bool,
+    hello: Option<(String, Handling)>,
+    watching: bool,
+    /// Whether they want to play in a battle royale rather than against one other player.
+    royale: bool,
+    /// The token of the match they want to get back into, if they lost their connection to it.
+    rejoin: Option<u64>,
+    since: Instant,
+}
+
+/// Someone sent to a match under way.
+enum Joining {
+    Spectator(Connection),
+    /// A player getting back into the match, with the token it gave them.
+    Rejoin(u64, Connection),
+}
+
+impl Joining {
+    fn into_connection(self) -> Connection {
+        match self {
+            Joining::Spectator(connection) | Joining::Rejoin(_, connection) => connection,
+        }
+    }
+}
+
+/// Where to send those joining a match still being played, and the tokens it gave its players.
+struct Running {
+    joining: Sender<Joining>,
+    tokens: [u64; 2],
+    thread: JoinHandle<()>,
+}
+
+impl Waiting {
+    fn new(connection: Connection) -> Self {
+        Waiting {
+            connection,
+            shaken: false,
+            hello: None,
+            watching: false,
+            royale: false,
+            rejoin: None,
+            since: Instant::now(),
+        }
+    }
+    fn into_player(self) -> (Connection, String, Handling) {
+        let (name, handling) = self.hello.expect("Only players who said hello are ready");
+        (self.connection, name, handling)
+    }
+}
+
+/// Runs a server on `port` that plays versus matches between whoever connects, two at a time, each match on a
+/// thread of its own. Players join it the same way as a game hosted by another player.
+/// Those who ask to watch are sent the latest match, or the next one to start if none is being played.
+/// Those who ask for a battle royale play it once there are enough of them, on a thread of its own too.
+/// The server is announced to the local network so players there can find it without its address.
+/// Nobody plays or watches before their handshake has shown their game can follow the server's.
+/// Those who send none within `HANDSHAKE_TIMEOUT` are hung up on.
+/// A player whose connection drops during a match has a little while to come back to it before they lose.
+/// Every match and battle royale is played by `rules`.
+/// This only returns if the port can't be listened on.
+pub fn run(port: u16, rules: RoomRules) -> io::Result<()> {
+    let host = Host::listen(port)?;
+    info!("Listening on port {}", host.port());
+    let mut beacon = Beacon::new("Server", host.port()).map_err(|e| warn!("Could not announce the server to the local network: {e}")).ok();
+    let mut waiting: Vec<Waiting> = Vec::new();
+    // The matches still being played, the latest last
+    let mut matches: Vec<Running> = Vec::new();
+    let mut started = 0;
+    let config = GameConfig::default();
+    loop {
+        if let Some(beacon) = &mut beacon {
+            beacon.update();
+        }
+        match host.accept() {
+            Ok(Some(mut connection)) => {
+                connection.send(&Message::handshake(&config));
+                waiting.push(Waiting::new(connection));
+            }
+            Ok(None) => (),
+            Err(e) => warn!("Could not accept a player: {e}"),
+        }
+        let mut refused = Vec::new();
+        for (i, player) in waiting.iter_mut().enumerate() {
+            while let Some(message) = player.connection.receive() {
+                match message {
+                    Message::Handshake { version, rules, engine, .. } => match check_handshake(version, rules, engine, &config) {
+                        Ok(()) => player.shaken = true,
+                        Err(e) => {
+                            info!("Refusing a player: {e}");
+                            let reason = "This game can't play with the server's. Update both to the same version, with the same settings.".to_owned();
+                            player.connection.send(&Message::Refused { reason });
+                            refused.push(i);
+                            break;
+                        }
+                    },
+                    Message::Hello { name, handling } => player.hello = Some((name, handling)),
+                    Message::Watch => player.watching = true,
+                    Message::Rejoin { token } => player.rejoin = Some(token),
+                    Message::JoinRoyale { name, handling } => {
+                        player.hello = Some((name, handling));
+                        player.royale = true;
+                    }
+                    message => warn!("Unexpected message before the match: {message:?}"),
+                }
+            }
+        }
+        for i in refused.into_iter().rev() {
+            waiting.remove(i);
+        }
+        waiting.retain(|player| !player.connection.is_closed());
+        for mut player in extract(&mut waiting, |p| !p.shaken && p.since.elapsed() >= HANDSHAKE_TIMEOUT) {
+            info!("Hanging up on someone who never sent a handshake");
+            player.connection.send(&Message::Refused { reason: "No handshake came in time".to_owned() });
+        }
+        matches.retain(|running| !running.thread.is_finished());
+
+        for player in extract(&mut waiting, |p| p.shaken && p.rejoin.is_some()) {
+            let token = player.rejoin.expect("Only those rejoining are taken");
+            if let Err(mut connection) = rejoin(&mut matches, token, player.connection) {
+                connection.send(&Message::Refused { reason: "The match is over".to_owned() });
+            }
+        }
+        for spectator in extract(&mut waiting, |p| p.shaken && p.watching) {
+            if let Some(connection) = watch_latest(&mut matches, spectator.connection) {
+                waiting.push(Waiting { shaken: true, watching: true, ..Waiting::new(connection) });
+            }
+        }
+
+        let royale: Vec<usize> = waiting.iter().enumerate().filter(|(_, p)| p.shaken && p.royale).map(|(i, _)| i).take(MAX_PLAYERS).collect();
+        let waited = royale.first().is_some_and(|&i| waiting[i].since.elapsed() >= ROYALE_WAIT);
+        if royale.len() == MAX_PLAYERS || (royale.len() >= MIN_PLAYERS && waited) {
+            started += 1;
+            let id = started;
+            // Taken out from the back so the positions of the rest stay the same
+            let mut players: Vec<_> = royale.iter().rev().map(|&i| waiting.remove(i).into_player()).collect();
+            players.reverse();
+            let rules = rules.clone();
+            if let Err(e) = thread::Builder::new().name(format!("royale {id}")).spawn(move || ServerRoyale::new(id, players, rules).run()) {
+                warn!("Could not start battle royale {id}: {e}");
+            }
+            continue;
+        }
+
+        // The first two to have said who they are play each other
+        let ready: Vec<usize> = waiting.iter().enumerate().filter(|(_, p)| p.shaken && p.hello.is_some() && !p.watching && !p.royale).map(|(i, _)| i).take(2).collect();
+        if let [a, b] = ready[..] {
+            let second = waiting.remove(b);
+            let first = waiting.remove(a);
+            started += 1;
+            let id = started;
+            let players = [first.into_player(), second.into_player()];
+            let tokens = [random_seed(), random_seed()];
+            let (joining, joined) = mpsc::channel();
+            let rules = rules.clone();
+            let thread = thread::Builder::new().name(format!("match {id}")).spawn(move || ServerMatch::new(id, players, tokens, joined, rules).run());
+            match thread {
+                Ok(thread) => matches.push(Running { joining, tokens, thread }),
+                Err(e) => warn!("Could not start match {id}: {e}"),
+            }
+        } else {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Sends someone who wants to watch to the latest match still being played,
+/// giving them back if there is none.
+fn watch_latest(matches: &mut Vec<Running>, mut connection: Connection) -> Option<Connection> {
+    while let Some(running) = matches.last() {
+        // A match that has ended has dropped its end of the channel, and gives the connection back
+        match running.joining.send(Joining::Spectator(connection)) {
+            Ok(()) => return None,
+            Err(mpsc::SendError(back)) => {
+                connection = back.into_connection();
+                matches.pop();
+            }
+        }
+    }
+    Some(connection)
+}
+
+/// Sends a player back to the match that gave them `token`, giving them back if it is over.
+fn rejoin(matches: &mut Vec<Running>, token: u64, connection: Connection) -> Result<(), Connection> {
+    let Some(i) = matches.iter().position(|running| running.tokens.contains(&token)) else {
+        return Err(connection);
+    };
+    matches[i].joining.send(Joining::Rejoin(token, connection)).map_err(|mpsc::SendError(back)| {
+        matches.remove(i);
+        back.into_connection()
+    })
+}
+
+/// Takes the ones matching `pred` out of `waiting`.
+fn extract(waiting: &mut Vec<Waiting>, pred: impl Fn(&Waiting) -> bool) -> Vec<Waiting> {
+    let (taken, kept) = std::mem::take(waiting).into_iter().partition(pred);
+    *waiting = kept;
+    taken
+}
+
+/// How fast a player has been playing, to tell a person from a program sending ticks faster than they can be played
+/// or more presses than anyone could make.
+struct Pace {
+    started: Instant,
+    /// How many keys were pressed in each of the last `PRESS_WINDOW` ticks.
+    presses: VecDeque<usize>,
+}
+
+impl Pace {
+    fn new() -> Self {
+        Pace { started: Instant::now(), presses: VecDeque::with_capacity(PRESS_WINDOW) }
+    }
+    /// Checks that tick `tick`, with `inputs`, could have been played by a person by now, a tick being `ms_per_tick` long.
+    fn check(&mut self, tick: u32, ms_per_tick: u32, inputs: &[(Action, bool)]) -> Result<(), String> {
+        let played = Duration::from_millis(tick as u64 * ms_per_tick as u64);
+        if played > self.started.elapsed() + TICK_LEAD {
+            return Err(format!("tick {tick} before it could have been played"));
+        }
+        let presses = inputs.iter().filter(|&&(action, pressed)| pressed && !matches!(action, Action::Left | Action::Right)).count();
+        if presses > MAX_PRESSES_PER_TICK {
+            return Err(format!("{presses} presses in tick {tick}"));
+        }
+        if self.presses.len() == PRESS_WINDOW {
+            self.presses.pop_front();
+        }
+        self.presses.push_back(presses);
+        let recent: usize = self.presses.iter().sum();
+        if recent > MAX_PRESSES_PER_WINDOW {
+            return Err(format!("{recent} presses in the {} ticks up to tick {tick}", self.presses.len()));
+        }
+        Ok(())
+    }
+}
+
+/// Someone watching a match. They are only sent as much as they have room for,
+/// so one who is slow to take it falls behind rather than being hung up on.
+struct Spectator {
+    connection: Connection,
+    /// How many of the match's ticks they have been sent.
+    sent: usize,
+}
+
+/// A match the server plays out itself as the players' inputs come in, passing them on to the other player.
+/// It only takes the players' word for what they pressed: the garbage and who won are worked out here,
+/// and a player whose ticks don't add up loses. A player whose connection drops is waited for
+/// for `RECONNECT_GRACE`, and picks up from the boards as the server last played them.
+struct ServerMatch {
+    id: u32,
+    connections: [Connection; 2],
+    names: [String; 2],
+    handling: [Handling; 2],
+    seed: u64,
+    sides: [Side; 2],
+    rules: RoomRules,
+    ticks: [u32; 2],
+    paces: [Pace; 2],
+    topped_out: [Option<u32>; 2],
+    /// Garbage sent to each board that the player has not yet said landed.
+    pending: [Vec<(u32, usize)>; 2],
+    /// What each player gets back into the match with if their connection drops.
+    tokens: [u64; 2],
+    /// When each player's connection dropped, if it has and they have not come back yet.
+    dropped: [Option<Instant>; 2],
+    /// Those who have asked to watch or are getting back into the match since last time.
+    joined: Receiver<Joining>,
+    spectators: Vec<Spectator>,
+    /// Every tick played, and when, for those watching to be sent once they are old enough.
+    history: Vec<(Instant, Message)>,
+}
+
+impl ServerMatch {
+    fn new(id: u32, players: [(Connection, String, Handling); 2], tokens: [u64; 2], joined: Receiver<Joining>, rules: RoomRules) -> Self {
+        let seed = random_seed();
+        let config = GameConfig::default();
+        let [(first, first_name, first_handling), (second, second_name, second_handling)] = players;
+        let mut connections = [first, second];
+        let names = [first_name, second_name];
+        let handling = [first_handling, second_handling];
+        for (i, connection) in connections.iter_mut().enumerate() {
+            connection.send(&Message::Hello { name: names[1 - i].clone(), handling: handling[1 - i] });
+            connection.send(&Message::Start { seed, rules: rules.clone(), player: i });
+            connection.send(&Message::Session { token: tokens[i] });
+        }
+        info!("Match {id}: {} against {} with seed {seed}", names[0], names[1]);
+        ServerMatch {
+            id,
+            connections,
+            sides: [0, 1].map(|i| Side::new(seed, i, rules.mode(), rules.cap(handling[i]), &config, rules.handicaps[i])),
+            names,
+            handling,
+            seed,
+            rules,
+            ticks: [0; 2],
+            paces: [Pace::new(), Pace::new()],
+            topped_out: [None; 2],
+            pending: [Vec::new(), Vec::new()],
+            tokens,
+            dropped: [None; 2],
+            joined,
+            spectators: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+    fn run(mut self) {
+        let outcome = loop {
+            let mut idle = true;
+            for i in 0..2 {
+                while let Some(message) = self.connections[i].receive() {
+                    idle = false;
+                    let (tick, garbage, inputs) = match message {
+                        Message::Tick { tick, garbage, inputs } => (tick, garbage, inputs),
+                        Message::Checksum { tick, score, lines, checksum } => {
+                            if let Err(e) = check_report(&self.sides[i], self.ticks[i], tick, score, lines, checksum) {
+                                warn!("Match {}: {} forfeits, {e}", self.id, self.names[i]);
+                                self.finish(Outcome::Winner(1 - i));
+                                return;
+                            }
+                            continue;
+                        }
+                        Message::Chat { text } => {
+                            let text = text.chars().take(MAX_LENGTH).collect();
+                            self.connections[1 - i].send(&Message::Chat { text });
+                            continue;
+                        }
+                        message => {
+                            warn!("Match {}: unexpected message from {}: {message:?}", self.id, self.names[i]);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = self.play(i, tick, &garbage, &inputs) {
+                        warn!("Match {}: {} forfeits, {e}", self.id, self.names[i]);
+                        self.finish(Outcome::Winner(1 - i));
+                        return;
+                    }
+                    let watched = Message::Watched { player: i, tick, garbage: garbage.clone(), inputs: inputs.clone() };
+                    self.history.push((Instant::now(), watched));
+                    self.connections[1 - i].send(&Message::Tick { tick, garbage, inputs });
+                }
+            }
+            self.update_spectators();
+            if let Some(outcome) = decide_match(self.rules.race, [&self.sides[0].board, &self.sides[1].board], self.topped_out, self.ticks) {
+                break outcome;
+            }
+            for i in 0..2 {
+                if self.connections[i].is_closed() && self.dropped[i].is_none() {
+                    info!("Match {}: {} lost their connection", self.id, self.names[i]);
+                    self.dropped[i] = Some(Instant::now());
+                    self.connections[1 - i].send(&Message::Dropped);
+                }
+            }
+            // Whoever leaves before it's over and doesn't come back gives up
+            if let Some(i) = (0..2).find(|&i| self.dropped[i].is_some_and(|since| since.elapsed() >= RECONNECT_GRACE)) {
+                info!("Match {}: {} left", self.id, self.names[i]);
+                break Outcome::Winner(1 - i);
+            }
+            if self.dropped.iter().all(Option::is_some) {
+                info!("Match {}: both players left", self.id);
+                break Outcome::Draw;
+            }
+            if idle {
+                thread::sleep(POLL_INTERVAL);
+            }
+        };
+        self.finish(outcome);
+    }
+    /// Plays player `i`'s tick, if it could have happened.
+    fn play(&mut self, i: usize, tick: u32, garbage: &[(u32, usize)], inputs: &[(Action, bool)]) -> Result<(), String> {
+        if self.topped_out[i].is_some() {
+            return Err(format!("tick {tick} after topping out"));
+        }
+        self.paces[i].check(tick, self.sides[i].board.game.ms_per_tick(), inputs)?;
+        let sent = play_tick(&mut self.sides[i], &mut self.ticks[i], &mut self.pending[i], tick, garbage, inputs, &self.rules)?;
+        if !self.rules.race {
+            self.pending[1 - i].extend(sent);
+        }
+        if self.sides[i].board.game.gameover {
+            self.topped_out[i] = Some(tick);
+        }
+        Ok(())
+    }
+    /// Puts player `token` is for back into the match on `connection`, with the boards as they are here.
+    fn rejoin(&mut self, token: u64, mut connection: Connection) {
+        let Some(i) = self.tokens.iter().position(|&t| t == token) else {
+            return;
+        };
+        info!("Match {}: {} is back", self.id, self.names[i]);
+        let sides = Box::new([self.sides[i].snapshot(), self.sides[1 - i].snapshot()]);
+        connection.send(&Message::Resync {
+            rules: self.rules.clone(),
+            player: i,
+            opponent: (self.names[1 - i].clone(), self.handling[1 - i]),
+            sides,
+            ticks: [self.ticks[i], self.ticks[1 - i]],
+            incoming: self.pending[i].clone(),
+        });
+        // Whatever the old connection still had to say never reached the boards here, so it is dropped with it
+        self.connections[i] = connection;
+        self.dropped[i] = None;
+        self.connections[1 - i].send(&Message::Rejoined);
+    }
+    fn welcome(&mut self, connection: Connection) {
+        let players = [0, 1].map(|i| (self.names[i].clone(), self.handling[i]));
+        let mut spectator = Spectator { connection, sent: 0 };
+        spectator.connection.send(&Message::Spectate { seed: self.seed, rules: self.rules.clone(), players });
+        self.spectators.push(spectator);
+    }
+    /// Welcomes those who have asked to watch or are getting back into the match,
+    /// and sends everyone watching the ticks that are old enough.
+    fn update_spectators(&mut self) {
+        for joining in self.joined.try_iter().collect::<Vec<_>>() {
+            match joining {
+                Joining::Spectator(connection) => self.welcome(connection),
+                Joining::Rejoin(token, connection) => self.rejoin(token, connection),
+            }
+        }
+        let shown = self.history.partition_point(|(played, _)| played.elapsed() >= SPECTATOR_DELAY);
+        for spectator in &mut self.spectators {
+            // What those watching say is of no interest, but reading it finds out when they have gone
+            while spectator.connection.receive().is_some() {}
+            let room = MAX_SPECTATOR_BACKLOG.saturating_sub(spectator.connection.queued());
+            let end = shown.min(spectator.sent + room);
+            for (_, message) in &self.history[spectator.sent..end] {
+                spectator.connection.send(message);
+            }
+            spectator.sent = end;
+        }
+        let id = self.id;
+        self.spectators.retain(|spectator| {
+            let open = !spectator.connection.is_closed();
+            if !open {
+                info!("Match {id}: a spectator has gone");
+            }
+            open
+        });
+    }
+    fn finish(mut self, outcome: Outcome) {
+        let winner = match outcome {
+            Outcome::Winner(i) => {
+                info!("Match {}: {} beat {} after {} ticks", self.id, self.names[i], self.names[1 - i], self.ticks[i]);
+                Some(i)
+            }
+            Outcome::Draw => {
+                info!("Match {}: {} and {} drew", self.id, self.names[0], self.names[1]);
+                None
+            }
+        };
+        for connection in &mut self.connections {
+            connection.send(&Message::Result { winner });
+        }
+        // Those watching find out once they have seen the end
+        while self.spectators.iter().any(|spectator| spectator.sent < self.history.len()) {
+            thread::sleep(POLL_INTERVAL);
+            self.update_spectators();
+        }
+        // Dropping their connections hangs up on them once the result has gone out
+        for mut spectator in self.spectators {
+            spectator.connection.send(&Message::Result { winner });
+        }
+    }
+}
+
+/// Plays a player's tick `tick` on `side`, if it could have happened after the `ticks` it has played
+/// with `pending` garbage sent to it, returning the garbage it sends.
+fn play_tick(side: &mut Side, ticks: &mut u32, pending: &mut Vec<(u32, usize)>, tick: u32, garbage: &[(u32, usize)], inputs: &[(Action, bool)], rules: &RoomRules) -> Result<Option<(u32, usize)>, String> {
+    if tick != *ticks + 1 {
+        return Err(format!("tick {tick} out of order"));
+    }
+    if inputs.len() > MAX_INPUTS_PER_TICK {
+        return Err(format!("{} inputs in tick {tick}", inputs.len()));
+    }
+    if let Some((action, _)) = inputs.iter().find(|(action, _)| !matches!(action, Action::Left | Action::Right | Action::RotLeft | Action::RotRight | Action::SoftDrop | Action::HardDrop | Action::Hold)) {
+        return Err(format!("{action:?} in tick {tick}"));
+    }
+    // Garbage lands in the order it was sent, and only once it has been
+    if !pending.starts_with(garbage) {
+        return Err(format!("garbage {garbage:?} in tick {tick} that was never sent"));
+    }
+    pending.drain(..garbage.len());
+
+    for &(rows, hole) in garbage {
+        side.board.game.receive_garbage(rows, hole);
+    }
+    *ticks = tick;
+    Ok(side.tick(inputs, &rules.attack, rules.cancel))
+}
+
+/// Checks what a player says their board was after tick `tick` against the server's copy of it,
+/// which has played `ticks` ticks. Anything a player could only report by playing differently
+/// from the inputs they sent, like a higher score, is caught here.
+fn check_report(side: &Side, ticks: u32, tick: u32, score: u32, lines: u32, checksum: u64) -> Result<(), String> {
+    let game = &side.board.game;
+    if tick != ticks {
+        return Err(format!("checksum of tick {tick} after tick {ticks}"));
+    }
+    if (score, lines) != (game.score, game.lines) {
+        return Err(format!("{score} points and {lines} lines at tick {tick} instead of {} and {}", game.score, game.lines));
+    }
+    if checksum != game.state_checksum() {
+        return Err(format!("a different board at tick {tick}"));
+    }
+    Ok(())
+}
+
+/// A battle royale the server plays out itself, like a `ServerMatch` between more players.
+/// Where the garbage each player sends goes is picked here by their targeting, and the order
+/// they top out in, as the server sees it, decides their places.
+struct ServerRoyale {
+    id: u32,
+    connections: Vec<Connection>,
+    names: Vec<String>,
+    sides: Vec<Side>,
+    rules: RoomRules,
+    ticks: Vec<u32>,
+    paces: Vec<Pace>,
+    /// Garbage sent to each board that the player has not yet said landed.
+    pending: Vec<Vec<(u32, usize)>>,
+    targeting: Vec<Targeting>,
+    /// Who last sent garbage to each player, who gets the badge if they top out.
+    attacked_by: Vec<Option<usize>>,
+    badges: Vec<u32>,
+    /// The place each player came in, once they are out.
+    places: Vec<Option<usize>>,
+    /// Picks targets, when the targeting leaves it to chance.
+    rng: Rand32,
+}
+
+impl ServerRoyale {
+    fn new(id: u32, players: Vec<(Connection, String, Handling)>, rules: RoomRules) -> Self {
+        let seed = random_seed();
+        let config = GameConfig::default();
+        let count = players.len();
+        let list: Vec<(String, Handling)> = players.iter().map(|(_, name, handling)| (name.clone(), *handling)).collect();
+        info!("Battle royale {id}: {} with seed {seed}", list.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", "));
+        let mut connections = Vec::with_capacity(count);
+        for (player, (mut connection, _, _)) in players.into_iter().enumerate() {
+            connection.send(&Message::RoyaleStart { seed, rules: rules.clone(), player, players: list.clone() });
+            connections.push(connection);
+        }
+        ServerRoyale {
+            id,
+            connections,
+            sides: list.iter().enumerate().map(|(i, (_, handling))| Side::new(seed, i, rules.mode(), rules.cap(*handling), &config, Handicap::default())).collect(),
+            names: list.into_iter().map(|(name, _)| name).collect(),
+            rules,
+            ticks: vec![0; count],
+            paces: (0..count).map(|_| Pace::new()).collect(),
+            pending: vec![Vec::new(); count],
+            targeting: vec![Targeting::default(); count],
+            attacked_by: vec![None; count],
+            badges: vec![0; count],
+            places: vec![None; count],
+            rng: Rand32::new(seed),
+        }
+    }
+    fn alive(&self) -> Vec<bool> {
+        self.places.iter().map(Option::is_none).collect()
+    }
+    fn run(mut self) {
+        while self.places.iter().filter(|place| place.is_none()).count() > 1 {
+            let mut idle = true;
+            for i in 0..self.connections.len() {
+                while let Some(message) = self.connections[i].receive() {
+                    idle = false;
+                    match message {
+                        Message::Tick { tick, garbage, inputs } if self.places[i].is_none() => {
+                            if let Err(e) = self.play(i, tick, &garbage, &inputs) {
+                                self.forfeit(i, e);
+                                break;
+                            }
+                            let watched = Message::Watched { player: i, tick, garbage, inputs };
+                            for (j, connection) in self.connections.iter_mut().enumerate() {
+                                if j != i {
+                                    connection.send(&watched);
+                                }
+                            }
+                            if self.sides[i].board.game.gameover {
+                                self.knock_out(i, self.attacked_by[i]);
+                            }
+                        }
+                        Message::Checksum { tick, score, lines, checksum } if self.places[i].is_none() => {
+                            if let Err(e) = check_report(&self.sides[i], self.ticks[i], tick, score, lines, checksum) {
+                                self.forfeit(i, e);
+                                break;
+                            }
+                        }
+                        // The rest of the game of a player who is already out
+                        Message::Tick { .. } | Message::Checksum { .. } => (),
+                        Message::Target { targeting } => self.targeting[i] = targeting,
+                        message => warn!("Battle royale {}: unexpected message from {}: {message:?}", self.id, self.names[i]),
+                    }
+                }
+                if self.connections[i].is_closed() && self.places[i].is_none() {
+                    info!("Battle royale {}: {} left", self.id, self.names[i]);
+                    self.knock_out(i, None);
+                }
+            }
+            if idle {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        let winner = self.places.iter().position(Option::is_none);
+        match winner {
+            Some(i) => info!("Battle royale {}: {} wins with {} badges", self.id, self.names[i], self.badges[i]),
+            None => info!("Battle royale {}: nobody wins", self.id),
+        }
+        for connection in &mut self.connections {
+            connection.send(&Message::Result { winner });
+        }
+    }
+    /// Plays player `i`'s tick, if it could have happened, sending the garbage it sends on to its target.
+    fn play(&mut self, i: usize, tick: u32, garbage: &[(u32, usize)], inputs: &[(Action, bool)]) -> Result<(), String> {
+        self.paces[i].check(tick, self.sides[i].board.game.ms_per_tick(), inputs)?;
+        let sent = play_tick(&mut self.sides[i], &mut self.ticks[i], &mut self.pending[i], tick, garbage, inputs, &self.rules)?;
+        let Some((rows, hole)) = sent else {
+            return Ok(());
+        };
+        let alive = self.alive();
+        if let Some(target) = pick_target(i, self.targeting[i], &alive, &self.badges, self.attacked_by[i], &mut self.rng) {
+            self.pending[target].push((rows, hole));
+            self.attacked_by[target] = Some(i);
+            self.connections[target].send(&Message::Attack { from: i, rows, hole });
+        }
+        Ok(())
+    }
+    /// Takes player `i` out of the game for `e`. Nothing more they send can count, so they are sent away.
+    fn forfeit(&mut self, i: usize, e: String) {
+        warn!("Battle royale {}: {} forfeits, {e}", self.id, self.names[i]);
+        self.knock_out(i, None);
+        self.connections[i].send(&Message::Refused { reason: format!("The server stopped your game: {e}") });
+        self.connections[i].close();
+    }
+    /// Takes player `i` out of the game, with a badge for whoever did it.
+    fn knock_out(&mut self, i: usize, by: Option<usize>) {
+        let place = self.places.iter().filter(|place| place.is_none()).count();
+        self.places[i] = Some(place);
+        let by = by.filter(|&by| by != i);
+        if let Some(by) = by {
+            self.badges[by] += 1;
+        }
+        info!("Battle royale {}: {} came in #{place}", self.id, self.names[i]);
+        for connection in &mut self.connections {
+            connection.send(&Message::KnockedOut { player: i, place, by });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presses(action: Action, count: usize) -> Vec<(Action, bool)> {
+        (0..count).flat_map(|_| [(action, true), (action, false)]).collect()
+    }
+
+    #[test]
+    fn ticks_from_the_future_are_refused() {
+        let mut pace = Pace::new();
+        assert!(pace.check(1, 40, &[]).is_ok());
+        assert!(pace.check(TICK_LEAD.as_millis() as u32 / 40 + 10, 40, &[]).is_err());
+    }
+
+    #[test]
+    fn too_many_presses_in_a_tick() {
+        assert!(Pace::new().check(0, 40, &presses(Action::RotRight, MAX_PRESSES_PER_TICK)).is_ok());
+        assert!(Pace::new().check(0, 40, &presses(Action::RotRight, MAX_PRESSES_PER_TICK + 1)).is_err());
+    }
+
+    #[test]
+    fn swiping_across_the_board_is_not_too_fast() {
+        let mut pace = Pace::new();
+        for _ in 0..PRESS_WINDOW {
+            assert!(pace.check(0, 40, &presses(Action::Left, 12)).is_ok());
+        }
+    }
+
+    #[test]
+    fn too_many_presses_in_a_second() {
+        let mut pace = Pace::new();
+        let each = MAX_PRESSES_PER_WINDOW / PRESS_WINDOW + 1;
+        let ok = (0..PRESS_WINDOW).map(|_| pace.check(0, 40, &presses(Action::HardDrop, each))).take_while(Result::is_ok).count();
+        assert!(ok < PRESS_WINDOW);
+        // Presses older than the window stop counting
+        let mut pace = Pace::new();
+        for _ in 0..3 * PRESS_WINDOW {
+            assert!(pace.check(0, 40, &presses(Action::HardDrop, 1)).is_ok());
+        }
+    }
+
+    /// A side of a match by the usual rules, after `ticks` ticks of soft dropping.
+    fn played(ticks: u32) -> Side {
+        let rules = RoomRules::default();
+        let mut side = Side::new(5, 0, rules.mode(), Handling::default(), &GameConfig::default(), Handicap::default());
+        let (mut played, mut pending) = (0, Vec::new());
+        for tick in 1..=ticks {
+            play_tick(&mut side, &mut played, &mut pending, tick, &[], &[(Action::SoftDrop, tick % 2 == 1)], &rules).unwrap();
+        }
+        side
+    }
+
+    #[test]
+    fn the_right_report_is_taken() {
+        let side = played(30);
+        let game = &side.board.game;
+        assert_eq!(check_report(&side, 30, 30, game.score, game.lines, game.state_checksum()), Ok(()));
+    }
+
+    #[test]
+    fn reports_that_dont_match_are_refused() {
+        let side = played(30);
+        let game = &side.board.game;
+        let checksum = game.state_checksum();
+        assert!(check_report(&side, 30, 29, game.score, game.lines, checksum).is_err());
+        assert!(check_report(&side, 30, 30, game.score + 100, game.lines, checksum).is_err());
+        assert!(check_report(&side, 30, 30, game.score, game.lines + 1, checksum).is_err());
+        assert!(check_report(&side, 30, 30, game.score, game.lines, checksum ^ 1).is_err());
+        // A board that went differently has a checksum of its own
+        assert_ne!(played(31).board.game.state_checksum(), checksum);
+    }
+
+    #[test]
+    fn ticks_that_couldnt_have_happened_are_refused() {
+        let rules = RoomRules::default();
+        let mut side = played(0);
+        let (mut ticks, mut pending) = (0, vec![(1, 3)]);
+        assert!(play_tick(&mut side, &mut ticks, &mut pending, 2, &[], &[], &rules).is_err());
+        assert!(play_tick(&mut side, &mut ticks, &mut pending, 1, &[(2, 3)], &[], &rules).is_err());
+        assert!(play_tick(&mut side, &mut ticks, &mut pending, 1, &[], &[(Action::Undo, true)], &rules).is_err());
+        assert_eq!(ticks, 0);
+        assert!(play_tick(&mut side, &mut ticks, &mut pending, 1, &[(1, 3)], &[(Action::Left, true)], &rules).is_ok());
+        assert_eq!((ticks, pending.len()), (1, 0));
+    }
+}
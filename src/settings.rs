@@ -0,0 +1,221 @@
+use ggez::{
+    graphics::{Color, Rect},
+    input::keyboard::{KeyCode, KeyMods},
+};
+
+use crate::{
+    config::Config,
+    input::{Keybindings, Preset},
+    match_replay::MatchReplay,
+    replay::Replay,
+    stats::Stats,
+    storage::Storage,
+    render::{Renderer, SCREEN_SIZE},
+};
+
+const NUM_ITEMS: usize = 18;
+const WATCH_REPLAY: usize = 11;
+const STATISTICS: usize = 12;
+const THEME: usize = 13;
+const INSTALL_THEMES: usize = 14;
+const EXPORT_THEME: usize = 15;
+const CLIP_RECORDER: usize = 16;
+const SEED: usize = 17;
+
+/// Navigating a menu, from whichever input device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuInput {
+    Up,
+    Down,
+    /// Changes the selected setting by the given step.
+    Adjust(i8),
+    Confirm,
+    Back,
+    NewProfile,
+}
+
+impl MenuInput {
+    pub fn from_key(keycode: KeyCode, mods: KeyMods, bindings: &Keybindings) -> Option<Self> {
+        let step = if mods.contains(KeyMods::SHIFT) { 10 } else { 1 };
+        Some(if bindings.menu_back.contains(&keycode) || bindings.pause.contains(&keycode) {
+            MenuInput::Back
+        } else if bindings.menu_confirm.contains(&keycode) {
+            MenuInput::Confirm
+        } else {
+            match keycode {
+                KeyCode::W | KeyCode::Up => MenuInput::Up,
+                KeyCode::S | KeyCode::Down => MenuInput::Down,
+                KeyCode::A | KeyCode::Left => MenuInput::Adjust(-step),
+                KeyCode::D | KeyCode::Right => MenuInput::Adjust(step),
+                KeyCode::N => MenuInput::NewProfile,
+                _ => return None,
+            }
+        })
+    }
+}
+
+/// What to do after the settings menu has handled an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuResult {
+    Open,
+    Close,
+    /// Watch the replay with this storage key.
+    WatchReplay(String),
+    ExportStats,
+    /// Install the packed themes put in the themes directory.
+    InstallThemes,
+    /// Pack the current theme for sharing.
+    ExportTheme,
+    /// Switch between random seeds and the seed of the game being played.
+    ToggleSeed,
+}
+
+/// The in-game settings menu, opened with the pause key. The game is paused while it is open.
+#[derive(Debug)]
+pub struct SettingsMenu {
+    selected: usize,
+    replays: Vec<String>,
+    replay: usize,
+    /// Whether the statistics screen is shown instead of the settings.
+    showing_stats: bool,
+    /// The installed themes.
+    pub themes: Vec<String>,
+    /// Shown at the bottom of the menu, e.g. where something was exported to.
+    pub message: Option<String>,
+    /// The seed new games are started with, or `None` for a random one each time.
+    pub seed: Option<u64>,
+}
+
+impl SettingsMenu {
+    pub fn new(storage: &dyn Storage, profile_dir: &str, themes: Vec<String>, seed: Option<u64>) -> Self {
+        SettingsMenu {
+            selected: 0,
+            replays: Replay::list(storage, profile_dir).into_iter().chain(MatchReplay::list(storage, profile_dir)).collect(),
+            replay: 0,
+            showing_stats: false,
+            themes,
+            message: None,
+            seed,
+        }
+    }
+    /// Handles navigating the menu.
+    pub fn input(&mut self, input: MenuInput, config: &mut Config) -> MenuResult {
+        if self.showing_stats {
+            match input {
+                MenuInput::Back => self.showing_stats = false,
+                MenuInput::Confirm => return MenuResult::ExportStats,
+                _ => (),
+            }
+            return MenuResult::Open;
+        }
+        match input {
+            MenuInput::Back => return MenuResult::Close,
+            MenuInput::Up => self.selected = (self.selected + NUM_ITEMS - 1) % NUM_ITEMS,
+            MenuInput::Down => self.selected = (self.selected + 1) % NUM_ITEMS,
+            MenuInput::Confirm if self.selected == WATCH_REPLAY => {
+                if let Some(path) = self.replays.get(self.replay) {
+                    return MenuResult::WatchReplay(path.clone());
+                }
+            }
+            MenuInput::Confirm if self.selected == STATISTICS => self.showing_stats = true,
+            MenuInput::Confirm if self.selected == INSTALL_THEMES => return MenuResult::InstallThemes,
+            MenuInput::Confirm if self.selected == EXPORT_THEME => return MenuResult::ExportTheme,
+            MenuInput::Confirm if self.selected == SEED => return MenuResult::ToggleSeed,
+            MenuInput::Adjust(step) => self.adjust(config, step),
+            MenuInput::Confirm => self.adjust(config, 1),
+            MenuInput::NewProfile if self.selected == 1 => config.new_profile(),
+            MenuInput::NewProfile => (),
+        }
+        MenuResult::Open
+    }
+    fn adjust(&mut self, config: &mut Config, delta: i8) {
+        let profile = config.profile_mut();
+        let handling = &mut profile.handling;
+        match self.selected {
+            0 => config.mode = config.mode.cycle(delta > 0),
+            1 => config.cycle_profile(delta > 0),
+            2 => {
+                let preset = Preset::matching(&profile.bindings).map_or(Preset::ALL[0], |p| p.cycle(delta > 0));
+                profile.bindings = preset.bindings();
+            }
+            3 => handling.das = handling.das.saturating_add_signed(delta as i32),
+            4 => handling.arr = handling.arr.saturating_add_signed(delta as i32),
+            5 => handling.sdf = handling.sdf.saturating_add_signed(delta).max(1),
+            6 => handling.misdrop_guard = handling.misdrop_guard.saturating_add_signed(delta as i32),
+            7 => profile.stick.deadzone = (profile.stick.deadzone + 0.01 * delta as f32).clamp(0., 0.95),
+            8 => config.touch_buttons = !config.touch_buttons,
+            9 => config.key_overlay = !config.key_overlay,
+            10 => config.hints = !config.hints,
+            WATCH_REPLAY => {
+                let n = self.replays.len().max(1);
+                self.replay = (self.replay + if delta > 0 { 1 } else { n - 1 }) % n;
+            }
+            THEME => config.cycle_theme(&self.themes, delta > 0),
+            CLIP_RECORDER => config.clip_recorder = !config.clip_recorder,
+            SEED => {
+                if let Some(seed) = &mut self.seed {
+                    *seed = seed.wrapping_add_signed(delta as i64);
+                }
+            }
+            _ => (),
+        }
+    }
+    pub fn draw(&self, r: &mut dyn Renderer, config: &Config, stats: &Stats) {
+        let handling = &config.profile().handling;
+        r.draw_panel(Rect::new(0., 0., SCREEN_SIZE.0, SCREEN_SIZE.1), Color::new(0., 0., 0., 0.8));
+        if self.showing_stats {
+            stats.draw(r, (64., 64.));
+            if let Some(message) = &self.message {
+                r.draw_text(message, 20., [64., SCREEN_SIZE.1 - 96.], Color::WHITE);
+            }
+            r.draw_text("Confirm: export as JSON and CSV  Back: return", 16., [64., SCREEN_SIZE.1 - 48.], Color::WHITE);
+            return;
+        }
+
+        r.draw_text("Settings", 48., [64., 64.], Color::WHITE);
+
+        let items = [
+            format!("Mode: {}", config.mode),
+            format!("Profile: {} (N: new)", config.profile),
+            format!("Controls: {}", Preset::matching(&config.profile().bindings).map_or("Custom", Preset::name)),
+            format!("DAS: {} ms", handling.das),
+            format!("ARR: {} ms", handling.arr),
+            format!("SDF: {}x", handling.sdf),
+            match handling.misdrop_guard {
+                0 => "Misdrop guard: off".to_owned(),
+                ms => format!("Misdrop guard: {ms} ms"),
+            },
+            format!("Stick deadzone: {:.2}", config.profile().stick.deadzone),
+            format!("Touch buttons: {}", if config.touch_buttons { "on" } else { "off" }),
+            format!("Key overlay: {}", if config.key_overlay { "on" } else { "off" }),
+            format!("Placement hints in Practice: {}", if config.hints { "on" } else { "off" }),
+            match self.replays.get(self.replay).and_then(|key| key.rsplit('/').next()) {
+                Some(name) => format!("Watch replay: {}", name.trim_end_matches(".replay").trim_end_matches(".match")),
+                None => "Watch replay: none saved".to_owned(),
+            },
+            "Statistics".to_owned(),
+            format!("Theme: {}", config.theme.as_deref().unwrap_or("classic")),
+            "Install themes".to_owned(),
+            "Export theme".to_owned(),
+            format!("Clip recorder (F9: save): {}", if config.clip_recorder { "on" } else { "off" }),
+            match self.seed {
+                Some(seed) => format!("Seed: {seed}"),
+                None => "Seed: random (Confirm: keep this game's)".to_owned(),
+            },
+        ];
+        for (i, item) in items.into_iter().enumerate() {
+            let colour = if i == self.selected { Color::YELLOW } else { Color::WHITE };
+            r.draw_text(&item, 32., [64., 160. + 40. * i as f32], colour);
+        }
+        if let Some(message) = &self.message {
+            r.draw_text(message, 20., [64., SCREEN_SIZE.1 - 88.], Color::WHITE);
+        }
+
+        r.draw_text(
+            "Up/Down: select  Left/Right: adjust (Shift: x10)  Back: resume",
+            16.,
+            [64., SCREEN_SIZE.1 - 48.],
+            Color::WHITE,
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ggez::{
+    graphics::Color,
+    GameError, GameResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{mode::Mode, render::Renderer, rules::{Efficiency, GameEvent}, storage::Storage, versioned::{self, Versioned}};
+
+const STATS_FILE: &str = "stats.json";
+
+/// How a single game went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub mode: Mode,
+    pub score: u32,
+    pub lines: u32,
+    pub pieces: u32,
+    pub ms: u32,
+    /// When the game was played, in seconds since the Unix epoch.
+    pub date: u64,
+    #[serde(default)]
+    pub efficiency: Efficiency,
+}
+
+impl GameRecord {
+    fn pps(&self) -> f32 {
+        if self.ms == 0 {
+            0.
+        } else {
+            self.pieces as f32 * 1000. / self.ms as f32
+        }
+    }
+}
+
+/// `count` and how many that is for every 100 of `pieces`, for counts that only mean something next to how much was played.
+pub fn per_100(count: u64, pieces: u64) -> String {
+    match (count * 100).checked_div(pieces) {
+        Some(rate) => format!("{count} ({rate} per 100 pieces)"),
+        None => count.to_string(),
+    }
+}
+
+/// Totals over every game played, kept in the profile's directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    pub games: u32,
+    pub pieces: u64,
+    pub lines: u64,
+    pub tetrises: u32,
+    pub t_spins: u32,
+    pub playtime_ms: u64,
+    /// The most pieces per second placed over a whole game.
+    pub best_pps: f32,
+    /// Placements that covered up empty cells, over every game.
+    pub misdrops: u64,
+    /// Turns beyond the fewest needed, over every game.
+    pub wasted_rotations: u64,
+    /// Every game played, oldest first.
+    pub history: Vec<GameRecord>,
+}
+
+/// Version 1 is the same as the unversioned statistics before it.
+impl Versioned for Stats {
+    const VERSION: u32 = 1;
+}
+
+impl Stats {
+    pub fn load(storage: &dyn Storage, dir: &str) -> GameResult<Self> {
+        Ok(versioned::load(storage, &format!("{dir}/{STATS_FILE}"))?.unwrap_or_default())
+    }
+    pub fn save(&self, storage: &dyn Storage, dir: &str) -> GameResult {
+        versioned::save(storage, &format!("{dir}/{STATS_FILE}"), self, true)
+    }
+    pub fn record(&mut self, event: GameEvent) {
+        match event {
+            GameEvent::PieceLocked { lines, t_spin } => {
+                self.pieces += 1;
+                self.lines += lines as u64;
+                self.tetrises += (lines == 4) as u32;
+                self.t_spins += t_spin as u32;
+            }
+            GameEvent::GameOver { mode, score, lines, pieces, ms, efficiency } => {
+                let date = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                let record = GameRecord { mode, score, lines, pieces, ms, date, efficiency };
+                self.games += 1;
+                self.playtime_ms += ms as u64;
+                self.misdrops += efficiency.misdrops as u64;
+                self.wasted_rotations += efficiency.wasted_rotations as u64;
+                self.best_pps = self.best_pps.max(record.pps());
+                self.history.push(record);
+            }
+        }
+    }
+    /// The statistics for use elsewhere, as CSV for a file name ending in `.csv` and JSON otherwise.
+    /// The CSV has a row for each game followed by one with the lifetime totals.
+    pub fn export(&self, file_name: &str) -> GameResult<String> {
+        Ok(if file_name.ends_with(".csv") {
+            let mut csv = "mode,date,score,lines,pieces,ms,pps,misdrops,wasted_rotations\n".to_owned();
+            for game in &self.history {
+                let (mode, date) = (game.mode.key(), game.date);
+                let (score, lines, pieces, ms) = (game.score, game.lines, game.pieces, game.ms);
+                let Efficiency { misdrops, wasted_rotations } = game.efficiency;
+                let _ = writeln!(csv, "{mode},{date},{score},{lines},{pieces},{ms},{:.3},{misdrops},{wasted_rotations}", game.pps());
+            }
+            let total_score: u64 = self.history.iter().map(|game| game.score as u64).sum();
+            let pps = if self.playtime_ms == 0 { 0. } else { self.pieces as f64 * 1000. / self.playtime_ms as f64 };
+            let _ = writeln!(
+                csv,
+                "total,,{total_score},{},{},{},{pps:.3},{},{}",
+                self.lines, self.pieces, self.playtime_ms, self.misdrops, self.wasted_rotations
+            );
+            csv
+        } else {
+            serde_json::to_string_pretty(self).map_err(|e| GameError::CustomError(e.to_string()))?
+        })
+    }
+    pub fn draw(&self, r: &mut dyn Renderer, (x, y): (f32, f32)) {
+        r.draw_text("Statistics", 48., [x, y], Color::WHITE);
+
+        let secs = self.playtime_ms / 1000;
+        let lines = [
+            format!("Games played: {}", self.games),
+            format!("Play time: {}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+            format!("Pieces placed: {}", self.pieces),
+            format!("Lines cleared: {}", self.lines),
+            format!("Tetrises: {}", self.tetrises),
+            format!("T-spins: {}", self.t_spins),
+            format!("Best PPS: {:.2}", self.best_pps),
+            format!("Misdrops: {}", per_100(self.misdrops, self.pieces)),
+            format!("Wasted rotations: {}", per_100(self.wasted_rotations, self.pieces)),
+        ];
+        for (i, line) in lines.into_iter().enumerate() {
+            r.draw_text(&line, 32., [x, y + 96. + 48. * i as f32], Color::WHITE);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    io::{self, BufRead, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ggez::{
+    conf::WindowSetup,
+    event::{self, EventHandler},
+    graphics::{Canvas, Color, Rect},
+    Context, GameResult,
+};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grid::Pos,
+    input::HeldActions,
+    overlay,
+    piece::Piece,
+    render::{cell_rect, draw_piece, fit, window_mode, Renderer, Shifted, Viewport},
+    rules::Game,
+    scores::format_time,
+    theme::Theme,
+};
+
+/// How big the stats window is laid out as, in pixels. It is scaled to fit if it is resized.
+const WINDOW_SIZE: (f32, f32) = (320., 480.);
+/// The background, for streaming software to key out.
+const CHROMA_KEY: Color = Color::new(0., 1., 0., 1.);
+/// How often the game is sent to the window at most.
+const SEND_INTERVAL: Duration = Duration::from_millis(33);
+
+/// What the stats window shows of the game being played, sent to it as a line of JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub mode: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    pub ms: u32,
+    pub pieces: u32,
+    pub next: Piece,
+    pub hold: Option<Piece>,
+    pub held: HeldActions,
+}
+
+impl Snapshot {
+    pub fn of(game: &Game) -> Self {
+        Snapshot {
+            mode: game.mode.to_string(),
+            score: game.score,
+            lines: game.lines,
+            level: game.level(),
+            ms: game.ms(),
+            pieces: game.pieces,
+            next: game.next_piece,
+            hold: game.hold_piece,
+            held: game.held.clone(),
+        }
+    }
+}
+
+/// A second window showing the stats of the game, for streaming, opened when it is turned on in the config.
+/// ggez can only open one window, so it is another run of the game started with `--stats-window`,
+/// which is sent the game over its standard input and quits once that is closed.
+#[derive(Debug)]
+pub struct StatsWindow {
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    sent: Option<(Snapshot, Instant)>,
+}
+
+impl StatsWindow {
+    /// Opens the window, or logs why it couldn't be.
+    pub fn open() -> Self {
+        let child = std::env::current_exe()
+            .and_then(|exe| Command::new(exe).arg("--stats-window").stdin(Stdio::piped()).spawn())
+            .map_err(|e| warn!("Could not open the stats window: {e}"))
+            .ok();
+        let mut window = StatsWindow { child, stdin: None, sent: None };
+        window.stdin = window.child.as_mut().and_then(|child| child.stdin.take());
+        if window.stdin.is_some() {
+            info!("Opened the stats window");
+        }
+        window
+    }
+    /// Shows `snapshot` in the window, unless the last one was sent too recently.
+    pub fn update(&mut self, snapshot: Snapshot) {
+        let due = match &self.sent {
+            None => true,
+            Some((sent, at)) => *sent != snapshot && at.elapsed() >= SEND_INTERVAL,
+        };
+        let Some(stdin) = self.stdin.as_mut().filter(|_| due) else {
+            return;
+        };
+        let mut line = serde_json::to_string(&snapshot).expect("Snapshots can always be written");
+        line.push('\n');
+        if let Err(e) = stdin.write_all(line.as_bytes()).and_then(|()| stdin.flush()) {
+            // Most likely the window was closed, and it stays closed until turned off and on again
+            info!("The stats window is gone: {e}");
+            self.stdin = None;
+        }
+        self.sent = Some((snapshot, Instant::now()));
+    }
+}
+
+impl Drop for StatsWindow {
+    fn drop(&mut self) {
+        // Closing its input is the window's cue to close, but it shouldn't be left open if it doesn't
+        self.stdin = None;
+        if let Some(child) = &mut self.child {
+            if let Err(e) = child.kill() {
+                debug!("Could not close the stats window: {e}");
+            }
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Runs the stats window itself, showing what comes in on standard input until it is closed.
+pub fn run() -> GameResult {
+    let (ctx, events_loop) = ggez::ContextBuilder::new("tetris", "Falch")
+        .window_setup(WindowSetup::default().title("Tetris stats"))
+        .window_mode(window_mode(WINDOW_SIZE))
+        .build()?;
+    let (sender, snapshots) = mpsc::channel();
+    thread::Builder::new().name("stats window".to_owned()).spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            // Anything else is skipped, as nobody sees the log of this process
+            if let Ok(snapshot) = serde_json::from_str(&line) {
+                if sender.send(snapshot).is_err() {
+                    break;
+                }
+            }
+        }
+    })?;
+    event::run(ctx, events_loop, Viewer { snapshots, shown: None, theme: Theme::default() })
+}
+
+struct Viewer {
+    snapshots: Receiver<Snapshot>,
+    shown: Option<Snapshot>,
+    theme: Theme,
+}
+
+impl Viewer {
+    /// Draws `piece` with the top left corner of its cells at `(x, y)`.
+    fn draw_piece_at(&self, r: &mut dyn Renderer, piece: &Piece, (x, y): (f32, f32)) {
+        let origin = cell_rect(0., 0.);
+        draw_piece(&mut Shifted { inner: r, by: [x - origin.x, y - origin.y] }, piece, Pos::new(0, 0), &self.theme);
+    }
+    fn draw_snapshot(&self, r: &mut dyn Renderer, snapshot: &Snapshot) {
+        r.draw_text(&snapshot.mode, 28., [16., 16.], Color::WHITE);
+        let pps = if snapshot.ms == 0 { 0. } else { snapshot.pieces as f32 * 1000. / snapshot.ms as f32 };
+        let lines = [
+            format!("Score: {}", snapshot.score),
+            format!("Lines: {}", snapshot.lines),
+            format!("Level: {}", snapshot.level),
+            format!("Time: {}", format_time(snapshot.ms)),
+            format!("PPS: {pps:.2}"),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            r.draw_text(line, 20., [16., 60. + 28. * i as f32], Color::WHITE);
+        }
+        r.draw_text("Next", 20., [16., 208.], Color::WHITE);
+        self.draw_piece_at(r, &snapshot.next, (16., 240.));
+        r.draw_text("Hold", 20., [176., 208.], Color::WHITE);
+        if let Some(hold) = &snapshot.hold {
+            self.draw_piece_at(r, hold, (176., 240.));
+        }
+        overlay::draw_keys(r, &snapshot.held, [16., 352.]);
+    }
+}
+
+impl EventHandler for Viewer {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        loop {
+            match self.snapshots.try_recv() {
+                Ok(snapshot) => self.shown = Some(snapshot),
+                Err(TryRecvError::Empty) => break,
+                // The game has closed the window
+                Err(TryRecvError::Disconnected) => {
+                    ctx.request_quit();
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut canvas = Canvas::from_frame(ctx, CHROMA_KEY);
+        let layout = Rect::new(0., 0., WINDOW_SIZE.0, WINDOW_SIZE.1);
+        let window = fit(layout, ctx.gfx.drawable_size());
+        {
+            let r = &mut Viewport::new(&mut canvas, layout, window);
+            match &self.shown {
+                Some(snapshot) => self.draw_snapshot(r, snapshot),
+                None => r.draw_text("Waiting for a game...", 20., [16., 16.], Color::WHITE),
+            }
+        }
+        canvas.finish(ctx)
+    }
+}
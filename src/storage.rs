@@ -0,0 +1,110 @@
+use std::{
+    fmt::Debug,
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use ggez::{filesystem::Filesystem, Context, GameResult};
+
+use crate::config::CONFIG_FILE;
+
+/// Where everything the game saves is kept, by key. Keys are `/`-separated paths like `profiles/default/scores.json`.
+pub trait Storage: Debug {
+    /// Reads what is stored at `key`, or `None` if nothing is.
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+    fn write(&self, key: &str, data: &[u8]) -> io::Result<()>;
+    /// Removes what is stored at `key`, if anything.
+    fn remove(&self, key: &str) -> io::Result<()>;
+    /// The keys directly inside `dir`.
+    fn list(&self, dir: &str) -> io::Result<Vec<String>>;
+    /// Where `key` is kept, to tell the player.
+    fn location(&self, key: &str) -> String;
+    /// When what is stored at `key` last changed, if that can be told, to notice it being edited outside the game.
+    fn modified(&self, _key: &str) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// Keeps everything in files, the config in the user config directory and the rest in the user data directory.
+#[derive(Debug)]
+pub struct FileStorage {
+    config_file: PathBuf,
+    data_dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(ctx: &Context) -> Self {
+        FileStorage {
+            config_file: ctx.fs.user_config_dir().join(CONFIG_FILE),
+            data_dir: ctx.fs.user_data_dir().to_owned(),
+        }
+    }
+    /// Keeps everything where the game does, for frontends that don't open a ggez window to ask.
+    pub fn without_window() -> GameResult<Self> {
+        let fs = Filesystem::new("tetris", "Falch", "resources", "resources.zip")?;
+        Ok(FileStorage {
+            config_file: fs.user_config_dir().join(CONFIG_FILE),
+            data_dir: fs.user_data_dir().to_owned(),
+        })
+    }
+    /// Keeps the config in `path` instead.
+    pub fn with_config_file(self, path: impl Into<PathBuf>) -> Self {
+        FileStorage {
+            config_file: path.into(),
+            ..self
+        }
+    }
+    fn path(&self, key: &str) -> PathBuf {
+        if key == CONFIG_FILE {
+            self.config_file.clone()
+        } else {
+            self.data_dir.join(key)
+        }
+    }
+}
+
+impl Storage for FileStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    fn write(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, data)
+    }
+    fn remove(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(self.path(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut keys = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                keys.push(format!("{dir}/{}", entry.file_name().to_string_lossy()));
+            }
+        }
+        Ok(keys)
+    }
+    fn location(&self, key: &str) -> String {
+        self.path(key).display().to_string()
+    }
+    fn modified(&self, key: &str) -> Option<SystemTime> {
+        fs::metadata(self.path(key)).and_then(|meta| meta.modified()).ok()
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use ggez::{graphics::Color, Context, GameError, GameResult};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use zip::{result::ZipError, write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::{piece::NUM_COLOURS, render::COLOURS};
+
+const THEMES_DIR: &str = "themes";
+const THEME_FILE: &str = "theme.toml";
+/// The extension of packed themes, which are zip archives.
+const ARCHIVE_EXTENSION: &str = "theme";
+/// Where exported themes go, inside the themes directory, so they aren't taken for ones to install.
+const EXPORT_DIR: &str = "exports";
+/// The files a theme may bring besides `theme.toml`, as the folder they go in and the extensions allowed there.
+const ASSETS: [(&str, &[&str]); 3] = [("", &["png"]), ("sprites", &["png"]), ("sounds", &["ogg", "wav", "flac"])];
+/// The largest file a theme may contain, to keep a broken archive from filling up the disk.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// How the game looks. Installed themes live in their own folder in the themes directory,
+/// with a `theme.toml` and any sprites, sounds and background image they come with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Theme {
+    /// The colours of the pieces, as RGB.
+    pub palette: Vec<[u8; 3]>,
+    /// The colour of empty cells.
+    pub background: [u8; 3],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::with_palette(&COLOURS)
+    }
+}
+
+fn zip_error(e: ZipError) -> GameError {
+    GameError::CustomError(format!("Invalid theme archive: {e}"))
+}
+
+impl Theme {
+    /// The classic theme with other colours for the pieces.
+    pub fn with_palette(palette: &[Color; NUM_COLOURS]) -> Self {
+        Theme {
+            palette: palette.iter().map(|c| <[u8; 3]>::from(c.to_rgb())).collect(),
+            background: [255, 0, 255],
+        }
+    }
+    pub fn colour(&self, i: usize) -> Color {
+        let [r, g, b] = self.palette[i];
+        Color::from_rgb(r, g, b)
+    }
+    pub fn background(&self) -> Color {
+        let [r, g, b] = self.background;
+        Color::from_rgb(r, g, b)
+    }
+    fn dir(ctx: &Context) -> PathBuf {
+        ctx.fs.user_data_dir().join(THEMES_DIR)
+    }
+    /// The names of the installed themes.
+    pub fn list(ctx: &Context) -> Vec<String> {
+        let mut names: Vec<_> = match fs::read_dir(Self::dir(ctx)) {
+            Ok(dir) => dir
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().join(THEME_FILE).is_file())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        names.sort();
+        names
+    }
+    /// Loads the installed theme called `name`, or the built-in one for `None`.
+    pub fn load(ctx: &Context, name: Option<&str>) -> GameResult<Self> {
+        let Some(name) = name else {
+            return Ok(Theme::default());
+        };
+        let theme: Theme = toml::from_str(&fs::read_to_string(Self::dir(ctx).join(name).join(THEME_FILE))?)?;
+        theme.validate()?;
+        Ok(theme)
+    }
+    /// When the installed theme called `name` was last changed, or `None` for the built-in one.
+    pub fn modified(ctx: &Context, name: Option<&str>) -> Option<SystemTime> {
+        let path = Self::dir(ctx).join(name?).join(THEME_FILE);
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+    fn validate(&self) -> GameResult {
+        if self.palette.len() != NUM_COLOURS {
+            return Err(GameError::CustomError(format!("A theme needs {NUM_COLOURS} colours in its palette")));
+        }
+        Ok(())
+    }
+
+    /// Packs the theme called `name` (the built-in one for `None`) into an archive for sharing,
+    /// returning its path.
+    pub fn export(&self, ctx: &Context, name: Option<&str>) -> GameResult<PathBuf> {
+        let name = name.unwrap_or("classic");
+        let dir = Self::dir(ctx);
+        fs::create_dir_all(dir.join(EXPORT_DIR))?;
+        let path = dir.join(EXPORT_DIR).join(name).with_extension(ARCHIVE_EXTENSION);
+        let mut zip = ZipWriter::new(File::create(&path)?);
+        zip.start_file(THEME_FILE, FileOptions::default()).map_err(zip_error)?;
+        zip.write_all(toml::to_string_pretty(self)?.as_bytes())?;
+        for (folder, extensions) in ASSETS {
+            let Ok(entries) = fs::read_dir(dir.join(name).join(folder)) else {
+                continue;
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let allowed = Path::new(&file_name)
+                    .extension()
+                    .is_some_and(|ext| extensions.iter().any(|&allowed| ext == allowed));
+                if !allowed || !entry.path().is_file() {
+                    continue;
+                }
+                let archived = if folder.is_empty() { file_name } else { format!("{folder}/{file_name}") };
+                zip.start_file(archived, FileOptions::default()).map_err(zip_error)?;
+                io::copy(&mut File::open(entry.path())?, &mut zip)?;
+            }
+        }
+        zip.finish().map_err(zip_error)?;
+        Ok(path)
+    }
+    /// Checks a packed theme and installs it under the archive's name, returning that name.
+    /// Nothing is installed unless the whole archive is valid.
+    pub fn import(ctx: &Context, archive: &Path) -> GameResult<String> {
+        let name = archive
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .ok_or_else(|| GameError::CustomError("A theme archive needs a name".to_owned()))?;
+        let mut zip = ZipArchive::new(File::open(archive)?).map_err(zip_error)?;
+
+        let mut files = Vec::new();
+        let mut theme = None;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).map_err(zip_error)?;
+            if file.is_dir() {
+                continue;
+            }
+            let Some(path) = file.enclosed_name().map(Path::to_owned) else {
+                return Err(GameError::CustomError(format!("{} is outside the theme", file.name())));
+            };
+            if file.size() > MAX_FILE_SIZE {
+                return Err(GameError::CustomError(format!("{} is too big", path.display())));
+            }
+            let mut contents = Vec::new();
+            file.by_ref().take(MAX_FILE_SIZE).read_to_end(&mut contents)?;
+            if path == Path::new(THEME_FILE) {
+                let parsed: Theme = toml::from_slice(&contents)?;
+                parsed.validate()?;
+                theme = Some(parsed);
+                continue;
+            }
+            let folder = path.parent().map_or(String::new(), |p| p.to_string_lossy().into_owned());
+            let allowed = ASSETS.iter().any(|&(f, extensions)| {
+                f == folder && path.extension().is_some_and(|ext| extensions.iter().any(|&allowed| ext == allowed))
+            });
+            if !allowed {
+                return Err(GameError::CustomError(format!("{} doesn't belong in a theme", path.display())));
+            }
+            files.push((path, contents));
+        }
+        let Some(theme) = theme else {
+            return Err(GameError::CustomError(format!("The theme has no {THEME_FILE}")));
+        };
+
+        let dir = Self::dir(ctx).join(&name);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(THEME_FILE), toml::to_string_pretty(&theme)?)?;
+        for (path, contents) in files {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+        Ok(name)
+    }
+    /// Installs every packed theme that has been put in the themes directory, removing the archives that worked.
+    /// Returns a line on how each went.
+    pub fn import_all(ctx: &Context) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(Self::dir(ctx)) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == ARCHIVE_EXTENSION))
+            .map(|path| match Theme::import(ctx, &path) {
+                Ok(name) => {
+                    if let Err(e) = fs::remove_file(&path) {
+                        warn!("Could not remove {}: {e}", path.display());
+                    }
+                    format!("Installed {name}")
+                }
+                Err(e) => format!("{}: {e}", path.display()),
+            })
+            .collect()
+    }
+}
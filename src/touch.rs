@@ -0,0 +1,111 @@
+use ggez::graphics::{Color, Rect};
+
+use crate::{
+    input::Action,
+    render::{Renderer, GRID_CELL_SIZE},
+};
+
+const CELL: f32 = GRID_CELL_SIZE.0 as f32;
+
+/// The on-screen buttons, laid out in the margins beside the board (in grid cells).
+const BUTTONS: [(Action, &str, (f32, f32)); 6] = [
+    (Action::SoftDrop, "v", (0.5, 15.)),
+    (Action::RotLeft, "<)", (0.5, 20.)),
+    (Action::Left, "<", (0.5, 25.)),
+    (Action::HardDrop, "V", (15.5, 15.)),
+    (Action::RotRight, "(>", (15.5, 20.)),
+    (Action::Right, ">", (15.5, 25.)),
+];
+const BUTTON_SIZE: f32 = 4.;
+/// The on-screen buttons of the portrait layout, in a row under the board.
+const PORTRAIT_BUTTONS: [(Action, &str, (f32, f32)); 6] = [
+    (Action::Left, "<", (4.125, 31.)),
+    (Action::Right, ">", (6.125, 31.)),
+    (Action::SoftDrop, "v", (8.125, 31.)),
+    (Action::HardDrop, "V", (10.125, 31.)),
+    (Action::RotLeft, "<)", (12.125, 31.)),
+    (Action::RotRight, "(>", (14.125, 31.)),
+];
+const PORTRAIT_BUTTON_SIZE: f32 = 1.75;
+
+/// The on-screen buttons of the landscape or portrait layout, with where they are on the screen.
+fn buttons(portrait: bool) -> impl Iterator<Item = (Action, &'static str, Rect)> {
+    let (buttons, size) = if portrait { (&PORTRAIT_BUTTONS, PORTRAIT_BUTTON_SIZE) } else { (&BUTTONS, BUTTON_SIZE) };
+    buttons
+        .iter()
+        .map(move |&(action, label, (x, y))| (action, label, Rect::new(x * CELL, y * CELL, size * CELL, size * CELL)))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Pointer {
+    /// An on-screen button being held down.
+    Button(Action),
+    /// A swipe that started at `start` and has shifted the piece `shifted` cells so far.
+    Swipe { start: (f32, f32), shifted: i32 },
+}
+
+/// Turns touches (and left mouse clicks) into actions:
+/// dragging sideways moves the piece a cell at a time, tapping rotates and swiping down hard drops.
+#[derive(Debug, Default)]
+pub struct TouchControls {
+    pointer: Option<Pointer>,
+}
+
+impl TouchControls {
+    /// Handles a touch starting, returning the actions pressed (`true`) and released (`false`).
+    /// The buttons are only pressed if `buttons` is set, where they are in the `portrait` layout or the landscape one.
+    pub fn start(&mut self, x: f32, y: f32, buttons: bool, portrait: bool) -> Vec<(Action, bool)> {
+        if self.pointer.is_some() {
+            return Vec::new();
+        }
+        let button = self::buttons(portrait).find(|&(_, _, rect)| buttons && rect.contains([x, y]));
+        if let Some((action, _, _)) = button {
+            self.pointer = Some(Pointer::Button(action));
+            vec![(action, true)]
+        } else {
+            self.pointer = Some(Pointer::Swipe { start: (x, y), shifted: 0 });
+            Vec::new()
+        }
+    }
+    pub fn moved(&mut self, x: f32, _y: f32) -> Vec<(Action, bool)> {
+        let mut actions = Vec::new();
+        if let Some(Pointer::Swipe { start, shifted }) = &mut self.pointer {
+            let cells = ((x - start.0) / CELL) as i32;
+            while *shifted != cells {
+                let action = if *shifted < cells {
+                    *shifted += 1;
+                    Action::Right
+                } else {
+                    *shifted -= 1;
+                    Action::Left
+                };
+                actions.extend([(action, true), (action, false)]);
+            }
+        }
+        actions
+    }
+    pub fn end(&mut self, x: f32, y: f32) -> Vec<(Action, bool)> {
+        let mut actions = self.moved(x, y);
+        match self.pointer.take() {
+            Some(Pointer::Button(action)) => actions.push((action, false)),
+            Some(Pointer::Swipe { start, shifted }) => {
+                let (dx, dy) = (x - start.0, y - start.1);
+                if dy > 2. * CELL && dy > dx.abs() {
+                    actions.extend([(Action::HardDrop, true), (Action::HardDrop, false)]);
+                } else if shifted == 0 && dx.abs() < CELL / 2. && dy.abs() < CELL / 2. {
+                    actions.extend([(Action::RotRight, true), (Action::RotRight, false)]);
+                }
+            }
+            None => (),
+        }
+        actions
+    }
+    pub fn draw(&self, r: &mut dyn Renderer, portrait: bool) {
+        for (action, label, rect) in buttons(portrait) {
+            let held = matches!(self.pointer, Some(Pointer::Button(a)) if a == action);
+            let alpha = if held { 0.5 } else { 0.2 };
+            r.draw_panel(rect, Color::new(1., 1., 1., alpha));
+            r.draw_text(label, rect.h * 3. / 8., [rect.x + rect.w / 4., rect.y + rect.h / 4.], Color::WHITE);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ggez::{
+    graphics::{Color, Rect},
+    input::keyboard::KeyCode,
+};
+use log::{info, warn};
+
+use crate::{
+    app::GameState,
+    input::Action,
+    render::{self, Renderer, SCREEN_SIZE},
+};
+
+/// How many pixels of the screen a character of the terminal stands for.
+/// A cell of the board is two characters wide, as characters are about twice as high as they are wide.
+const CHAR_SIZE: (f32, f32) = (16., 32.);
+/// The rows of characters above this one are left out, as nothing is drawn there during a game
+/// and terminals are often only 24 rows high.
+const FIRST_ROW: usize = 6;
+/// How many ticks soft drop is held for after its key comes in. Terminals only say when a key is pressed,
+/// and again as it repeats, so it is let go of once the repeats stop.
+const SOFT_DROP_TICKS: u32 = 4;
+
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G, KeyCode::H, KeyCode::I,
+    KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+];
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Char {
+    ch: char,
+    fg: Color,
+    bg: Color,
+}
+
+/// The screen as characters, drawn the same way as the window is and printed with ANSI escapes.
+#[derive(Debug)]
+pub struct Terminal {
+    chars: Vec<Char>,
+    width: usize,
+    height: usize,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        let (width, height) = ((SCREEN_SIZE.0 / CHAR_SIZE.0) as usize, (SCREEN_SIZE.1 / CHAR_SIZE.1) as usize);
+        Terminal { chars: vec![Char { ch: ' ', fg: Color::WHITE, bg: Color::BLACK }; width * height], width, height }
+    }
+}
+
+impl Terminal {
+    fn clear(&mut self) {
+        self.chars.fill(Char { ch: ' ', fg: Color::WHITE, bg: Color::BLACK });
+    }
+    /// The screen from `FIRST_ROW` down, starting with moving the cursor to the top left corner.
+    fn to_ansi(&self) -> String {
+        let mut out = "\x1b[H".to_owned();
+        for row in self.chars.chunks(self.width).skip(FIRST_ROW) {
+            let mut last = None;
+            for c in row {
+                if last != Some((c.fg, c.bg)) {
+                    let ((fr, fg, fb), (br, bg, bb)) = (c.fg.to_rgb(), c.bg.to_rgb());
+                    out += &format!("\x1b[38;2;{fr};{fg};{fb};48;2;{br};{bg};{bb}m");
+                    last = Some((c.fg, c.bg));
+                }
+                out.push(c.ch);
+            }
+            out += "\x1b[0m\r\n";
+        }
+        out
+    }
+}
+
+/// `over` drawn on top of `under`, as much as it is opaque.
+fn blend(under: Color, over: Color) -> Color {
+    let mix = |a: f32, b: f32| a + (b - a) * over.a;
+    Color::new(mix(under.r, over.r), mix(under.g, over.g), mix(under.b, over.b), 1.)
+}
+
+impl Renderer for Terminal {
+    fn draw_text(&mut self, text: &str, scale: f32, [x, y]: [f32; 2], colour: Color) {
+        // On the row the middle of the text is on
+        let (col, row) = ((x / CHAR_SIZE.0).max(0.) as usize, ((y + scale / 2.) / CHAR_SIZE.1).max(0.) as usize);
+        if row >= self.height {
+            return;
+        }
+        for (i, ch) in text.chars().enumerate().take(self.width.saturating_sub(col)) {
+            let c = &mut self.chars[row * self.width + col + i];
+            c.ch = ch;
+            c.fg = colour;
+        }
+    }
+    fn draw_text_centred(&mut self, text: &str, scale: f32, [x, y]: [f32; 2], colour: Color) {
+        let half_width = text.chars().count() as f32 * CHAR_SIZE.0 / 2.;
+        self.draw_text(text, scale, [x - half_width, y - scale / 2.], colour);
+    }
+    fn draw_panel(&mut self, rect: Rect, colour: Color) {
+        // Only the characters with their middles inside are filled, so thin outlines don't show
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let (x, y) = ((col as f32 + 0.5) * CHAR_SIZE.0, (row as f32 + 0.5) * CHAR_SIZE.1);
+                if rect.contains([x, y]) {
+                    let c = &mut self.chars[row * self.width + col];
+                    c.ch = ' ';
+                    c.bg = blend(c.bg, colour);
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the terminal passing keys on as they are pressed, without showing them, until it is dropped,
+/// and draws on a screen of its own that the terminal goes back from afterwards.
+struct RawMode {
+    /// The settings to go back to, from `stty -g`.
+    saved: String,
+}
+
+impl RawMode {
+    fn enter() -> io::Result<Self> {
+        let saved = stty(&["-g"])?.trim().to_owned();
+        stty(&["-icanon", "-echo", "-isig"])?;
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        io::stdout().flush()?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        if let Err(e) = stty(&[&self.saved]) {
+            warn!("Could not put the terminal back how it was: {e}");
+        }
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_owned()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads what comes in on the standard input on a thread of its own, a byte at a time.
+fn read_input() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            if byte.ok().is_none_or(|byte| sender.send(byte).is_err()) {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// The keys the terminal sent `bytes` for, as the keys they are in the window. Ctrl+C comes in as Escape.
+fn keys(bytes: &[u8]) -> Vec<KeyCode> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let key = match bytes[i] {
+            // The arrows are sent as escape sequences
+            0x1b if bytes.get(i + 1) == Some(&b'[') => {
+                i += 2;
+                match bytes.get(i) {
+                    Some(b'A') => Some(KeyCode::Up),
+                    Some(b'B') => Some(KeyCode::Down),
+                    Some(b'C') => Some(KeyCode::Right),
+                    Some(b'D') => Some(KeyCode::Left),
+                    _ => None,
+                }
+            }
+            0x1b | 3 => Some(KeyCode::Escape),
+            b' ' => Some(KeyCode::Space),
+            b'\r' | b'\n' => Some(KeyCode::Return),
+            b'\t' => Some(KeyCode::Tab),
+            b @ (b'a'..=b'z' | b'A'..=b'Z') => Some(LETTERS[(b.to_ascii_lowercase() - b'a') as usize]),
+            b @ b'0'..=b'9' => Some(DIGITS[(b - b'0') as usize]),
+            _ => None,
+        };
+        keys.extend(key);
+        i += 1;
+    }
+    keys
+}
+
+fn draw(state: &GameState, terminal: &mut Terminal) {
+    terminal.clear();
+    let game = &state.game;
+    render::draw_game(terminal, game, 0., &state.theme);
+    let secs = game.ms() / 1000;
+    let lines = [
+        game.mode.to_string(),
+        String::new(),
+        "Score".to_owned(),
+        game.score.to_string(),
+        "Lines".to_owned(),
+        game.lines.to_string(),
+        "Level".to_owned(),
+        game.level().to_string(),
+        "Time".to_owned(),
+        format!("{}:{:02}", secs / 60, secs % 60),
+    ];
+    let x = SCREEN_SIZE.0 - 9. * CHAR_SIZE.0;
+    for (i, line) in lines.iter().enumerate() {
+        terminal.draw_text(line, CHAR_SIZE.1, [x, (FIRST_ROW + 4 + i) as f32 * CHAR_SIZE.1], Color::WHITE);
+    }
+    terminal.draw_text("Esc: quit", CHAR_SIZE.1, [x, SCREEN_SIZE.1 - CHAR_SIZE.1], Color::WHITE);
+    if game.gameover {
+        let middle = SCREEN_SIZE.0 / 2.;
+        terminal.draw_text_centred("Game over", CHAR_SIZE.1, [middle, SCREEN_SIZE.1 / 2.], Color::RED);
+        terminal.draw_text_centred("Enter: again", CHAR_SIZE.1, [middle, SCREEN_SIZE.1 / 2. + CHAR_SIZE.1], Color::WHITE);
+    }
+}
+
+/// Plays in the terminal until Escape is pressed, with the keys, scores and statistics of the profile `state` is on,
+/// saving the game in progress to be continued next time as the window does.
+pub fn run(mut state: GameState) -> io::Result<()> {
+    let _raw = RawMode::enter()?;
+    let input = read_input();
+    let mut terminal = Terminal::default();
+    let mut soft_drop = 0;
+    let mut out = io::stdout();
+    loop {
+        let started = Instant::now();
+        let bytes: Vec<u8> = input.try_iter().collect();
+        let mut inputs = Vec::new();
+        for key in keys(&bytes) {
+            match (key, state.bindings.action(key)) {
+                (KeyCode::Escape, _) | (_, Some(Action::Pause)) => {
+                    info!("Leaving the terminal");
+                    state.save_on_exit();
+                    return Ok(());
+                }
+                (_, Some(Action::Restart)) => {
+                    state.reset(state.game.mode);
+                    soft_drop = 0;
+                }
+                (KeyCode::Return, _) if state.game.gameover => {
+                    state.reset(state.game.mode);
+                    soft_drop = 0;
+                }
+                (_, Some(Action::SoftDrop)) if state.accepts_input() => {
+                    if soft_drop == 0 {
+                        inputs.push((Action::SoftDrop, true));
+                    }
+                    soft_drop = SOFT_DROP_TICKS;
+                }
+                // Every other key is let go of straight away, as a tap
+                (_, Some(action)) if state.accepts_input() => inputs.extend([(action, true), (action, false)]),
+                _ => (),
+            }
+        }
+        if soft_drop > 0 {
+            soft_drop -= 1;
+            if soft_drop == 0 {
+                inputs.push((Action::SoftDrop, false));
+            }
+        }
+        if !state.game.gameover {
+            let result = state.game.tick(&inputs);
+            if state.keeps_records() {
+                for event in result.events {
+                    state.data.stats.record(event);
+                }
+            }
+            if result.game_over {
+                state.record_score();
+            }
+        }
+        draw(&state, &mut terminal);
+        out.write_all(terminal.to_ansi().as_bytes())?;
+        out.flush()?;
+        let tick = Duration::from_millis(state.game.ms_per_tick() as u64);
+        thread::sleep(tick.saturating_sub(started.elapsed()));
+    }
+}
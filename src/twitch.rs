@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use ggez::graphics::Color;
+use log::{info, warn};
+use oorandom::Rand32;
+
+use crate::{
+    app::random_seed,
+    piece::{Piece, Tetromino},
+    render::Renderer,
+    rules::Game,
+    theme::Theme,
+};
+
+/// Twitch's chat server, which takes plain IRC.
+const SERVER: &str = "irc.chat.twitch.tv:6667";
+/// How long each vote runs for.
+const VOTE_TIME: Duration = Duration::from_secs(30);
+/// How long to wait before connecting again after losing the chat.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// How often the chat thread looks up from waiting for messages to see if it should stop.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How many rows of garbage the garbage events send.
+const GARBAGE_ROWS: u32 = 4;
+
+/// What the viewers can vote for, by typing its number in the chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Rows of garbage with their gaps lined up, to dig through in one go.
+    Garbage,
+    /// Rows of garbage with the gap somewhere else in each.
+    Cheese,
+    /// A one-time item: the next piece is an I piece.
+    IPiece,
+    /// The pieces take each other's colours.
+    Colours,
+}
+
+impl Event {
+    pub const ALL: [Event; 4] = [Event::Garbage, Event::Cheese, Event::IPiece, Event::Colours];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Garbage => "Garbage",
+            Event::Cheese => "Cheese",
+            Event::IPiece => "I piece",
+            Event::Colours => "Colours",
+        }
+    }
+    /// Does the event to the game and how it looks, between pieces so nothing changes under the falling one.
+    pub fn apply(self, game: &mut Game, theme: &mut Theme, rng: &mut Rand32) {
+        let width = game.grid.size().0 as u32;
+        match self {
+            Event::Garbage => game.receive_garbage(GARBAGE_ROWS, rng.rand_range(0..width) as usize),
+            Event::Cheese => {
+                for _ in 0..GARBAGE_ROWS {
+                    game.receive_garbage(1, rng.rand_range(0..width) as usize);
+                }
+            }
+            Event::IPiece => game.next_piece = Piece::new(Tetromino::I),
+            Event::Colours => {
+                for i in (1..theme.palette.len()).rev() {
+                    theme.palette.swap(i, rng.rand_range(0..i as u32 + 1) as usize);
+                }
+            }
+        }
+    }
+}
+
+/// What the chat thread hears.
+enum Heard {
+    Joined,
+    Message { user: String, text: String },
+    Lost(String),
+}
+
+/// The number a chat message votes for, as `1` or `!1`, counting from 0.
+fn vote(text: &str) -> Option<usize> {
+    let n: usize = text.trim().trim_start_matches('!').parse().ok()?;
+    (1..=Event::ALL.len()).contains(&n).then(|| n - 1)
+}
+
+/// Who sent an IRC `PRIVMSG` line and what it said.
+fn parse_message(line: &str) -> Option<(String, String)> {
+    let (prefix, rest) = line.strip_prefix(':')?.split_once(' ')?;
+    let (_, text) = rest.strip_prefix("PRIVMSG ")?.split_once(" :")?;
+    let user = prefix.split('!').next()?;
+    Some((user.to_owned(), text.to_owned()))
+}
+
+/// Reads `channel`'s chat until `stop` is set, passing on what is heard.
+/// Twitch lets anyone read a chat without logging in, as one of its `justinfan` users.
+fn listen(channel: &str, stop: &AtomicBool, heard: &Sender<Heard>) -> Result<(), String> {
+    let mut stream = TcpStream::connect(SERVER).map_err(|e| format!("Could not reach Twitch: {e}"))?;
+    stream.set_read_timeout(Some(POLL_INTERVAL)).map_err(|e| e.to_string())?;
+    let nick = format!("justinfan{}", random_seed() % 100_000);
+    write!(stream, "NICK {nick}\r\nJOIN #{}\r\n", channel.to_lowercase()).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let mut line = String::new();
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err("Twitch closed the connection".to_owned()),
+            Ok(_) => (),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+        let message = line.trim_end();
+        if let Some(server) = message.strip_prefix("PING ") {
+            write!(stream, "PONG {server}\r\n").map_err(|e| e.to_string())?;
+        } else if message.contains(" JOIN #") {
+            let _ = heard.send(Heard::Joined);
+        } else if let Some((user, text)) = parse_message(message) {
+            let _ = heard.send(Heard::Message { user, text });
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+/// Lets the viewers of a Twitch stream vote on what happens to the game, from its chat.
+/// A vote is held every `VOTE_TIME`, with each viewer's last vote counting, and what wins
+/// is done to the game when the next piece locks.
+pub struct TwitchVotes {
+    channel: String,
+    stop: Arc<AtomicBool>,
+    heard: Receiver<Heard>,
+    joined: bool,
+    /// What each viewer voted for in this vote.
+    votes: HashMap<String, usize>,
+    vote_started: Instant,
+    /// What won the last vote, waiting for the next piece to lock.
+    won: Option<Event>,
+    rng: Rand32,
+}
+
+impl TwitchVotes {
+    /// Starts reading the chat of `channel` on a thread of its own.
+    pub fn join(channel: &str) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, heard) = mpsc::channel();
+        let thread_stop = stop.clone();
+        let thread_channel = channel.to_owned();
+        let spawned = thread::Builder::new().name("twitch".to_owned()).spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                if let Err(e) = listen(&thread_channel, &thread_stop, &sender) {
+                    if sender.send(Heard::Lost(e)).is_err() {
+                        break;
+                    }
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        });
+        if let Err(e) = spawned {
+            warn!("Could not start the Twitch chat thread: {e}");
+        }
+        TwitchVotes {
+            channel: channel.to_owned(),
+            stop,
+            heard,
+            joined: false,
+            votes: HashMap::new(),
+            vote_started: Instant::now(),
+            won: None,
+            rng: Rand32::new(random_seed()),
+        }
+    }
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+    /// How many votes each event has in this vote.
+    fn tally(&self) -> [usize; Event::ALL.len()] {
+        let mut tally = [0; Event::ALL.len()];
+        for &vote in self.votes.values() {
+            tally[vote] += 1;
+        }
+        tally
+    }
+    /// Counts the votes that came in, and ends the vote if its time is up,
+    /// returning what to tell the streamer.
+    pub fn update(&mut self) -> Vec<String> {
+        let mut messages = Vec::new();
+        for heard in self.heard.try_iter() {
+            match heard {
+                Heard::Joined if !self.joined => {
+                    info!("Joined the Twitch chat of {}", self.channel);
+                    messages.push(format!("Joined the Twitch chat of {}", self.channel));
+                    self.joined = true;
+                }
+                Heard::Joined => (),
+                Heard::Message { user, text } => {
+                    if let Some(vote) = vote(&text) {
+                        self.votes.insert(user, vote);
+                    }
+                }
+                Heard::Lost(e) => {
+                    warn!("Lost the Twitch chat of {}: {e}", self.channel);
+                    if self.joined {
+                        messages.push(format!("Lost the Twitch chat: {e}"));
+                    }
+                    self.joined = false;
+                }
+            }
+        }
+        if self.vote_started.elapsed() >= VOTE_TIME {
+            let tally = self.tally();
+            let most = tally.iter().copied().max().unwrap_or(0);
+            if most > 0 {
+                // Ties are broken at random
+                let tied: Vec<usize> = (0..tally.len()).filter(|&i| tally[i] == most).collect();
+                let event = Event::ALL[tied[self.rng.rand_range(0..tied.len() as u32) as usize]];
+                info!("The chat voted for {} with {most} votes", event.name());
+                messages.push(format!("Chat voted for {}", event.name()));
+                self.won = Some(event);
+            }
+            self.votes.clear();
+            self.vote_started = Instant::now();
+        }
+        messages
+    }
+    /// Does what won the last vote to the game, if anything did, for when a piece has just locked.
+    /// Returns whether the game itself was changed.
+    pub fn apply_won(&mut self, game: &mut Game, theme: &mut Theme) -> bool {
+        match self.won.take() {
+            Some(event) => {
+                event.apply(game, theme, &mut self.rng);
+                event != Event::Colours
+            }
+            None => false,
+        }
+    }
+    /// Draws the vote under way with its top left corner at `(x, y)`.
+    pub fn draw(&self, r: &mut dyn Renderer, (x, y): (f32, f32)) {
+        if !self.joined {
+            r.draw_text("Joining chat...", 16., [x, y], Color::WHITE);
+            return;
+        }
+        let left = VOTE_TIME.saturating_sub(self.vote_started.elapsed()).as_secs();
+        r.draw_text(&format!("Chat vote: {left} s"), 16., [x, y], Color::WHITE);
+        for (i, (event, votes)) in Event::ALL.iter().zip(self.tally()).enumerate() {
+            let colour = if Some(*event) == self.won { Color::YELLOW } else { Color::WHITE };
+            r.draw_text(&format!("{} {}: {votes}", i + 1, event.name()), 16., [x, y + 20. * (i + 1) as f32], colour);
+        }
+    }
+}
+
+impl Drop for TwitchVotes {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
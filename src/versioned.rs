@@ -0,0 +1,79 @@
+use ggez::{GameError, GameResult};
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::storage::Storage;
+
+/// A JSON file whose layout can change between releases, such as the saved game, scores and statistics.
+/// Files are written with a `"version"` field, and older ones are upgraded a version at a time when loaded,
+/// so a change never drops what players have kept. Files from before there were versions are version 0.
+/// Replays have a binary format with a version of its own, upgraded in `Replay::decode`.
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// The version files are written as.
+    const VERSION: u32;
+
+    /// Upgrades `value`, a file of `version`, to `version + 1`.
+    /// Fields added with a `#[serde(default)]` need no upgrading, so only changes of layout go here.
+    fn migrate(_version: u32, _value: &mut Value) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn from_json(data: &[u8]) -> GameResult<Self> {
+        let mut value: Value = serde_json::from_slice(data).map_err(|e| GameError::CustomError(e.to_string()))?;
+        let Value::Object(fields) = &mut value else {
+            return Err(GameError::CustomError("Expected a JSON object".to_owned()));
+        };
+        let version = match fields.remove("version") {
+            None => 0,
+            Some(v) => v
+                .as_u64()
+                .ok_or_else(|| GameError::CustomError(format!("Invalid version {v}")))? as u32,
+        };
+        if version > Self::VERSION {
+            return Err(GameError::CustomError(format!(
+                "Version {version} is newer than this build understands ({})",
+                Self::VERSION
+            )));
+        }
+        for from in version..Self::VERSION {
+            Self::migrate(from, &mut value)
+                .map_err(|e| GameError::CustomError(format!("Could not upgrade from version {from}: {e}")))?;
+        }
+        serde_json::from_value(value).map_err(|e| GameError::CustomError(e.to_string()))
+    }
+    fn to_json(&self, pretty: bool) -> GameResult<String> {
+        #[derive(Serialize)]
+        struct Tagged<'a, T> {
+            version: u32,
+            #[serde(flatten)]
+            data: &'a T,
+        }
+        let tagged = Tagged { version: Self::VERSION, data: self };
+        if pretty {
+            serde_json::to_string_pretty(&tagged)
+        } else {
+            serde_json::to_string(&tagged)
+        }
+        .map_err(|e| GameError::CustomError(e.to_string()))
+    }
+}
+
+/// Loads `key` from `storage`, if it is there.
+/// A file that can't be loaded is copied aside to `<key>.unreadable` first,
+/// so starting afresh and saving over it doesn't lose it for good, e.g. one written by a newer build.
+pub fn load<T: Versioned>(storage: &dyn Storage, key: &str) -> GameResult<Option<T>> {
+    let Some(data) = storage.read(key)? else {
+        return Ok(None);
+    };
+    T::from_json(&data).map(Some).inspect_err(|_| {
+        if let Err(e) = storage.write(&format!("{key}.unreadable"), &data) {
+            warn!("Could not keep a copy of {key}: {e}");
+        }
+    })
+}
+
+pub fn save<T: Versioned>(storage: &dyn Storage, key: &str, data: &T, pretty: bool) -> GameResult {
+    storage.write(key, data.to_json(pretty)?.as_bytes())?;
+    Ok(())
+}
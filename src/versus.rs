@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+
+use ggez::{
+    event::{Button, GamepadId},
+    graphics::{Color, Rect},
+    input::keyboard::{KeyCode, KeyMods},
+    Context,
+};
+use log::{info, warn};
+use oorandom::Rand32;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::Ai,
+    app::{random_seed, GameState},
+    attack::{AttackTable, Attacker},
+    config::{GameConfig, Handling},
+    handicap::Handicap,
+    input::{Action, Keybindings},
+    lobby::RoomRules,
+    match_replay::{MatchReplay, Round, Track},
+    mode::Mode,
+    render::{Renderer, SCREEN_SIZE},
+    rules::{Game, GameEvent},
+    ruleset::SPRINT_LINES,
+    save::SavedGame,
+    scene::{Scene, Transition},
+    settings::MenuInput,
+    theme::Theme,
+    widget::TetrisWidget,
+};
+
+/// How long the countdown before the games start lasts (ms).
+pub const COUNTDOWN_MS: u32 = 3000;
+/// Room above the boards for the names and the countdown.
+const HEADER_HEIGHT: f32 = 96.;
+
+/// The keys each side plays with, so both fit on one keyboard: WASD on the left and the arrows on the right.
+fn bindings(player: usize) -> Keybindings {
+    let common = Keybindings {
+        pause: vec![KeyCode::Escape],
+        menu_confirm: vec![KeyCode::Return],
+        menu_back: vec![KeyCode::Escape],
+        restart: Vec::new(),
+        undo: Vec::new(),
+        redo: Vec::new(),
+        ..Keybindings::default()
+    };
+    match player {
+        0 => Keybindings {
+            left: vec![KeyCode::A],
+            right: vec![KeyCode::D],
+            soft_drop: vec![KeyCode::S],
+            hard_drop: vec![KeyCode::W],
+            rotate_left: vec![KeyCode::Q],
+            rotate_right: vec![KeyCode::E],
+            hold: vec![KeyCode::LShift],
+            ..common
+        },
+        _ => Keybindings {
+            left: vec![KeyCode::Left],
+            right: vec![KeyCode::Right],
+            soft_drop: vec![KeyCode::Down],
+            hard_drop: vec![KeyCode::RShift],
+            rotate_left: vec![KeyCode::RControl],
+            rotate_right: vec![KeyCode::Up],
+            hold: vec![KeyCode::Slash],
+            ..common
+        },
+    }
+}
+
+/// A player's board in a versus match, along with what decides the garbage it sends.
+/// Everything about it follows from the seed and the inputs, so the other end of an online match
+/// can play it out the same way from the inputs alone.
+#[derive(Clone)]
+pub struct Side {
+    pub board: TetrisWidget,
+    attacker: Attacker,
+    /// Picks the gaps in the garbage this side sends, apart from the pieces so both sides get the same ones.
+    garbage_rng: Rand32,
+    handicap: Handicap,
+}
+
+impl Side {
+    /// Player `player`'s side of a match of `mode` played with `seed`, held back or helped by `handicap`.
+    pub fn new(seed: u64, player: usize, mode: Mode, handling: Handling, config: &GameConfig, handicap: Handicap) -> Self {
+        let mut config = config.clone();
+        if handicap.gravity > 1 {
+            let frames_per_row = config.frames_per_row().unwrap_or_else(|| mode.rules().frames_per_row(0));
+            config.frames_per_row = Some(frames_per_row.saturating_mul(handicap.gravity));
+        }
+        let mut board = TetrisWidget::new(mode, mode.rules(), seed, handling, &config);
+        // Only the scene playing the main game is told how far between ticks a frame is
+        board.smooth_fall = false;
+        // Starting garbage has a gap of its own in each row, picked apart from everything else
+        let mut holes = Rand32::new(seed.rotate_left(16) ^ player as u64);
+        for _ in 0..handicap.garbage {
+            let hole = holes.rand_range(0..config.grid_size.0 as u32) as usize;
+            board.game.add_garbage(1, hole);
+        }
+        Side {
+            board,
+            attacker: Attacker::default(),
+            garbage_rng: Rand32::new(seed.rotate_left(32) ^ player as u64),
+            handicap,
+        }
+    }
+    /// Plays a tick with `inputs`, returning the garbage it sends as `(rows, hole)`, scaled by its handicap.
+    /// With `cancel`, what a clear would send first cancels out the garbage waiting for this side,
+    /// and only what is left over is sent; without it, both players get all the garbage.
+    pub fn tick(&mut self, inputs: &[(Action, bool)], table: &AttackTable, cancel: bool) -> Option<(u32, usize)> {
+        let mut sent = 0;
+        for event in self.board.tick(inputs).events {
+            if let GameEvent::PieceLocked { lines, t_spin } = event {
+                // A piece locks at most once a tick, so the board as the tick left it is as the clear left it
+                let perfect = lines > 0 && self.board.game.grid.column_heights().iter().all(|&height| height == 0);
+                sent += self.attacker.piece_locked(table, lines, t_spin, perfect);
+            }
+        }
+        let sent = self.handicap.scale_attack(sent);
+        let game = &mut self.board.game;
+        let sent = if cancel { game.cancel_garbage(sent) } else { sent };
+        (sent > 0).then(|| (sent, self.garbage_rng.rand_range(0..game.grid.size().0 as u32) as usize))
+    }
+}
+
+/// Everything about a side as it is after a tick, for it to be played on from somewhere else,
+/// e.g. by a player getting back into a match on a server after losing their connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SideSnapshot {
+    game: SavedGame,
+    attacker: Attacker,
+    /// The state of the side's `garbage_rng`, from `Rand32::state`.
+    garbage_rng: (u64, u64),
+    #[serde(default)]
+    handicap: Handicap,
+}
+
+impl Side {
+    pub fn snapshot(&self) -> SideSnapshot {
+        SideSnapshot {
+            game: self.board.game.to_save(),
+            attacker: self.attacker.clone(),
+            garbage_rng: self.garbage_rng.state(),
+            handicap: self.handicap,
+        }
+    }
+    pub fn from_snapshot(snapshot: SideSnapshot) -> Self {
+        let mut board = TetrisWidget::with_game(Game::from_save(snapshot.game));
+        board.smooth_fall = false;
+        Side {
+            board,
+            attacker: snapshot.attacker,
+            garbage_rng: Rand32::from_state(snapshot.garbage_rng),
+            handicap: snapshot.handicap,
+        }
+    }
+}
+
+/// Keys and a gamepad driving one side, with the actions they made waiting for the next tick.
+#[derive(Debug, Default)]
+pub struct Controls {
+    pub bindings: Keybindings,
+    /// The keys held down and the action they were pressed as.
+    held_keys: BTreeMap<KeyCode, Action>,
+    /// The gamepad playing this side, once one has pressed a button.
+    pub gamepad: Option<GamepadId>,
+    /// Actions pressed or released since the last tick.
+    input_queue: Vec<(Action, bool)>,
+}
+
+impl Controls {
+    pub fn new(bindings: Keybindings) -> Self {
+        Controls { bindings, ..Controls::default() }
+    }
+    /// Presses whatever `keycode` is bound to, returning it.
+    pub fn key_down(&mut self, keycode: KeyCode) -> Option<Action> {
+        let action = self.bindings.action(keycode)?;
+        if !matches!(action, Action::Pause | Action::MenuConfirm | Action::MenuBack) && self.held_keys.insert(keycode, action).is_none() {
+            self.input_queue.push((action, true));
+        }
+        Some(action)
+    }
+    pub fn key_up(&mut self, keycode: KeyCode) {
+        if let Some(action) = self.held_keys.remove(&keycode) {
+            self.input_queue.push((action, false));
+        }
+    }
+    pub fn push(&mut self, action: Action, pressed: bool) {
+        self.input_queue.push((action, pressed));
+    }
+    /// The actions made since the last tick.
+    pub fn take(&mut self) -> Vec<(Action, bool)> {
+        std::mem::take(&mut self.input_queue)
+    }
+}
+
+/// How a match ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Winner(usize),
+    /// Both topped out on the same tick.
+    Draw,
+}
+
+/// Who has won a match, once both boards have got far enough to tell from how many ticks each has played
+/// and the tick each topped out on: the board that topped out first loses.
+pub fn decide(topped_out: [Option<u32>; 2], ticks: [u32; 2]) -> Option<Outcome> {
+    match topped_out {
+        [Some(a), Some(b)] if a == b => Some(Outcome::Draw),
+        [Some(a), Some(b)] => Some(Outcome::Winner(if a < b { 1 } else { 0 })),
+        [Some(a), None] if ticks[1] >= a => Some(Outcome::Winner(1)),
+        [None, Some(b)] if ticks[0] >= b => Some(Outcome::Winner(0)),
+        _ => None,
+    }
+}
+
+/// Who has won a race, once both boards have got far enough to tell, from the tick each board's game ended on
+/// and whether it ended by clearing the lines rather than topping out. A board loses on the tick it tops out,
+/// or on the tick the other board finishes, whichever comes first.
+pub fn decide_race(ended: [Option<u32>; 2], finished: [bool; 2], ticks: [u32; 2]) -> Option<Outcome> {
+    let lost = [0, 1].map(|i| {
+        let topped_out = ended[i].filter(|_| !finished[i]);
+        let beaten = ended[1 - i].filter(|_| finished[1 - i]);
+        topped_out.into_iter().chain(beaten).min()
+    });
+    // Either board can lose by what the other does, so neither is known not to have lost past the one behind
+    let known = ticks[0].min(ticks[1]);
+    decide(lost, [known; 2])
+}
+
+/// Who has won a match of versus, or a race if `race`, from how the boards are after `ticks`
+/// and the tick each one's game ended on.
+pub fn decide_match(race: bool, boards: [&TetrisWidget; 2], ended: [Option<u32>; 2], ticks: [u32; 2]) -> Option<Outcome> {
+    if race {
+        decide_race(ended, boards.map(|board| board.game.lines >= SPRINT_LINES), ticks)
+    } else {
+        decide(ended, ticks)
+    }
+}
+
+/// Draws a bar under each player's name filling up with the lines they have cleared towards the end of a race.
+pub fn draw_race_progress(r: &mut dyn Renderer, lines: [u32; 2]) {
+    let half = SCREEN_SIZE.0 / 2.;
+    for (i, lines) in lines.into_iter().enumerate() {
+        let bar = Rect::new(half * i as f32 + 24., 60., half - 48., 20.);
+        let done = lines.min(SPRINT_LINES) as f32 / SPRINT_LINES as f32;
+        let colour = if lines >= SPRINT_LINES { Color::YELLOW } else { Color::GREEN };
+        r.draw_panel(bar, Color::new(0.2, 0.2, 0.2, 1.));
+        r.draw_panel(Rect { w: bar.w * done, ..bar }, colour);
+        r.draw_text_centred(&format!("{}/{SPRINT_LINES}", lines.min(SPRINT_LINES)), 16., [bar.x + bar.w / 2., bar.y + bar.h / 2.], Color::WHITE);
+    }
+}
+
+/// Draws the boards side by side under their players' names, along with the countdown and how the match ended.
+pub fn draw_match(r: &mut dyn Renderer, boards: [(&str, &TetrisWidget); 2], countdown_ms: u32, outcome: Option<Outcome>, theme: &Theme) {
+    let half = SCREEN_SIZE.0 / 2.;
+    for (i, (name, board)) in boards.into_iter().enumerate() {
+        let x = half * i as f32;
+        r.draw_text_centred(name, 32., [x + half / 2., 32.], Color::WHITE);
+        let rect = Rect::new(x + 8., HEADER_HEIGHT, half - 16., SCREEN_SIZE.1 - HEADER_HEIGHT - 48.);
+        board.draw(r, rect, 0., theme);
+    }
+
+    let middle = [SCREEN_SIZE.0 / 2., SCREEN_SIZE.1 / 2.];
+    if countdown_ms > 0 {
+        let seconds = countdown_ms.div_ceil(1000);
+        r.draw_text_centred(&seconds.to_string(), 128., middle, Color::YELLOW);
+    }
+    if let Some(outcome) = outcome {
+        let text = match outcome {
+            Outcome::Winner(i) => format!("{} wins!", boards[i].0),
+            Outcome::Draw => "Draw!".to_owned(),
+        };
+        r.draw_panel(Rect::new(0., middle[1] - 64., SCREEN_SIZE.0, 128.), Color::new(0., 0., 0., 0.8));
+        r.draw_text_centred(&text, 64., middle, Color::YELLOW);
+    }
+}
+
+/// Two players side by side, each with a board and a set of keys or a gamepad of their own,
+/// playing Marathon on the same pieces until one of them tops out.
+/// Clears send garbage to the other board by the attack table in the config, and each player plays
+/// with the handicap set for them in the config.
+/// In a race they play Sprint on the same pieces instead, with no garbage, and the first to clear the lines wins.
+/// Against the computer, the right board is played by the AI instead.
+/// Each game is added to a replay of the match, which goes on through rematches.
+pub struct VersusScene {
+    sides: [Side; 2],
+    replay: MatchReplay,
+    controls: [Controls; 2],
+    /// The AI playing the right board, if the computer plays it.
+    ai: Option<Ai>,
+    race: bool,
+    countdown_ms: u32,
+    ticks: u32,
+    ended: [Option<u32>; 2],
+    outcome: Option<Outcome>,
+}
+
+impl VersusScene {
+    /// Starts the next game of the match in `replay`, with the right board played by the computer if `computer`.
+    pub fn new(state: &GameState, race: bool, computer: bool, mut replay: MatchReplay) -> Self {
+        let seed = state.seed.unwrap_or_else(random_seed);
+        let mode = if race { Mode::Sprint } else { Mode::Marathon };
+        info!("Starting a {} game with seed {seed}{}", if race { "race" } else { "versus" }, if computer { " against the computer" } else { "" });
+        let profile = state.config.profile().handling;
+        // The AI doesn't need any help from the handling, it shifts one column at a time
+        let handling = [profile, if computer { Handling::default() } else { profile }];
+        let rules = RoomRules { attack: state.config.attack.clone(), race, handicaps: state.config.handicaps, ..RoomRules::default() };
+        let names = Self::names(computer);
+        replay.rounds.push(Round::new(seed, rules, [0, 1].map(|i| Track::new(names[i], handling[i]))));
+        let sides = [0, 1].map(|i| Side::new(seed, i, mode, handling[i], &state.game_config, state.config.handicaps[i]));
+        let ai = computer.then(|| match &state.config.bot {
+            Some(command) => Ai::bot(command, &sides[1].board.game),
+            None => Ai::with_weights(state.config.difficulty, state.config.ai_weights),
+        });
+        VersusScene {
+            sides,
+            replay,
+            controls: [0, 1].map(|i| Controls::new(bindings(i))),
+            ai,
+            race,
+            countdown_ms: COUNTDOWN_MS,
+            ticks: 0,
+            ended: [None; 2],
+            outcome: None,
+        }
+    }
+    fn names(computer: bool) -> [&'static str; 2] {
+        ["Player 1", if computer { "Computer" } else { "Player 2" }]
+    }
+    fn playing(&self) -> bool {
+        self.countdown_ms == 0 && self.outcome.is_none()
+    }
+}
+
+impl Scene for VersusScene {
+    fn update(&mut self, state: &mut GameState, _ctx: &mut Context) -> Transition {
+        let ms = self.sides[0].board.game.ms_per_tick();
+        if self.countdown_ms > 0 {
+            self.countdown_ms = self.countdown_ms.saturating_sub(ms);
+            return Transition::None;
+        }
+        if self.outcome.is_some() {
+            return Transition::None;
+        }
+        let tick = self.ticks + 1;
+        let round = self.replay.rounds.last_mut().expect("Every game is a round of the replay");
+        let sent = [0, 1].map(|i| {
+            let inputs = match &mut self.ai {
+                Some(ai) if i == 1 => ai.inputs(&self.sides[i].board.game),
+                // Against the computer either set of keys plays the left board
+                Some(_) => {
+                    let mut inputs = self.controls[0].take();
+                    inputs.extend(self.controls[1].take());
+                    inputs
+                }
+                None => self.controls[i].take(),
+            };
+            round.tracks[i].record(tick, &[], &inputs);
+            self.sides[i].tick(&inputs, &state.config.attack, true)
+        });
+        for (from, garbage) in sent.into_iter().enumerate() {
+            if let Some((rows, hole)) = garbage.filter(|_| !self.race) {
+                self.sides[1 - from].board.game.receive_garbage(rows, hole);
+                round.tracks[1 - from].land(tick + 1, rows, hole);
+            }
+        }
+        self.ticks = tick;
+        for (side, ended) in self.sides.iter().zip(&mut self.ended) {
+            if side.board.game.gameover && ended.is_none() {
+                *ended = Some(self.ticks);
+            }
+        }
+        let boards = [&self.sides[0].board, &self.sides[1].board];
+        self.outcome = decide_match(self.race, boards, self.ended, [self.ticks; 2]);
+        if let Some(outcome) = self.outcome {
+            info!("Versus game over: {outcome:?}");
+            round.winner = match outcome {
+                Outcome::Winner(i) => Some(i),
+                Outcome::Draw => None,
+            };
+            if let Err(e) = self.replay.save(&*state.storage, &state.data.dir) {
+                warn!("Could not save the match replay: {e}");
+            }
+        }
+        Transition::None
+    }
+    fn draw(&self, state: &GameState, r: &mut dyn Renderer) {
+        let names = Self::names(self.ai.is_some());
+        let boards = [(names[0], &self.sides[0].board), (names[1], &self.sides[1].board)];
+        draw_match(r, boards, self.countdown_ms, self.outcome, &state.theme);
+        if self.race {
+            draw_race_progress(r, [0, 1].map(|i| self.sides[i].board.game.lines));
+        }
+        let footer = [16., SCREEN_SIZE.1 - 32.];
+        match self.outcome {
+            Some(_) => r.draw_text("Confirm: rematch  Back: choose mode", 16., footer, Color::WHITE),
+            None if self.ai.is_some() => r.draw_text("WASD, Q/E, left Shift or the arrows, right Ctrl, right Shift, /", 16., footer, Color::WHITE),
+            None => r.draw_text("Left: WASD, Q/E, left Shift  Right: arrows, right Ctrl, right Shift, /", 16., footer, Color::WHITE),
+        }
+    }
+    fn menu_input(&mut self, state: &mut GameState, _ctx: &mut Context, input: MenuInput) -> Transition {
+        match input {
+            MenuInput::Confirm if self.outcome.is_some() => {
+                let replay = std::mem::take(&mut self.replay);
+                Transition::Replace(Box::new(VersusScene::new(state, self.race, self.ai.is_some(), replay)))
+            }
+            MenuInput::Back => Transition::Pop(1),
+            _ => Transition::None,
+        }
+    }
+    fn key_down(&mut self, state: &mut GameState, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, repeated: bool) -> Transition {
+        if !self.playing() {
+            return match MenuInput::from_key(keycode, mods, &self.controls[0].bindings) {
+                Some(input) => self.menu_input(state, ctx, input),
+                None => Transition::None,
+            };
+        }
+        if repeated {
+            return Transition::None;
+        }
+        let pressed = self.controls.each_mut().map(|controls| controls.key_down(keycode));
+        if pressed.contains(&Some(Action::Pause)) {
+            return Transition::Pop(1);
+        }
+        Transition::None
+    }
+    fn key_up(&mut self, _state: &mut GameState, keycode: KeyCode) {
+        for controls in &mut self.controls {
+            controls.key_up(keycode);
+        }
+    }
+    fn gamepad_button_down(&mut self, state: &mut GameState, ctx: &mut Context, btn: Button, id: GamepadId) -> Transition {
+        if !self.playing() {
+            return match state.config.profile().buttons.menu_input(btn) {
+                Some(input) => self.menu_input(state, ctx, input),
+                None => Transition::None,
+            };
+        }
+        match state.config.profile().buttons.action(btn) {
+            Some(Action::Pause) => return Transition::Pop(1),
+            Some(action) => {
+                // A gamepad plays the first side without one the first time it is pressed
+                let controls = &mut self.controls;
+                let i = controls.iter().position(|c| c.gamepad == Some(id)).or_else(|| controls.iter().position(|c| c.gamepad.is_none()));
+                if let Some(controls) = i.map(|i| &mut controls[i]) {
+                    controls.gamepad = Some(id);
+                    controls.push(action, true);
+                }
+            }
+            None => (),
+        }
+        Transition::None
+    }
+    fn gamepad_button_up(&mut self, state: &mut GameState, btn: Button, id: GamepadId) {
+        if let Some(action) = state.config.profile().buttons.action(btn).filter(|&a| a != Action::Pause) {
+            if let Some(controls) = self.controls.iter_mut().find(|c| c.gamepad == Some(id)) {
+                controls.push(action, false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_board_that_tops_out_first_loses() {
+        assert_eq!(decide([None, None], [500, 500]), None);
+        assert_eq!(decide([Some(100), Some(50)], [100, 50]), Some(Outcome::Winner(0)));
+        assert_eq!(decide([Some(50), Some(100)], [50, 100]), Some(Outcome::Winner(1)));
+        assert_eq!(decide([Some(80), Some(80)], [80, 80]), Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn waiting_for_the_board_behind() {
+        // The other board could still top out before tick 100, until it has played that far
+        assert_eq!(decide([Some(100), None], [100, 90]), None);
+        assert_eq!(decide([Some(100), None], [100, 100]), Some(Outcome::Winner(1)));
+        assert_eq!(decide([None, Some(100)], [99, 100]), None);
+        assert_eq!(decide([None, Some(100)], [150, 100]), Some(Outcome::Winner(0)));
+    }
+
+    #[test]
+    fn the_first_to_finish_a_race_wins() {
+        assert_eq!(decide_race([Some(500), None], [true, false], [500, 400]), None);
+        assert_eq!(decide_race([Some(500), None], [true, false], [500, 500]), Some(Outcome::Winner(0)));
+        assert_eq!(decide_race([Some(500), Some(500)], [true, true], [500, 500]), Some(Outcome::Draw));
+        assert_eq!(decide_race([Some(520), Some(500)], [true, true], [520, 500]), Some(Outcome::Winner(1)));
+    }
+
+    #[test]
+    fn topping_out_loses_a_race_unless_the_other_finished_first() {
+        assert_eq!(decide_race([None, Some(300)], [false, false], [300, 300]), Some(Outcome::Winner(0)));
+        assert_eq!(decide_race([None, Some(300)], [false, false], [299, 300]), None);
+        assert_eq!(decide_race([Some(200), Some(300)], [true, false], [200, 300]), Some(Outcome::Winner(0)));
+    }
+
+    #[test]
+    fn matches_are_decided_as_races_only_in_races() {
+        let config = GameConfig::default();
+        let finished = {
+            let mut board = TetrisWidget::new(Mode::Marathon, Mode::Marathon.rules(), 1, Handling::default(), &config);
+            board.game.lines = SPRINT_LINES;
+            board
+        };
+        let playing = TetrisWidget::new(Mode::Marathon, Mode::Marathon.rules(), 1, Handling::default(), &config);
+        let boards = [&finished, &playing];
+        assert_eq!(decide_match(true, boards, [Some(400), None], [400, 400]), Some(Outcome::Winner(0)));
+        // Outside a race, a game ending is topping out whatever the lines
+        assert_eq!(decide_match(false, boards, [Some(400), None], [400, 400]), Some(Outcome::Winner(1)));
+    }
+}
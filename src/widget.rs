@@ -0,0 +1,75 @@
+use ggez::graphics::{Color, Rect};
+
+use crate::{
+    config::{GameConfig, Handling},
+    input::Action,
+    mode::Mode,
+    render::{self, board_offset, cell_rect, Renderer, Viewport},
+    rules::{Game, TickResult},
+    ruleset::GameRules,
+    theme::Theme,
+};
+
+/// A board with its own game that can be drawn anywhere on the screen at any size,
+/// so more than one can be shown at once, e.g. side by side for splitscreen or spectating.
+#[derive(Clone)]
+pub struct TetrisWidget {
+    pub game: Game,
+    /// Whether the falling piece is drawn sliding down between rows.
+    pub smooth_fall: bool,
+}
+
+impl TetrisWidget {
+    /// A new game of `mode` played by `rules`, usually `mode.rules()`, on the board `config` describes.
+    pub fn new(mode: Mode, rules: &'static dyn GameRules, seed: u64, handling: Handling, config: &GameConfig) -> Self {
+        TetrisWidget::with_game(Game::with_config(mode, rules, seed, handling, config))
+    }
+    /// Shows a game that is already going, e.g. a resumed one or one received from another player.
+    pub fn with_game(game: Game) -> Self {
+        TetrisWidget { game, smooth_fall: true }
+    }
+    pub fn tick(&mut self, actions: &[(Action, bool)]) -> TickResult {
+        self.game.tick(actions)
+    }
+    /// The part of the screen the board and the pieces beside it take up when drawn full size, with a line above for the score.
+    pub fn layout(&self) -> Rect {
+        let (width, height) = self.game.grid.size();
+        let [dx, dy] = board_offset((width, height));
+        let top_left = cell_rect(-4., -5.);
+        let bottom_right = cell_rect(width as f32, height as f32);
+        Rect::new(top_left.x + dx, top_left.y + dy, bottom_right.x - top_left.x, bottom_right.y - top_left.y)
+    }
+    /// The part of the screen the board alone takes up when drawn full size, with the garbage meter beside it.
+    fn board_layout(&self) -> Rect {
+        let (width, height) = self.game.grid.size();
+        let [dx, dy] = board_offset((width, height));
+        let top_left = cell_rect(-0.25, 0.);
+        let bottom_right = cell_rect(width as f32, height as f32);
+        Rect::new(top_left.x + dx, top_left.y + dy, bottom_right.x - top_left.x, bottom_right.y - top_left.y)
+    }
+    /// Draws just the board into `rect`, scaled to fit, for when there are so many on the screen
+    /// that the pieces beside them and the score would be too small to make out.
+    pub fn draw_mini(&self, r: &mut dyn Renderer, rect: Rect, theme: &Theme) {
+        let r = &mut Viewport::new(r, self.board_layout(), rect);
+        render::draw_board(r, &self.game, 0., theme);
+        if self.game.gameover {
+            r.draw_panel(self.board_layout(), Color::new(0., 0., 0., 0.6));
+        }
+    }
+    /// Draws the game into `rect`, scaled to fit, `between_ticks` of the way to the next tick.
+    pub fn draw(&self, r: &mut dyn Renderer, rect: Rect, between_ticks: f32, theme: &Theme) {
+        let layout = self.layout();
+        let r = &mut Viewport::new(r, layout, rect);
+        let fall = if self.smooth_fall && !self.game.gameover { self.game.fall_progress(between_ticks) } else { 0. };
+        render::draw_game(r, &self.game, fall, theme);
+
+        let status = format!("Score: {}  Lines: {}", self.game.score, self.game.lines);
+        r.draw_text(&status, 24., [layout.x, layout.y], Color::WHITE);
+        if self.game.gameover {
+            let (width, height) = self.game.grid.size();
+            let [dx, dy] = board_offset((width, height));
+            let middle = cell_rect(width as f32 / 2., height as f32 / 2.);
+            r.draw_text_centred("GAME OVER", 48., [middle.x + dx, middle.y + dy], Color::RED);
+        }
+    }
+}
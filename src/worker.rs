@@ -0,0 +1,98 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use log::warn;
+
+/// A thread doing slow work for the game loop, like encoding a clip or searching for the AI's next move,
+/// so that it never holds up a tick or a frame. Jobs are sent to it over one channel and what they come to
+/// comes back over another, to be picked up with `results` on a later update.
+///
+/// Dropping the worker, e.g. along with the scene it belongs to, stops it: the job under way is finished,
+/// or given up on if it checks its `Stop`, and the jobs still waiting are thrown away.
+pub struct Worker<J, R> {
+    name: String,
+    jobs: Option<Sender<J>>,
+    results: Receiver<R>,
+    stop: Stop,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Tells a job that its worker is being stopped, so a long search can give up early.
+#[derive(Debug, Clone, Default)]
+pub struct Stop(Arc<AtomicBool>);
+
+impl Stop {
+    pub fn requested(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl<J: Send + 'static, R: Send + 'static> Worker<J, R> {
+    /// Starts a thread called `name` doing `work` for each job sent to it, in order.
+    pub fn spawn(name: &str, mut work: impl FnMut(J, &Stop) -> R + Send + 'static) -> Self {
+        let (jobs, queued) = mpsc::channel::<J>();
+        let (done, results) = mpsc::channel();
+        let stop = Stop::default();
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new().name(name.to_owned()).spawn(move || {
+            for job in queued {
+                if thread_stop.requested() {
+                    break;
+                }
+                // Nobody is waiting for the results any more
+                if done.send(work(job, &thread_stop)).is_err() {
+                    break;
+                }
+            }
+        });
+        let thread = thread.map_err(|e| warn!("Could not start the {name} thread: {e}")).ok();
+        Worker {
+            name: name.to_owned(),
+            jobs: thread.is_some().then_some(jobs),
+            results,
+            stop,
+            thread,
+        }
+    }
+}
+
+impl<J, R> Worker<J, R> {
+    /// Queues up a job, returning whether the worker is still there to do it.
+    pub fn send(&self, job: J) -> bool {
+        self.jobs.as_ref().is_some_and(|jobs| jobs.send(job).is_ok())
+    }
+    /// What the jobs finished since last time came to, in the order they were sent.
+    pub fn results(&self) -> impl Iterator<Item = R> + '_ {
+        self.results.try_iter()
+    }
+    /// Stops taking jobs and waits for the ones already sent to be done, e.g. to finish a clip before quitting.
+    pub fn finish(mut self) {
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("The {} thread panicked", self.name);
+            }
+        }
+    }
+}
+
+impl<J, R> Drop for Worker<J, R> {
+    fn drop(&mut self) {
+        self.stop.0.store(true, Ordering::Relaxed);
+        // Closing the channel ends the thread once it is done with the job it is on
+        self.jobs = None;
+    }
+}
+
+impl<J, R> fmt::Debug for Worker<J, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Worker").field("name", &self.name).field("running", &self.jobs.is_some()).finish()
+    }
+}